@@ -101,6 +101,25 @@ impl Bencode {
         Ok(bencode)
     }
 
+    /// Parses the bencoded value at the start of a vec of bytes, that may be followed by other data.
+    ///
+    /// Returns the Bencode enum and the number of bytes the value takes.
+    ///
+    /// # Example
+    ///
+    /// ```rust
+    /// use bencoder::bencode::Bencode;
+    ///
+    /// let data = b"i123eextra data";
+    /// let (bencode, size) = Bencode::decode_prefix(data).unwrap();
+    ///
+    /// assert_eq!(bencode, Bencode::BNumber(123));
+    /// assert_eq!(&data[size..], b"extra data");
+    /// ```
+    pub fn decode_prefix(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
+        Bencode::do_decode(data)
+    }
+
     fn do_decode(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         if data.is_empty() {
             return Err(BencodeError::InvalidBencode);
@@ -115,33 +134,32 @@ impl Bencode {
     }
 
     fn decode_string(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
-        let mut i = 0;
-        while data[i] != b':' {
-            i += 1;
-        }
+        let i = data
+            .iter()
+            .position(|b| *b == b':')
+            .ok_or(BencodeError::InvalidBencodeString)?;
         let length = &data[0..i];
         let length = match String::from_utf8(length.to_vec()) {
             Ok(s) => s,
             Err(_) => return Err(BencodeError::InvalidBencodeString),
         };
-        let length = match length.parse::<i64>() {
+        let length = match length.parse::<usize>() {
             Ok(n) => n,
             Err(_) => return Err(BencodeError::InvalidBencodeString),
         };
-        let mut i = i + 1;
-        let mut string = Vec::new();
-        for _ in 0..length {
-            string.push(data[i]);
-            i += 1;
-        }
-        Ok((Bencode::BString(string), i))
+        let start = i + 1;
+        let end = start
+            .checked_add(length)
+            .filter(|end| *end <= data.len())
+            .ok_or(BencodeError::InvalidBencodeString)?;
+        Ok((Bencode::BString(data[start..end].to_vec()), end))
     }
 
     fn decode_number(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
-        let mut i = 1;
-        while data[i] != b'e' {
-            i += 1;
-        }
+        let i = data
+            .iter()
+            .position(|b| *b == b'e')
+            .ok_or(BencodeError::InvalidBencodeNumber)?;
         let number = &data[1..i];
         let number = match String::from_utf8(number.to_vec()) {
             Ok(s) => s,
@@ -157,7 +175,7 @@ impl Bencode {
     fn decode_list(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         let mut i = 1;
         let mut list = Vec::new();
-        while data[i] != b'e' {
+        while *data.get(i).ok_or(BencodeError::InvalidBencodeList)? != b'e' {
             let (value, size) = Bencode::do_decode(&data[i..])?;
             list.push(value);
             i += size;
@@ -168,7 +186,7 @@ impl Bencode {
     fn decode_dict(data: &[u8]) -> Result<(Bencode, usize), BencodeError> {
         let mut i = 1;
         let mut dict = BTreeMap::new();
-        while data[i] != b'e' {
+        while *data.get(i).ok_or(BencodeError::InvalidBencodeDict)? != b'e' {
             let (key, size) = Bencode::do_decode(&data[i..])?;
             i += size;
             let (value, size) = Bencode::do_decode(&data[i..])?;
@@ -259,6 +277,26 @@ mod tests {
         assert_eq!(Bencode::decode(data), Err(BencodeError::InvalidBencode));
     }
 
+    #[test]
+    fn test_decode_truncated_data() {
+        assert_eq!(
+            Bencode::decode(b"5:spa"),
+            Err(BencodeError::InvalidBencodeString)
+        );
+        assert_eq!(
+            Bencode::decode(b"i123"),
+            Err(BencodeError::InvalidBencodeNumber)
+        );
+        assert_eq!(
+            Bencode::decode(b"l4:spam"),
+            Err(BencodeError::InvalidBencodeList)
+        );
+        assert_eq!(
+            Bencode::decode(b"d3:cow"),
+            Err(BencodeError::InvalidBencode)
+        );
+    }
+
     #[test]
     fn test_decode_string() {
         let data = b"4:spam";
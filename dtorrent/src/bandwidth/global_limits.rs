@@ -0,0 +1,173 @@
+use chrono::{Local, NaiveTime};
+use std::{
+    sync::{Arc, Mutex, MutexGuard, PoisonError},
+    thread,
+    time::Duration,
+};
+
+use super::{
+    rate_limiter::{RateLimits, Rates},
+    speed_schedule::SpeedSchedule,
+};
+use crate::config::cfg::Cfg;
+
+/// Time between checks of the alternative speed schedule.
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug)]
+struct Settings {
+    normal: Rates,
+    alternative: Rates,
+    alternative_active: bool,
+}
+
+/// The rate limits shared by all the torrents of the client.
+///
+/// The normal limits are switched to the alternative ones during the windows of the speed schedule.
+/// Both can be changed at any time, and the change is seen right away by all the peer sessions.
+///
+/// To create a new `GlobalRateLimits`, use GlobalRateLimits::new(config).
+#[derive(Debug)]
+pub struct GlobalRateLimits {
+    limits: Arc<RateLimits>,
+    schedule: SpeedSchedule,
+    settings: Mutex<Settings>,
+}
+
+impl GlobalRateLimits {
+    /// Creates the global limits from the rates and the schedule of the configuration, using the normal rates.
+    pub fn new(config: &Cfg) -> Self {
+        let normal = Rates::from_kbps(config.max_download_kbps, config.max_upload_kbps);
+        Self {
+            limits: Arc::new(RateLimits::new(normal)),
+            schedule: config.alt_speed_schedule.clone(),
+            settings: Mutex::new(Settings {
+                normal,
+                alternative: Rates::from_kbps(
+                    config.alt_max_download_kbps,
+                    config.alt_max_upload_kbps,
+                ),
+                alternative_active: false,
+            }),
+        }
+    }
+
+    /// Returns the limiters used by the peer sessions.
+    pub fn limits(&self) -> Arc<RateLimits> {
+        self.limits.clone()
+    }
+
+    /// Returns the normal rates.
+    pub fn rates(&self) -> Rates {
+        self.lock_settings().normal
+    }
+
+    /// Changes the normal rates, they are applied now unless the alternative ones are being used.
+    pub fn set_rates(&self, rates: Rates) {
+        let mut settings = self.lock_settings();
+        settings.normal = rates;
+        if !settings.alternative_active {
+            self.limits.set_rates(rates);
+        }
+    }
+
+    /// Returns the alternative rates.
+    pub fn alternative_rates(&self) -> Rates {
+        self.lock_settings().alternative
+    }
+
+    /// Changes the alternative rates, they are applied now if the schedule is in one of its windows.
+    pub fn set_alternative_rates(&self, rates: Rates) {
+        let mut settings = self.lock_settings();
+        settings.alternative = rates;
+        if settings.alternative_active {
+            self.limits.set_rates(rates);
+        }
+    }
+
+    /// Returns true if the alternative rates are being used.
+    pub fn is_alternative_active(&self) -> bool {
+        self.lock_settings().alternative_active
+    }
+
+    /// Uses the alternative rates if the time is inside a window of the schedule, and the normal ones otherwise.
+    pub fn update(&self, time: NaiveTime) {
+        let mut settings = self.lock_settings();
+        let alternative_active = self.schedule.contains(time);
+        if alternative_active == settings.alternative_active {
+            return;
+        }
+        settings.alternative_active = alternative_active;
+        if alternative_active {
+            self.limits.set_rates(settings.alternative);
+        } else {
+            self.limits.set_rates(settings.normal);
+        }
+    }
+
+    /// Follows the schedule forever, returning right away if it has no windows.
+    pub fn run_schedule(&self) {
+        if self.schedule.is_empty() {
+            return;
+        }
+        loop {
+            self.update(Local::now().time());
+            thread::sleep(SCHEDULE_CHECK_INTERVAL);
+        }
+    }
+
+    /// The settings are always valid, even if a thread panicked while changing them.
+    fn lock_settings(&self) -> MutexGuard<'_, Settings> {
+        self.settings.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_alternative_rates_follow_the_schedule() {
+        let limits = GlobalRateLimits::new(&config("08:00-18:00"));
+        assert_eq!(limits.limits().rates(), Rates::from_kbps(100, 50));
+
+        limits.update(time(9, 0));
+        assert!(limits.is_alternative_active());
+        assert_eq!(limits.limits().rates(), Rates::from_kbps(10, 5));
+
+        limits.update(time(19, 0));
+        assert!(!limits.is_alternative_active());
+        assert_eq!(limits.limits().rates(), Rates::from_kbps(100, 50));
+    }
+
+    #[test]
+    fn test_rates_can_be_changed() {
+        let limits = GlobalRateLimits::new(&config("08:00-18:00"));
+
+        limits.set_alternative_rates(Rates::from_kbps(1, 1));
+        assert_eq!(limits.limits().rates(), Rates::from_kbps(100, 50));
+
+        limits.update(time(9, 0));
+        limits.set_rates(Rates::from_kbps(200, 0));
+        assert_eq!(limits.limits().rates(), Rates::from_kbps(1, 1));
+
+        limits.update(time(18, 0));
+        assert_eq!(limits.limits().rates(), Rates::from_kbps(200, 0));
+    }
+
+    // Auxiliary functions
+
+    fn config(schedule: &str) -> Cfg {
+        let mut config = Cfg::new("config.cfg").unwrap();
+        config.max_download_kbps = 100;
+        config.max_upload_kbps = 50;
+        config.alt_max_download_kbps = 10;
+        config.alt_max_upload_kbps = 5;
+        config.alt_speed_schedule = schedule.parse().unwrap();
+        config
+    }
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+}
@@ -0,0 +1,3 @@
+pub mod global_limits;
+pub mod rate_limiter;
+pub mod speed_schedule;
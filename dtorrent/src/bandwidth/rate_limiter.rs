@@ -0,0 +1,201 @@
+use std::{
+    sync::{Mutex, MutexGuard, PoisonError},
+    time::Instant,
+};
+
+/// Seconds of transfer at the full rate that the tokens can accumulate, so an idle limiter allows a short burst.
+const BURST_SECONDS: f64 = 1.0;
+
+/// Transfer rates in bytes per second, 0 means unlimited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Rates {
+    pub download: u64,
+    pub upload: u64,
+}
+
+impl Rates {
+    /// Creates the rates from kilobytes per second, as they are set in the configuration.
+    pub fn from_kbps(download_kbps: u64, upload_kbps: u64) -> Self {
+        Self {
+            download: download_kbps * 1024,
+            upload: upload_kbps * 1024,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Bucket {
+    rate: u64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl Bucket {
+    fn capacity(&self) -> f64 {
+        self.rate as f64 * BURST_SECONDS
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now
+            .saturating_duration_since(self.last_refill)
+            .as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * self.rate as f64).min(self.capacity());
+    }
+}
+
+/// A token bucket limiting the bytes transferred per second in one direction.
+///
+/// The tokens refill at the rate, up to one second of transfer, and each byte transferred takes a token.
+/// A transfer can take more tokens than there are, leaving a debt that is paid before the next one, so whole blocks can be sent under low rates.
+///
+/// It is shared by the sessions it limits, and its rate can be changed at any time. A rate of 0 means unlimited.
+///
+/// To create a new `RateLimiter`, use RateLimiter::new(rate).
+#[derive(Debug)]
+pub struct RateLimiter {
+    bucket: Mutex<Bucket>,
+}
+
+impl RateLimiter {
+    /// Creates a new `RateLimiter` with the rate in bytes per second, starting with a full bucket.
+    pub fn new(rate: u64) -> Self {
+        let bucket = Bucket {
+            rate,
+            tokens: 0.0,
+            last_refill: Instant::now(),
+        };
+        let tokens = bucket.capacity();
+        Self {
+            bucket: Mutex::new(Bucket { tokens, ..bucket }),
+        }
+    }
+
+    /// Returns the rate in bytes per second, 0 if it is unlimited.
+    pub fn rate(&self) -> u64 {
+        self.lock_bucket().rate
+    }
+
+    /// Returns true if the limiter has a rate.
+    pub fn is_limited(&self) -> bool {
+        self.rate() > 0
+    }
+
+    /// Changes the rate in bytes per second, 0 to remove the limit.
+    ///
+    /// The tokens accumulated with the previous rate are kept, up to the capacity of the new one.
+    pub fn set_rate(&self, rate: u64) {
+        let mut bucket = self.lock_bucket();
+        bucket.refill(Instant::now());
+        bucket.rate = rate;
+        bucket.tokens = bucket.tokens.min(bucket.capacity());
+    }
+
+    /// Returns the bytes that can be transferred now, 0 while there is a debt, or `None` if it is unlimited.
+    pub fn available(&self) -> Option<usize> {
+        let mut bucket = self.lock_bucket();
+        if bucket.rate == 0 {
+            return None;
+        }
+        bucket.refill(Instant::now());
+        Some(bucket.tokens.max(0.0) as usize)
+    }
+
+    /// Takes the tokens of the bytes transferred, even if there are not enough.
+    pub fn consume(&self, bytes: usize) {
+        let mut bucket = self.lock_bucket();
+        if bucket.rate == 0 {
+            return;
+        }
+        bucket.refill(Instant::now());
+        bucket.tokens -= bytes as f64;
+    }
+
+    /// A poisoned bucket is still valid, at worst a transfer was not counted.
+    fn lock_bucket(&self) -> MutexGuard<'_, Bucket> {
+        self.bucket.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+/// The download and upload limiters of the client or of a torrent.
+///
+/// To create a new `RateLimits`, use RateLimits::new(rates).
+#[derive(Debug)]
+pub struct RateLimits {
+    pub download: RateLimiter,
+    pub upload: RateLimiter,
+}
+
+impl RateLimits {
+    /// Creates the limiters with the given rates.
+    pub fn new(rates: Rates) -> Self {
+        Self {
+            download: RateLimiter::new(rates.download),
+            upload: RateLimiter::new(rates.upload),
+        }
+    }
+
+    /// Returns the current rates of the limiters.
+    pub fn rates(&self) -> Rates {
+        Rates {
+            download: self.download.rate(),
+            upload: self.upload.rate(),
+        }
+    }
+
+    /// Changes the rates of the limiters.
+    pub fn set_rates(&self, rates: Rates) {
+        self.download.set_rate(rates.download);
+        self.upload.set_rate(rates.upload);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{thread, time::Duration};
+
+    #[test]
+    fn test_unlimited_limiter() {
+        let limiter = RateLimiter::new(0);
+        limiter.consume(1_000_000);
+
+        assert!(!limiter.is_limited());
+        assert_eq!(limiter.available(), None);
+    }
+
+    #[test]
+    fn test_tokens_are_consumed_and_refilled() {
+        let limiter = RateLimiter::new(10_000);
+        assert_eq!(limiter.available(), Some(10_000));
+
+        limiter.consume(10_000);
+        assert!(limiter.available().unwrap() < 100);
+
+        thread::sleep(Duration::from_millis(200));
+        let available = limiter.available().unwrap();
+        assert!((1_500..=10_000).contains(&available));
+    }
+
+    #[test]
+    fn test_debt_is_paid_before_the_next_transfer() {
+        let limiter = RateLimiter::new(1_000);
+        limiter.consume(3_000);
+
+        thread::sleep(Duration::from_millis(100));
+        assert_eq!(limiter.available(), Some(0));
+    }
+
+    #[test]
+    fn test_rate_can_be_changed() {
+        let limits = RateLimits::new(Rates::from_kbps(100, 0));
+        assert_eq!(limits.download.available(), Some(100 * 1024));
+        assert_eq!(limits.upload.available(), None);
+
+        limits.set_rates(Rates::from_kbps(10, 5));
+
+        assert_eq!(limits.rates(), Rates::from_kbps(10, 5));
+        assert_eq!(limits.download.available(), Some(10 * 1024));
+        assert!(limits.upload.available().unwrap() < 1024);
+    }
+}
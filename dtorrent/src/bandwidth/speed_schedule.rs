@@ -0,0 +1,114 @@
+use chrono::NaiveTime;
+use std::str::FromStr;
+
+const TIME_FORMAT: &str = "%H:%M";
+
+/// Posible `SpeedSchedule` errors.
+#[derive(Debug)]
+pub enum SpeedScheduleError {
+    InvalidWindow(String),
+    InvalidTime(String),
+}
+
+/// Time-of-day windows during which the alternative speed limits are used.
+///
+/// It is written as comma separated `HH:MM-HH:MM` windows, like `08:00-18:00,22:30-23:30`.
+/// A window that ends before it starts goes through midnight, and an empty schedule has no windows.
+///
+/// To create a new `SpeedSchedule`, parse it with `str::parse()`.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SpeedSchedule {
+    windows: Vec<(NaiveTime, NaiveTime)>,
+}
+
+impl SpeedSchedule {
+    /// Returns true if the schedule has no windows.
+    pub fn is_empty(&self) -> bool {
+        self.windows.is_empty()
+    }
+
+    /// Returns true if the time is inside one of the windows, including its start and excluding its end.
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        self.windows.iter().any(|(start, end)| {
+            if start <= end {
+                *start <= time && time < *end
+            } else {
+                *start <= time || time < *end
+            }
+        })
+    }
+
+    fn parse_time(time: &str) -> Result<NaiveTime, SpeedScheduleError> {
+        NaiveTime::parse_from_str(time.trim(), TIME_FORMAT)
+            .map_err(|_| SpeedScheduleError::InvalidTime(time.to_string()))
+    }
+}
+
+impl FromStr for SpeedSchedule {
+    type Err = SpeedScheduleError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let windows = s
+            .split(',')
+            .map(str::trim)
+            .filter(|window| !window.is_empty())
+            .map(|window| match window.split_once('-') {
+                Some((start, end)) => Ok((Self::parse_time(start)?, Self::parse_time(end)?)),
+                None => Err(SpeedScheduleError::InvalidWindow(window.to_string())),
+            })
+            .collect::<Result<Vec<(NaiveTime, NaiveTime)>, SpeedScheduleError>>()?;
+        Ok(Self { windows })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_windows_of_the_day() {
+        let schedule: SpeedSchedule = "08:00-18:00, 20:30-21:00".parse().unwrap();
+
+        assert!(schedule.contains(time(8, 0)));
+        assert!(schedule.contains(time(12, 15)));
+        assert!(!schedule.contains(time(18, 0)));
+        assert!(schedule.contains(time(20, 45)));
+        assert!(!schedule.contains(time(7, 59)));
+    }
+
+    #[test]
+    fn test_window_through_midnight() {
+        let schedule: SpeedSchedule = "23:00-06:30".parse().unwrap();
+
+        assert!(schedule.contains(time(23, 30)));
+        assert!(schedule.contains(time(3, 0)));
+        assert!(!schedule.contains(time(6, 30)));
+        assert!(!schedule.contains(time(12, 0)));
+    }
+
+    #[test]
+    fn test_empty_schedule() {
+        let schedule: SpeedSchedule = "".parse().unwrap();
+
+        assert!(schedule.is_empty());
+        assert!(!schedule.contains(time(12, 0)));
+    }
+
+    #[test]
+    fn test_invalid_schedules() {
+        assert!(matches!(
+            "08:00".parse::<SpeedSchedule>(),
+            Err(SpeedScheduleError::InvalidWindow(_))
+        ));
+        assert!(matches!(
+            "08:00-25:00".parse::<SpeedSchedule>(),
+            Err(SpeedScheduleError::InvalidTime(_))
+        ));
+    }
+
+    // Auxiliary functions
+
+    fn time(hour: u32, minute: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, minute, 0).unwrap()
+    }
+}
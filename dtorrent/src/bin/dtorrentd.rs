@@ -0,0 +1,99 @@
+use dtorrent::{bt_client::btclient::BtClient, statistics::torrent_stats::TorrentStats};
+use signal_hook::consts::{SIGINT, SIGTERM};
+use std::{
+    env, process,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+/// Time between checks of the termination signals.
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time between the status lines printed for each torrent.
+const STATUS_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Runs the client without a display, until it gets SIGTERM or SIGINT.
+///
+/// On those signals every torrent is stopped and announces the `stopped` event before exiting.
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() != 3 {
+        eprintln!("Usage: dtorrentd <config file> <torrents directory>");
+        process::exit(2);
+    }
+
+    let client = match BtClient::init_with_config(&args[1], args[2].clone()) {
+        Ok(client) => Arc::new(client),
+        Err(error) => {
+            eprintln!("Couldn't start the client: {:?}", error);
+            process::exit(1);
+        }
+    };
+
+    let terminate = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGINT] {
+        if let Err(error) = signal_hook::flag::register(signal, terminate.clone()) {
+            eprintln!("Couldn't handle signal {}: {}", signal, error);
+            process::exit(1);
+        }
+    }
+
+    let (stats_sender, stats_receiver) = mpsc::channel();
+    let running_client = client.clone();
+    let client_thread = match thread::Builder::new()
+        .name("Client".to_string())
+        .spawn(move || running_client.run(stats_sender))
+    {
+        Ok(handle) => handle,
+        Err(error) => {
+            eprintln!("Couldn't start the client: {}", error);
+            process::exit(1);
+        }
+    };
+
+    let mut last_status = Instant::now();
+    while !terminate.load(Ordering::Relaxed) {
+        match stats_receiver.recv_timeout(SIGNAL_POLL_INTERVAL) {
+            Ok(stats) if last_status.elapsed() >= STATUS_INTERVAL => {
+                stats.iter().for_each(print_status);
+                last_status = Instant::now();
+            }
+            Err(RecvTimeoutError::Disconnected) => thread::sleep(SIGNAL_POLL_INTERVAL),
+            _ => {}
+        }
+    }
+
+    println!("Stopping the torrents...");
+    if let Err(error) = client.shutdown() {
+        eprintln!("Couldn't stop the torrents: {:?}", error);
+        process::exit(1);
+    }
+    if client_thread.join().is_err() {
+        eprintln!("The client stopped with an error.");
+        process::exit(1);
+    }
+}
+
+fn print_status(stats: &TorrentStats) {
+    let progress = if stats.pieces_amount == 0 {
+        0.0
+    } else {
+        stats.downloaded_pieces_amount as f64 * 100.0 / stats.pieces_amount as f64
+    };
+    let state = if stats.paused { "paused" } else { "active" };
+    println!(
+        "{} [{}] {:.1}% - {} peers - down {:.1} kb/s - up {:.1} kb/s - eta {}",
+        stats.torrent_name,
+        state,
+        progress,
+        stats.peers_amount,
+        stats.download_speed,
+        stats.upload_speed,
+        stats.eta
+    );
+}
@@ -1,70 +1,429 @@
 use crate::{
+    bandwidth::{global_limits::GlobalRateLimits, rate_limiter::Rates},
     bt_client::btclient_error::BtClientError,
+    bt_client::client_command::ClientCommand,
+    bt_client::directory_watcher::DirectoryWatcher,
     bt_client::error_message::ErrorMessage,
     bt_server::server::BtServer,
     config::cfg::Cfg,
+    dht::node::DhtNode,
+    reactor::peer_reactor::{PeerReactor, DEFAULT_CONNECTORS, DEFAULT_EVENT_LOOPS},
+    rpc::rpc_server::RpcServer,
     statistics::statistics_updater::StatisticsUpdater,
+    statistics::stats_sink::StatsSink,
     statistics::torrent_stats::TorrentStats,
-    torrent_handler::{handler::TorrentHandler, status::AtomicTorrentStatus},
+    torrent_handler::{
+        choker::Choker, handler::TorrentHandler, metadata_fetcher::MetadataFetcher,
+        scrape_updater::ScrapeUpdater, seed_limits::SeedLimitAction, status::AtomicTorrentStatus,
+        torrent_list::TorrentList,
+    },
+    torrent_parser::magnet::Magnet,
     torrent_parser::parser::TorrentParser,
     torrent_parser::torrent::Torrent,
+    utp::socket::UtpSocket,
 };
-use gtk::glib;
 use logger::logger_receiver::Logger;
 use logger::logger_sender::LoggerSender;
 use rand::Rng;
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     fs, io,
-    sync::Arc,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, MutexGuard, PoisonError,
+    },
     thread::{self, JoinHandle},
+    time::{Duration, Instant},
 };
 
 const CONFIG_FILE_PATH: &str = "config.cfg";
 
+/// Maximum time to wait for the peer sessions of a removed torrent to close before deleting its data.
+const SESSIONS_CLOSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Time between checks of the peer sessions of a removed torrent that are still open.
+const SESSIONS_CLOSE_POLL_INTERVAL: Duration = Duration::from_millis(200);
+
 /**
 Represents the BitTorrent client application.
 
 It holds the code for initializing the client, and for starting the torrent downloading process.
 
+While it is running, torrents can be added, and each torrent can be paused, resumed, rechecked or removed, with the commands sent through its control channel.
+The torrents directory is watched to add the .torrent files dropped in it.
+
+The statistics of the torrents are sent to a `StatsSink`, so the client can run with the UI or without it.
+If it is enabled in the configuration, the client can also be controlled with the JSON over HTTP RPC.
+
 */
 pub struct BtClient {
-    config: Cfg,
+    config: Mutex<Cfg>,
     logger: Logger,
     torrents: Vec<Torrent>,
+    magnets: Vec<Magnet>,
+    torrents_directory: String,
     client_peer_id: String,
+    dht: Option<Arc<DhtNode>>,
+    utp: Option<Arc<UtpSocket>>,
+    reactor: Arc<PeerReactor>,
+    rate_limits: Arc<GlobalRateLimits>,
+    torrent_list: TorrentList,
+    command_sender: Sender<ClientCommand>,
+    command_receiver: Mutex<Receiver<ClientCommand>>,
 }
 
 impl BtClient {
     /**
     Method for initializing the BitTorrent client application.
 
-    Recieves a path to a directory containing the .torrent files to download.
+    Recieves a path to a directory containing the .torrent files to download, and .magnet files with a magnet link each.
 
     It reads the configuration file (./config.cfg), starts a Logger writing to the folder indicated by that configuration file, and then attempts to parse the torrent files placed inside the provided torrents directory.
+    If uTP is enabled in the configuration, its UDP socket is bound on the TCP port, and shared with the DHT node if it uses the same port.
+    If the DHT is enabled in the configuration, the DHT node is started before, to find peers of the torrents without a working tracker.
+    The peer reactor that drives the sessions with the peers of all the torrents is started too, within the global rate limits of the configuration.
+    The magnet links are only parsed, their metadata is fetched from their peers once the client runs.
 
-    The corrently parsed torrents are stored inside the BtClient struct, and will begin downloading when the '.run()' method is called.
+    The corrently parsed torrents and magnet links are stored inside the BtClient struct, and will begin downloading when the '.run()' method is called.
     */
     pub fn init(torrents_directory: String) -> Result<Self, BtClientError> {
-        let config = Self::read_configuration_file(CONFIG_FILE_PATH)?;
+        Self::init_with_config(CONFIG_FILE_PATH, torrents_directory)
+    }
+
+    /// Initializes the BitTorrent client application like `init()`, reading the configuration from the given file instead of ./config.cfg.
+    pub fn init_with_config(
+        config_path: &str,
+        torrents_directory: String,
+    ) -> Result<Self, BtClientError> {
+        let config = Self::read_configuration_file(config_path)?;
         let logger = Logger::new(&config.log_directory, config.max_log_file_kb_size * 1000)?;
 
         let logger_sender = logger.new_sender();
         logger_sender.info("Initializing client...");
         logger_sender.info("Configuration file loaded correctly.");
 
-        let torrents = Self::parse_torrents_in_directory(logger_sender, torrents_directory)?;
-
         let client_peer_id = Self::generate_peer_id();
+        let utp = Self::start_utp(&logger_sender, &config);
+        let dht = Self::start_dht(&logger_sender, &config, &utp);
+        let rate_limits = Self::start_rate_limits(&logger_sender, &config);
+        let reactor = PeerReactor::start(
+            DEFAULT_EVENT_LOOPS,
+            DEFAULT_CONNECTORS,
+            rate_limits.limits(),
+            logger_sender.clone(),
+        )
+        .map_err(BtClientError::ReactorError)?;
 
+        let torrents =
+            Self::parse_torrents_in_directory(logger_sender.clone(), torrents_directory.clone())?;
+        let magnets = Self::parse_magnets_in_directory(&logger_sender, torrents_directory.clone())?;
+
+        let (command_sender, command_receiver) = mpsc::channel();
         Ok(Self {
-            config,
+            config: Mutex::new(config),
             logger,
             torrents,
+            magnets,
+            torrents_directory,
             client_peer_id,
+            dht,
+            utp,
+            reactor,
+            rate_limits,
+            torrent_list: TorrentList::new(),
+            command_sender,
+            command_receiver: Mutex::new(command_receiver),
         })
     }
 
+    /// Adds a new torrent to the client, which starts downloading it.
+    ///
+    /// The command is handled by the `run()` method.
+    ///
+    /// # Errors
+    /// - `TorrentAlreadyAdded` if the client is already running a torrent with the same info hash.
+    /// - `CommandError` if the command could not be sent.
+    pub fn add_torrent(&self, torrent: Torrent) -> Result<(), BtClientError> {
+        if self.torrent_list.find(&torrent.info_hash()).is_some() {
+            return Err(BtClientError::TorrentAlreadyAdded(torrent.info_hash()));
+        }
+        self.send_command(ClientCommand::Add(torrent))
+    }
+
+    /// Parses the contents of a .torrent file, like an uploaded one, and adds its torrent to the client.
+    ///
+    /// # Errors
+    /// - `InvalidTorrent` if the contents are not a valid torrent file.
+    /// - `TorrentAlreadyAdded` if the client is already running a torrent with the same info hash.
+    /// - `CommandError` if the command could not be sent.
+    pub fn add_torrent_file(&self, contents: &[u8]) -> Result<(), BtClientError> {
+        let torrent = TorrentParser::parse_bytes(contents).map_err(|error| {
+            BtClientError::InvalidTorrent(ErrorMessage::new(format!(
+                "Couldn't parse the torrent file: {:?}",
+                error
+            )))
+        })?;
+        self.add_torrent(torrent)
+    }
+
+    /// Adds the torrent of a magnet link to the client once its metadata is fetched from the peers, on another thread.
+    ///
+    /// # Errors
+    /// - `InvalidTorrent` if the magnet link is not valid.
+    /// - `TorrentAlreadyAdded` if the client is already running a torrent with the same info hash.
+    /// - `CommandError` if the thread fetching the metadata could not be spawned.
+    pub fn add_magnet(&self, link: &str) -> Result<(), BtClientError> {
+        let magnet = Magnet::parse(link).map_err(|error| {
+            BtClientError::InvalidTorrent(ErrorMessage::new(format!(
+                "Couldn't parse the magnet link: {:?}",
+                error
+            )))
+        })?;
+        if self.torrent_list.find(&magnet.info_hash).is_some() {
+            return Err(BtClientError::TorrentAlreadyAdded(magnet.info_hash));
+        }
+        self.fetch_magnet(magnet)
+    }
+
+    /// Fetches the metadata of the magnet link from its peers on another thread, and then adds its torrent to the client.
+    fn fetch_magnet(&self, magnet: Magnet) -> Result<(), BtClientError> {
+        let logger = self.logger.new_sender();
+        let name = magnet.name();
+        let fetcher = MetadataFetcher::new(
+            magnet,
+            self.config(),
+            logger.clone(),
+            self.client_peer_id.clone(),
+            self.dht.clone(),
+        );
+        let command_sender = self.command_sender.clone();
+        let builder = thread::Builder::new().name(format!("Metadata: {}", name));
+        builder
+            .spawn(move || match fetcher.fetch() {
+                Ok(torrent) => {
+                    logger.info(&format!("Magnet {} resolved correctly.", name));
+                    let _ = command_sender.send(ClientCommand::Add(torrent));
+                }
+                Err(error) => logger.warn(&format!(
+                    "Couldn't fetch the metadata of magnet {}: {:?}",
+                    name, error
+                )),
+            })
+            .map(|_| ())
+            .map_err(|error| {
+                BtClientError::CommandError(ErrorMessage::new(format!(
+                    "Couldn't spawn the metadata fetcher: {}",
+                    error
+                )))
+            })
+    }
+
+    /// Pauses the torrent with the given info hash: its peer sessions are closed and the `stopped` event is announced to its tracker.
+    ///
+    /// The command is handled by the `run()` method.
+    ///
+    /// # Errors
+    /// - `TorrentNotFound` if the client is not running a torrent with that info hash.
+    /// - `CommandError` if the command could not be sent.
+    pub fn pause_torrent(&self, info_hash: &str) -> Result<(), BtClientError> {
+        let torrent_status = self.find_torrent(info_hash)?;
+        self.send_command(ClientCommand::Pause(torrent_status.torrent.info_hash()))
+    }
+
+    /// Resumes the paused torrent with the given info hash, or the one that stopped because of an error, which is announced again and connects to its peers.
+    ///
+    /// The command is handled by the `run()` method.
+    ///
+    /// # Errors
+    /// - `TorrentNotFound` if the client is not running a torrent with that info hash.
+    /// - `CommandError` if the command could not be sent.
+    pub fn resume_torrent(&self, info_hash: &str) -> Result<(), BtClientError> {
+        let torrent_status = self.find_torrent(info_hash)?;
+        self.send_command(ClientCommand::Resume(torrent_status.torrent.info_hash()))
+    }
+
+    /// Hash-checks the downloaded data of the torrent with the given info hash on another thread, and saves the result in its resume file.
+    ///
+    /// The command is handled by the `run()` method.
+    ///
+    /// # Errors
+    /// - `TorrentNotFound` if the client is not running a torrent with that info hash.
+    /// - `CommandError` if the command could not be sent.
+    pub fn recheck_torrent(&self, info_hash: &str) -> Result<(), BtClientError> {
+        let torrent_status = self.find_torrent(info_hash)?;
+        self.send_command(ClientCommand::Recheck(torrent_status.torrent.info_hash()))
+    }
+
+    /// Removes the torrent with the given info hash from the client, deleting its downloaded data and resume file if `delete_data` is true.
+    ///
+    /// The command is handled by the `run()` method.
+    ///
+    /// # Errors
+    /// - `TorrentNotFound` if the client is not running a torrent with that info hash.
+    /// - `CommandError` if the command could not be sent.
+    pub fn remove_torrent(&self, info_hash: &str, delete_data: bool) -> Result<(), BtClientError> {
+        let torrent_status = self.find_torrent(info_hash)?;
+        self.send_command(ClientCommand::Remove {
+            info_hash: torrent_status.torrent.info_hash(),
+            delete_data,
+        })
+    }
+
+    /// Returns the status of the torrent with the given info hash, to read or change its limits.
+    ///
+    /// # Errors
+    /// - `TorrentNotFound` if the client is not running a torrent with that info hash.
+    pub fn find_torrent(&self, info_hash: &str) -> Result<Arc<AtomicTorrentStatus>, BtClientError> {
+        self.torrent_list
+            .find(info_hash)
+            .ok_or_else(|| BtClientError::TorrentNotFound(info_hash.to_string()))
+    }
+
+    /// Returns the statistics of every torrent of the client, in the order they were added.
+    pub fn torrent_stats(&self) -> Vec<TorrentStats> {
+        self.torrent_list
+            .statuses()
+            .iter()
+            .filter_map(|torrent_status| TorrentStats::for_torrent(torrent_status).ok())
+            .collect()
+    }
+
+    /// Returns a sender to the log of the client.
+    pub fn logger_sender(&self) -> LoggerSender {
+        self.logger.new_sender()
+    }
+
+    /// Returns the current configuration of the client.
+    pub fn config(&self) -> Cfg {
+        self.lock_config().clone()
+    }
+
+    /// Changes settings of the configuration, named and written like in the config file. The changes are not saved to the file.
+    ///
+    /// The global rate limits are applied right away, the other settings are used by the torrents added afterwards.
+    ///
+    /// # Errors
+    /// - `SettingError` if a setting name is wrong or its value is not valid, then none of the settings are changed.
+    pub fn set_settings(&self, settings: &[(String, String)]) -> Result<(), BtClientError> {
+        let mut config = self.lock_config();
+        let new_config = settings
+            .iter()
+            .try_fold(config.clone(), |new_config, (name, value)| {
+                new_config.with_setting(name, value)
+            })
+            .map_err(|error| BtClientError::SettingError(ErrorMessage::new(error.to_string())))?;
+
+        self.rate_limits.set_rates(Rates::from_kbps(
+            new_config.max_download_kbps,
+            new_config.max_upload_kbps,
+        ));
+        self.rate_limits.set_alternative_rates(Rates::from_kbps(
+            new_config.alt_max_download_kbps,
+            new_config.alt_max_upload_kbps,
+        ));
+        *config = new_config;
+        Ok(())
+    }
+
+    /// The configuration is always valid, even if a thread panicked while changing it.
+    fn lock_config(&self) -> MutexGuard<'_, Cfg> {
+        self.config.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    /// Stops every torrent and makes the `run()` method return once their handlers announced the `stopped` event.
+    ///
+    /// # Errors
+    /// - `CommandError` if the command could not be sent.
+    pub fn shutdown(&self) -> Result<(), BtClientError> {
+        self.send_command(ClientCommand::Shutdown)
+    }
+
+    fn send_command(&self, command: ClientCommand) -> Result<(), BtClientError> {
+        self.command_sender.send(command).map_err(|error| {
+            BtClientError::CommandError(ErrorMessage::new(format!(
+                "Couldn't send the command to the client: {}",
+                error
+            )))
+        })
+    }
+
+    /// Returns the global rate limits, to change them while the client is running.
+    ///
+    /// The limits of each torrent are changed through the `rate_limits()` of its `AtomicTorrentStatus`.
+    pub fn rate_limits(&self) -> Arc<GlobalRateLimits> {
+        self.rate_limits.clone()
+    }
+
+    /// Creates the global rate limits of the configuration, following its alternative speed schedule on a thread if it has one.
+    fn start_rate_limits(log_sender: &LoggerSender, config: &Cfg) -> Arc<GlobalRateLimits> {
+        let rate_limits = Arc::new(GlobalRateLimits::new(config));
+        if !config.alt_speed_schedule.is_empty() {
+            let schedule_limits = rate_limits.clone();
+            let builder = thread::Builder::new().name("Speed schedule".to_string());
+            if let Err(error) = builder.spawn(move || schedule_limits.run_schedule()) {
+                log_sender.error(&format!("An error occurred while trying to spawn a new thread for the speed schedule: {:?}", error));
+            }
+        }
+        rate_limits
+    }
+
+    /// Binds the uTP socket if uTP is enabled in the configuration.
+    ///
+    /// If it could not be bound, the peers are only connected over TCP.
+    fn start_utp(log_sender: &LoggerSender, config: &Cfg) -> Option<Arc<UtpSocket>> {
+        if !config.utp_enabled {
+            return None;
+        }
+        match UtpSocket::bind(config.tcp_port, log_sender.clone()) {
+            Ok(utp) => {
+                log_sender.info("uTP socket started.");
+                Some(utp)
+            }
+            Err(error) => {
+                log_sender.warn(&format!("Couldn't start the uTP socket: {:?}", error));
+                None
+            }
+        }
+    }
+
+    /// Starts the DHT node if it is enabled in the configuration.
+    ///
+    /// If the uTP socket is bound on the DHT port, the node shares it instead of binding its own.
+    /// If it could not be started, the torrents only get peers from their trackers.
+    fn start_dht(
+        log_sender: &LoggerSender,
+        config: &Cfg,
+        utp: &Option<Arc<UtpSocket>>,
+    ) -> Option<Arc<DhtNode>> {
+        if !config.dht_enabled {
+            return None;
+        }
+        let dht = match utp {
+            Some(utp) if config.dht_port == config.tcp_port => DhtNode::start_on_utp_socket(
+                utp,
+                config.dht_bootstrap_nodes.clone(),
+                config.dht_routing_table_path.clone(),
+                log_sender.clone(),
+            ),
+            _ => DhtNode::start(
+                config.dht_port,
+                config.dht_bootstrap_nodes.clone(),
+                config.dht_routing_table_path.clone(),
+                log_sender.clone(),
+            ),
+        };
+        match dht {
+            Ok(dht) => {
+                log_sender.info("DHT node started.");
+                Some(dht)
+            }
+            Err(error) => {
+                log_sender.warn(&format!("Couldn't start the DHT node: {:?}", error));
+                None
+            }
+        }
+    }
+
     /// Generates a random peer ID.
     fn generate_peer_id() -> String {
         let mut peer_id = String::from("DTorrent:");
@@ -78,45 +437,363 @@ impl BtClient {
     }
 
     /// Method for starting the torrent downloading process.
-    pub fn run(&self, sender: glib::Sender<Vec<TorrentStats>>) {
+    ///
+    /// Once the torrents are started, it blocks handling the commands of the control channel, until the client is shut down.
+    pub fn run<S: StatsSink>(self: &Arc<Self>, sink: S) {
         let logger = self.logger.new_sender();
         logger.info("Starting client...");
 
-        let mut torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>> = HashMap::new();
-        let mut handler_status_list = Vec::new();
-        let mut torrent_handlers_joins = Vec::new();
+        let mut handler_threads: HashMap<String, JoinHandle<TorrentHandler>> = HashMap::new();
         self.torrents.iter().for_each(|torrent| {
-            let handler = TorrentHandler::new(torrent.clone(), self.config.clone(), logger.clone(), self.client_peer_id.clone());
-            handler_status_list.push(handler.status());
-            torrents_with_status.insert(torrent.clone(), handler.status());
-            let thread_handle = self.spawn_torrent_handler(torrent, handler);
-            match thread_handle {
-                Ok(handle) => {
-                    torrent_handlers_joins.push(handle);
+            if let Some(handle) = self.start_torrent(torrent) {
+                handler_threads.insert(torrent.info_hash(), handle);
+            }
+        });
+        self.magnets.iter().for_each(|magnet| {
+            if let Err(error) = self.fetch_magnet(magnet.clone()) {
+                logger.error(&format!("{:?}", error));
+            }
+        });
+
+        let scrape_updater = ScrapeUpdater::new(
+            self.torrent_list.clone(),
+            self.config().tcp_port.into(),
+            self.client_peer_id.clone(),
+            self.logger.new_sender(),
+        );
+        let _jh = self.spawn_scrape_updater(scrape_updater);
+
+        let runner = StatisticsUpdater::new(self.torrent_list.clone(), sink);
+        let _jh = self.spawn_statistics_runner(runner);
+
+        self.start_server();
+        self.start_directory_watcher();
+        self.start_rpc_server();
+
+        self.handle_commands(handler_threads);
+    }
+
+    /// Starts the handler and the choker of a torrent and adds it to the torrent list, returning the thread of the handler.
+    ///
+    /// A torrent the client already has is not started again.
+    fn start_torrent(&self, torrent: &Torrent) -> Option<JoinHandle<TorrentHandler>> {
+        let logger = self.logger.new_sender();
+        if self.torrent_list.find(&torrent.info_hash()).is_some() {
+            logger.warn(&format!("Torrent {} was already added.", torrent.name()));
+            return None;
+        }
+
+        let config = self.config();
+        let upload_slots = config.upload_slots as usize;
+        let handler = TorrentHandler::new(
+            torrent.clone(),
+            config,
+            logger.clone(),
+            self.client_peer_id.clone(),
+            self.dht.clone(),
+            self.utp.clone(),
+            self.reactor.clone(),
+        );
+        let torrent_status = handler.status();
+        let choker = Choker::new(handler.status(), upload_slots, logger.clone());
+        match self.spawn_torrent_handler(torrent, handler) {
+            Ok(handle) => {
+                self.torrent_list.add(torrent_status);
+                if let Err(error) = self.spawn_choker(torrent, choker) {
+                    logger.error(&format!(
+                        "An error occurred while trying to spawn a new thread for a choker: {:?}",
+                        error
+                    ));
                 }
-                Err(error) => {
-                    let error_message = format!("An error occurred while trying to spawn a new thread for a torrent_handler: {:?}", error);
-                    logger.error(&error_message);
-                    handler_status_list.pop();
-                    torrents_with_status.remove(torrent);
+                Some(handle)
+            }
+            Err(error) => {
+                let error_message = format!("An error occurred while trying to spawn a new thread for a torrent_handler: {:?}", error);
+                logger.error(&error_message);
+                None
+            }
+        }
+    }
+
+    /// Serves the RPC on a thread, if it is enabled in the configuration and it has a token.
+    fn start_rpc_server(self: &Arc<Self>) {
+        let config = self.config();
+        if !config.rpc_enabled {
+            return;
+        }
+        let logger = self.logger.new_sender();
+        if config.rpc_token.is_empty() {
+            logger.error("The RPC server was not started, RPC_TOKEN is not set.");
+            return;
+        }
+
+        let server = match RpcServer::new(
+            self.clone(),
+            &config.rpc_bind_address,
+            config.rpc_token,
+            logger.clone(),
+        ) {
+            Ok(server) => server,
+            Err(error) => {
+                logger.error(&format!(
+                    "Couldn't start the RPC server on {}: {:?}",
+                    config.rpc_bind_address, error
+                ));
+                return;
+            }
+        };
+        let builder = thread::Builder::new().name("RPC server".to_string());
+        if let Err(error) = builder.spawn(move || server.serve()) {
+            logger.error(&format!(
+                "An error occurred while trying to spawn a new thread for the RPC server: {:?}",
+                error
+            ));
+        }
+    }
+
+    /// Watches the torrents directory on a thread, unless it is disabled in the configuration.
+    fn start_directory_watcher(&self) {
+        let config = self.config();
+        if config.watch_interval_seconds == 0 {
+            return;
+        }
+        let mut watcher = DirectoryWatcher::new(
+            &self.torrents_directory,
+            config.watch_remove_deleted,
+            self.command_sender.clone(),
+            self.logger.new_sender(),
+        );
+        let interval = Duration::from_secs(config.watch_interval_seconds);
+
+        let builder = thread::Builder::new().name("Directory watcher".to_string());
+        if let Err(error) = builder.spawn(move || watcher.watch(interval)) {
+            self.logger.new_sender().error(&format!(
+                "An error occurred while trying to spawn a new thread for the directory watcher: {:?}",
+                error
+            ));
+        }
+    }
+
+    /// Handles the commands of the control channel, for as long as the client exists.
+    ///
+    /// The torrent handler threads return their `TorrentHandler` when they finish, so a paused torrent is resumed by running its handler again on a new thread.
+    /// If the handler of a paused torrent is still finishing, like while it announces the `stopped` event, the torrent is marked as resuming
+    /// and it is resumed when the handler sends `HandlerFinished`, so its thread is not joined while it runs.
+    fn handle_commands(&self, mut handler_threads: HashMap<String, JoinHandle<TorrentHandler>>) {
+        let command_receiver = self
+            .command_receiver
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner);
+        let mut resuming: HashSet<String> = HashSet::new();
+
+        for command in command_receiver.iter() {
+            match command {
+                ClientCommand::Add(torrent) => {
+                    if let Some(handle) = self.start_torrent(&torrent) {
+                        handler_threads.insert(torrent.info_hash(), handle);
+                    }
+                }
+                ClientCommand::Pause(info_hash) => {
+                    resuming.remove(&info_hash);
+                    self.pause(&info_hash);
+                }
+                ClientCommand::Resume(info_hash) => {
+                    let stopping = handler_threads
+                        .get(&info_hash)
+                        .is_some_and(|handle| !handle.is_finished());
+                    if !stopping {
+                        self.resume(&info_hash, &mut handler_threads);
+                    } else if self.is_stopped(&info_hash) {
+                        resuming.insert(info_hash);
+                    }
                 }
+                ClientCommand::HandlerFinished(info_hash) => {
+                    if resuming.remove(&info_hash) {
+                        self.resume(&info_hash, &mut handler_threads);
+                    }
+                }
+                ClientCommand::Recheck(info_hash) => self.recheck(&info_hash),
+                ClientCommand::Remove {
+                    info_hash,
+                    delete_data,
+                } => {
+                    resuming.remove(&info_hash);
+                    if let Some(handle) = handler_threads.remove(&info_hash) {
+                        self.remove(&info_hash, handle, delete_data);
+                    }
+                }
+                ClientCommand::Shutdown => return self.stop_all(handler_threads),
             }
+        }
+    }
+
+    /// Stops the torrent, its handler announces the `stopped` event and its peer sessions are closed by the reactor.
+    fn pause(&self, info_hash: &str) {
+        if let Some(torrent_status) = self.torrent_list.find(info_hash) {
+            torrent_status.stop();
+            self.logger.new_sender().info(&format!(
+                "Torrent {} paused.",
+                torrent_status.torrent.name()
+            ));
+        }
+    }
+
+    /// Hash-checks the data of the torrent on another thread, so the commands are not blocked while its pieces are read.
+    ///
+    /// The pieces that are being downloaded are not checked.
+    fn recheck(&self, info_hash: &str) {
+        let logger = self.logger.new_sender();
+        let torrent_status = match self.torrent_list.find(info_hash) {
+            Some(torrent_status) => torrent_status,
+            None => return,
+        };
+
+        let builder =
+            thread::Builder::new().name(format!("Recheck: {}", torrent_status.torrent.name()));
+        let recheck_logger = logger.clone();
+        let spawned = builder.spawn(move || match torrent_status.force_recheck() {
+            Ok(()) => recheck_logger.info(&format!(
+                "Torrent {} rechecked, {} of {} pieces are valid.",
+                torrent_status.torrent.name(),
+                torrent_status.downloaded_pieces(),
+                torrent_status.torrent.total_pieces()
+            )),
+            Err(error) => recheck_logger.error(&format!(
+                "Couldn't recheck torrent {}: {:?}",
+                torrent_status.torrent.name(),
+                error
+            )),
         });
+        if let Err(error) = spawned {
+            logger.error(&format!(
+                "An error occurred while trying to spawn a new thread to recheck a torrent: {:?}",
+                error
+            ));
+        }
+    }
 
-        let runner = StatisticsUpdater::new(handler_status_list, sender);
-        let _jh = self.spawn_statistics_runner(runner);
+    /// Stops every torrent and waits for their handlers to finish, so the `stopped` event is announced to their trackers.
+    fn stop_all(&self, handler_threads: HashMap<String, JoinHandle<TorrentHandler>>) {
+        let logger = self.logger.new_sender();
+        logger.info("Stopping client...");
+        self.torrent_list
+            .statuses()
+            .iter()
+            .for_each(|torrent_status| torrent_status.stop());
+
+        for handle in handler_threads.into_values() {
+            if handle.join().is_err() {
+                logger
+                    .error("An error occurred while attempting to join a torrent_handler thread.");
+            }
+        }
+        logger.info("Client stopped.");
+    }
+
+    /// Returns true if the client has the torrent and it is stopped.
+    fn is_stopped(&self, info_hash: &str) -> bool {
+        self.torrent_list
+            .find(info_hash)
+            .is_some_and(|torrent_status| torrent_status.is_stopped())
+    }
+
+    /// Runs the handler of a torrent again once its thread finished, replacing its thread in `handler_threads`.
+    ///
+    /// That is the case of a paused torrent, and of one whose handler stopped because of an error.
+    /// The thread must have finished or be about to, after sending `HandlerFinished`, as it is joined.
+    fn resume(
+        &self,
+        info_hash: &str,
+        handler_threads: &mut HashMap<String, JoinHandle<TorrentHandler>>,
+    ) {
+        let logger = self.logger.new_sender();
+        let (torrent_status, handle) = match (
+            self.torrent_list.find(info_hash),
+            handler_threads.remove(info_hash),
+        ) {
+            (Some(torrent_status), Some(handle)) => (torrent_status, handle),
+            _ => return,
+        };
+
+        let handler = match handle.join() {
+            Ok(handler) => handler,
+            Err(_) => {
+                logger
+                    .error("An error occurred while attempting to join a torrent_handler thread.");
+                return;
+            }
+        };
+        torrent_status.resume();
+        match self.spawn_torrent_handler(&torrent_status.torrent, handler) {
+            Ok(handle) => {
+                logger.info(&format!(
+                    "Torrent {} resumed.",
+                    torrent_status.torrent.name()
+                ));
+                handler_threads.insert(info_hash.to_string(), handle);
+            }
+            Err(error) => {
+                torrent_status.stop();
+                logger.error(&format!("An error occurred while trying to spawn a new thread for a torrent_handler: {:?}", error));
+            }
+        }
+    }
 
-        self.start_server(torrents_with_status);
+    /// Removes the torrent from the client, and finishes its removal on another thread so the commands are not blocked meanwhile.
+    ///
+    /// That thread waits for its handler to announce the `stopped` event and, if `delete_data` is true, for its peer sessions to close before deleting its data.
+    fn remove(&self, info_hash: &str, handle: JoinHandle<TorrentHandler>, delete_data: bool) {
+        let logger = self.logger.new_sender();
+        let torrent_status = match self.torrent_list.remove(info_hash) {
+            Some(torrent_status) => torrent_status,
+            None => return,
+        };
+        torrent_status.remove();
 
-        self.join_handles(torrent_handlers_joins);
+        let builder =
+            thread::Builder::new().name(format!("Remove: {}", torrent_status.torrent.name()));
+        let remove_logger = logger.clone();
+        let spawned = builder.spawn(move || {
+            if handle.join().is_err() {
+                remove_logger
+                    .error("An error occurred while attempting to join a torrent_handler thread.");
+            }
+            if delete_data {
+                let start = Instant::now();
+                while torrent_status.all_current_peers() > 0
+                    && start.elapsed() < SESSIONS_CLOSE_TIMEOUT
+                {
+                    thread::sleep(SESSIONS_CLOSE_POLL_INTERVAL);
+                }
+                if let Err(error) = torrent_status.delete_data() {
+                    remove_logger.error(&format!(
+                        "Couldn't delete the data of torrent {}: {:?}",
+                        torrent_status.torrent.name(),
+                        error
+                    ));
+                }
+            }
+            remove_logger.info(&format!(
+                "Torrent {} removed.",
+                torrent_status.torrent.name()
+            ));
+        });
+        if let Err(error) = spawned {
+            logger.error(&format!(
+                "An error occurred while trying to spawn a new thread to remove a torrent: {:?}",
+                error
+            ));
+        }
     }
 
-    fn start_server(&self, torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>) {
+    fn start_server(&self) {
         let mut server = BtServer::new(
-            torrents_with_status,
-            self.config.clone(),
+            self.torrent_list.clone(),
+            self.config(),
             self.logger.new_sender(),
             self.client_peer_id.clone(),
+            self.utp.clone(),
+            self.reactor.clone(),
         );
 
         let builder = thread::Builder::new().name("Server".to_string());
@@ -134,24 +811,51 @@ impl BtClient {
         }
     }
 
+    /// Runs the torrent handler on a new thread, which returns it when it finishes.
+    ///
+    /// If the torrent reached a seeding limit whose action is to remove it, the thread asks the client to remove it, keeping its data.
+    /// Otherwise it sends `HandlerFinished`, so the torrent is run again if it was resumed while the handler was stopping.
     fn spawn_torrent_handler(
         &self,
         torrent: &Torrent,
         mut torrent_handler: TorrentHandler,
-    ) -> Result<JoinHandle<()>, io::Error> {
+    ) -> Result<JoinHandle<TorrentHandler>, io::Error> {
         let logger = self.logger.new_sender();
+        let command_sender = self.command_sender.clone();
 
         let builder = thread::Builder::new().name(format!("Torrent handler: {}", torrent.name()));
         builder.spawn(move || {
+            let torrent_status = torrent_handler.status();
             if let Err(torrent_error) = torrent_handler.handle() {
                 logger.error(&format!("{:?}", torrent_error));
+                // The torrent is shown as paused, so it can be resumed.
+                torrent_status.stop();
+            }
+            if torrent_status.is_seed_limit_reached() && torrent_status.seed_limits().action == SeedLimitAction::Remove {
+                let command = ClientCommand::Remove { info_hash: torrent_status.torrent.info_hash(), delete_data: false };
+                if command_sender.send(command).is_err() {
+                    logger.error("Couldn't send the command to remove a torrent that reached its seeding limit.");
+                }
+            } else {
+                // The client may have stopped handling commands already, when it is shut down.
+                let _ = command_sender.send(ClientCommand::HandlerFinished(torrent_status.torrent.info_hash()));
             }
+            torrent_handler
         })
     }
 
-    fn spawn_statistics_runner(
+    fn spawn_choker(
+        &self,
+        torrent: &Torrent,
+        mut choker: Choker,
+    ) -> Result<JoinHandle<()>, io::Error> {
+        let builder = thread::Builder::new().name(format!("Choker: {}", torrent.name()));
+        builder.spawn(move || choker.run())
+    }
+
+    fn spawn_statistics_runner<S: StatsSink>(
         &self,
-        runner: StatisticsUpdater,
+        runner: StatisticsUpdater<S>,
     ) -> Result<JoinHandle<()>, io::Error> {
         let logger = self.logger.new_sender();
 
@@ -163,14 +867,12 @@ impl BtClient {
         })
     }
 
-    fn join_handles(&self, torrent_handlers: Vec<JoinHandle<()>>) {
-        torrent_handlers.into_iter().for_each(|torrent_handler| {
-            if torrent_handler.join().is_err() {
-                self.logger
-                    .new_sender()
-                    .error("An error occurred while attempting to join a torrent_handler thread.");
-            };
-        });
+    fn spawn_scrape_updater(
+        &self,
+        scrape_updater: ScrapeUpdater,
+    ) -> Result<JoinHandle<()>, io::Error> {
+        let builder = thread::Builder::new().name("Torrent scrape".to_string());
+        builder.spawn(move || scrape_updater.run())
     }
 
     fn read_configuration_file(filename: &str) -> Result<Cfg, BtClientError> {
@@ -190,7 +892,7 @@ impl BtClient {
         torrents_directory: String,
     ) -> Result<Vec<Torrent>, BtClientError> {
         let torrents: Vec<Torrent> =
-            Self::list_torrent_filenames_in_directory(&log_sender, torrents_directory.clone())?
+            Self::list_filenames_in_directory(&log_sender, torrents_directory.clone(), ".torrent")?
                 .iter()
                 .filter_map(|filename| {
                     Self::parse_torrent(
@@ -219,14 +921,53 @@ impl BtClient {
         }
     }
 
-    fn list_torrent_filenames_in_directory(
+    /// Reads the magnet links of the .magnet files in the directory, their metadata is fetched once the client runs.
+    ///
+    /// The magnet links that could not be read are logged and skipped.
+    fn parse_magnets_in_directory(
+        log_sender: &LoggerSender,
+        torrents_directory: String,
+    ) -> Result<Vec<Magnet>, BtClientError> {
+        let magnets =
+            Self::list_filenames_in_directory(log_sender, torrents_directory.clone(), ".magnet")?
+                .iter()
+                .filter_map(|filename| {
+                    Self::parse_magnet(log_sender, &format!("{}/{}", torrents_directory, filename))
+                })
+                .collect();
+
+        Ok(magnets)
+    }
+
+    fn parse_magnet(log_sender: &LoggerSender, magnet_filename: &str) -> Option<Magnet> {
+        let parsed_magnet = fs::read_to_string(magnet_filename)
+            .map_err(|error| format!("{}", error))
+            .and_then(|link| Magnet::parse(&link).map_err(|error| format!("{:?}", error)));
+
+        match parsed_magnet {
+            Ok(magnet) => {
+                log_sender.info(&format!("Magnet {} parsed correctly.", magnet_filename));
+                Some(magnet)
+            }
+            Err(error) => {
+                log_sender.warn(&format!(
+                    "Couldn't parse magnet file {}: {}",
+                    magnet_filename, error
+                ));
+                None
+            }
+        }
+    }
+
+    fn list_filenames_in_directory(
         log_sender: &LoggerSender,
         directory: String,
+        extension: &str,
     ) -> Result<Vec<String>, BtClientError> {
         let filenames = Self::open_directory(log_sender, directory)?
             .flatten()
             .flat_map(|dir_entry| dir_entry.file_name().into_string())
-            .filter(|filename| filename.ends_with(".torrent"))
+            .filter(|filename| filename.ends_with(extension))
             .collect();
 
         Ok(filenames)
@@ -248,3 +989,204 @@ impl BtClient {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{storage_manager::resume_data::ResumeData, torrent_parser::info::Info};
+    use sha1::{Digest, Sha1};
+    use std::path::Path;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_recheck_torrent() {
+        let torrents_directory = "./test_recheck_torrent";
+        fs::create_dir_all(torrents_directory).unwrap();
+        let client =
+            BtClient::init_with_config(CONFIG_PATH, torrents_directory.to_string()).unwrap();
+        let config = client.config();
+        let torrent = create_test_torrent("test_recheck_torrent", b"abcd");
+        fs::write(
+            format!("{}/{}", config.download_directory, torrent.info.name),
+            b"abcx",
+        )
+        .unwrap();
+        let torrent_status = add_test_status(&client, &torrent);
+
+        client.recheck_torrent(&torrent.info_hash()).unwrap();
+        client.shutdown().unwrap();
+        client.handle_commands(HashMap::new());
+
+        let start = Instant::now();
+        while torrent_status.downloaded_pieces() == 0 && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        assert_eq!(torrent_status.downloaded_pieces(), 1);
+        assert!(client.recheck_torrent("unknown").is_err());
+
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+        ResumeData::delete(&torrent.info_hash, &config).unwrap();
+        fs::remove_dir_all(torrents_directory).unwrap();
+    }
+
+    #[test]
+    fn test_resume_does_not_block_the_commands_while_the_handler_stops() {
+        let name = "test_resume_while_the_handler_stops";
+        let torrents_directory = format!("./{}", name);
+        fs::create_dir_all(&torrents_directory).unwrap();
+        let client =
+            Arc::new(BtClient::init_with_config(CONFIG_PATH, torrents_directory.clone()).unwrap());
+        let config = client.config();
+
+        let mut torrent = create_test_torrent(name, b"abcd");
+        torrent.announce_url = "http://127.0.0.1:1/announce".to_string();
+        let (torrent_status, stopping_handle) = start_stopping_handler(&client, &torrent);
+        let other_torrent = create_test_torrent("test_resume_while_the_handler_stops_other", b"ab");
+        let other_status = add_test_status(&client, &other_torrent);
+
+        let command_client = client.clone();
+        let handler_threads = HashMap::from([(torrent.info_hash(), stopping_handle)]);
+        let commands = thread::spawn(move || command_client.handle_commands(handler_threads));
+
+        client.resume_torrent(&torrent.info_hash()).unwrap();
+        client.pause_torrent(&other_torrent.info_hash()).unwrap();
+        let start = Instant::now();
+        while !other_status.is_stopped() && start.elapsed() < Duration::from_millis(500) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let paused_while_stopping = other_status.is_stopped();
+        let resumed_while_stopping = ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .is_some();
+
+        // The resumed handler saves the resume data after checking the existing data.
+        let start = Instant::now();
+        while ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .is_none()
+            && start.elapsed() < Duration::from_secs(5)
+        {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let resumed = ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .is_some();
+        client.shutdown().unwrap();
+        commands.join().unwrap();
+
+        torrent_status.delete_data().unwrap();
+        fs::remove_dir_all(torrents_directory).unwrap();
+        assert!(paused_while_stopping);
+        assert!(!resumed_while_stopping);
+        assert!(resumed);
+    }
+
+    #[test]
+    fn test_remove_does_not_block_the_commands_while_the_handler_stops() {
+        let name = "test_remove_while_the_handler_stops";
+        let torrents_directory = format!("./{}", name);
+        fs::create_dir_all(&torrents_directory).unwrap();
+        let client =
+            Arc::new(BtClient::init_with_config(CONFIG_PATH, torrents_directory.clone()).unwrap());
+        let config = client.config();
+
+        let torrent = create_test_torrent(name, b"abcd");
+        let data_path = format!("{}/{}", config.download_directory, torrent.info.name);
+        fs::write(&data_path, b"abcd").unwrap();
+        let (_, stopping_handle) = start_stopping_handler(&client, &torrent);
+        let other_torrent = create_test_torrent("test_remove_while_the_handler_stops_other", b"ab");
+        let other_status = add_test_status(&client, &other_torrent);
+
+        let command_client = client.clone();
+        let handler_threads = HashMap::from([(torrent.info_hash(), stopping_handle)]);
+        let commands = thread::spawn(move || command_client.handle_commands(handler_threads));
+
+        client.remove_torrent(&torrent.info_hash(), true).unwrap();
+        client.pause_torrent(&other_torrent.info_hash()).unwrap();
+        let start = Instant::now();
+        while !other_status.is_stopped() && start.elapsed() < Duration::from_millis(500) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let paused_while_stopping = other_status.is_stopped();
+        let deleted_while_stopping = !Path::new(&data_path).exists();
+
+        let start = Instant::now();
+        while Path::new(&data_path).exists() && start.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let deleted = !Path::new(&data_path).exists();
+        client.shutdown().unwrap();
+        commands.join().unwrap();
+
+        fs::remove_dir_all(torrents_directory).unwrap();
+        assert!(paused_while_stopping);
+        assert!(!deleted_while_stopping);
+        assert!(deleted);
+        assert!(client.find_torrent(&torrent.info_hash()).is_err());
+    }
+
+    // Auxiliary functions
+
+    /// Adds a paused torrent to the client whose handler thread takes 2 seconds to finish,
+    /// like when the tracker is slow to answer the `stopped` event.
+    fn start_stopping_handler(
+        client: &BtClient,
+        torrent: &Torrent,
+    ) -> (Arc<AtomicTorrentStatus>, JoinHandle<TorrentHandler>) {
+        let handler = TorrentHandler::new(
+            torrent.clone(),
+            client.config(),
+            client.logger_sender(),
+            client.client_peer_id.clone(),
+            None,
+            None,
+            client.reactor.clone(),
+        );
+        let torrent_status = handler.status();
+        torrent_status.stop();
+        client.torrent_list.add(torrent_status.clone());
+
+        let command_sender = client.command_sender.clone();
+        let info_hash = torrent.info_hash();
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_secs(2));
+            let _ = command_sender.send(ClientCommand::HandlerFinished(info_hash));
+            handler
+        });
+        (torrent_status, handle)
+    }
+
+    /// Adds a torrent to the client without running its handler.
+    fn add_test_status(client: &BtClient, torrent: &Torrent) -> Arc<AtomicTorrentStatus> {
+        let (torrent_status, _) = AtomicTorrentStatus::new(torrent, client.config());
+        let torrent_status = Arc::new(torrent_status);
+        client.torrent_list.add(torrent_status.clone());
+        torrent_status
+    }
+
+    /// Creates a torrent with pieces of 2 bytes whose hashes match the given data.
+    fn create_test_torrent(name: &str, data: &[u8]) -> Torrent {
+        let info = Info {
+            length: data.len() as i64,
+            name: name.to_string(),
+            piece_length: 2,
+            pieces: data
+                .chunks(2)
+                .flat_map(|piece| Sha1::digest(piece).to_vec())
+                .collect(),
+            files: None,
+        };
+
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info,
+            info_hash: name.to_string(),
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use crate::bt_client::error_message::ErrorMessage;
+use crate::reactor::peer_reactor::PeerReactorError;
 use logger::logger_error::LoggerError;
 
-/// Represents an error that happened while initializing a BtClient struct
+/// Represents an error that happened while initializing a BtClient struct, or while controlling its torrents.
 #[derive(Debug)]
 pub enum BtClientError {
     ConfigurationFileError(ErrorMessage),
@@ -9,6 +10,12 @@ pub enum BtClientError {
     LogError(LoggerError),
     ArgumentError(ErrorMessage),
     UIBuildingError(ErrorMessage),
+    ReactorError(PeerReactorError),
+    TorrentNotFound(String),
+    TorrentAlreadyAdded(String),
+    CommandError(ErrorMessage),
+    InvalidTorrent(ErrorMessage),
+    SettingError(ErrorMessage),
 }
 
 impl From<LoggerError> for BtClientError {
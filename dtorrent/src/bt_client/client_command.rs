@@ -0,0 +1,25 @@
+use crate::torrent_parser::torrent::Torrent;
+
+/// Commands that control the torrents while the `BtClient` is running, sent through its control channel.
+///
+/// The torrents are identified by their info hash, as an hex string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientCommand {
+    /// Starts downloading a new torrent, unless the client already has it.
+    Add(Torrent),
+    /// Closes the peer sessions of the torrent and announces the `stopped` event to its tracker.
+    Pause(String),
+    /// Runs a paused torrent again.
+    Resume(String),
+    /// Hash-checks the downloaded data of the torrent against its pieces.
+    Recheck(String),
+    /// Stops the torrent for good and drops it from the client, deleting its downloaded data if asked to.
+    Remove {
+        info_hash: String,
+        delete_data: bool,
+    },
+    /// Sent by the thread of a torrent handler when it finishes, to run it again if the torrent was resumed while it was stopping.
+    HandlerFinished(String),
+    /// Stops every torrent, waits for their handlers to announce the `stopped` event and makes the client stop running.
+    Shutdown,
+}
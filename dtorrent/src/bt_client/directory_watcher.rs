@@ -0,0 +1,294 @@
+use super::client_command::ClientCommand;
+use crate::torrent_parser::parser::TorrentParser;
+use logger::logger_sender::LoggerSender;
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+/// Time a new file must be left unmodified before it is parsed, so files that are still being written are not taken as invalid.
+const FILE_SETTLE_TIME: Duration = Duration::from_secs(2);
+
+/// Extension added to the invalid .torrent files when they are moved aside.
+const INVALID_EXTENSION: &str = "invalid";
+
+/// Struct for watching the torrents directory while the client runs, by polling it.
+///
+/// The new .torrent files are parsed and their torrents are added to the client.
+/// The invalid ones are moved aside, adding the `.invalid` extension to their name.
+/// If `remove_deleted` is true, the torrents whose file is deleted are removed from the client, keeping their data.
+///
+/// The commands are sent through the control channel of the `BtClient`.
+///
+/// To create a new `DirectoryWatcher`, use DirectoryWatcher::new(directory, remove_deleted, command_sender, logger_sender).
+#[derive(Debug)]
+pub struct DirectoryWatcher {
+    directory: PathBuf,
+    remove_deleted: bool,
+    /// The .torrent files seen in the directory, with the info hash of their torrent if they were valid.
+    known_files: HashMap<PathBuf, Option<String>>,
+    command_sender: Sender<ClientCommand>,
+    logger_sender: LoggerSender,
+}
+
+impl DirectoryWatcher {
+    /// Creates a new `DirectoryWatcher` of a directory, taking the .torrent files already in it as known.
+    ///
+    /// Those files are expected to be added by the client when it starts.
+    pub fn new(
+        directory: &str,
+        remove_deleted: bool,
+        command_sender: Sender<ClientCommand>,
+        logger_sender: LoggerSender,
+    ) -> Self {
+        let directory = PathBuf::from(directory);
+        let known_files = Self::torrent_files(&directory)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|path| {
+                let info_hash = TorrentParser::parse(path.to_string_lossy().to_string())
+                    .ok()
+                    .map(|torrent| torrent.info_hash());
+                (path, info_hash)
+            })
+            .collect();
+
+        Self {
+            directory,
+            remove_deleted,
+            known_files,
+            command_sender,
+            logger_sender,
+        }
+    }
+
+    /// Scans the directory every `interval`, until the client is dropped.
+    pub fn watch(&mut self, interval: Duration) {
+        loop {
+            if let Err(err) = self.scan() {
+                self.logger_sender
+                    .warn(&format!("Couldn't scan the torrents directory: {:?}", err));
+            }
+            thread::sleep(interval);
+        }
+    }
+
+    /// Adds the torrents of the new .torrent files, and removes the ones whose file was deleted if `remove_deleted` is true.
+    ///
+    /// # Errors
+    /// - `io::Error` if the directory could not be read.
+    /// - `io::ErrorKind::BrokenPipe` if the client is no longer receiving commands.
+    pub fn scan(&mut self) -> io::Result<()> {
+        let files = Self::torrent_files(&self.directory)?;
+
+        for path in &files {
+            if !self.known_files.contains_key(path) && Self::is_settled(path) {
+                let info_hash = self.add_torrent_file(path)?;
+                self.known_files.insert(path.clone(), info_hash);
+            }
+        }
+
+        let deleted_files: Vec<PathBuf> = self
+            .known_files
+            .keys()
+            .filter(|path| !files.contains(path))
+            .cloned()
+            .collect();
+        for path in deleted_files {
+            if let Some(Some(info_hash)) = self.known_files.remove(&path) {
+                if self.remove_deleted {
+                    self.logger_sender.info(&format!(
+                        "Torrent file {} deleted, removing its torrent.",
+                        path.display()
+                    ));
+                    self.send(ClientCommand::Remove {
+                        info_hash,
+                        delete_data: false,
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Parses a new .torrent file and asks the client to add its torrent, returning its info hash.
+    ///
+    /// An invalid file is moved aside and the error is logged.
+    fn add_torrent_file(&self, path: &Path) -> io::Result<Option<String>> {
+        match TorrentParser::parse(path.to_string_lossy().to_string()) {
+            Ok(torrent) => {
+                self.logger_sender
+                    .info(&format!("Torrent {} parsed correctly.", path.display()));
+                let info_hash = torrent.info_hash();
+                self.send(ClientCommand::Add(torrent))?;
+                Ok(Some(info_hash))
+            }
+            Err(error) => {
+                self.logger_sender.error(&format!(
+                    "Couldn't parse torrent file {}, moving it aside: {:?}",
+                    path.display(),
+                    error
+                ));
+                if let Err(err) = fs::rename(path, Self::invalid_path(path)) {
+                    self.logger_sender.error(&format!(
+                        "Couldn't move invalid torrent file {}: {:?}",
+                        path.display(),
+                        err
+                    ));
+                }
+                Ok(None)
+            }
+        }
+    }
+
+    fn send(&self, command: ClientCommand) -> io::Result<()> {
+        self.command_sender
+            .send(command)
+            .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+    }
+
+    /// Returns the paths of the .torrent files in the directory.
+    fn torrent_files(directory: &Path) -> io::Result<Vec<PathBuf>> {
+        Ok(fs::read_dir(directory)?
+            .flatten()
+            .map(|dir_entry| dir_entry.path())
+            .filter(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "torrent"))
+            .collect())
+    }
+
+    /// Returns true if the file was not modified during the last `FILE_SETTLE_TIME`.
+    fn is_settled(path: &Path) -> bool {
+        fs::metadata(path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+            .is_some_and(|elapsed| elapsed >= FILE_SETTLE_TIME)
+    }
+
+    fn invalid_path(path: &Path) -> PathBuf {
+        let mut invalid_path = path.as_os_str().to_owned();
+        invalid_path.push(".");
+        invalid_path.push(INVALID_EXTENSION);
+        PathBuf::from(invalid_path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_parser::{info::Info, torrent::Torrent};
+    use bencoder::bencode::Bencode;
+    use logger::logger_receiver::Logger;
+    use std::{fs::File, sync::mpsc};
+
+    #[test]
+    fn test_new_torrent_files_are_added() {
+        let directory = "./test_watcher_new_files";
+        let (mut watcher, receiver) = create_test_watcher(directory, false);
+        let torrent = create_test_torrent("test_watcher_new_files");
+        write_settled_file(
+            &format!("{}/new.torrent", directory),
+            &Bencode::encode(&torrent),
+        );
+
+        watcher.scan().unwrap();
+        watcher.scan().unwrap();
+
+        assert_eq!(receiver.try_recv().unwrap(), ClientCommand::Add(torrent));
+        assert!(receiver.try_recv().is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_files_being_written_are_not_parsed_yet() {
+        let directory = "./test_watcher_unsettled_files";
+        let (mut watcher, receiver) = create_test_watcher(directory, false);
+        fs::write(format!("{}/new.torrent", directory), b"d8:announce").unwrap();
+
+        watcher.scan().unwrap();
+
+        assert!(receiver.try_recv().is_err());
+        assert!(Path::new(&format!("{}/new.torrent", directory)).exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_torrent_files_are_moved_aside() {
+        let directory = "./test_watcher_invalid_files";
+        let (mut watcher, receiver) = create_test_watcher(directory, false);
+        write_settled_file(&format!("{}/invalid.torrent", directory), b"not a torrent");
+
+        watcher.scan().unwrap();
+
+        assert!(receiver.try_recv().is_err());
+        assert!(!Path::new(&format!("{}/invalid.torrent", directory)).exists());
+        assert!(Path::new(&format!("{}/invalid.torrent.invalid", directory)).exists());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn test_deleted_torrent_files_remove_their_torrent() {
+        let directory = "./test_watcher_deleted_files";
+        let torrent = create_test_torrent("test_watcher_deleted_files");
+        let path = format!("{}/deleted.torrent", directory);
+        fs::create_dir_all(directory).unwrap();
+        write_settled_file(&path, &Bencode::encode(&torrent));
+        let (mut watcher, receiver) = create_test_watcher(directory, true);
+
+        fs::remove_file(&path).unwrap();
+        watcher.scan().unwrap();
+
+        assert_eq!(
+            receiver.try_recv().unwrap(),
+            ClientCommand::Remove {
+                info_hash: torrent.info_hash(),
+                delete_data: false
+            }
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    // Auxiliary functions
+
+    fn create_test_watcher(
+        directory: &str,
+        remove_deleted: bool,
+    ) -> (DirectoryWatcher, mpsc::Receiver<ClientCommand>) {
+        fs::create_dir_all(directory).unwrap();
+        let logger = Logger::new(&format!("{}/logs", directory), 10000).unwrap();
+        let (sender, receiver) = mpsc::channel();
+        let watcher = DirectoryWatcher::new(directory, remove_deleted, sender, logger.new_sender());
+        (watcher, receiver)
+    }
+
+    fn create_test_torrent(name: &str) -> Torrent {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 10,
+            pieces: vec![0; 20],
+            files: None,
+        };
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![vec!["announce".to_string()]],
+            info_hash: Torrent::create_info_hash(&info).unwrap(),
+            info,
+        }
+    }
+
+    /// Writes a file that was modified long enough ago to be parsed.
+    fn write_settled_file(path: &str, contents: &[u8]) {
+        fs::write(path, contents).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - FILE_SETTLE_TIME)
+            .unwrap();
+    }
+}
@@ -1,3 +1,5 @@
 pub mod btclient;
 pub mod btclient_error;
+pub mod client_command;
+pub mod directory_watcher;
 pub mod error_message;
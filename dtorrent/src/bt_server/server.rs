@@ -1,24 +1,33 @@
 use crate::config::cfg::Cfg;
 use crate::peer::bt_peer::{BtPeer, BtPeerError};
+use crate::peer::encryption::mse::{self, MseError};
 use crate::peer::peer_session::{PeerSession, PeerSessionError};
+use crate::peer::peer_stream::{PeerStream, Transport};
+use crate::reactor::peer_reactor::{PeerReactor, PeerReactorError};
 use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
-use crate::torrent_parser::torrent::Torrent;
+use crate::torrent_handler::torrent_list::TorrentList;
+use crate::utp::socket::UtpSocket;
 use logger::logger_sender::LoggerSender;
-use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream};
+use std::net::TcpListener;
 use std::sync::Arc;
 use std::thread;
 use std::time::Duration;
 
 /// Struct for handling the server side.
 ///
-/// To create a new `BtServer`, use BtServer::new(torrent, config, logger_sender).
-#[derive(Debug)]
+/// The peers connect over TCP, or over uTP if a `UtpSocket` is given.
+/// Once the handshakes are done, their sessions are driven by the `PeerReactor`.
+/// Only the torrents in the `TorrentList` that are not stopped accept connections.
+///
+/// To create a new `BtServer`, use BtServer::new(torrent_list, config, logger_sender, client_peer_id, utp, reactor).
+#[derive(Debug, Clone)]
 pub struct BtServer {
     config: Cfg,
-    torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+    torrent_list: TorrentList,
     logger_sender: LoggerSender,
     client_peer_id: String,
+    utp: Option<Arc<UtpSocket>>,
+    reactor: Arc<PeerReactor>,
 }
 
 /// Posible BtServer errors.
@@ -29,41 +38,58 @@ pub enum BtServerError {
     HandleConnectionError(std::io::Error),
     PeerSessionError(PeerSessionError),
     BtPeerError(BtPeerError),
+    EncryptionError(MseError),
+    ReactorError(PeerReactorError),
     TorrentNotFound(String),
+    TorrentStopped(String),
     ErrorSettingStreamTimeout,
     MaxPeersConnectedReached(String),
 }
 
 impl BtServer {
-    /// Creates a new `BtServer` from a `HashMap` containing a torrent with its `AtomicTorrentStatus`, a `Config` and a `Logger Sender`.
+    /// Creates a new `BtServer` from the `TorrentList` of the client, a `Config`, a `Logger Sender`, the uTP socket, if any, and the reactor driving the sessions.
     pub fn new(
-        torrents_with_status: HashMap<Torrent, Arc<AtomicTorrentStatus>>,
+        torrent_list: TorrentList,
         config: Cfg,
         logger_sender: LoggerSender,
         client_peer_id: String,
+        utp: Option<Arc<UtpSocket>>,
+        reactor: Arc<PeerReactor>,
     ) -> Self {
         Self {
             config,
-            torrents_with_status,
+            torrent_list,
             logger_sender,
             client_peer_id,
+            utp,
+            reactor,
         }
     }
 
     /// Starts the server and starts listening for connections.
     ///
+    /// The uTP connections are accepted in another thread.
+    ///
     /// # Errors
     /// - `OpeningListenerError` if the TcpLister couldn't be opened.
     pub fn init(&mut self) -> Result<(), BtServerError> {
         let listener = TcpListener::bind(format!("0.0.0.0:{}", self.config.tcp_port))
             .map_err(BtServerError::OpeningListenerError)?;
 
+        if let Some(utp) = self.utp.clone() {
+            let server = self.clone();
+            thread::Builder::new()
+                .name("uTP server".to_string())
+                .spawn(move || server.accept_utp_connections(&utp))
+                .map_err(BtServerError::OpeningListenerError)?;
+        }
+
         self.logger_sender
             .info("Server started, listening for connections.");
 
         for stream in listener.incoming() {
             match stream {
-                Ok(stream) => match self.handle_connection(stream) {
+                Ok(stream) => match self.handle_connection(Transport::Tcp(stream)) {
                     Ok(_) => (),
                     Err(e) => self
                         .logger_sender
@@ -78,7 +104,26 @@ impl BtServer {
         Ok(())
     }
 
-    fn handle_connection(&self, mut stream: TcpStream) -> Result<(), BtServerError> {
+    fn accept_utp_connections(&self, utp: &UtpSocket) {
+        loop {
+            match utp.accept() {
+                Ok(stream) => {
+                    if let Err(e) = self.handle_connection(Transport::Utp(stream)) {
+                        self.logger_sender
+                            .warn(&format!("Could't handle incoming uTP connection: {:?}", e));
+                    }
+                }
+                Err(e) => {
+                    self.logger_sender
+                        .error(&format!("Stopped accepting uTP connections: {:?}", e));
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Handles an incoming connection, plaintext or encrypted according to the encryption mode of the config.
+    fn handle_connection(&self, mut stream: Transport) -> Result<(), BtServerError> {
         let addr = stream
             .peer_addr()
             .map_err(BtServerError::HandleConnectionError)?;
@@ -86,7 +131,16 @@ impl BtServer {
         // set timeouts
         self.set_stream_timeouts(&mut stream)?;
 
-        let mut peer = BtPeer::new(addr.ip().to_string(), addr.port() as i64);
+        let info_hashes: Vec<Vec<u8>> = self
+            .torrent_list
+            .statuses()
+            .iter()
+            .filter_map(|torrent_status| torrent_status.torrent.get_info_hash_as_bytes().ok())
+            .collect();
+        let mut stream = mse::accept(stream, &info_hashes, self.config.encryption_mode)
+            .map_err(BtServerError::EncryptionError)?;
+
+        let mut peer = BtPeer::new(addr.ip(), addr.port() as i64);
 
         let info_hash = peer.receive_handshake(&mut stream).map_err(|err| {
             self.logger_sender.warn(&format!(
@@ -99,22 +153,24 @@ impl BtServer {
         })?;
 
         // See if the torrent is in the list of torrents.
-        let (torrent, torrent_status) = match self.find_torrent_and_status(info_hash) {
-            Ok(value) => value,
-            Err(value) => return value,
-        };
+        let torrent_status = self.find_torrent_status(info_hash)?;
+        if torrent_status.is_stopped() {
+            return Err(BtServerError::TorrentStopped(torrent_status.torrent.name()));
+        }
 
         let current_peers = torrent_status.all_current_peers();
         // if we reached the max number of peers, we can't accept any more connections.
         if current_peers >= self.config.max_peers_per_torrent as usize {
-            return Err(BtServerError::MaxPeersConnectedReached(torrent.name()));
+            return Err(BtServerError::MaxPeersConnectedReached(
+                torrent_status.torrent.name(),
+            ));
         }
 
-        let mut peer_session = self.create_peer_session(&peer, torrent, torrent_status)?;
+        let mut peer_session = self.create_peer_session(&peer, &torrent_status)?;
 
         match peer_session.handshake_incoming_leecher(&mut stream) {
             Ok(_) => {
-                self.unchoke_peer(peer_session, peer, stream, torrent.clone(), torrent_status)?;
+                self.start_peer_session(peer_session, stream, &torrent_status)?;
             }
             Err(err) => {
                 self.logger_sender.warn(&format!("{:?}", err));
@@ -123,47 +179,35 @@ impl BtServer {
         Ok(())
     }
 
-    fn find_torrent_and_status(
+    fn find_torrent_status(
         &self,
         info_hash: Vec<u8>,
-    ) -> Result<(&Torrent, &Arc<AtomicTorrentStatus>), Result<(), BtServerError>> {
-        let (torrent, torrent_status) =
-            match self.torrents_with_status.iter().find(|(torrent, _)| {
-                match torrent.get_info_hash_as_bytes() {
-                    Ok(info_hash_bytes) => info_hash_bytes == info_hash,
-                    Err(_) => false,
-                }
-            }) {
-                Some((torrent, torrent_status)) => (torrent, torrent_status),
-                None => {
-                    return Err(Err(BtServerError::TorrentNotFound(
-                        String::from_utf8_lossy(&info_hash).to_string(),
-                    )))
-                }
-            };
-        Ok((torrent, torrent_status))
+    ) -> Result<Arc<AtomicTorrentStatus>, BtServerError> {
+        self.torrent_list.find_by_bytes(&info_hash).ok_or_else(|| {
+            BtServerError::TorrentNotFound(String::from_utf8_lossy(&info_hash).to_string())
+        })
     }
 
     fn create_peer_session(
         &self,
         peer: &BtPeer,
-        torrent: &Torrent,
         torrent_status: &Arc<AtomicTorrentStatus>,
     ) -> Result<PeerSession, BtServerError> {
         let peer_session = PeerSession::new(
             peer.clone(),
-            torrent.clone(),
+            torrent_status.torrent.clone(),
             torrent_status.clone(),
             self.config.clone(),
             self.logger_sender.clone(),
             self.client_peer_id.clone(),
+            self.utp.clone(),
         )
         .map_err(BtServerError::PeerSessionError)?;
         Ok(peer_session)
     }
 
     /// Sets read and write timeouts for the stream.
-    fn set_stream_timeouts(&self, stream: &mut TcpStream) -> Result<(), BtServerError> {
+    fn set_stream_timeouts(&self, stream: &mut Transport) -> Result<(), BtServerError> {
         stream
             .set_read_timeout(Some(Duration::from_secs(
                 self.config.read_write_seconds_timeout,
@@ -178,37 +222,17 @@ impl BtServer {
         Ok(())
     }
 
-    fn unchoke_peer(
+    /// Hands the session of an incoming leecher to the reactor.
+    fn start_peer_session(
         &self,
-        mut peer_session: PeerSession,
-        peer: BtPeer,
-        mut stream: TcpStream,
-        torrent: Torrent,
+        peer_session: PeerSession,
+        stream: PeerStream,
         torrent_status: &Arc<AtomicTorrentStatus>,
     ) -> Result<(), BtServerError> {
         torrent_status.peer_connecting();
-        let peer_name = format!("{}:{}", peer.ip, peer.port);
-
-        let builder = thread::Builder::new().name(format!(
-            "Torrent: {} / Peer: {}",
-            torrent.info.name, peer_name
-        ));
-        let peer_logger_sender = self.logger_sender.clone();
-
-        let join =
-            builder.spawn(
-                move || match peer_session.unchoke_incoming_leecher(&mut stream) {
-                    Ok(_) => (),
-                    Err(err) => {
-                        peer_logger_sender.warn(&format!("{:?}", err));
-                    }
-                },
-            );
-        match join {
-            Ok(_) => (),
-            Err(err) => {
-                self.logger_sender.error(&format!("{:?}", err));
-            }
+        if let Err(err) = self.reactor.register(peer_session, stream) {
+            torrent_status.peer_connecting_failed();
+            return Err(BtServerError::ReactorError(err));
         }
         Ok(())
     }
@@ -5,6 +5,17 @@ use std::io::BufReader;
 use std::str::FromStr;
 
 use super::constants;
+use crate::bandwidth::speed_schedule::SpeedSchedule;
+use crate::peer::encryption::EncryptionMode;
+use crate::storage_manager::storage::{AllocationMode, StorageBackend};
+use crate::torrent_handler::seed_limits::SeedLimitAction;
+
+/// Well known nodes used to join the DHT when `DHT_BOOTSTRAP_NODES` is not set.
+const DEFAULT_DHT_BOOTSTRAP_NODES: [&str; 3] = [
+    "router.bittorrent.com:6881",
+    "router.utorrent.com:6881",
+    "dht.transmissionbt.com:6881",
+];
 
 /// `Cfg` struct containing the config file information, previusly created with `Cfg::new`.
 ///
@@ -15,6 +26,27 @@ use super::constants;
 /// - `read_write_seconds_timeout`: timeout in seconds for the read and write operations to a peer,
 /// - `max_peers_per_torrent`: maximum number of simultaneous peers that a torrent can have,
 /// - `max_log_file_kb_size`: max file size in kilobytes the log can have,
+/// - `storage_backend`: where the torrents data is stored, `file` (default), `mmap` or `memory`,
+/// - `file_allocation`: how the files are allocated, `lazy` (default), `sparse` or `full`,
+/// - `upload_slots`: number of peers of a torrent unchoked by their upload rate, besides the optimistic unchoke (default 4),
+/// - `dht_enabled`: whether peers are also searched in the DHT, `true` (default) or `false`,
+/// - `dht_port`: UDP port of the DHT node (default the same as `tcp_port`),
+/// - `dht_bootstrap_nodes`: comma separated `host:port` addresses of the nodes used to join the DHT,
+/// - `dht_routing_table_path`: file where the DHT routing table is saved between runs (default ./dht_routing_table),
+/// - `encryption_mode`: encryption of the connections with peers, `plaintext`, `prefer_encrypted` (default) or `require_encrypted`,
+/// - `utp_enabled`: whether connections with peers are tried over uTP before TCP, on the UDP port `tcp_port`, `true` (default) or `false`,
+/// - `max_download_kbps` and `max_upload_kbps`: rate limits in kilobytes per second shared by all the torrents, 0 for unlimited (default),
+/// - `torrent_max_download_kbps` and `torrent_max_upload_kbps`: rate limits in kilobytes per second of each torrent, 0 for unlimited (default),
+/// - `alt_max_download_kbps` and `alt_max_upload_kbps`: alternative rate limits shared by all the torrents, used during the windows of `alt_speed_schedule`,
+/// - `alt_speed_schedule`: comma separated `HH:MM-HH:MM` time-of-day windows of the alternative rate limits (default none),
+/// - `seed_ratio_limit`: share ratio at which a finished torrent stops seeding, 0 for no limit (default),
+/// - `seed_time_limit_minutes` and `seed_idle_limit_minutes`: minutes seeding, and minutes seeding without uploading, after which a torrent stops seeding, 0 for no limit (default),
+/// - `seed_limit_action`: what is done with a torrent that reached a seeding limit, `pause` (default) or `remove`,
+/// - `watch_interval_seconds`: seconds between scans of the torrents directory for new .torrent files, 0 to not watch it (default 5),
+/// - `watch_remove_deleted`: whether the torrents whose .torrent file is deleted from the torrents directory are removed, `true` or `false` (default),
+/// - `rpc_enabled`: whether the client can be controlled with the JSON over HTTP RPC, `true` or `false` (default),
+/// - `rpc_bind_address`: `ip:port` address where the RPC server listens (default 127.0.0.1:9091),
+/// - `rpc_token`: token the RPC requests must send in their `Authorization: Bearer` header, the RPC server is not started without one,
 #[derive(Debug, Clone)]
 pub struct Cfg {
     pub tcp_port: u16,
@@ -24,6 +56,31 @@ pub struct Cfg {
     pub read_write_seconds_timeout: u64,
     pub max_peers_per_torrent: u32,
     pub max_log_file_kb_size: u32,
+    pub storage_backend: StorageBackend,
+    pub file_allocation: AllocationMode,
+    pub upload_slots: u32,
+    pub dht_enabled: bool,
+    pub dht_port: u16,
+    pub dht_bootstrap_nodes: Vec<String>,
+    pub dht_routing_table_path: String,
+    pub encryption_mode: EncryptionMode,
+    pub utp_enabled: bool,
+    pub max_download_kbps: u64,
+    pub max_upload_kbps: u64,
+    pub torrent_max_download_kbps: u64,
+    pub torrent_max_upload_kbps: u64,
+    pub alt_max_download_kbps: u64,
+    pub alt_max_upload_kbps: u64,
+    pub alt_speed_schedule: SpeedSchedule,
+    pub seed_ratio_limit: f64,
+    pub seed_time_limit_minutes: u64,
+    pub seed_idle_limit_minutes: u64,
+    pub seed_limit_action: SeedLimitAction,
+    pub watch_interval_seconds: u64,
+    pub watch_remove_deleted: bool,
+    pub rpc_enabled: bool,
+    pub rpc_bind_address: String,
+    pub rpc_token: String,
 }
 
 impl Cfg {
@@ -40,6 +97,15 @@ impl Cfg {
     /// - read_write_timeout setting is not a valid number in the config file.
     /// - max_peers_per_torrent  setting is not a valid number in the config file.
     /// - max_log_file_size setting is not a valid number in the config file.
+    /// - storage_backend or file_allocation settings are not valid options in the config file.
+    /// - upload_slots setting is not a valid number in the config file.
+    /// - dht_enabled setting is not a valid bool or dht_port is not a valid number in the config file.
+    /// - encryption_mode setting is not a valid option in the config file.
+    /// - utp_enabled setting is not a valid bool in the config file.
+    /// - a rate limit setting is not a valid number or alt_speed_schedule has wrong format in the config file.
+    /// - a seeding limit setting is not a valid number or seed_limit_action is not a valid option in the config file.
+    /// - watch_interval_seconds setting is not a valid number or watch_remove_deleted is not a valid bool in the config file.
+    /// - rpc_enabled setting is not a valid bool in the config file.
     /// - Minimum number of correct settings were not reached.
     pub fn new(path: &str) -> io::Result<Self> {
         let mut cfg = Self {
@@ -50,6 +116,34 @@ impl Cfg {
             read_write_seconds_timeout: 0,
             max_peers_per_torrent: 0,
             max_log_file_kb_size: 0,
+            storage_backend: StorageBackend::File,
+            file_allocation: AllocationMode::Lazy,
+            upload_slots: 4,
+            dht_enabled: true,
+            dht_port: 0,
+            dht_bootstrap_nodes: DEFAULT_DHT_BOOTSTRAP_NODES
+                .iter()
+                .map(|node| node.to_string())
+                .collect(),
+            dht_routing_table_path: String::from("./dht_routing_table"),
+            encryption_mode: EncryptionMode::PreferEncrypted,
+            utp_enabled: true,
+            max_download_kbps: 0,
+            max_upload_kbps: 0,
+            torrent_max_download_kbps: 0,
+            torrent_max_upload_kbps: 0,
+            alt_max_download_kbps: 0,
+            alt_max_upload_kbps: 0,
+            alt_speed_schedule: SpeedSchedule::default(),
+            seed_ratio_limit: 0.0,
+            seed_time_limit_minutes: 0,
+            seed_idle_limit_minutes: 0,
+            seed_limit_action: SeedLimitAction::Pause,
+            watch_interval_seconds: 5,
+            watch_remove_deleted: false,
+            rpc_enabled: false,
+            rpc_bind_address: String::from("127.0.0.1:9091"),
+            rpc_token: String::new(),
         };
 
         let file = File::open(path)?;
@@ -59,15 +153,14 @@ impl Cfg {
 
         for line in reader.lines() {
             let current_line = line?;
-            let setting: Vec<&str> = current_line.split('=').collect();
-
-            if setting.len() != 2 {
-                return Err(io::Error::new(
+            // Only the first `=` separates the name, the value can contain others like a base64 token.
+            let (name, value) = current_line.split_once('=').ok_or_else(|| {
+                io::Error::new(
                     io::ErrorKind::InvalidInput,
                     format!("Invalid config input: {}", current_line),
-                ));
-            }
-            cfg = Self::load_setting(cfg, setting[0], setting[1])?;
+                )
+            })?;
+            cfg = Self::load_setting(cfg, name, value)?;
             settings_loaded += 1;
         }
         if settings_loaded < constants::MIN_SETTINGS {
@@ -79,9 +172,19 @@ impl Cfg {
                 ),
             ));
         }
+        if cfg.dht_port == 0 {
+            cfg.dht_port = cfg.tcp_port;
+        }
         Ok(cfg)
     }
 
+    /// Returns the configuration with one of its settings changed, named and written like in the config file.
+    ///
+    /// It returns an io::Error if the setting name is wrong or its value is not valid.
+    pub fn with_setting(self, name: &str, value: &str) -> io::Result<Self> {
+        self.load_setting(name, value)
+    }
+
     fn load_setting(mut self, name: &str, value: &str) -> io::Result<Self> {
         match name {
             constants::TCP_PORT => {
@@ -110,6 +213,113 @@ impl Cfg {
                     self.parse_value(value, constants::MAX_LOG_FILE_KB_SIZE)?;
             }
 
+            constants::STORAGE_BACKEND => {
+                self.storage_backend = self.parse_value(value, constants::STORAGE_BACKEND)?;
+            }
+
+            constants::FILE_ALLOCATION => {
+                self.file_allocation = self.parse_value(value, constants::FILE_ALLOCATION)?;
+            }
+
+            constants::UPLOAD_SLOTS => {
+                self.upload_slots = self.parse_value(value, constants::UPLOAD_SLOTS)?;
+            }
+
+            constants::DHT_ENABLED => {
+                self.dht_enabled = self.parse_value(value, constants::DHT_ENABLED)?;
+            }
+
+            constants::DHT_PORT => {
+                self.dht_port = self.parse_value(value, constants::DHT_PORT)?;
+            }
+
+            constants::DHT_BOOTSTRAP_NODES => {
+                self.dht_bootstrap_nodes = value
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|node| !node.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+
+            constants::DHT_ROUTING_TABLE_PATH => self.dht_routing_table_path = String::from(value),
+
+            constants::ENCRYPTION_MODE => {
+                self.encryption_mode = self.parse_value(value, constants::ENCRYPTION_MODE)?;
+            }
+
+            constants::UTP_ENABLED => {
+                self.utp_enabled = self.parse_value(value, constants::UTP_ENABLED)?;
+            }
+
+            constants::MAX_DOWNLOAD_KBPS => {
+                self.max_download_kbps = self.parse_value(value, constants::MAX_DOWNLOAD_KBPS)?;
+            }
+
+            constants::MAX_UPLOAD_KBPS => {
+                self.max_upload_kbps = self.parse_value(value, constants::MAX_UPLOAD_KBPS)?;
+            }
+
+            constants::TORRENT_MAX_DOWNLOAD_KBPS => {
+                self.torrent_max_download_kbps =
+                    self.parse_value(value, constants::TORRENT_MAX_DOWNLOAD_KBPS)?;
+            }
+
+            constants::TORRENT_MAX_UPLOAD_KBPS => {
+                self.torrent_max_upload_kbps =
+                    self.parse_value(value, constants::TORRENT_MAX_UPLOAD_KBPS)?;
+            }
+
+            constants::ALT_MAX_DOWNLOAD_KBPS => {
+                self.alt_max_download_kbps =
+                    self.parse_value(value, constants::ALT_MAX_DOWNLOAD_KBPS)?;
+            }
+
+            constants::ALT_MAX_UPLOAD_KBPS => {
+                self.alt_max_upload_kbps =
+                    self.parse_value(value, constants::ALT_MAX_UPLOAD_KBPS)?;
+            }
+
+            constants::ALT_SPEED_SCHEDULE => {
+                self.alt_speed_schedule = self.parse_value(value, constants::ALT_SPEED_SCHEDULE)?;
+            }
+
+            constants::SEED_RATIO_LIMIT => {
+                self.seed_ratio_limit = self.parse_value(value, constants::SEED_RATIO_LIMIT)?;
+            }
+
+            constants::SEED_TIME_LIMIT_MINUTES => {
+                self.seed_time_limit_minutes =
+                    self.parse_value(value, constants::SEED_TIME_LIMIT_MINUTES)?;
+            }
+
+            constants::SEED_IDLE_LIMIT_MINUTES => {
+                self.seed_idle_limit_minutes =
+                    self.parse_value(value, constants::SEED_IDLE_LIMIT_MINUTES)?;
+            }
+
+            constants::SEED_LIMIT_ACTION => {
+                self.seed_limit_action = self.parse_value(value, constants::SEED_LIMIT_ACTION)?;
+            }
+
+            constants::WATCH_INTERVAL_SECONDS => {
+                self.watch_interval_seconds =
+                    self.parse_value(value, constants::WATCH_INTERVAL_SECONDS)?;
+            }
+
+            constants::WATCH_REMOVE_DELETED => {
+                self.watch_remove_deleted =
+                    self.parse_value(value, constants::WATCH_REMOVE_DELETED)?;
+            }
+
+            constants::RPC_ENABLED => {
+                self.rpc_enabled = self.parse_value(value, constants::RPC_ENABLED)?;
+            }
+
+            constants::RPC_BIND_ADDRESS => self.rpc_bind_address = String::from(value),
+
+            constants::RPC_TOKEN => self.rpc_token = String::from(value),
+
             _ => {
                 return Err(io::Error::new(
                     io::ErrorKind::InvalidInput,
@@ -252,6 +462,208 @@ mod tests {
         create_and_assert_config_is_ok(path, 2500, "./log2", "./download2", 10, 10, 1, 100);
     }
 
+    #[test]
+    fn test_storage_settings() {
+        let path = "./test_storage_settings.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSTORAGE_BACKEND=mmap\nFILE_ALLOCATION=sparse";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.storage_backend, StorageBackend::Mmap);
+        assert_eq!(config.file_allocation, AllocationMode::Sparse);
+    }
+
+    #[test]
+    fn test_invalid_storage_backend() {
+        let path = "./test_invalid_storage_backend.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSTORAGE_BACKEND=tape";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_upload_slots() {
+        let path = "./test_upload_slots.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nUPLOAD_SLOTS=2";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.upload_slots, 2);
+    }
+
+    #[test]
+    fn test_dht_settings() {
+        let path = "./test_dht_settings.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nDHT_ENABLED=false\nDHT_BOOTSTRAP_NODES=127.0.0.1:7000, localhost:7001\nDHT_ROUTING_TABLE_PATH=./dht";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(!config.dht_enabled);
+        assert_eq!(config.dht_port, 1000);
+        assert_eq!(
+            config.dht_bootstrap_nodes,
+            vec!["127.0.0.1:7000".to_string(), "localhost:7001".to_string()]
+        );
+        assert_eq!(config.dht_routing_table_path, "./dht");
+    }
+
+    #[test]
+    fn test_encryption_mode() {
+        let path = "./test_encryption_mode.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENCRYPTION_MODE=require_encrypted";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.encryption_mode, EncryptionMode::RequireEncrypted);
+    }
+
+    #[test]
+    fn test_invalid_encryption_mode() {
+        let path = "./test_invalid_encryption_mode.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nENCRYPTION_MODE=rot13";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_utp_enabled() {
+        let path = "./test_utp_enabled.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nUTP_ENABLED=false";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(!config.utp_enabled);
+    }
+
+    #[test]
+    fn test_rate_limits() {
+        let path = "./test_rate_limits.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nMAX_DOWNLOAD_KBPS=500\nTORRENT_MAX_UPLOAD_KBPS=20\nALT_MAX_DOWNLOAD_KBPS=50\nALT_SPEED_SCHEDULE=08:00-18:00";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.max_download_kbps, 500);
+        assert_eq!(config.max_upload_kbps, 0);
+        assert_eq!(config.torrent_max_upload_kbps, 20);
+        assert_eq!(config.alt_max_download_kbps, 50);
+        assert_eq!(
+            config.alt_speed_schedule,
+            "08:00-18:00".parse::<SpeedSchedule>().unwrap()
+        );
+    }
+
+    #[test]
+    fn test_invalid_speed_schedule() {
+        let path = "./test_invalid_speed_schedule.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nALT_SPEED_SCHEDULE=mornings";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_seed_limits() {
+        let path = "./test_seed_limits.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSEED_RATIO_LIMIT=1.5\nSEED_IDLE_LIMIT_MINUTES=30\nSEED_LIMIT_ACTION=remove";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.seed_ratio_limit, 1.5);
+        assert_eq!(config.seed_time_limit_minutes, 0);
+        assert_eq!(config.seed_idle_limit_minutes, 30);
+        assert_eq!(config.seed_limit_action, SeedLimitAction::Remove);
+    }
+
+    #[test]
+    fn test_invalid_seed_limit_action() {
+        let path = "./test_invalid_seed_limit_action.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nSEED_LIMIT_ACTION=delete";
+        create_and_write_file(path, contents);
+
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_watch_directory() {
+        let path = "./test_watch_directory.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nWATCH_INTERVAL_SECONDS=0\nWATCH_REMOVE_DELETED=true";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.watch_interval_seconds, 0);
+        assert!(config.watch_remove_deleted);
+    }
+
+    #[test]
+    fn test_rpc() {
+        let path = "./test_rpc.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nRPC_ENABLED=true\nRPC_BIND_ADDRESS=0.0.0.0:9000\nRPC_TOKEN=secret";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert!(config.rpc_enabled);
+        assert_eq!(config.rpc_bind_address, "0.0.0.0:9000");
+        assert_eq!(config.rpc_token, "secret");
+    }
+
+    #[test]
+    fn test_rpc_token_with_equals_signs() {
+        let path = "./test_rpc_token_with_equals_signs.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nRPC_TOKEN=c2VjcmV0IHRva2Vu==";
+        create_and_write_file(path, contents);
+
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(config.rpc_token, "c2VjcmV0IHRva2Vu==");
+    }
+
+    #[test]
+    fn test_invalid_rpc_enabled() {
+        let path = "./test_invalid_rpc_enabled.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100\nRPC_ENABLED=maybe";
+        create_and_write_file(path, contents);
+        create_and_assert_config_is_not_ok(path);
+    }
+
+    #[test]
+    fn test_with_setting() {
+        let path = "./test_with_setting.cfg";
+        let contents = b"TCP_PORT=1000\nLOG_DIRECTORY=./log\nDOWNLOAD_DIRECTORY=./download\nPIPELINING_SIZE=5\nREAD_WRITE_SECONDS_TIMEOUT=120\nMAX_PEERS_PER_TORRENT=5\nMAX_LOG_FILE_KB_SIZE=100";
+        create_and_write_file(path, contents);
+        let config = Cfg::new(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let config = config.with_setting("MAX_UPLOAD_KBPS", "50").unwrap();
+
+        assert_eq!(config.max_upload_kbps, 50);
+        assert!(config
+            .clone()
+            .with_setting("MAX_UPLOAD_KBPS", "a lot")
+            .is_err());
+        assert!(config.with_setting("NOT_A_SETTING", "1").is_err());
+    }
+
     #[test]
     fn test_bad_format() {
         let path = "./test_bad_format.cfg";
@@ -5,5 +5,30 @@ pub const PIPELINING_SIZE: &str = "PIPELINING_SIZE";
 pub const READ_WRITE_SECONDS_TIMEOUT: &str = "READ_WRITE_SECONDS_TIMEOUT";
 pub const MAX_PEERS_PER_TORRENT: &str = "MAX_PEERS_PER_TORRENT";
 pub const MAX_LOG_FILE_KB_SIZE: &str = "MAX_LOG_FILE_KB_SIZE";
+pub const STORAGE_BACKEND: &str = "STORAGE_BACKEND";
+pub const FILE_ALLOCATION: &str = "FILE_ALLOCATION";
+pub const UPLOAD_SLOTS: &str = "UPLOAD_SLOTS";
+pub const DHT_ENABLED: &str = "DHT_ENABLED";
+pub const DHT_PORT: &str = "DHT_PORT";
+pub const DHT_BOOTSTRAP_NODES: &str = "DHT_BOOTSTRAP_NODES";
+pub const DHT_ROUTING_TABLE_PATH: &str = "DHT_ROUTING_TABLE_PATH";
+pub const ENCRYPTION_MODE: &str = "ENCRYPTION_MODE";
+pub const UTP_ENABLED: &str = "UTP_ENABLED";
+pub const MAX_DOWNLOAD_KBPS: &str = "MAX_DOWNLOAD_KBPS";
+pub const MAX_UPLOAD_KBPS: &str = "MAX_UPLOAD_KBPS";
+pub const TORRENT_MAX_DOWNLOAD_KBPS: &str = "TORRENT_MAX_DOWNLOAD_KBPS";
+pub const TORRENT_MAX_UPLOAD_KBPS: &str = "TORRENT_MAX_UPLOAD_KBPS";
+pub const ALT_MAX_DOWNLOAD_KBPS: &str = "ALT_MAX_DOWNLOAD_KBPS";
+pub const ALT_MAX_UPLOAD_KBPS: &str = "ALT_MAX_UPLOAD_KBPS";
+pub const ALT_SPEED_SCHEDULE: &str = "ALT_SPEED_SCHEDULE";
+pub const SEED_RATIO_LIMIT: &str = "SEED_RATIO_LIMIT";
+pub const SEED_TIME_LIMIT_MINUTES: &str = "SEED_TIME_LIMIT_MINUTES";
+pub const SEED_IDLE_LIMIT_MINUTES: &str = "SEED_IDLE_LIMIT_MINUTES";
+pub const SEED_LIMIT_ACTION: &str = "SEED_LIMIT_ACTION";
+pub const WATCH_INTERVAL_SECONDS: &str = "WATCH_INTERVAL_SECONDS";
+pub const WATCH_REMOVE_DELETED: &str = "WATCH_REMOVE_DELETED";
+pub const RPC_ENABLED: &str = "RPC_ENABLED";
+pub const RPC_BIND_ADDRESS: &str = "RPC_BIND_ADDRESS";
+pub const RPC_TOKEN: &str = "RPC_TOKEN";
 
 pub const MIN_SETTINGS: i8 = 7;
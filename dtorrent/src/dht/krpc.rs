@@ -0,0 +1,470 @@
+use bencoder::bencode::{Bencode, BencodeError, ToBencode};
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+};
+
+use super::node_id::{NodeId, ID_LENGTH};
+
+/// Length of the compact info of a node, 20 bytes of ID, 4 bytes of IPv4 address and 2 bytes of port.
+const COMPACT_NODE_LENGTH: usize = ID_LENGTH + 6;
+
+/// Error code of a KRPC error message about a malformed query.
+pub const PROTOCOL_ERROR: i64 = 203;
+
+/// Error code of a KRPC error message about an unknown query.
+pub const METHOD_UNKNOWN: i64 = 204;
+
+/// Posible KRPC errors.
+#[derive(Debug, PartialEq)]
+pub enum KrpcError {
+    DecodeError(BencodeError),
+    NotADict,
+    MissingField(String),
+    InvalidField(String),
+    UnknownQuery(String),
+}
+
+/// Contact information of a DHT node.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct NodeInfo {
+    pub id: NodeId,
+    pub addr: SocketAddr,
+}
+
+/// Queries of the DHT protocol, without the ID of the querying node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Query {
+    Ping,
+    FindNode {
+        target: NodeId,
+    },
+    GetPeers {
+        info_hash: NodeId,
+    },
+    AnnouncePeer {
+        info_hash: NodeId,
+        port: u16,
+        implied_port: bool,
+        token: Vec<u8>,
+    },
+}
+
+/// Response to a query. Only the fields of the query that was answered are present.
+///
+/// - `id`: the ID of the responding node.
+/// - `nodes`: the closest nodes to the target of `find_node` and `get_peers`.
+/// - `values`: the peers of the torrent of `get_peers`.
+/// - `token`: the token needed to announce to the node after `get_peers`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Response {
+    pub id: NodeId,
+    pub nodes: Vec<NodeInfo>,
+    pub values: Vec<SocketAddr>,
+    pub token: Option<Vec<u8>>,
+}
+
+/// Body of a KRPC message, the `y` key of the dict.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KrpcBody {
+    Query { id: NodeId, query: Query },
+    Response(Response),
+    Error { code: i64, message: String },
+}
+
+/// A message of the KRPC protocol of the DHT (BEP 5), a bencoded dict sent in a UDP datagram.
+///
+/// The `transaction_id` is chosen by the querying node and copied in the response, to match them.
+///
+/// Only IPv4 nodes and peers are supported.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KrpcMessage {
+    pub transaction_id: Vec<u8>,
+    pub body: KrpcBody,
+}
+
+impl Response {
+    /// Creates a response with only the ID of the responding node.
+    pub fn new(id: NodeId) -> Self {
+        Self {
+            id,
+            nodes: vec![],
+            values: vec![],
+            token: None,
+        }
+    }
+}
+
+impl KrpcMessage {
+    /// Parses a KRPC message from the bytes of a datagram.
+    ///
+    /// It returns a `KrpcError` if:
+    /// - The datagram is not a bencoded dict.
+    /// - A field needed by the type of message or query is missing or invalid.
+    /// - The query is unknown.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, KrpcError> {
+        let bencode = Bencode::decode(bytes).map_err(KrpcError::DecodeError)?;
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(KrpcError::NotADict),
+        };
+
+        let transaction_id = Self::get_bytes(&d, "t")?;
+        let body = match Self::get_bytes(&d, "y")?.as_slice() {
+            b"q" => Self::create_query(&d)?,
+            b"r" => KrpcBody::Response(Self::create_response(Self::get_dict(&d, "r")?)?),
+            b"e" => Self::create_error(&d)?,
+            _ => return Err(KrpcError::InvalidField("y".to_string())),
+        };
+
+        Ok(Self {
+            transaction_id,
+            body,
+        })
+    }
+
+    /// Returns the transaction ID of a datagram, to answer with an error the queries that could not be parsed.
+    pub fn transaction_id_of(bytes: &[u8]) -> Option<Vec<u8>> {
+        match Bencode::decode(bytes) {
+            Ok(Bencode::BDict(d)) => Self::get_bytes(&d, "t").ok(),
+            _ => None,
+        }
+    }
+
+    fn create_query(d: &BTreeMap<Vec<u8>, Bencode>) -> Result<KrpcBody, KrpcError> {
+        let method = String::from_utf8_lossy(&Self::get_bytes(d, "q")?).to_string();
+        let args = Self::get_dict(d, "a")?;
+        let id = Self::get_node_id(args, "id")?;
+
+        let query = match method.as_str() {
+            "ping" => Query::Ping,
+            "find_node" => Query::FindNode {
+                target: Self::get_node_id(args, "target")?,
+            },
+            "get_peers" => Query::GetPeers {
+                info_hash: Self::get_node_id(args, "info_hash")?,
+            },
+            "announce_peer" => Query::AnnouncePeer {
+                info_hash: Self::get_node_id(args, "info_hash")?,
+                port: u16::try_from(Self::get_number(args, "port")?)
+                    .map_err(|_| KrpcError::InvalidField("port".to_string()))?,
+                implied_port: matches!(
+                    args.get(b"implied_port".as_slice()),
+                    Some(Bencode::BNumber(1))
+                ),
+                token: Self::get_bytes(args, "token")?,
+            },
+            _ => return Err(KrpcError::UnknownQuery(method)),
+        };
+        Ok(KrpcBody::Query { id, query })
+    }
+
+    fn create_response(r: &BTreeMap<Vec<u8>, Bencode>) -> Result<Response, KrpcError> {
+        let mut response = Response::new(Self::get_node_id(r, "id")?);
+
+        if let Some(nodes) = r.get(b"nodes".as_slice()) {
+            response.nodes = match nodes {
+                Bencode::BString(nodes) => Self::create_nodes(nodes),
+                _ => return Err(KrpcError::InvalidField("nodes".to_string())),
+            };
+        }
+        if let Some(values) = r.get(b"values".as_slice()) {
+            response.values = match values {
+                Bencode::BList(values) => values
+                    .iter()
+                    .filter_map(|value| match value {
+                        Bencode::BString(peer) => Self::create_peer(peer),
+                        _ => None,
+                    })
+                    .collect(),
+                _ => return Err(KrpcError::InvalidField("values".to_string())),
+            };
+        }
+        if r.contains_key(b"token".as_slice()) {
+            response.token = Some(Self::get_bytes(r, "token")?);
+        }
+        Ok(response)
+    }
+
+    fn create_error(d: &BTreeMap<Vec<u8>, Bencode>) -> Result<KrpcBody, KrpcError> {
+        match d.get(b"e".as_slice()) {
+            Some(Bencode::BList(list)) => match list.as_slice() {
+                [Bencode::BNumber(code), Bencode::BString(message)] => Ok(KrpcBody::Error {
+                    code: *code,
+                    message: String::from_utf8_lossy(message).to_string(),
+                }),
+                _ => Err(KrpcError::InvalidField("e".to_string())),
+            },
+            _ => Err(KrpcError::MissingField("e".to_string())),
+        }
+    }
+
+    /// Parses a list of compact node infos. Incomplete entries are ignored.
+    pub fn create_nodes(bytes: &[u8]) -> Vec<NodeInfo> {
+        bytes
+            .chunks_exact(COMPACT_NODE_LENGTH)
+            .filter_map(|chunk| {
+                Some(NodeInfo {
+                    id: NodeId::from_bytes(&chunk[..ID_LENGTH])?,
+                    addr: Self::create_peer(&chunk[ID_LENGTH..])?,
+                })
+            })
+            .collect()
+    }
+
+    /// Parses a compact peer info, 4 bytes of IPv4 address and 2 bytes of port.
+    fn create_peer(bytes: &[u8]) -> Option<SocketAddr> {
+        match bytes {
+            [a, b, c, d, p1, p2] => Some(SocketAddr::new(
+                IpAddr::V4(Ipv4Addr::new(*a, *b, *c, *d)),
+                u16::from_be_bytes([*p1, *p2]),
+            )),
+            _ => None,
+        }
+    }
+
+    fn get_field<'a>(
+        d: &'a BTreeMap<Vec<u8>, Bencode>,
+        key: &str,
+    ) -> Result<&'a Bencode, KrpcError> {
+        d.get(key.as_bytes())
+            .ok_or_else(|| KrpcError::MissingField(key.to_string()))
+    }
+
+    fn get_bytes(d: &BTreeMap<Vec<u8>, Bencode>, key: &str) -> Result<Vec<u8>, KrpcError> {
+        match Self::get_field(d, key)? {
+            Bencode::BString(bytes) => Ok(bytes.clone()),
+            _ => Err(KrpcError::InvalidField(key.to_string())),
+        }
+    }
+
+    fn get_number(d: &BTreeMap<Vec<u8>, Bencode>, key: &str) -> Result<i64, KrpcError> {
+        match Self::get_field(d, key)? {
+            Bencode::BNumber(n) => Ok(*n),
+            _ => Err(KrpcError::InvalidField(key.to_string())),
+        }
+    }
+
+    fn get_dict<'a>(
+        d: &'a BTreeMap<Vec<u8>, Bencode>,
+        key: &str,
+    ) -> Result<&'a BTreeMap<Vec<u8>, Bencode>, KrpcError> {
+        match Self::get_field(d, key)? {
+            Bencode::BDict(dict) => Ok(dict),
+            _ => Err(KrpcError::InvalidField(key.to_string())),
+        }
+    }
+
+    fn get_node_id(d: &BTreeMap<Vec<u8>, Bencode>, key: &str) -> Result<NodeId, KrpcError> {
+        NodeId::from_bytes(&Self::get_bytes(d, key)?)
+            .ok_or_else(|| KrpcError::InvalidField(key.to_string()))
+    }
+
+    /// Converts the message to the bytes of a datagram.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Bencode::encode(self)
+    }
+
+    /// Converts nodes to the compact node info format. IPv6 nodes are skipped.
+    pub fn compact_nodes(nodes: &[NodeInfo]) -> Vec<u8> {
+        nodes
+            .iter()
+            .filter_map(|node| {
+                Self::compact_peer(&node.addr).map(|addr| [node.id.as_bytes(), addr].concat())
+            })
+            .flatten()
+            .collect()
+    }
+
+    fn compact_peer(addr: &SocketAddr) -> Option<Vec<u8>> {
+        match addr {
+            SocketAddr::V4(addr) => Some(
+                [
+                    addr.ip().octets().to_vec(),
+                    addr.port().to_be_bytes().to_vec(),
+                ]
+                .concat(),
+            ),
+            SocketAddr::V6(_) => None,
+        }
+    }
+}
+
+impl ToBencode for KrpcMessage {
+    fn to_bencode(&self) -> Bencode {
+        let mut d = BTreeMap::new();
+        d.insert(b"t".to_vec(), self.transaction_id.to_bencode());
+
+        match &self.body {
+            KrpcBody::Query { id, query } => {
+                let mut args = BTreeMap::new();
+                args.insert(b"id".to_vec(), id.as_bytes().to_bencode());
+                let method = match query {
+                    Query::Ping => "ping",
+                    Query::FindNode { target } => {
+                        args.insert(b"target".to_vec(), target.as_bytes().to_bencode());
+                        "find_node"
+                    }
+                    Query::GetPeers { info_hash } => {
+                        args.insert(b"info_hash".to_vec(), info_hash.as_bytes().to_bencode());
+                        "get_peers"
+                    }
+                    Query::AnnouncePeer {
+                        info_hash,
+                        port,
+                        implied_port,
+                        token,
+                    } => {
+                        args.insert(b"info_hash".to_vec(), info_hash.as_bytes().to_bencode());
+                        args.insert(b"port".to_vec(), port.to_bencode());
+                        args.insert(
+                            b"implied_port".to_vec(),
+                            Bencode::BNumber(*implied_port as i64),
+                        );
+                        args.insert(b"token".to_vec(), token.to_bencode());
+                        "announce_peer"
+                    }
+                };
+                d.insert(b"y".to_vec(), b"q".to_vec().to_bencode());
+                d.insert(b"q".to_vec(), method.to_string().to_bencode());
+                d.insert(b"a".to_vec(), Bencode::BDict(args));
+            }
+            KrpcBody::Response(response) => {
+                let mut r = BTreeMap::new();
+                r.insert(b"id".to_vec(), response.id.as_bytes().to_bencode());
+                if !response.nodes.is_empty() {
+                    r.insert(
+                        b"nodes".to_vec(),
+                        Self::compact_nodes(&response.nodes).to_bencode(),
+                    );
+                }
+                if !response.values.is_empty() {
+                    let values = response
+                        .values
+                        .iter()
+                        .filter_map(Self::compact_peer)
+                        .map(Bencode::BString)
+                        .collect();
+                    r.insert(b"values".to_vec(), Bencode::BList(values));
+                }
+                if let Some(token) = &response.token {
+                    r.insert(b"token".to_vec(), token.to_bencode());
+                }
+                d.insert(b"y".to_vec(), b"r".to_vec().to_bencode());
+                d.insert(b"r".to_vec(), Bencode::BDict(r));
+            }
+            KrpcBody::Error { code, message } => {
+                d.insert(b"y".to_vec(), b"e".to_vec().to_bencode());
+                d.insert(
+                    b"e".to_vec(),
+                    Bencode::BList(vec![Bencode::BNumber(*code), message.to_bencode()]),
+                );
+            }
+        }
+        Bencode::BDict(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ping_query_as_bytes() {
+        let message = KrpcMessage {
+            transaction_id: b"aa".to_vec(),
+            body: KrpcBody::Query {
+                id: NodeId(*b"abcdefghij0123456789"),
+                query: Query::Ping,
+            },
+        };
+
+        assert_eq!(
+            message.as_bytes(),
+            b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe"
+        );
+    }
+
+    #[test]
+    fn test_get_peers_response_from_bytes() {
+        let bytes = b"d1:rd2:id20:abcdefghij01234567895:token8:aoeusnth6:valuesl6:\x7f\x00\x00\x01\x1a\xe1ee1:t2:aa1:y1:re";
+
+        let message = KrpcMessage::from_bytes(bytes).unwrap();
+
+        let mut response = Response::new(NodeId(*b"abcdefghij0123456789"));
+        response.token = Some(b"aoeusnth".to_vec());
+        response.values = vec!["127.0.0.1:6881".parse().unwrap()];
+        assert_eq!(message.body, KrpcBody::Response(response));
+        assert_eq!(message.transaction_id, b"aa");
+    }
+
+    #[test]
+    fn test_announce_peer_roundtrip() {
+        let message = KrpcMessage {
+            transaction_id: b"xy".to_vec(),
+            body: KrpcBody::Query {
+                id: NodeId::random(),
+                query: Query::AnnouncePeer {
+                    info_hash: NodeId::random(),
+                    port: 6881,
+                    implied_port: true,
+                    token: b"token".to_vec(),
+                },
+            },
+        };
+
+        assert_eq!(KrpcMessage::from_bytes(&message.as_bytes()), Ok(message));
+    }
+
+    #[test]
+    fn test_find_node_response_roundtrip() {
+        let mut response = Response::new(NodeId::random());
+        response.nodes = vec![
+            NodeInfo {
+                id: NodeId::random(),
+                addr: "10.0.0.1:6881".parse().unwrap(),
+            },
+            NodeInfo {
+                id: NodeId::random(),
+                addr: "10.0.0.2:51413".parse().unwrap(),
+            },
+        ];
+        let message = KrpcMessage {
+            transaction_id: b"xy".to_vec(),
+            body: KrpcBody::Response(response),
+        };
+
+        assert_eq!(KrpcMessage::from_bytes(&message.as_bytes()), Ok(message));
+    }
+
+    #[test]
+    fn test_error_from_bytes() {
+        let message =
+            KrpcMessage::from_bytes(b"d1:eli201e23:A Generic Error Ocurrede1:t2:aa1:y1:ee")
+                .unwrap();
+
+        assert_eq!(
+            message.body,
+            KrpcBody::Error {
+                code: 201,
+                message: "A Generic Error Ocurred".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_invalid_messages() {
+        assert_eq!(
+            KrpcMessage::from_bytes(b"d1:t2:aa1:y1:q1:q4:pinge"),
+            Err(KrpcError::MissingField("a".to_string()))
+        );
+        assert_eq!(
+            KrpcMessage::from_bytes(b"d1:ad2:id20:abcdefghij0123456789e1:q4:vote1:t2:aa1:y1:qe"),
+            Err(KrpcError::UnknownQuery("vote".to_string()))
+        );
+        assert!(matches!(
+            KrpcMessage::from_bytes(b"d1:t2:a"),
+            Err(KrpcError::DecodeError(_))
+        ));
+    }
+}
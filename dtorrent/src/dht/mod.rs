@@ -0,0 +1,4 @@
+pub mod krpc;
+pub mod node;
+pub mod node_id;
+pub mod routing_table;
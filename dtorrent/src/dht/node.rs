@@ -0,0 +1,767 @@
+use logger::logger_sender::LoggerSender;
+use rand::Rng;
+use sha1::{Digest, Sha1};
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    net::{IpAddr, SocketAddr, ToSocketAddrs, UdpSocket},
+    sync::{
+        atomic::{AtomicU16, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    krpc::{
+        KrpcBody, KrpcError, KrpcMessage, NodeInfo, Query, Response, METHOD_UNKNOWN, PROTOCOL_ERROR,
+    },
+    node_id::NodeId,
+    routing_table::{RoutingTable, RoutingTableError, BUCKET_SIZE},
+};
+use crate::utp::socket::{DatagramHandler, UtpSocket};
+
+/// Time to wait for the response to a query.
+const QUERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Number of queries sent at the same time during a lookup, the `alpha` of Kademlia.
+const LOOKUP_PARALLELISM: usize = 3;
+
+/// Time between checks of the nodes of the routing table, that also saves it to disk.
+const MAINTENANCE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Nodes not heard from in this time are pinged during maintenance, as BEP 5 considers them questionable.
+const NODE_QUESTIONABLE_AFTER: Duration = Duration::from_secs(15 * 60);
+
+/// Time after which the secret used to create tokens is replaced. Tokens of the previous secret are still accepted.
+const TOKEN_SECRET_LIFETIME: Duration = Duration::from_secs(5 * 60);
+
+/// Time a peer announced to us is kept without being announced again.
+const PEER_LIFETIME: Duration = Duration::from_secs(30 * 60);
+
+/// Maximum number of peers stored for each info hash, and sent in a `get_peers` response.
+const MAX_PEERS_PER_INFO_HASH: usize = 50;
+
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+/// Channels of the threads waiting for a response, by transaction ID.
+type Transactions = HashMap<Vec<u8>, Sender<KrpcMessage>>;
+
+/// Peers announced to us, by info hash, with the time of their last announce.
+type StoredPeers = HashMap<NodeId, HashMap<SocketAddr, Instant>>;
+
+/// Posible `DhtNode` errors.
+#[derive(Debug)]
+pub enum DhtError {
+    SocketError(io::Error),
+    Timeout,
+    ErrorResponse(i64, String),
+    UnexpectedResponse,
+    PoisonedRoutingTableLock,
+    PoisonedTransactionsLock,
+    PoisonedPeersLock,
+    PoisonedTokenSecretsLock,
+    RoutingTableError(RoutingTableError),
+}
+
+/// Secrets used to create the tokens of `get_peers` responses, so only nodes that asked us can announce.
+#[derive(Debug)]
+struct TokenSecrets {
+    current: [u8; 20],
+    previous: [u8; 20],
+    rotated_at: Instant,
+}
+
+/// A node of the mainline DHT (BEP 5), to find the peers of torrents without a tracker.
+///
+/// It answers the `ping`, `find_node`, `get_peers` and `announce_peer` queries of other nodes, and sends them to find peers and announce our torrents.
+/// Queries and responses are matched by their transaction ID: the listener thread hands each response to the thread waiting for it.
+///
+/// The routing table is saved to disk periodically, and loaded on start to keep our ID and the known nodes between runs.
+///
+/// The node can have its own UDP socket, or share the socket of the uTP connections, which then hands it the datagrams that are not uTP packets.
+///
+/// To start a new `DhtNode`, use DhtNode::start(port, bootstrap_nodes, routing_table_path, logger_sender),
+/// or DhtNode::start_on_utp_socket(utp, bootstrap_nodes, routing_table_path, logger_sender).
+#[derive(Debug)]
+pub struct DhtNode {
+    socket: UdpSocket,
+    routing_table: Mutex<RoutingTable>,
+    transactions: Mutex<Transactions>,
+    next_transaction_id: AtomicU16,
+    peers: Mutex<StoredPeers>,
+    token_secrets: Mutex<TokenSecrets>,
+    bootstrap_nodes: Vec<String>,
+    routing_table_path: String,
+    logger_sender: LoggerSender,
+}
+
+/// Result of a lookup of the nodes closest to a target.
+///
+/// - `peers`: the peers of the torrent found in `get_peers` lookups.
+/// - `closest_nodes`: the closest nodes that answered, with the token they sent in `get_peers` lookups.
+struct Lookup {
+    peers: HashSet<SocketAddr>,
+    closest_nodes: Vec<(NodeInfo, Option<Vec<u8>>)>,
+}
+
+impl DhtNode {
+    /// Binds the UDP socket of the node, joins the DHT through the known nodes and starts answering queries.
+    ///
+    /// The routing table is loaded from `routing_table_path` if it exists, otherwise a new ID is generated.
+    /// The `bootstrap_nodes` are `host:port` addresses of nodes that are used to join the DHT.
+    ///
+    /// It returns a `DhtError` if the socket could not be bound or the threads could not be spawned.
+    pub fn start(
+        port: u16,
+        bootstrap_nodes: Vec<String>,
+        routing_table_path: String,
+        logger_sender: LoggerSender,
+    ) -> Result<Arc<Self>, DhtError> {
+        let socket = UdpSocket::bind(("0.0.0.0", port)).map_err(DhtError::SocketError)?;
+        let node = Self::new(socket, bootstrap_nodes, routing_table_path, logger_sender);
+
+        let listener = node.clone();
+        thread::Builder::new()
+            .name("DHT listener".to_string())
+            .spawn(move || listener.listen())
+            .map_err(DhtError::SocketError)?;
+
+        node.join()?;
+        Ok(node)
+    }
+
+    /// Joins the DHT like `start`, but using the UDP socket of the uTP connections instead of binding a new one.
+    ///
+    /// It returns a `DhtError` if the socket could not be cloned or the threads could not be spawned.
+    pub fn start_on_utp_socket(
+        utp: &UtpSocket,
+        bootstrap_nodes: Vec<String>,
+        routing_table_path: String,
+        logger_sender: LoggerSender,
+    ) -> Result<Arc<Self>, DhtError> {
+        let socket = utp.try_clone_udp_socket().map_err(DhtError::SocketError)?;
+        let node = Self::new(socket, bootstrap_nodes, routing_table_path, logger_sender);
+        utp.set_datagram_handler(node.clone());
+
+        node.join()?;
+        Ok(node)
+    }
+
+    fn new(
+        socket: UdpSocket,
+        bootstrap_nodes: Vec<String>,
+        routing_table_path: String,
+        logger_sender: LoggerSender,
+    ) -> Arc<Self> {
+        let routing_table = match RoutingTable::load(&routing_table_path) {
+            Ok(routing_table) => {
+                logger_sender.info(&format!(
+                    "DHT routing table loaded with {} nodes.",
+                    routing_table.len()
+                ));
+                routing_table
+            }
+            Err(_) => RoutingTable::new(NodeId::random()),
+        };
+
+        Arc::new(Self {
+            socket,
+            routing_table: Mutex::new(routing_table),
+            transactions: Mutex::new(HashMap::new()),
+            next_transaction_id: AtomicU16::new(rand::thread_rng().gen()),
+            peers: Mutex::new(HashMap::new()),
+            token_secrets: Mutex::new(TokenSecrets {
+                current: rand::thread_rng().gen(),
+                previous: rand::thread_rng().gen(),
+                rotated_at: Instant::now(),
+            }),
+            bootstrap_nodes,
+            routing_table_path,
+            logger_sender,
+        })
+    }
+
+    /// Bootstraps the node, once it is receiving datagrams, and starts the maintenance of the routing table.
+    fn join(self: &Arc<Self>) -> Result<(), DhtError> {
+        self.bootstrap();
+
+        let maintainer = self.clone();
+        thread::Builder::new()
+            .name("DHT maintenance".to_string())
+            .spawn(move || maintainer.run_maintenance())
+            .map_err(DhtError::SocketError)?;
+        Ok(())
+    }
+
+    /// Returns the address the UDP socket of the node is bound to.
+    pub fn local_addr(&self) -> Result<SocketAddr, DhtError> {
+        self.socket.local_addr().map_err(DhtError::SocketError)
+    }
+
+    /// Returns the number of nodes in the routing table.
+    pub fn routing_table_len(&self) -> Result<usize, DhtError> {
+        Ok(self.lock_routing_table()?.len())
+    }
+
+    /// Joins the DHT: asks the bootstrap nodes and the nodes of the routing table for the nodes closest to our ID.
+    ///
+    /// Failures are only logged, the node keeps answering queries and learning nodes from them.
+    pub fn bootstrap(&self) {
+        let own_id = match self.own_id() {
+            Ok(own_id) => own_id,
+            Err(err) => {
+                self.logger_sender
+                    .warn(&format!("Could not bootstrap the DHT: {:?}", err));
+                return;
+            }
+        };
+
+        for bootstrap_node in &self.bootstrap_nodes {
+            let addrs = match bootstrap_node.to_socket_addrs() {
+                Ok(addrs) => addrs,
+                Err(err) => {
+                    self.logger_sender.warn(&format!(
+                        "Could not resolve DHT bootstrap node {}: {}",
+                        bootstrap_node, err
+                    ));
+                    continue;
+                }
+            };
+            for addr in addrs.filter(SocketAddr::is_ipv4) {
+                if let Err(err) = self.query(addr, Query::FindNode { target: own_id }) {
+                    self.logger_sender.warn(&format!(
+                        "DHT bootstrap node {} did not answer: {:?}",
+                        bootstrap_node, err
+                    ));
+                }
+            }
+        }
+
+        match self.lookup(own_id, false) {
+            Ok(_) => self.logger_sender.info(&format!(
+                "DHT bootstrapped, {} nodes known.",
+                self.routing_table_len().unwrap_or_default()
+            )),
+            Err(err) => self
+                .logger_sender
+                .warn(&format!("Could not bootstrap the DHT: {:?}", err)),
+        }
+    }
+
+    /// Finds peers of a torrent in the DHT.
+    ///
+    /// It returns a `DhtError` if a lock is poisoned.
+    pub fn get_peers(&self, info_hash: NodeId) -> Result<Vec<SocketAddr>, DhtError> {
+        Ok(self.lookup(info_hash, true)?.peers.into_iter().collect())
+    }
+
+    /// Finds peers of a torrent in the DHT and announces that we are downloading it on the given TCP port.
+    ///
+    /// We are announced to the closest nodes to the info hash that sent us a token.
+    ///
+    /// It returns a `DhtError` if a lock is poisoned.
+    pub fn announce(&self, info_hash: NodeId, port: u16) -> Result<Vec<SocketAddr>, DhtError> {
+        let lookup = self.lookup(info_hash, true)?;
+
+        thread::scope(|scope| {
+            for (node, token) in &lookup.closest_nodes {
+                if let Some(token) = token {
+                    let query = Query::AnnouncePeer {
+                        info_hash,
+                        port,
+                        implied_port: false,
+                        token: token.clone(),
+                    };
+                    scope.spawn(move || self.query_node(node, query));
+                }
+            }
+        });
+
+        Ok(lookup.peers.into_iter().collect())
+    }
+
+    /// Saves the routing table to disk.
+    pub fn save_routing_table(&self) -> Result<(), DhtError> {
+        self.lock_routing_table()?
+            .save(&self.routing_table_path)
+            .map_err(DhtError::RoutingTableError)
+    }
+
+    /// Iterative lookup of the nodes closest to the target.
+    ///
+    /// In each round the `LOOKUP_PARALLELISM` closest nodes not queried yet are queried, and the nodes they return are added to the candidates.
+    /// It ends when the `BUCKET_SIZE` closest candidates were all queried.
+    fn lookup(&self, target: NodeId, get_peers: bool) -> Result<Lookup, DhtError> {
+        let own_id = self.own_id()?;
+        let mut candidates = self.lock_routing_table()?.closest(&target, BUCKET_SIZE);
+        let mut queried = HashSet::new();
+        let mut responded = Vec::new();
+        let mut peers = HashSet::new();
+
+        loop {
+            candidates.sort_by_key(|node| node.id.distance(&target));
+            candidates.dedup_by_key(|node| node.id);
+            let round: Vec<NodeInfo> = candidates
+                .iter()
+                .take(BUCKET_SIZE)
+                .filter(|node| !queried.contains(&node.id))
+                .take(LOOKUP_PARALLELISM)
+                .copied()
+                .collect();
+            if round.is_empty() {
+                break;
+            }
+
+            let responses: Vec<(NodeInfo, Result<Response, DhtError>)> = thread::scope(|scope| {
+                let handles: Vec<_> = round
+                    .iter()
+                    .map(|node| {
+                        let query = if get_peers {
+                            Query::GetPeers { info_hash: target }
+                        } else {
+                            Query::FindNode { target }
+                        };
+                        (*node, scope.spawn(move || self.query_node(node, query)))
+                    })
+                    .collect();
+                handles
+                    .into_iter()
+                    .map(|(node, handle)| {
+                        let response = handle.join().unwrap_or(Err(DhtError::Timeout));
+                        (node, response)
+                    })
+                    .collect()
+            });
+
+            for (node, response) in responses {
+                queried.insert(node.id);
+                let response = match response {
+                    Ok(response) => response,
+                    Err(_) => {
+                        candidates.retain(|candidate| candidate.id != node.id);
+                        continue;
+                    }
+                };
+                candidates.extend(
+                    response
+                        .nodes
+                        .iter()
+                        .filter(|candidate| candidate.id != own_id),
+                );
+                peers.extend(response.values);
+                responded.push((node, response.token));
+            }
+        }
+
+        responded.sort_by_key(|(node, _)| node.id.distance(&target));
+        responded.truncate(BUCKET_SIZE);
+        Ok(Lookup {
+            peers,
+            closest_nodes: responded,
+        })
+    }
+
+    /// Sends a query to a node of the routing table, recording it as failed if it does not answer.
+    fn query_node(&self, node: &NodeInfo, query: Query) -> Result<Response, DhtError> {
+        let response = self.query(node.addr, query);
+        if response.is_err() {
+            self.lock_routing_table()?.query_failed(&node.id);
+        }
+        response
+    }
+
+    /// Sends a query and waits for its response. The node that answered is added to the routing table.
+    ///
+    /// It returns a `DhtError` if:
+    /// - The query could not be sent.
+    /// - The node did not answer before `QUERY_TIMEOUT`.
+    /// - The node answered with an error.
+    fn query(&self, addr: SocketAddr, query: Query) -> Result<Response, DhtError> {
+        let transaction_id = self
+            .next_transaction_id
+            .fetch_add(1, Ordering::Relaxed)
+            .to_be_bytes()
+            .to_vec();
+        let message = KrpcMessage {
+            transaction_id: transaction_id.clone(),
+            body: KrpcBody::Query {
+                id: self.own_id()?,
+                query,
+            },
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        self.lock_transactions()?
+            .insert(transaction_id.clone(), sender);
+        let sent = self.socket.send_to(&message.as_bytes(), addr);
+        let response = sent.map_err(DhtError::SocketError).and_then(|_| {
+            receiver
+                .recv_timeout(QUERY_TIMEOUT)
+                .map_err(|_| DhtError::Timeout)
+        });
+        self.lock_transactions()?.remove(&transaction_id);
+
+        match response?.body {
+            KrpcBody::Response(response) => {
+                self.lock_routing_table()?.insert(NodeInfo {
+                    id: response.id,
+                    addr,
+                });
+                Ok(response)
+            }
+            KrpcBody::Error { code, message } => Err(DhtError::ErrorResponse(code, message)),
+            KrpcBody::Query { .. } => Err(DhtError::UnexpectedResponse),
+        }
+    }
+
+    /// Receives the datagrams of the socket: queries are answered and responses are handed to the thread waiting for them.
+    fn listen(&self) {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        loop {
+            let (size, addr) = match self.socket.recv_from(&mut buffer) {
+                Ok(received) => received,
+                Err(_) => continue,
+            };
+            self.datagram_received(&buffer[..size], addr);
+        }
+    }
+
+    /// Handles a datagram received from a node.
+    ///
+    /// Unknown queries are answered with an error, the rest of the datagrams that are not valid KRPC messages are ignored.
+    fn handle_datagram(&self, datagram: &[u8], addr: SocketAddr) -> Result<(), DhtError> {
+        let message = match KrpcMessage::from_bytes(datagram) {
+            Ok(message) => message,
+            Err(KrpcError::UnknownQuery(method)) => {
+                return match KrpcMessage::transaction_id_of(datagram) {
+                    Some(transaction_id) => self.send_response(
+                        transaction_id,
+                        KrpcBody::Error {
+                            code: METHOD_UNKNOWN,
+                            message: format!("Method Unknown: {}", method),
+                        },
+                        addr,
+                    ),
+                    None => Ok(()),
+                }
+            }
+            Err(_) => return Ok(()),
+        };
+
+        match message.body {
+            KrpcBody::Query { id, query } => {
+                let body = self.handle_query(query, addr)?;
+                self.lock_routing_table()?.insert(NodeInfo { id, addr });
+                self.send_response(message.transaction_id, body, addr)?;
+            }
+            KrpcBody::Response(_) | KrpcBody::Error { .. } => {
+                if let Some(sender) = self.lock_transactions()?.get(&message.transaction_id) {
+                    // The querying thread may have timed out already.
+                    let _ = sender.send(message);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn send_response(
+        &self,
+        transaction_id: Vec<u8>,
+        body: KrpcBody,
+        addr: SocketAddr,
+    ) -> Result<(), DhtError> {
+        let response = KrpcMessage {
+            transaction_id,
+            body,
+        };
+        self.socket
+            .send_to(&response.as_bytes(), addr)
+            .map_err(DhtError::SocketError)?;
+        Ok(())
+    }
+
+    /// Returns the body of the response to a query.
+    fn handle_query(&self, query: Query, addr: SocketAddr) -> Result<KrpcBody, DhtError> {
+        let mut response = Response::new(self.own_id()?);
+
+        match query {
+            Query::Ping => (),
+            Query::FindNode { target } => {
+                response.nodes = self.lock_routing_table()?.closest(&target, BUCKET_SIZE);
+            }
+            Query::GetPeers { info_hash } => {
+                response.token = Some(self.create_token(addr.ip(), false)?);
+                response.values = self.stored_peers(&info_hash)?;
+                if response.values.is_empty() {
+                    response.nodes = self.lock_routing_table()?.closest(&info_hash, BUCKET_SIZE);
+                }
+            }
+            Query::AnnouncePeer {
+                info_hash,
+                port,
+                implied_port,
+                token,
+            } => {
+                if token != self.create_token(addr.ip(), false)?
+                    && token != self.create_token(addr.ip(), true)?
+                {
+                    return Ok(KrpcBody::Error {
+                        code: PROTOCOL_ERROR,
+                        message: "Bad token".to_string(),
+                    });
+                }
+                let port = if implied_port { addr.port() } else { port };
+                self.store_peer(info_hash, SocketAddr::new(addr.ip(), port))?;
+            }
+        }
+        Ok(KrpcBody::Response(response))
+    }
+
+    /// Creates the token a node with the given IP needs to announce to us, from the current or the previous secret.
+    fn create_token(&self, ip: IpAddr, previous: bool) -> Result<Vec<u8>, DhtError> {
+        let mut secrets = self
+            .token_secrets
+            .lock()
+            .map_err(|_| DhtError::PoisonedTokenSecretsLock)?;
+        if secrets.rotated_at.elapsed() >= TOKEN_SECRET_LIFETIME {
+            secrets.previous = secrets.current;
+            secrets.current = rand::thread_rng().gen();
+            secrets.rotated_at = Instant::now();
+        }
+
+        let secret = if previous {
+            secrets.previous
+        } else {
+            secrets.current
+        };
+        let ip = match ip {
+            IpAddr::V4(ip) => ip.octets().to_vec(),
+            IpAddr::V6(ip) => ip.octets().to_vec(),
+        };
+        Ok(Sha1::digest([secret.to_vec(), ip].concat()).to_vec())
+    }
+
+    fn stored_peers(&self, info_hash: &NodeId) -> Result<Vec<SocketAddr>, DhtError> {
+        let mut peers = self.lock_peers()?;
+        Ok(match peers.get_mut(info_hash) {
+            Some(peers) => {
+                peers.retain(|_, announced_at| announced_at.elapsed() < PEER_LIFETIME);
+                peers.keys().copied().collect()
+            }
+            None => vec![],
+        })
+    }
+
+    fn store_peer(&self, info_hash: NodeId, peer: SocketAddr) -> Result<(), DhtError> {
+        let mut peers = self.lock_peers()?;
+        let torrent_peers = peers.entry(info_hash).or_default();
+        torrent_peers.retain(|_, announced_at| announced_at.elapsed() < PEER_LIFETIME);
+        if torrent_peers.len() < MAX_PEERS_PER_INFO_HASH || torrent_peers.contains_key(&peer) {
+            torrent_peers.insert(peer, Instant::now());
+        }
+        Ok(())
+    }
+
+    /// Every `MAINTENANCE_INTERVAL` pings the questionable nodes, joins the DHT again if no node is left, and saves the routing table.
+    fn run_maintenance(&self) {
+        loop {
+            thread::sleep(MAINTENANCE_INTERVAL);
+
+            let questionable_nodes = match (
+                self.lock_routing_table(),
+                Instant::now().checked_sub(NODE_QUESTIONABLE_AFTER),
+            ) {
+                (Ok(routing_table), Some(instant)) => routing_table.nodes_not_seen_since(instant),
+                _ => vec![],
+            };
+            for node in questionable_nodes {
+                let _ = self.query_node(&node, Query::Ping);
+            }
+
+            if self.routing_table_len().unwrap_or_default() == 0 {
+                self.bootstrap();
+            }
+
+            if let Err(err) = self.save_routing_table() {
+                self.logger_sender
+                    .warn(&format!("Could not save the DHT routing table: {:?}", err));
+            }
+        }
+    }
+
+    fn own_id(&self) -> Result<NodeId, DhtError> {
+        Ok(self.lock_routing_table()?.own_id())
+    }
+
+    fn lock_routing_table(&self) -> Result<MutexGuard<'_, RoutingTable>, DhtError> {
+        self.routing_table
+            .lock()
+            .map_err(|_| DhtError::PoisonedRoutingTableLock)
+    }
+
+    fn lock_transactions(&self) -> Result<MutexGuard<'_, Transactions>, DhtError> {
+        self.transactions
+            .lock()
+            .map_err(|_| DhtError::PoisonedTransactionsLock)
+    }
+
+    fn lock_peers(&self) -> Result<MutexGuard<'_, StoredPeers>, DhtError> {
+        self.peers.lock().map_err(|_| DhtError::PoisonedPeersLock)
+    }
+}
+
+impl DatagramHandler for DhtNode {
+    fn datagram_received(&self, datagram: &[u8], addr: SocketAddr) {
+        if let Err(err) = self.handle_datagram(datagram, addr) {
+            self.logger_sender
+                .warn(&format!("Could not handle DHT message: {:?}", err));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::logger_receiver::Logger;
+    use std::fs;
+
+    #[test]
+    fn test_nodes_on_loopback_find_each_other() {
+        let name = "test_dht_nodes_on_loopback_find_each_other";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let first = start_test_node(name, 0, vec![], &logger);
+        let first_addr = loopback_addr(&first);
+
+        let second = start_test_node(name, 1, vec![first_addr.clone()], &logger);
+        let third = start_test_node(name, 2, vec![first_addr], &logger);
+
+        assert_eq!(first.routing_table_len().unwrap(), 2);
+        assert_eq!(third.routing_table_len().unwrap(), 2);
+        assert!(second.routing_table_len().unwrap() >= 1);
+        remove_test_files(name, 3);
+    }
+
+    #[test]
+    fn test_announced_peers_are_found() {
+        let name = "test_dht_announced_peers_are_found";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let first = start_test_node(name, 0, vec![], &logger);
+        let first_addr = loopback_addr(&first);
+        let second = start_test_node(name, 1, vec![first_addr.clone()], &logger);
+        let third = start_test_node(name, 2, vec![first_addr], &logger);
+        let info_hash = NodeId::random();
+
+        assert!(second.announce(info_hash, 7000).unwrap().is_empty());
+        let peers = third.get_peers(info_hash).unwrap();
+
+        assert_eq!(peers, vec!["127.0.0.1:7000".parse().unwrap()]);
+        remove_test_files(name, 3);
+    }
+
+    #[test]
+    fn test_announce_with_bad_token_is_rejected() {
+        let name = "test_dht_announce_with_bad_token_is_rejected";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let first = start_test_node(name, 0, vec![], &logger);
+        let second = start_test_node(name, 1, vec![], &logger);
+        let info_hash = NodeId::random();
+
+        let result = second.query(
+            loopback_addr(&first).parse().unwrap(),
+            Query::AnnouncePeer {
+                info_hash,
+                port: 7000,
+                implied_port: false,
+                token: b"bad token".to_vec(),
+            },
+        );
+
+        assert!(matches!(
+            result,
+            Err(DhtError::ErrorResponse(PROTOCOL_ERROR, _))
+        ));
+        assert!(first.stored_peers(&info_hash).unwrap().is_empty());
+        remove_test_files(name, 2);
+    }
+
+    #[test]
+    fn test_routing_table_is_persisted() {
+        let name = "test_dht_routing_table_is_persisted";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let first = start_test_node(name, 0, vec![], &logger);
+        let second = start_test_node(name, 1, vec![loopback_addr(&first)], &logger);
+
+        second.save_routing_table().unwrap();
+        let routing_table = RoutingTable::load(&routing_table_path(name, 1)).unwrap();
+
+        assert_eq!(routing_table.own_id(), second.own_id().unwrap());
+        assert_eq!(routing_table.nodes()[0].id, first.own_id().unwrap());
+        remove_test_files(name, 2);
+    }
+
+    #[test]
+    fn test_unknown_query_gets_an_error() {
+        let name = "test_dht_unknown_query_gets_an_error";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let node = start_test_node(name, 0, vec![], &logger);
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket
+            .set_read_timeout(Some(Duration::from_secs(1)))
+            .unwrap();
+
+        socket
+            .send_to(
+                b"d1:ad2:id20:abcdefghij0123456789e1:q4:vote1:t2:aa1:y1:qe",
+                loopback_addr(&node),
+            )
+            .unwrap();
+        let mut buffer = [0; 1024];
+        let (size, _) = socket.recv_from(&mut buffer).unwrap();
+
+        assert!(matches!(
+            KrpcMessage::from_bytes(&buffer[..size]).unwrap().body,
+            KrpcBody::Error {
+                code: METHOD_UNKNOWN,
+                ..
+            }
+        ));
+        remove_test_files(name, 1);
+    }
+
+    // Auxiliary functions
+
+    fn routing_table_path(name: &str, index: usize) -> String {
+        format!("./{}_routing_table_{}", name, index)
+    }
+
+    fn start_test_node(
+        name: &str,
+        index: usize,
+        bootstrap_nodes: Vec<String>,
+        logger: &Logger,
+    ) -> Arc<DhtNode> {
+        // A table left by a previous failed run would add nodes that no longer exist.
+        let _ = fs::remove_file(routing_table_path(name, index));
+        DhtNode::start(
+            0,
+            bootstrap_nodes,
+            routing_table_path(name, index),
+            logger.new_sender(),
+        )
+        .unwrap()
+    }
+
+    fn loopback_addr(node: &DhtNode) -> String {
+        format!("127.0.0.1:{}", node.local_addr().unwrap().port())
+    }
+
+    fn remove_test_files(name: &str, nodes: usize) {
+        for index in 0..nodes {
+            let _ = fs::remove_file(routing_table_path(name, index));
+        }
+        fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+}
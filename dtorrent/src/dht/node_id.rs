@@ -0,0 +1,87 @@
+use rand::Rng;
+use std::fmt::{self, Write};
+
+/// Length in bytes of node IDs and info hashes.
+pub const ID_LENGTH: usize = 20;
+
+/// ID of a DHT node, a 160-bit number in the same space as the info hashes.
+///
+/// The distance between two IDs is their XOR, compared as an unsigned integer.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub [u8; ID_LENGTH]);
+
+impl NodeId {
+    /// Creates a random `NodeId`.
+    pub fn random() -> Self {
+        Self(rand::thread_rng().gen())
+    }
+
+    /// Builds a `NodeId` from a slice of 20 bytes, or `None` if the slice has another length.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        <[u8; ID_LENGTH]>::try_from(bytes).ok().map(Self)
+    }
+
+    /// Returns the XOR distance to another ID.
+    pub fn distance(&self, other: &NodeId) -> [u8; ID_LENGTH] {
+        let mut distance = [0; ID_LENGTH];
+        for (i, byte) in distance.iter_mut().enumerate() {
+            *byte = self.0[i] ^ other.0[i];
+        }
+        distance
+    }
+
+    /// Returns the index of the bucket of the routing table where another ID belongs, the number of leading bits both IDs share.
+    ///
+    /// It returns `None` for the same ID.
+    pub fn bucket_index(&self, other: &NodeId) -> Option<usize> {
+        let distance = self.distance(other);
+        distance
+            .iter()
+            .position(|byte| *byte != 0)
+            .map(|i| i * 8 + distance[i].leading_zeros() as usize)
+    }
+
+    /// Returns the ID as bytes.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        self.0.to_vec()
+    }
+}
+
+impl fmt::Debug for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut hex_string = String::with_capacity(ID_LENGTH * 2);
+        for b in self.0 {
+            write!(&mut hex_string, "{:02x}", b)?;
+        }
+        write!(f, "NodeId({})", hex_string)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distance_is_symmetric() {
+        let a = NodeId::random();
+        let b = NodeId::random();
+
+        assert_eq!(a.distance(&b), b.distance(&a));
+        assert_eq!(a.distance(&a), [0; ID_LENGTH]);
+    }
+
+    #[test]
+    fn test_bucket_index() {
+        let own_id = NodeId([0; ID_LENGTH]);
+        let mut other = [0; ID_LENGTH];
+
+        other[0] = 0x80;
+        assert_eq!(own_id.bucket_index(&NodeId(other)), Some(0));
+        other[0] = 0x01;
+        assert_eq!(own_id.bucket_index(&NodeId(other)), Some(7));
+        other[0] = 0;
+        other[19] = 0x01;
+        assert_eq!(own_id.bucket_index(&NodeId(other)), Some(159));
+        assert_eq!(own_id.bucket_index(&own_id), None);
+    }
+}
@@ -0,0 +1,279 @@
+use bencoder::bencode::{Bencode, BencodeError, ToBencode};
+use std::{collections::BTreeMap, fs, io, time::Instant};
+
+use super::{
+    krpc::{KrpcMessage, NodeInfo},
+    node_id::{NodeId, ID_LENGTH},
+};
+
+/// Maximum number of nodes of each bucket, the `K` of Kademlia.
+pub const BUCKET_SIZE: usize = 8;
+
+/// Number of consecutive queries a node can fail before it is removed from the table.
+const MAX_FAILED_QUERIES: u32 = 2;
+
+/// Posible `RoutingTable` errors.
+#[derive(Debug)]
+pub enum RoutingTableError {
+    IoError(io::Error),
+    DecodeError(BencodeError),
+    InvalidFile,
+}
+
+/// A node of the routing table, with the time it was last heard from and the number of queries it failed since then.
+#[derive(Debug, Clone)]
+struct Node {
+    info: NodeInfo,
+    last_seen: Instant,
+    failed_queries: u32,
+}
+
+/// Routing table of a DHT node (BEP 5), with the contact information of other nodes.
+///
+/// There is a bucket for each length of the prefix a node ID shares with our own ID, each holding up to `BUCKET_SIZE` nodes.
+/// So the table knows many nodes close to our ID and a few of the rest of the space.
+///
+/// When a bucket is full, new nodes only replace nodes that failed to answer our queries.
+///
+/// To create a new empty `RoutingTable` use the method builder `new()`, or `load()` to read one saved with `save()`.
+#[derive(Debug)]
+pub struct RoutingTable {
+    own_id: NodeId,
+    buckets: Vec<Vec<Node>>,
+}
+
+impl RoutingTable {
+    /// Creates an empty `RoutingTable` for a node with the given ID.
+    pub fn new(own_id: NodeId) -> Self {
+        Self {
+            own_id,
+            buckets: vec![vec![]; ID_LENGTH * 8],
+        }
+    }
+
+    /// Returns the ID of our node.
+    pub fn own_id(&self) -> NodeId {
+        self.own_id
+    }
+
+    /// Adds a node that we heard from, or refreshes it if it is already in the table.
+    ///
+    /// Returns `false` if the node was not added because its bucket is full of good nodes.
+    pub fn insert(&mut self, info: NodeInfo) -> bool {
+        let index = match self.own_id.bucket_index(&info.id) {
+            Some(index) => index,
+            None => return false,
+        };
+        let bucket = &mut self.buckets[index];
+        let node = Node {
+            info,
+            last_seen: Instant::now(),
+            failed_queries: 0,
+        };
+
+        if let Some(position) = bucket.iter().position(|node| node.info.id == info.id) {
+            bucket.remove(position);
+            bucket.push(node);
+            return true;
+        }
+        if bucket.len() >= BUCKET_SIZE {
+            match bucket.iter().position(|node| node.failed_queries > 0) {
+                Some(position) => {
+                    bucket.remove(position);
+                }
+                None => return false,
+            }
+        }
+        bucket.push(node);
+        true
+    }
+
+    /// Records that a node did not answer a query, removing it after `MAX_FAILED_QUERIES`.
+    pub fn query_failed(&mut self, id: &NodeId) {
+        if let Some(bucket) = self
+            .own_id
+            .bucket_index(id)
+            .map(|index| &mut self.buckets[index])
+        {
+            bucket.retain_mut(|node| {
+                if node.info.id == *id {
+                    node.failed_queries += 1;
+                }
+                node.failed_queries < MAX_FAILED_QUERIES
+            });
+        }
+    }
+
+    /// Returns up to `count` nodes of the table, the closest to the target first.
+    pub fn closest(&self, target: &NodeId, count: usize) -> Vec<NodeInfo> {
+        let mut nodes = self.nodes();
+        nodes.sort_by_key(|node| node.id.distance(target));
+        nodes.truncate(count);
+        nodes
+    }
+
+    /// Returns all the nodes of the table.
+    pub fn nodes(&self) -> Vec<NodeInfo> {
+        self.buckets
+            .iter()
+            .flatten()
+            .map(|node| node.info)
+            .collect()
+    }
+
+    /// Returns the nodes that were not heard from since the given instant, to check if they are still alive.
+    pub fn nodes_not_seen_since(&self, instant: Instant) -> Vec<NodeInfo> {
+        self.buckets
+            .iter()
+            .flatten()
+            .filter(|node| node.last_seen < instant)
+            .map(|node| node.info)
+            .collect()
+    }
+
+    /// Returns the number of nodes in the table.
+    pub fn len(&self) -> usize {
+        self.buckets.iter().map(Vec::len).sum()
+    }
+
+    /// Returns `true` if the table has no nodes.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Writes our ID and the nodes of the table to a file, as a bencoded dict with the nodes in compact format.
+    ///
+    /// The table is written to a temporary file that then replaces the previous one, so an interrupted save does not lose it.
+    ///
+    /// It returns a `RoutingTableError` if the file could not be written.
+    pub fn save(&self, path: &str) -> Result<(), RoutingTableError> {
+        let temporary_path = format!("{}.tmp", path);
+        fs::write(&temporary_path, Bencode::encode(self)).map_err(RoutingTableError::IoError)?;
+        fs::rename(temporary_path, path).map_err(RoutingTableError::IoError)
+    }
+
+    /// Reads a routing table saved with `save()`, keeping our ID from the previous run.
+    ///
+    /// It returns a `RoutingTableError` if:
+    /// - The file could not be read.
+    /// - The file is not valid bencode or does not contain a valid ID.
+    pub fn load(path: &str) -> Result<Self, RoutingTableError> {
+        let bytes = fs::read(path).map_err(RoutingTableError::IoError)?;
+        let d = match Bencode::decode(&bytes).map_err(RoutingTableError::DecodeError)? {
+            Bencode::BDict(d) => d,
+            _ => return Err(RoutingTableError::InvalidFile),
+        };
+
+        let own_id = match d.get(b"id".as_slice()) {
+            Some(Bencode::BString(id)) => {
+                NodeId::from_bytes(id).ok_or(RoutingTableError::InvalidFile)?
+            }
+            _ => return Err(RoutingTableError::InvalidFile),
+        };
+        let mut routing_table = Self::new(own_id);
+        if let Some(Bencode::BString(nodes)) = d.get(b"nodes".as_slice()) {
+            for node in KrpcMessage::create_nodes(nodes) {
+                routing_table.insert(node);
+            }
+        }
+        Ok(routing_table)
+    }
+}
+
+impl ToBencode for RoutingTable {
+    fn to_bencode(&self) -> Bencode {
+        let mut d = BTreeMap::new();
+        d.insert(b"id".to_vec(), self.own_id.as_bytes().to_bencode());
+        d.insert(
+            b"nodes".to_vec(),
+            KrpcMessage::compact_nodes(&self.nodes()).to_bencode(),
+        );
+        Bencode::BDict(d)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_insert_and_closest() {
+        let mut routing_table = RoutingTable::new(create_test_id(0x00));
+        routing_table.insert(create_test_node(0x80, 1));
+        routing_table.insert(create_test_node(0x40, 2));
+        routing_table.insert(create_test_node(0x01, 3));
+
+        let closest = routing_table.closest(&create_test_id(0x41), 2);
+
+        assert_eq!(routing_table.len(), 3);
+        assert_eq!(
+            closest,
+            vec![create_test_node(0x40, 2), create_test_node(0x01, 3)]
+        );
+    }
+
+    #[test]
+    fn test_own_id_is_not_inserted() {
+        let mut routing_table = RoutingTable::new(create_test_id(0x00));
+
+        assert!(!routing_table.insert(create_test_node(0x00, 1)));
+        assert!(routing_table.is_empty());
+    }
+
+    #[test]
+    fn test_full_bucket_only_replaces_failing_nodes() {
+        let mut routing_table = RoutingTable::new(create_test_id(0x00));
+        for i in 0..BUCKET_SIZE as u8 {
+            assert!(routing_table.insert(create_test_node(0x80 + i, i)));
+        }
+
+        assert!(!routing_table.insert(create_test_node(0xf0, 100)));
+
+        routing_table.query_failed(&create_test_id(0x80));
+        assert!(routing_table.insert(create_test_node(0xf0, 100)));
+        assert_eq!(routing_table.len(), BUCKET_SIZE);
+        assert!(!routing_table.nodes().contains(&create_test_node(0x80, 0)));
+    }
+
+    #[test]
+    fn test_nodes_are_removed_after_failing_queries() {
+        let mut routing_table = RoutingTable::new(create_test_id(0x00));
+        routing_table.insert(create_test_node(0x80, 1));
+
+        routing_table.query_failed(&create_test_id(0x80));
+        assert_eq!(routing_table.len(), 1);
+        routing_table.query_failed(&create_test_id(0x80));
+        assert!(routing_table.is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let path = "./test_save_and_load_routing_table";
+        let mut routing_table = RoutingTable::new(NodeId::random());
+        routing_table.insert(create_test_node(0x80, 1));
+        routing_table.insert(create_test_node(0x40, 2));
+
+        routing_table.save(path).unwrap();
+        let loaded = RoutingTable::load(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(loaded.own_id(), routing_table.own_id());
+        assert_eq!(loaded.len(), 2);
+        assert!(loaded.nodes().contains(&create_test_node(0x40, 2)));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_id(first_byte: u8) -> NodeId {
+        let mut id = [0; ID_LENGTH];
+        id[0] = first_byte;
+        NodeId(id)
+    }
+
+    fn create_test_node(first_byte: u8, host: u8) -> NodeInfo {
+        NodeInfo {
+            id: create_test_id(first_byte),
+            addr: format!("10.0.0.{}:6881", host).parse().unwrap(),
+        }
+    }
+}
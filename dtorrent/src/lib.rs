@@ -1,10 +1,16 @@
+pub mod bandwidth;
 pub mod bt_client;
 pub mod bt_server;
 pub mod config;
+pub mod dht;
 pub mod peer;
+pub mod reactor;
+pub mod rpc;
 pub mod statistics;
 pub mod storage_manager;
 pub mod torrent_handler;
 pub mod torrent_parser;
 pub mod tracker;
+#[cfg(feature = "gui")]
 pub mod ui;
+pub mod utp;
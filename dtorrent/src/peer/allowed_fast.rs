@@ -0,0 +1,69 @@
+use sha1::{Digest, Sha1};
+use std::net::Ipv4Addr;
+
+/// Number of pieces a peer can download from us while it is choked.
+pub const ALLOWED_FAST_SET_SIZE: usize = 10;
+
+/// Generates the allowed fast set of a peer with the canonical algorithm of the fast extension (BEP 6).
+///
+/// The set depends only on the /24 network of the peer and the info hash, so a peer can not get more pieces by reconnecting with another port or address of its network.
+///
+/// It returns up to `size` different piece indices, or all the pieces if the torrent has less than `size`.
+pub fn allowed_fast_set(
+    ip: Ipv4Addr,
+    info_hash: &[u8],
+    pieces_count: u32,
+    size: usize,
+) -> Vec<u32> {
+    let mut allowed_fast = Vec::with_capacity(size);
+    if pieces_count == 0 {
+        return allowed_fast;
+    }
+    let size = size.min(pieces_count as usize);
+
+    let mut x = (u32::from(ip) & 0xFFFFFF00).to_be_bytes().to_vec();
+    x.extend(info_hash);
+    while allowed_fast.len() < size {
+        x = Sha1::digest(&x).to_vec();
+        for chunk in x.chunks_exact(4) {
+            if allowed_fast.len() >= size {
+                break;
+            }
+            let y = u32::from_be_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+            let index = y % pieces_count;
+            if !allowed_fast.contains(&index) {
+                allowed_fast.push(index);
+            }
+        }
+    }
+    allowed_fast
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_allowed_fast_set_of_the_specification() {
+        let ip = Ipv4Addr::new(80, 4, 4, 200);
+        let info_hash = [0xaa; 20];
+
+        assert_eq!(
+            allowed_fast_set(ip, &info_hash, 1313, 7),
+            vec![1059, 431, 808, 1217, 287, 376, 1188]
+        );
+        assert_eq!(
+            allowed_fast_set(ip, &info_hash, 1313, 9),
+            vec![1059, 431, 808, 1217, 287, 376, 1188, 353, 508]
+        );
+    }
+
+    #[test]
+    fn test_allowed_fast_set_of_small_torrent() {
+        let mut allowed_fast = allowed_fast_set(Ipv4Addr::LOCALHOST, &[0xaa; 20], 3, 10);
+        allowed_fast.sort();
+
+        assert_eq!(allowed_fast, vec![0, 1, 2]);
+        assert!(allowed_fast_set(Ipv4Addr::LOCALHOST, &[0xaa; 20], 0, 10).is_empty());
+    }
+}
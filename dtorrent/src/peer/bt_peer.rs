@@ -1,9 +1,9 @@
 use bencoder::bencode::Bencode;
 use std::io::Read;
 use std::io::Write;
-use std::net::TcpStream;
+use std::net::{IpAddr, SocketAddr};
 
-use super::handshake::Handshake;
+use super::{handshake::Handshake, peer_stream::PeerStream};
 
 /// `BtPeer` struct containing individual BtPeer information.
 ///
@@ -11,9 +11,11 @@ use super::handshake::Handshake;
 #[derive(Debug, Clone)]
 pub struct BtPeer {
     pub peer_id: Option<Vec<u8>>,
-    pub ip: String,
+    pub ip: IpAddr,
     pub port: i64,
     pub info_hash: Option<Vec<u8>>,
+    pub supports_extension_protocol: bool,
+    pub supports_fast_extension: bool,
 }
 
 impl PartialEq for BtPeer {
@@ -42,13 +44,15 @@ pub enum BtPeerError {
 }
 
 impl BtPeer {
-    /// Builds a new `BtPeer` decoding a bencoded Vec<u8> cointaining the BtPeer information.
-    pub fn new(ip: String, port: i64) -> Self {
+    /// Builds a new `BtPeer` from an **ip** and a **port** passed by parameters.
+    pub fn new(ip: IpAddr, port: i64) -> Self {
         Self {
             peer_id: None,
             ip,
             port,
             info_hash: None,
+            supports_extension_protocol: false,
+            supports_fast_extension: false,
         }
     }
 
@@ -62,7 +66,7 @@ impl BtPeer {
     /// - The bencoded peer is not a Dict.
     pub fn from(bencode: Bencode) -> Result<BtPeer, BtPeerError> {
         let mut peer_id: Vec<u8> = Vec::new();
-        let mut ip: String = String::new();
+        let mut ip = None;
         let mut port: i64 = 0;
 
         let d = match bencode {
@@ -74,7 +78,7 @@ impl BtPeer {
             if k == b"peer id" {
                 peer_id = Self::create_peer_id(v)?;
             } else if k == b"ip" {
-                ip = Self::create_ip(v)?;
+                ip = Some(Self::create_ip(v)?);
             } else if k == b"port" {
                 port = Self::create_port(v)?;
             }
@@ -82,9 +86,11 @@ impl BtPeer {
 
         Ok(BtPeer {
             peer_id: Some(peer_id),
-            ip,
+            ip: ip.ok_or(BtPeerError::InvalidIp)?,
             port,
             info_hash: None,
+            supports_extension_protocol: false,
+            supports_fast_extension: false,
         })
     }
 
@@ -97,7 +103,7 @@ impl BtPeer {
         Ok(peer_id)
     }
 
-    fn create_ip(bencode: &Bencode) -> Result<String, BtPeerError> {
+    fn create_ip(bencode: &Bencode) -> Result<IpAddr, BtPeerError> {
         let ip = match bencode {
             Bencode::BString(s) => s,
             _ => return Err(BtPeerError::InvalidIp),
@@ -108,7 +114,7 @@ impl BtPeer {
             Err(_) => return Err(BtPeerError::InvalidIp),
         };
 
-        Ok(ip)
+        ip.parse().map_err(|_| BtPeerError::InvalidIp)
     }
 
     fn create_port(bencode: &Bencode) -> Result<i64, BtPeerError> {
@@ -120,10 +126,15 @@ impl BtPeer {
         Ok(port)
     }
 
+    /// Returns the socket address of the peer.
+    pub fn socket_addr(&self) -> SocketAddr {
+        SocketAddr::new(self.ip, self.port as u16)
+    }
+
     /// Reads a handshake from the peer and returns the info hash.
     ///
     /// It returns an error if the handshake could not be read or the handshake was not successful.
-    pub fn receive_handshake(&mut self, stream: &mut TcpStream) -> Result<Vec<u8>, BtPeerError> {
+    pub fn receive_handshake(&mut self, stream: &mut PeerStream) -> Result<Vec<u8>, BtPeerError> {
         let mut buffer = [0; 68];
         stream
             .read_exact(&mut buffer)
@@ -132,6 +143,8 @@ impl BtPeer {
         let handshake = Handshake::from_bytes(&buffer).map_err(|_| BtPeerError::HandshakeError)?;
 
         self.info_hash = Some(handshake.info_hash.clone());
+        self.supports_extension_protocol = handshake.supports_extension_protocol();
+        self.supports_fast_extension = handshake.supports_fast_extension();
         self.peer_id = Some(handshake.peer_id);
 
         Ok(handshake.info_hash)
@@ -142,7 +155,7 @@ impl BtPeer {
     /// It returns an error if the handshake could not be sent or the handshake was not successful.
     pub fn send_handshake(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
         info_hash: Vec<u8>,
         client_peer_id: String,
     ) -> Result<(), BtPeerError> {
@@ -171,16 +184,41 @@ mod tests {
         let bt_peer = BtPeer::from(bencode).unwrap();
 
         assert_eq!(bt_peer.peer_id, Some(b"peer id".to_vec()));
-        assert_eq!(bt_peer.ip, "127.0.0.1");
+        assert_eq!(bt_peer.ip, "127.0.0.1".parse::<IpAddr>().unwrap());
         assert_eq!(bt_peer.port, 6868);
     }
 
+    #[test]
+    fn test_from_bt_peer_ipv6() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"ip".to_vec(), Bencode::BString(b"2001:db8::1".to_vec()));
+        dict.insert(b"port".to_vec(), Bencode::BNumber(6868));
+
+        let bt_peer = BtPeer::from(Bencode::BDict(dict)).unwrap();
+
+        assert_eq!(bt_peer.ip, "2001:db8::1".parse::<IpAddr>().unwrap());
+        assert_eq!(bt_peer.socket_addr().to_string(), "[2001:db8::1]:6868");
+    }
+
+    #[test]
+    fn test_from_bt_peer_invalid_ip() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"ip".to_vec(), Bencode::BString(b"not an ip".to_vec()));
+        dict.insert(b"port".to_vec(), Bencode::BNumber(6868));
+
+        assert!(matches!(
+            BtPeer::from(Bencode::BDict(dict)),
+            Err(BtPeerError::InvalidIp)
+        ));
+    }
+
     #[test]
     fn test_new_peer() {
-        let bt_peer = BtPeer::new("127.0.0.1".to_string(), 6868);
+        let bt_peer = BtPeer::new("127.0.0.1".parse().unwrap(), 6868);
 
         assert_eq!(bt_peer.peer_id, None);
-        assert_eq!(bt_peer.ip, "127.0.0.1");
+        assert_eq!(bt_peer.ip, "127.0.0.1".parse::<IpAddr>().unwrap());
         assert_eq!(bt_peer.port, 6868);
+        assert_eq!(bt_peer.socket_addr().to_string(), "127.0.0.1:6868");
     }
 }
@@ -0,0 +1,213 @@
+use rand::Rng;
+
+/// Length in bytes of the public keys and the shared secret.
+pub const KEY_LENGTH: usize = 96;
+
+/// Length in bytes of the private keys, 160 bits are enough for the security of the 768-bit group.
+const PRIVATE_KEY_LENGTH: usize = 20;
+
+/// Number of 32-bit limbs of a `BigNum`, one more than the 768 bits of the keys so additions do not overflow.
+const LIMBS: usize = KEY_LENGTH / 4 + 1;
+
+/// The 768-bit prime of the Diffie-Hellman group of Message Stream Encryption.
+const PRIME: &str = "FFFFFFFFFFFFFFFFC90FDAA22168C234C4C6628B80DC1CD129024E088A67CC74020BBEA63B139B22514A08798E3404DDEF9519B3CD3A431B302B0A6DF25F14374FE1356D6D51C245E485B576625E7EC6F44C42E9A63A36210000000000090563";
+
+/// The generator of the Diffie-Hellman group.
+const GENERATOR: u32 = 2;
+
+/// Unsigned integer of up to 800 bits, stored in little endian 32-bit limbs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BigNum([u32; LIMBS]);
+
+impl BigNum {
+    fn from_u32(value: u32) -> Self {
+        let mut limbs = [0; LIMBS];
+        limbs[0] = value;
+        Self(limbs)
+    }
+
+    /// Builds a `BigNum` from big endian bytes, ignoring the bytes that do not fit.
+    fn from_bytes(bytes: &[u8]) -> Self {
+        let mut limbs = [0; LIMBS];
+        for (i, byte) in bytes.iter().rev().take(KEY_LENGTH).enumerate() {
+            limbs[i / 4] |= (*byte as u32) << (8 * (i % 4));
+        }
+        Self(limbs)
+    }
+
+    fn from_hex(hex: &str) -> Self {
+        let bytes: Vec<u8> = (0..hex.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0))
+            .collect();
+        Self::from_bytes(&bytes)
+    }
+
+    /// Returns the lower 768 bits as big endian bytes.
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = vec![0; KEY_LENGTH];
+        for (i, byte) in bytes.iter_mut().rev().enumerate() {
+            *byte = (self.0[i / 4] >> (8 * (i % 4))) as u8;
+        }
+        bytes
+    }
+
+    fn bits(&self) -> usize {
+        match self.0.iter().rposition(|limb| *limb != 0) {
+            Some(i) => i * 32 + 32 - self.0[i].leading_zeros() as usize,
+            None => 0,
+        }
+    }
+
+    fn bit(&self, index: usize) -> bool {
+        (self.0[index / 32] >> (index % 32)) & 1 == 1
+    }
+
+    fn add(&mut self, other: &BigNum) {
+        let mut carry = 0;
+        for (limb, other) in self.0.iter_mut().zip(other.0) {
+            let sum = *limb as u64 + other as u64 + carry;
+            *limb = sum as u32;
+            carry = sum >> 32;
+        }
+    }
+
+    fn sub(&mut self, other: &BigNum) {
+        let mut borrow = 0;
+        for (limb, other) in self.0.iter_mut().zip(other.0) {
+            let difference = *limb as i64 - other as i64 - borrow;
+            *limb = difference as u32;
+            borrow = (difference < 0) as i64;
+        }
+    }
+
+    fn double(&mut self) {
+        let mut carry = 0;
+        for limb in self.0.iter_mut() {
+            let next_carry = *limb >> 31;
+            *limb = (*limb << 1) | carry;
+            carry = next_carry;
+        }
+    }
+
+    fn greater_or_equal(&self, other: &BigNum) -> bool {
+        for (limb, other) in self.0.iter().zip(other.0).rev() {
+            if *limb != other {
+                return *limb > other;
+            }
+        }
+        true
+    }
+
+    /// Reduces a number lower than twice the modulus.
+    fn reduce(&mut self, modulus: &BigNum) {
+        if self.greater_or_equal(modulus) {
+            self.sub(modulus);
+        }
+    }
+
+    /// Returns `self * other mod modulus`, both factors must be lower than the modulus.
+    fn mul_mod(&self, other: &BigNum, modulus: &BigNum) -> BigNum {
+        let mut result = BigNum::from_u32(0);
+        for i in (0..other.bits()).rev() {
+            result.double();
+            result.reduce(modulus);
+            if other.bit(i) {
+                result.add(self);
+                result.reduce(modulus);
+            }
+        }
+        result
+    }
+
+    /// Returns `self ^ exponent mod modulus`.
+    fn pow_mod(&self, exponent: &BigNum, modulus: &BigNum) -> BigNum {
+        let mut base = *self;
+        base.reduce(modulus);
+        let mut result = BigNum::from_u32(1);
+        for i in (0..exponent.bits()).rev() {
+            result = result.mul_mod(&result, modulus);
+            if exponent.bit(i) {
+                result = result.mul_mod(&base, modulus);
+            }
+        }
+        result
+    }
+}
+
+/// Diffie-Hellman key pair of one side of a Message Stream Encryption handshake.
+///
+/// To create a new random `KeyPair`, use KeyPair::generate().
+pub struct KeyPair {
+    private_key: BigNum,
+    public_key: Vec<u8>,
+}
+
+impl KeyPair {
+    /// Generates a random private key and its public key.
+    pub fn generate() -> Self {
+        let private_key = BigNum::from_bytes(&rand::thread_rng().gen::<[u8; PRIVATE_KEY_LENGTH]>());
+        let public_key = BigNum::from_u32(GENERATOR)
+            .pow_mod(&private_key, &BigNum::from_hex(PRIME))
+            .to_bytes();
+        Self {
+            private_key,
+            public_key,
+        }
+    }
+
+    /// Returns the public key to send to the other side, as 96 big endian bytes.
+    pub fn public_key(&self) -> &[u8] {
+        &self.public_key
+    }
+
+    /// Returns the secret shared with the side that sent the given public key, as 96 big endian bytes.
+    pub fn shared_secret(&self, other_public_key: &[u8]) -> Vec<u8> {
+        BigNum::from_bytes(other_public_key)
+            .pow_mod(&self.private_key, &BigNum::from_hex(PRIME))
+            .to_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pow_mod() {
+        let result = BigNum::from_u32(4).pow_mod(&BigNum::from_u32(13), &BigNum::from_u32(497));
+        assert_eq!(result, BigNum::from_u32(445));
+
+        // Fermat's little theorem: 2^(p-1) = 1 mod p.
+        let prime = BigNum::from_hex(PRIME);
+        let mut exponent = prime;
+        exponent.sub(&BigNum::from_u32(1));
+        assert_eq!(
+            BigNum::from_u32(GENERATOR).pow_mod(&exponent, &prime),
+            BigNum::from_u32(1)
+        );
+    }
+
+    #[test]
+    fn test_bytes_conversion() {
+        let mut bytes = vec![0; KEY_LENGTH];
+        bytes[0] = 0x12;
+        bytes[95] = 0x34;
+
+        assert_eq!(BigNum::from_bytes(&bytes).to_bytes(), bytes);
+        assert_eq!(BigNum::from_bytes(&[1, 0]), BigNum::from_u32(256));
+    }
+
+    #[test]
+    fn test_shared_secret_is_the_same_for_both_sides() {
+        let a = KeyPair::generate();
+        let b = KeyPair::generate();
+
+        assert_eq!(a.public_key().len(), KEY_LENGTH);
+        assert_ne!(a.public_key(), b.public_key());
+        assert_eq!(
+            a.shared_secret(b.public_key()),
+            b.shared_secret(a.public_key())
+        );
+    }
+}
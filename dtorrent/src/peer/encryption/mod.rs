@@ -0,0 +1,30 @@
+use std::str::FromStr;
+
+mod dh;
+pub mod mse;
+pub mod rc4;
+
+/// How the connections with peers are encrypted with Message Stream Encryption.
+///
+/// - `Plaintext`: connections are never encrypted, and encrypted incoming connections are refused,
+/// - `PreferEncrypted`: connections are encrypted when the peer supports it, falling back to plaintext otherwise,
+/// - `RequireEncrypted`: only encrypted connections are made and accepted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncryptionMode {
+    Plaintext,
+    PreferEncrypted,
+    RequireEncrypted,
+}
+
+impl FromStr for EncryptionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "plaintext" => Ok(EncryptionMode::Plaintext),
+            "prefer_encrypted" => Ok(EncryptionMode::PreferEncrypted),
+            "require_encrypted" => Ok(EncryptionMode::RequireEncrypted),
+            _ => Err(format!("Invalid encryption mode: {}", s)),
+        }
+    }
+}
@@ -0,0 +1,486 @@
+use rand::Rng;
+use sha1::{Digest, Sha1};
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    sync::Arc,
+    time::Duration,
+};
+
+use super::{
+    dh::{KeyPair, KEY_LENGTH},
+    rc4::Rc4,
+    EncryptionMode,
+};
+use crate::{
+    peer::peer_stream::{PeerStream, Transport},
+    utp::socket::UtpSocket,
+};
+
+/// Verification constant, sent encrypted so each side can check the keys of the other.
+const VC: [u8; 8] = [0; 8];
+
+/// Crypto method where only the handshake is obfuscated.
+const CRYPTO_PLAINTEXT: u32 = 0x01;
+/// Crypto method where the whole connection is encrypted with RC4.
+const CRYPTO_RC4: u32 = 0x02;
+
+/// Maximum length of the random paddings.
+const MAX_PAD_LENGTH: usize = 512;
+
+/// Bytes of the RC4 keystream discarded before encrypting, as the first ones leak information about the key.
+const DISCARDED_KEYSTREAM: usize = 1024;
+
+/// Beginning of a plaintext BitTorrent handshake.
+const PLAINTEXT_PROTOCOL_HEADER: &[u8; 20] = b"\x13BitTorrent protocol";
+
+/// Time to wait for a peer to answer a uTP connection before falling back to TCP.
+const UTP_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Posible `Mse` errors.
+#[derive(Debug)]
+pub enum MseError {
+    CouldNotConnect(io::Error),
+    IoError(io::Error),
+    SynchronizationNotFound,
+    UnknownInfoHash,
+    InvalidVerificationConstant,
+    InvalidPadLength,
+    NoCommonCryptoMethod,
+    PlaintextNotAllowed,
+    EncryptionNotAllowed,
+}
+
+/// Connects to a peer, negotiating the encryption of the connection according to the mode.
+///
+/// With `PreferEncrypted`, a new plaintext connection is opened if the peer answered but rejected the encrypted handshake.
+/// A peer that could not be connected or did not answer in time is not tried again.
+/// If a uTP socket is given, the connection is tried over uTP first, falling back to TCP if the peer does not answer.
+///
+/// It returns an error if:
+/// - The connection could not be established.
+/// - The encryption is required and could not be negotiated.
+pub fn connect(
+    addr: SocketAddr,
+    info_hash: &[u8],
+    mode: EncryptionMode,
+    timeout: Duration,
+    utp: &Option<Arc<UtpSocket>>,
+) -> Result<PeerStream, MseError> {
+    match mode {
+        EncryptionMode::Plaintext => Ok(PeerStream::plaintext(open(addr, timeout, utp)?)),
+        EncryptionMode::RequireEncrypted => initiate(open(addr, timeout, utp)?, info_hash, mode),
+        EncryptionMode::PreferEncrypted => {
+            match initiate(open(addr, timeout, utp)?, info_hash, mode) {
+                Err(error) if is_rejection(&error) => {
+                    Ok(PeerStream::plaintext(open(addr, timeout, utp)?))
+                }
+                result => result,
+            }
+        }
+    }
+}
+
+/// Returns true if the error means that the peer rejected the encrypted handshake, like a plaintext only peer that closes the connection.
+///
+/// Timeouts are not rejections, as the peer is probably dead and a plaintext connection would time out too.
+fn is_rejection(error: &MseError) -> bool {
+    match error {
+        MseError::CouldNotConnect(_) => false,
+        MseError::IoError(error) => !matches!(
+            error.kind(),
+            io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+        ),
+        _ => true,
+    }
+}
+
+/// Opens a connection with the given timeout for connecting, reading and writing.
+///
+/// It is opened over uTP if a socket is given and the peer answers, and over TCP otherwise.
+fn open(
+    addr: SocketAddr,
+    timeout: Duration,
+    utp: &Option<Arc<UtpSocket>>,
+) -> Result<Transport, MseError> {
+    let stream = match utp
+        .as_ref()
+        .and_then(|utp| utp.connect(addr, UTP_CONNECT_TIMEOUT.min(timeout)).ok())
+    {
+        Some(stream) => Transport::Utp(stream),
+        None => Transport::Tcp(
+            TcpStream::connect_timeout(&addr, timeout).map_err(MseError::CouldNotConnect)?,
+        ),
+    };
+    stream
+        .set_read_timeout(Some(timeout))
+        .map_err(MseError::CouldNotConnect)?;
+    stream
+        .set_write_timeout(Some(timeout))
+        .map_err(MseError::CouldNotConnect)?;
+    Ok(stream)
+}
+
+/// Performs the encrypted handshake as the side that opened the connection, for the torrent with the given info hash.
+///
+/// Only RC4 is offered to the peer, the BitTorrent handshake must be sent afterwards through the returned stream.
+pub fn initiate(
+    mut stream: Transport,
+    info_hash: &[u8],
+    mode: EncryptionMode,
+) -> Result<PeerStream, MseError> {
+    if mode == EncryptionMode::Plaintext {
+        return Err(MseError::EncryptionNotAllowed);
+    }
+    let key_pair = KeyPair::generate();
+    write(
+        &mut stream,
+        &[key_pair.public_key(), &random_pad()].concat(),
+    )?;
+
+    let mut other_public_key = [0; KEY_LENGTH];
+    read(&mut stream, &mut other_public_key)?;
+    let secret = key_pair.shared_secret(&other_public_key);
+    let (mut encryptor, mut decryptor) = create_ciphers(&secret, info_hash, true);
+
+    let mut message = hash(&[b"req1", &secret]);
+    message.extend(xor(
+        &hash(&[b"req2", info_hash]),
+        &hash(&[b"req3", &secret]),
+    ));
+    let mut encrypted = VC.to_vec();
+    encrypted.extend(CRYPTO_RC4.to_be_bytes());
+    // No padding and no initial payload.
+    encrypted.extend(0u16.to_be_bytes());
+    encrypted.extend(0u16.to_be_bytes());
+    encryptor.apply(&mut encrypted);
+    message.extend(encrypted);
+    write(&mut stream, &message)?;
+
+    // The answer starts after the padding of the peer, at the verification constant.
+    let mut encrypted_vc = VC.to_vec();
+    decryptor.clone().apply(&mut encrypted_vc);
+    synchronize(&mut stream, &encrypted_vc, MAX_PAD_LENGTH + VC.len())?;
+    decryptor.discard(VC.len());
+
+    let mut answer = [0; 6];
+    read(&mut stream, &mut answer)?;
+    decryptor.apply(&mut answer);
+    let crypto_select = u32::from_be_bytes([answer[0], answer[1], answer[2], answer[3]]);
+    let pad_length = u16::from_be_bytes([answer[4], answer[5]]) as usize;
+    if pad_length > MAX_PAD_LENGTH {
+        return Err(MseError::InvalidPadLength);
+    }
+    let mut pad = vec![0; pad_length];
+    read(&mut stream, &mut pad)?;
+    decryptor.apply(&mut pad);
+
+    if crypto_select != CRYPTO_RC4 {
+        return Err(MseError::NoCommonCryptoMethod);
+    }
+    Ok(PeerStream::new(
+        stream,
+        Some((encryptor, decryptor)),
+        vec![],
+    ))
+}
+
+/// Accepts an incoming connection, performing the encrypted handshake if the peer started one.
+///
+/// The info hash of the torrent is found among the given ones, and the BitTorrent handshake can be read afterwards through the returned stream.
+///
+/// It returns an error if:
+/// - The peer sent a plaintext handshake and the encryption is required.
+/// - The peer started an encrypted handshake and the mode is `Plaintext`.
+/// - The encrypted handshake failed or was for an unknown torrent.
+pub fn accept(
+    mut stream: Transport,
+    info_hashes: &[Vec<u8>],
+    mode: EncryptionMode,
+) -> Result<PeerStream, MseError> {
+    let mut header = [0; 20];
+    read(&mut stream, &mut header)?;
+    if &header == PLAINTEXT_PROTOCOL_HEADER {
+        if mode == EncryptionMode::RequireEncrypted {
+            return Err(MseError::PlaintextNotAllowed);
+        }
+        return Ok(PeerStream::new(stream, None, header.to_vec()));
+    }
+    if mode == EncryptionMode::Plaintext {
+        return Err(MseError::EncryptionNotAllowed);
+    }
+
+    let mut other_public_key = header.to_vec();
+    other_public_key.resize(KEY_LENGTH, 0);
+    read(&mut stream, &mut other_public_key[header.len()..])?;
+    let key_pair = KeyPair::generate();
+    write(
+        &mut stream,
+        &[key_pair.public_key(), &random_pad()].concat(),
+    )?;
+    let secret = key_pair.shared_secret(&other_public_key);
+
+    // The request starts after the padding of the peer.
+    synchronize(
+        &mut stream,
+        &hash(&[b"req1", &secret]),
+        MAX_PAD_LENGTH + Sha1::output_size(),
+    )?;
+    let mut skey_hash = [0; 20];
+    read(&mut stream, &mut skey_hash)?;
+    let req3 = hash(&[b"req3", &secret]);
+    let info_hash = info_hashes
+        .iter()
+        .find(|info_hash| xor(&hash(&[b"req2", info_hash]), &req3) == skey_hash)
+        .ok_or(MseError::UnknownInfoHash)?;
+    let (mut encryptor, mut decryptor) = create_ciphers(&secret, info_hash, false);
+
+    let mut request = [0; 14];
+    read(&mut stream, &mut request)?;
+    decryptor.apply(&mut request);
+    if request[..8] != VC {
+        return Err(MseError::InvalidVerificationConstant);
+    }
+    let crypto_provide = u32::from_be_bytes([request[8], request[9], request[10], request[11]]);
+    let pad_length = u16::from_be_bytes([request[12], request[13]]) as usize;
+    if pad_length > MAX_PAD_LENGTH {
+        return Err(MseError::InvalidPadLength);
+    }
+    let mut pad = vec![0; pad_length];
+    read(&mut stream, &mut pad)?;
+    decryptor.apply(&mut pad);
+
+    let mut initial_payload_length = [0; 2];
+    read(&mut stream, &mut initial_payload_length)?;
+    decryptor.apply(&mut initial_payload_length);
+    let mut initial_payload = vec![0; u16::from_be_bytes(initial_payload_length) as usize];
+    read(&mut stream, &mut initial_payload)?;
+    decryptor.apply(&mut initial_payload);
+
+    let crypto_select = if crypto_provide & CRYPTO_RC4 != 0 {
+        CRYPTO_RC4
+    } else if crypto_provide & CRYPTO_PLAINTEXT != 0 && mode != EncryptionMode::RequireEncrypted {
+        CRYPTO_PLAINTEXT
+    } else {
+        return Err(MseError::NoCommonCryptoMethod);
+    };
+
+    let mut answer = VC.to_vec();
+    answer.extend(crypto_select.to_be_bytes());
+    answer.extend(0u16.to_be_bytes());
+    encryptor.apply(&mut answer);
+    write(&mut stream, &answer)?;
+
+    let ciphers = (crypto_select == CRYPTO_RC4).then_some((encryptor, decryptor));
+    Ok(PeerStream::new(stream, ciphers, initial_payload))
+}
+
+/// Creates the RC4 ciphers used to encrypt and decrypt the messages of one side of the connection.
+fn create_ciphers(secret: &[u8], info_hash: &[u8], initiator: bool) -> (Rc4, Rc4) {
+    let mut key_a = Rc4::new(&hash(&[b"keyA", secret, info_hash]));
+    let mut key_b = Rc4::new(&hash(&[b"keyB", secret, info_hash]));
+    key_a.discard(DISCARDED_KEYSTREAM);
+    key_b.discard(DISCARDED_KEYSTREAM);
+    if initiator {
+        (key_a, key_b)
+    } else {
+        (key_b, key_a)
+    }
+}
+
+/// Reads from the stream until the given bytes are found, reading at most `max_length` bytes.
+fn synchronize(stream: &mut Transport, pattern: &[u8], max_length: usize) -> Result<(), MseError> {
+    let mut received = Vec::with_capacity(max_length);
+    let mut byte = [0; 1];
+    while received.len() < max_length {
+        read(stream, &mut byte)?;
+        received.push(byte[0]);
+        if received.ends_with(pattern) {
+            return Ok(());
+        }
+    }
+    Err(MseError::SynchronizationNotFound)
+}
+
+fn hash(parts: &[&[u8]]) -> Vec<u8> {
+    let mut hasher = Sha1::new();
+    for part in parts {
+        hasher.update(part);
+    }
+    hasher.finalize().to_vec()
+}
+
+fn xor(a: &[u8], b: &[u8]) -> Vec<u8> {
+    a.iter().zip(b).map(|(a, b)| a ^ b).collect()
+}
+
+fn random_pad() -> Vec<u8> {
+    let mut rng = rand::thread_rng();
+    (0..rng.gen_range(0..=MAX_PAD_LENGTH))
+        .map(|_| rng.gen())
+        .collect()
+}
+
+fn read(stream: &mut Transport, buf: &mut [u8]) -> Result<(), MseError> {
+    stream.read_exact(buf).map_err(MseError::IoError)
+}
+
+fn write(stream: &mut Transport, buf: &[u8]) -> Result<(), MseError> {
+    stream.write_all(buf).map_err(MseError::IoError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_encrypted_handshake() {
+        let info_hash = vec![0xaa; 20];
+        let (mut client, mut server) = connect_test_peers(
+            EncryptionMode::RequireEncrypted,
+            EncryptionMode::PreferEncrypted,
+            vec![vec![0xbb; 20], info_hash.clone()],
+            info_hash,
+        );
+
+        assert!(client.is_encrypted());
+        assert!(server.is_encrypted());
+        assert_messages_are_exchanged(&mut client, &mut server);
+    }
+
+    #[test]
+    fn test_plaintext_connection_is_accepted() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            stream.write_all(PLAINTEXT_PROTOCOL_HEADER).unwrap();
+            stream
+        });
+        let (stream, _) = listener.accept().unwrap();
+        let mut server =
+            accept(Transport::Tcp(stream), &[], EncryptionMode::PreferEncrypted).unwrap();
+        let mut client = PeerStream::plaintext(Transport::Tcp(client.join().unwrap()));
+
+        let mut header = [0; 20];
+        server.read_exact(&mut header).unwrap();
+        assert_eq!(&header, PLAINTEXT_PROTOCOL_HEADER);
+        assert!(!server.is_encrypted());
+        assert_messages_are_exchanged(&mut client, &mut server);
+    }
+
+    #[test]
+    fn test_unknown_info_hash_is_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let client = thread::spawn(move || {
+            initiate(
+                Transport::Tcp(TcpStream::connect(addr).unwrap()),
+                &[0xaa; 20],
+                EncryptionMode::RequireEncrypted,
+            )
+        });
+        let (stream, _) = listener.accept().unwrap();
+
+        assert!(matches!(
+            accept(
+                Transport::Tcp(stream),
+                &[vec![0xbb; 20]],
+                EncryptionMode::PreferEncrypted
+            ),
+            Err(MseError::UnknownInfoHash)
+        ));
+        assert!(client.join().unwrap().is_err());
+    }
+
+    #[test]
+    fn test_prefer_encrypted_falls_back_to_plaintext() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            // The first connection is refused by a plaintext only peer.
+            let (stream, _) = listener.accept().unwrap();
+            assert!(matches!(
+                accept(
+                    Transport::Tcp(stream),
+                    &[vec![0xaa; 20]],
+                    EncryptionMode::Plaintext
+                ),
+                Err(MseError::EncryptionNotAllowed)
+            ));
+            let (stream, _) = listener.accept().unwrap();
+            accept(
+                Transport::Tcp(stream),
+                &[vec![0xaa; 20]],
+                EncryptionMode::Plaintext,
+            )
+            .unwrap()
+        });
+
+        let mut client = connect(
+            addr,
+            &[0xaa; 20],
+            EncryptionMode::PreferEncrypted,
+            Duration::from_secs(5),
+            &None,
+        )
+        .unwrap();
+        client.write_all(PLAINTEXT_PROTOCOL_HEADER).unwrap();
+        let mut server = server.join().unwrap();
+
+        let mut header = [0; 20];
+        server.read_exact(&mut header).unwrap();
+        assert!(!client.is_encrypted());
+        assert_messages_are_exchanged(&mut client, &mut server);
+    }
+
+    #[test]
+    fn test_prefer_encrypted_does_not_fall_back_on_timeout() {
+        // The peer accepts the connection but never answers.
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let result = connect(
+            addr,
+            &[0xaa; 20],
+            EncryptionMode::PreferEncrypted,
+            Duration::from_millis(200),
+            &None,
+        );
+
+        assert!(matches!(result, Err(MseError::IoError(_))));
+        listener.accept().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        assert!(listener.accept().is_err());
+    }
+
+    // Auxiliary functions
+
+    fn connect_test_peers(
+        client_mode: EncryptionMode,
+        server_mode: EncryptionMode,
+        server_info_hashes: Vec<Vec<u8>>,
+        info_hash: Vec<u8>,
+    ) -> (PeerStream, PeerStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            accept(Transport::Tcp(stream), &server_info_hashes, server_mode).unwrap()
+        });
+        let client = connect(addr, &info_hash, client_mode, Duration::from_secs(5), &None).unwrap();
+        (client, server.join().unwrap())
+    }
+
+    fn assert_messages_are_exchanged(client: &mut PeerStream, server: &mut PeerStream) {
+        client.write_all(b"ping").unwrap();
+        server.write_all(b"pong").unwrap();
+
+        let mut buffer = [0; 4];
+        server.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"ping");
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"pong");
+    }
+}
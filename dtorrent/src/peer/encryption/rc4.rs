@@ -0,0 +1,74 @@
+/// RC4 stream cipher, used to obfuscate the connections negotiated with Message Stream Encryption.
+///
+/// To create a new `Rc4`, use Rc4::new(key).
+#[derive(Clone)]
+pub struct Rc4 {
+    s: [u8; 256],
+    i: u8,
+    j: u8,
+}
+
+impl Rc4 {
+    /// Creates a new `Rc4` initialized with the given key.
+    pub fn new(key: &[u8]) -> Self {
+        let mut s = [0; 256];
+        for (i, byte) in s.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        let mut j: u8 = 0;
+        for i in 0..256 {
+            j = j.wrapping_add(s[i]).wrapping_add(key[i % key.len()]);
+            s.swap(i, j as usize);
+        }
+        Self { s, i: 0, j: 0 }
+    }
+
+    /// Encrypts or decrypts the data in place, advancing the keystream.
+    pub fn apply(&mut self, data: &mut [u8]) {
+        for byte in data.iter_mut() {
+            self.i = self.i.wrapping_add(1);
+            self.j = self.j.wrapping_add(self.s[self.i as usize]);
+            self.s.swap(self.i as usize, self.j as usize);
+            let k = self.s[self.s[self.i as usize].wrapping_add(self.s[self.j as usize]) as usize];
+            *byte ^= k;
+        }
+    }
+
+    /// Discards the next `count` bytes of the keystream.
+    pub fn discard(&mut self, count: usize) {
+        self.apply(&mut vec![0; count]);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rc4_known_vectors() {
+        let mut data = b"Plaintext".to_vec();
+        Rc4::new(b"Key").apply(&mut data);
+        assert_eq!(data, [0xBB, 0xF3, 0x16, 0xE8, 0xD9, 0x40, 0xAF, 0x0A, 0xD3]);
+
+        let mut data = b"Attack at dawn".to_vec();
+        Rc4::new(b"Secret").apply(&mut data);
+        assert_eq!(
+            data,
+            [0x45, 0xA0, 0x1F, 0x64, 0x5F, 0xC3, 0x5B, 0x38, 0x35, 0x52, 0x54, 0x4B, 0x9B, 0xF5]
+        );
+    }
+
+    #[test]
+    fn test_rc4_decrypts_what_it_encrypts() {
+        let mut encryptor = Rc4::new(b"key");
+        let mut decryptor = Rc4::new(b"key");
+        encryptor.discard(1024);
+        decryptor.discard(1024);
+
+        let mut data = b"BitTorrent protocol".to_vec();
+        encryptor.apply(&mut data);
+        assert_ne!(data, b"BitTorrent protocol");
+        decryptor.apply(&mut data);
+        assert_eq!(data, b"BitTorrent protocol");
+    }
+}
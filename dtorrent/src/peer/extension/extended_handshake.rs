@@ -0,0 +1,241 @@
+use bencoder::bencode::{Bencode, BencodeError, ToBencode};
+use std::{
+    collections::{BTreeMap, HashMap},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+};
+
+/// Posible `ExtendedHandshake` errors.
+#[derive(Debug)]
+pub enum ExtendedHandshakeError {
+    DecodeError(BencodeError),
+    NotADict,
+    InvalidExtensions,
+    InvalidClientVersion,
+    InvalidPort,
+    InvalidRequestQueue,
+    InvalidIp,
+    InvalidMetadataSize,
+}
+
+/// Name and version of the client, sent in the `v` field of the handshake.
+pub const CLIENT_VERSION: &str = concat!("DTorrent ", env!("CARGO_PKG_VERSION"));
+
+/// Represents the handshake of the extension protocol (BEP 10), sent as the extended message with ID 0.
+///
+/// - `extensions`: the `m` dictionary, with the ID each side uses to receive the messages of each extension. An ID of 0 disables the extension.
+/// - `client_version`: the `v` field, name and version of the client.
+/// - `port`: the `p` field, the TCP port the client listens on.
+/// - `request_queue`: the `reqq` field, the number of outstanding requests the client accepts.
+/// - `your_ip`: the `yourip` field, the IP address of the receiver as seen by the sender.
+/// - `metadata_size`: the `metadata_size` field, the size in bytes of the info dict of the torrent (BEP 9).
+///
+/// Unknown fields are ignored.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct ExtendedHandshake {
+    pub extensions: HashMap<String, u8>,
+    pub client_version: Option<String>,
+    pub port: Option<u16>,
+    pub request_queue: Option<u32>,
+    pub your_ip: Option<IpAddr>,
+    pub metadata_size: Option<u64>,
+}
+
+impl ExtendedHandshake {
+    /// Parses the payload of an extended handshake message.
+    ///
+    /// It returns an `ExtendedHandshakeError` if:
+    /// - The payload is not valid bencode.
+    /// - The payload is not a dict.
+    /// - One of the known fields has an invalid type or value.
+    pub fn from_bytes(payload: &[u8]) -> Result<Self, ExtendedHandshakeError> {
+        let bencode = Bencode::decode(payload).map_err(ExtendedHandshakeError::DecodeError)?;
+        Self::from(&bencode)
+    }
+
+    /// Builds a new `ExtendedHandshake` from a bencoded dict.
+    pub fn from(bencode: &Bencode) -> Result<Self, ExtendedHandshakeError> {
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(ExtendedHandshakeError::NotADict),
+        };
+
+        let mut handshake = Self::default();
+        for (k, v) in d.iter() {
+            if k == b"m" {
+                handshake.extensions = Self::create_extensions(v)?;
+            } else if k == b"v" {
+                handshake.client_version = Some(Self::create_client_version(v)?);
+            } else if k == b"p" {
+                handshake.port = Some(Self::create_port(v)?);
+            } else if k == b"reqq" {
+                handshake.request_queue = Some(Self::create_request_queue(v)?);
+            } else if k == b"yourip" {
+                handshake.your_ip = Some(Self::create_ip(v)?);
+            } else if k == b"metadata_size" {
+                handshake.metadata_size = Some(Self::create_metadata_size(v)?);
+            }
+        }
+        Ok(handshake)
+    }
+
+    fn create_extensions(bencode: &Bencode) -> Result<HashMap<String, u8>, ExtendedHandshakeError> {
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(ExtendedHandshakeError::InvalidExtensions),
+        };
+
+        d.iter()
+            .map(|(name, id)| {
+                let name = String::from_utf8(name.clone())
+                    .map_err(|_| ExtendedHandshakeError::InvalidExtensions)?;
+                match id {
+                    Bencode::BNumber(id) => u8::try_from(*id)
+                        .map(|id| (name, id))
+                        .map_err(|_| ExtendedHandshakeError::InvalidExtensions),
+                    _ => Err(ExtendedHandshakeError::InvalidExtensions),
+                }
+            })
+            .collect()
+    }
+
+    fn create_client_version(bencode: &Bencode) -> Result<String, ExtendedHandshakeError> {
+        match bencode {
+            Bencode::BString(s) => Ok(String::from_utf8_lossy(s).to_string()),
+            _ => Err(ExtendedHandshakeError::InvalidClientVersion),
+        }
+    }
+
+    fn create_port(bencode: &Bencode) -> Result<u16, ExtendedHandshakeError> {
+        match bencode {
+            Bencode::BNumber(n) => {
+                u16::try_from(*n).map_err(|_| ExtendedHandshakeError::InvalidPort)
+            }
+            _ => Err(ExtendedHandshakeError::InvalidPort),
+        }
+    }
+
+    fn create_request_queue(bencode: &Bencode) -> Result<u32, ExtendedHandshakeError> {
+        match bencode {
+            Bencode::BNumber(n) => {
+                u32::try_from(*n).map_err(|_| ExtendedHandshakeError::InvalidRequestQueue)
+            }
+            _ => Err(ExtendedHandshakeError::InvalidRequestQueue),
+        }
+    }
+
+    fn create_metadata_size(bencode: &Bencode) -> Result<u64, ExtendedHandshakeError> {
+        match bencode {
+            Bencode::BNumber(n) => {
+                u64::try_from(*n).map_err(|_| ExtendedHandshakeError::InvalidMetadataSize)
+            }
+            _ => Err(ExtendedHandshakeError::InvalidMetadataSize),
+        }
+    }
+
+    /// Parses a compact IP address, 4 bytes for IPv4 and 16 bytes for IPv6.
+    fn create_ip(bencode: &Bencode) -> Result<IpAddr, ExtendedHandshakeError> {
+        let bytes = match bencode {
+            Bencode::BString(s) => s,
+            _ => return Err(ExtendedHandshakeError::InvalidIp),
+        };
+
+        if let Ok(octets) = <[u8; 4]>::try_from(bytes.as_slice()) {
+            Ok(IpAddr::V4(Ipv4Addr::from(octets)))
+        } else if let Ok(octets) = <[u8; 16]>::try_from(bytes.as_slice()) {
+            Ok(IpAddr::V6(Ipv6Addr::from(octets)))
+        } else {
+            Err(ExtendedHandshakeError::InvalidIp)
+        }
+    }
+
+    /// Converts the `ExtendedHandshake` to the payload of an extended handshake message.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Bencode::encode(self)
+    }
+}
+
+impl ToBencode for ExtendedHandshake {
+    fn to_bencode(&self) -> Bencode {
+        let mut handshake = BTreeMap::new();
+
+        let extensions = self
+            .extensions
+            .iter()
+            .map(|(name, id)| (name.as_bytes().to_vec(), Bencode::BNumber(*id as i64)))
+            .collect();
+        handshake.insert(b"m".to_vec(), Bencode::BDict(extensions));
+
+        if let Some(client_version) = &self.client_version {
+            handshake.insert(b"v".to_vec(), client_version.to_bencode());
+        }
+        if let Some(port) = self.port {
+            handshake.insert(b"p".to_vec(), port.to_bencode());
+        }
+        if let Some(request_queue) = self.request_queue {
+            handshake.insert(b"reqq".to_vec(), request_queue.to_bencode());
+        }
+        if let Some(your_ip) = self.your_ip {
+            let octets = match your_ip {
+                IpAddr::V4(ip) => ip.octets().to_vec(),
+                IpAddr::V6(ip) => ip.octets().to_vec(),
+            };
+            handshake.insert(b"yourip".to_vec(), octets.to_bencode());
+        }
+        if let Some(metadata_size) = self.metadata_size {
+            handshake.insert(b"metadata_size".to_vec(), metadata_size.to_bencode());
+        }
+        Bencode::BDict(handshake)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_as_bytes() {
+        let handshake = ExtendedHandshake {
+            extensions: HashMap::from([("ut_metadata".to_string(), 1)]),
+            client_version: Some("DTorrent".to_string()),
+            port: Some(6881),
+            request_queue: Some(250),
+            your_ip: Some("127.0.0.1".parse().unwrap()),
+            metadata_size: Some(31235),
+        };
+
+        assert_eq!(
+            handshake.as_bytes(),
+            b"d1:md11:ut_metadatai1ee13:metadata_sizei31235e1:pi6881e4:reqqi250e1:v8:DTorrent6:yourip4:\x7f\x00\x00\x01e"
+        );
+    }
+
+    #[test]
+    fn test_from_bytes() {
+        let payload = b"d1:md11:ut_metadatai3e6:ut_pexi0ee1:pi6881e4:reqqi500e1:v13:\xc2\xb5Torrent 1.26:yourip16:\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x00\x01e";
+
+        let handshake = ExtendedHandshake::from_bytes(payload).unwrap();
+
+        assert_eq!(handshake.extensions.get("ut_metadata"), Some(&3));
+        assert_eq!(handshake.extensions.get("ut_pex"), Some(&0));
+        assert_eq!(handshake.client_version, Some("µTorrent 1.2".to_string()));
+        assert_eq!(handshake.port, Some(6881));
+        assert_eq!(handshake.request_queue, Some(500));
+        assert_eq!(handshake.your_ip, Some("::1".parse().unwrap()));
+        assert_eq!(handshake.metadata_size, None);
+    }
+
+    #[test]
+    fn test_from_bytes_ignores_unknown_fields() {
+        let handshake = ExtendedHandshake::from_bytes(b"d1:mde3:fooi1ee").unwrap();
+
+        assert_eq!(handshake, ExtendedHandshake::default());
+    }
+
+    #[test]
+    fn test_from_bytes_invalid_extension_id() {
+        assert!(matches!(
+            ExtendedHandshake::from_bytes(b"d1:md6:ut_pexi300eee"),
+            Err(ExtendedHandshakeError::InvalidExtensions)
+        ));
+    }
+}
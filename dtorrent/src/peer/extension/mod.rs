@@ -0,0 +1,4 @@
+pub mod extended_handshake;
+pub mod registry;
+pub mod ut_metadata;
+pub mod ut_pex;
@@ -0,0 +1,274 @@
+use super::extended_handshake::{ExtendedHandshake, ExtendedHandshakeError};
+
+/// ID of the extended handshake inside the extended messages.
+pub const EXTENDED_HANDSHAKE_ID: u8 = 0;
+
+/// Posible extension protocol errors.
+#[derive(Debug)]
+pub enum ExtensionError {
+    EmptyMessage,
+    InvalidHandshake(ExtendedHandshakeError),
+    UnknownExtension(u8),
+    InvalidMessage(String),
+}
+
+/// Handler of the messages of an extension built on the extension protocol (BEP 10), like `ut_metadata` or `ut_pex`.
+pub trait ExtensionHandler: Send {
+    /// Returns the name of the extension in the `m` dictionary of the extended handshake.
+    fn name(&self) -> &str;
+
+    /// Adds the fields the extension needs to our extended handshake, like `metadata_size` for `ut_metadata`.
+    fn extend_handshake(&self, _handshake: &mut ExtendedHandshake) {}
+
+    /// Called when the extended handshake of the peer is received and it supports the extension.
+    ///
+    /// Returns the payloads of the messages to send to the peer.
+    fn handshake_received(&mut self, _handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        vec![]
+    }
+
+    /// Handles the payload of a message of the extension received from the peer.
+    ///
+    /// Returns the payloads of the messages to send to the peer.
+    fn message_received(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError>;
+
+    /// Called periodically during the session, for the extensions that send messages on their own, like `ut_pex`.
+    ///
+    /// Returns the payloads of the messages to send to the peer.
+    fn tick(&mut self) -> Vec<Vec<u8>> {
+        vec![]
+    }
+}
+
+/// Registry of the extensions supported in a peer session.
+///
+/// Each registered extension receives its messages with the ID of its position in the registry, starting at 1, and they are dispatched to its handler.
+/// The messages sent to the peer use the IDs from the `m` dictionary of its extended handshake.
+///
+/// To create a new empty `ExtensionRegistry` use the method builder `new()`, and add the extensions with `register()`.
+#[derive(Default)]
+pub struct ExtensionRegistry {
+    handlers: Vec<Box<dyn ExtensionHandler>>,
+    peer_handshake: Option<ExtendedHandshake>,
+}
+
+impl ExtensionRegistry {
+    /// Creates a new `ExtensionRegistry` without extensions.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds an extension to the registry.
+    pub fn register(&mut self, handler: Box<dyn ExtensionHandler>) {
+        self.handlers.push(handler);
+    }
+
+    /// Returns our extended handshake, with the ID of every registered extension in its `m` dictionary and the fields added by the extensions.
+    ///
+    /// The other fields of the handshake have to be filled by the caller.
+    pub fn handshake(&self) -> ExtendedHandshake {
+        let mut handshake = ExtendedHandshake {
+            extensions: self
+                .handlers
+                .iter()
+                .enumerate()
+                .map(|(index, handler)| (handler.name().to_string(), index as u8 + 1))
+                .collect(),
+            ..Default::default()
+        };
+        for handler in &self.handlers {
+            handler.extend_handshake(&mut handshake);
+        }
+        handshake
+    }
+
+    /// Returns the extended handshake received from the peer, if any.
+    pub fn peer_handshake(&self) -> Option<&ExtendedHandshake> {
+        self.peer_handshake.as_ref()
+    }
+
+    /// Returns the ID the peer uses for an extension, or `None` if it does not support it.
+    pub fn peer_extension_id(&self, name: &str) -> Option<u8> {
+        self.peer_handshake
+            .as_ref()
+            .and_then(|handshake| handshake.extensions.get(name))
+            .copied()
+            .filter(|id| *id != 0)
+    }
+
+    /// Handles the payload of an extended message received from the peer, the extended message ID followed by the message.
+    ///
+    /// Returns the payloads of the extended messages to send to the peer in response, with the extended message ID of the peer included.
+    ///
+    /// It returns an `ExtensionError` if:
+    /// - The payload is empty.
+    /// - The extended handshake is invalid.
+    /// - The message ID does not belong to a registered extension.
+    /// - The handler of the extension could not handle the message.
+    pub fn handle_message(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+        let (id, message) = payload.split_first().ok_or(ExtensionError::EmptyMessage)?;
+
+        if *id == EXTENDED_HANDSHAKE_ID {
+            let handshake =
+                ExtendedHandshake::from_bytes(message).map_err(ExtensionError::InvalidHandshake)?;
+            self.peer_handshake = Some(handshake.clone());
+
+            let mut responses = vec![];
+            for index in 0..self.handlers.len() {
+                if self
+                    .peer_extension_id(self.handlers[index].name())
+                    .is_some()
+                {
+                    let payloads = self.handlers[index].handshake_received(&handshake);
+                    responses.extend(self.with_peer_id(index, payloads));
+                }
+            }
+            return Ok(responses);
+        }
+
+        let index = *id as usize - 1;
+        let handler = self
+            .handlers
+            .get_mut(index)
+            .ok_or(ExtensionError::UnknownExtension(*id))?;
+        let payloads = handler.message_received(message)?;
+        Ok(self.with_peer_id(index, payloads))
+    }
+
+    /// Gives every extension supported by the peer the chance to send messages on its own.
+    ///
+    /// Returns the payloads of the extended messages to send to the peer, with the extended message ID of the peer included.
+    pub fn tick(&mut self) -> Vec<Vec<u8>> {
+        let mut messages = vec![];
+        for index in 0..self.handlers.len() {
+            if self
+                .peer_extension_id(self.handlers[index].name())
+                .is_some()
+            {
+                let payloads = self.handlers[index].tick();
+                messages.extend(self.with_peer_id(index, payloads));
+            }
+        }
+        messages
+    }
+
+    /// Prepends the extended message ID the peer uses for an extension to its payloads.
+    ///
+    /// The payloads are dropped if the peer does not support the extension.
+    fn with_peer_id(&self, index: usize, payloads: Vec<Vec<u8>>) -> Vec<Vec<u8>> {
+        match self.peer_extension_id(self.handlers[index].name()) {
+            Some(peer_id) => payloads
+                .into_iter()
+                .map(|payload| [vec![peer_id], payload].concat())
+                .collect(),
+            None => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_assigns_ids_in_order() {
+        let registry = create_test_registry();
+
+        let handshake = registry.handshake();
+
+        assert_eq!(handshake.extensions.get("echo"), Some(&1));
+        assert_eq!(handshake.extensions.get("greeting"), Some(&2));
+    }
+
+    #[test]
+    fn test_peer_handshake() {
+        let mut registry = create_test_registry();
+
+        let responses = registry
+            .handle_message(b"\x00d1:md4:echoi7e8:greetingi0eee")
+            .unwrap();
+
+        assert_eq!(registry.peer_extension_id("echo"), Some(7));
+        assert_eq!(registry.peer_extension_id("greeting"), None);
+        assert_eq!(responses, vec![b"\x07hello".to_vec()]);
+    }
+
+    #[test]
+    fn test_messages_are_dispatched_by_our_id() {
+        let mut registry = create_test_registry();
+        registry.handle_message(b"\x00d1:md4:echoi7eee").unwrap();
+
+        let responses = registry.handle_message(b"\x01ping").unwrap();
+
+        assert_eq!(responses, vec![b"\x07ping".to_vec()]);
+    }
+
+    #[test]
+    fn test_unknown_extension() {
+        let mut registry = create_test_registry();
+
+        assert!(matches!(
+            registry.handle_message(b"\x03ping"),
+            Err(ExtensionError::UnknownExtension(3))
+        ));
+        assert!(matches!(
+            registry.handle_message(b""),
+            Err(ExtensionError::EmptyMessage)
+        ));
+    }
+
+    #[test]
+    fn test_tick_only_asks_extensions_supported_by_the_peer() {
+        let mut registry = create_test_registry();
+        assert!(registry.tick().is_empty());
+
+        registry
+            .handle_message(b"\x00d1:md8:greetingi5eee")
+            .unwrap();
+
+        assert_eq!(registry.tick(), vec![b"\x05tick".to_vec()]);
+    }
+
+    // Auxiliary functions
+
+    /// Extension that sends `hello` after the handshake and answers every message with the same payload.
+    struct EchoExtension;
+
+    impl ExtensionHandler for EchoExtension {
+        fn name(&self) -> &str {
+            "echo"
+        }
+
+        fn handshake_received(&mut self, _handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+            vec![b"hello".to_vec()]
+        }
+
+        fn message_received(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+            Ok(vec![payload.to_vec()])
+        }
+    }
+
+    /// Extension that ignores every message and sends `tick` periodically.
+    struct GreetingExtension;
+
+    impl ExtensionHandler for GreetingExtension {
+        fn name(&self) -> &str {
+            "greeting"
+        }
+
+        fn message_received(&mut self, _payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+            Ok(vec![])
+        }
+
+        fn tick(&mut self) -> Vec<Vec<u8>> {
+            vec![b"tick".to_vec()]
+        }
+    }
+
+    fn create_test_registry() -> ExtensionRegistry {
+        let mut registry = ExtensionRegistry::new();
+        registry.register(Box::new(EchoExtension));
+        registry.register(Box::new(GreetingExtension));
+        registry
+    }
+}
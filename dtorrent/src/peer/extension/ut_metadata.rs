@@ -0,0 +1,453 @@
+use bencoder::bencode::{Bencode, ToBencode};
+use sha1::{Digest, Sha1};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+};
+
+use super::{
+    extended_handshake::ExtendedHandshake,
+    registry::{ExtensionError, ExtensionHandler},
+};
+use crate::torrent_parser::torrent::Torrent;
+
+/// Name of the extension in the `m` dictionary of the extended handshake.
+pub const UT_METADATA: &str = "ut_metadata";
+
+/// Size of each piece of the metadata, the last one may be smaller.
+pub const METADATA_PIECE_SIZE: usize = 16384;
+
+/// Largest metadata accepted from a peer, to avoid allocating whatever size a peer announces.
+const MAX_METADATA_SIZE: usize = 10 * 1024 * 1024;
+
+/// Types of the messages of the `ut_metadata` extension.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MetadataMessageType {
+    Request = 0,
+    Data = 1,
+    Reject = 2,
+}
+
+/// Posible `ut_metadata` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum MetadataError {
+    InvalidMessage,
+    UnknownMessageType(i64),
+    InvalidSize(u64),
+    InvalidPiece(u32),
+    RequestRejected(u32),
+    HashDoesNotMatch,
+    PoisonedDownloadLock,
+}
+
+/// A message of the `ut_metadata` extension (BEP 9).
+///
+/// It is a bencoded dict with the `msg_type` and the `piece` index, followed by the piece data in `Data` messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MetadataMessage {
+    pub msg_type: MetadataMessageType,
+    pub piece: u32,
+    pub total_size: Option<u64>,
+    pub data: Vec<u8>,
+}
+
+impl MetadataMessage {
+    /// Creates a message requesting a piece of the metadata.
+    pub fn request(piece: u32) -> Self {
+        Self {
+            msg_type: MetadataMessageType::Request,
+            piece,
+            total_size: None,
+            data: vec![],
+        }
+    }
+
+    /// Creates a message with a piece of the metadata.
+    pub fn data(piece: u32, total_size: u64, data: Vec<u8>) -> Self {
+        Self {
+            msg_type: MetadataMessageType::Data,
+            piece,
+            total_size: Some(total_size),
+            data,
+        }
+    }
+
+    /// Creates a message rejecting the request of a piece.
+    pub fn reject(piece: u32) -> Self {
+        Self {
+            msg_type: MetadataMessageType::Reject,
+            piece,
+            total_size: None,
+            data: vec![],
+        }
+    }
+
+    /// Parses the payload of a `ut_metadata` message, without the extended message ID.
+    ///
+    /// It returns a `MetadataError` if:
+    /// - The payload does not start with a bencoded dict.
+    /// - The `msg_type` or the `piece` are missing or invalid.
+    pub fn from_bytes(payload: &[u8]) -> Result<Self, MetadataError> {
+        let (bencode, size) =
+            Bencode::decode_prefix(payload).map_err(|_| MetadataError::InvalidMessage)?;
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(MetadataError::InvalidMessage),
+        };
+
+        let msg_type = match d.get(b"msg_type".as_slice()) {
+            Some(Bencode::BNumber(0)) => MetadataMessageType::Request,
+            Some(Bencode::BNumber(1)) => MetadataMessageType::Data,
+            Some(Bencode::BNumber(2)) => MetadataMessageType::Reject,
+            Some(Bencode::BNumber(n)) => return Err(MetadataError::UnknownMessageType(*n)),
+            _ => return Err(MetadataError::InvalidMessage),
+        };
+        let piece = match d.get(b"piece".as_slice()) {
+            Some(Bencode::BNumber(n)) => {
+                u32::try_from(*n).map_err(|_| MetadataError::InvalidMessage)?
+            }
+            _ => return Err(MetadataError::InvalidMessage),
+        };
+        let total_size = match d.get(b"total_size".as_slice()) {
+            Some(Bencode::BNumber(n)) => {
+                Some(u64::try_from(*n).map_err(|_| MetadataError::InvalidMessage)?)
+            }
+            _ => None,
+        };
+
+        Ok(Self {
+            msg_type,
+            piece,
+            total_size,
+            data: payload[size..].to_vec(),
+        })
+    }
+
+    /// Converts the message to the payload of an extended message, without the extended message ID.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut d = BTreeMap::new();
+        d.insert(b"msg_type".to_vec(), Bencode::BNumber(self.msg_type as i64));
+        d.insert(b"piece".to_vec(), self.piece.to_bencode());
+        if let Some(total_size) = self.total_size {
+            d.insert(b"total_size".to_vec(), total_size.to_bencode());
+        }
+        [Bencode::encode(&d), self.data.clone()].concat()
+    }
+}
+
+/// State of the download of the metadata of a torrent from its peers.
+///
+/// The pieces received are kept between peers, and once all of them arrive the metadata is checked against the info hash.
+#[derive(Debug)]
+pub struct MetadataDownload {
+    info_hash: Vec<u8>,
+    size: Option<usize>,
+    pieces: Vec<Option<Vec<u8>>>,
+    metadata: Option<Vec<u8>>,
+}
+
+impl MetadataDownload {
+    /// Creates a new `MetadataDownload` for the torrent with the given info hash.
+    pub fn new(info_hash: Vec<u8>) -> Self {
+        Self {
+            info_hash,
+            size: None,
+            pieces: vec![],
+            metadata: None,
+        }
+    }
+
+    /// Returns the size of the metadata, if a peer already sent it.
+    pub fn size(&self) -> Option<usize> {
+        self.size
+    }
+
+    /// Sets the size of the metadata announced by a peer.
+    ///
+    /// If it differs from the size announced by a previous peer, the pieces received are discarded.
+    ///
+    /// It returns a `MetadataError` if the size is 0 or larger than `MAX_METADATA_SIZE`.
+    pub fn set_size(&mut self, size: u64) -> Result<(), MetadataError> {
+        if size == 0 || size > MAX_METADATA_SIZE as u64 {
+            return Err(MetadataError::InvalidSize(size));
+        }
+        let size = size as usize;
+        if self.size != Some(size) {
+            self.size = Some(size);
+            self.pieces = vec![None; size.div_ceil(METADATA_PIECE_SIZE)];
+        }
+        Ok(())
+    }
+
+    /// Returns the indexes of the pieces that were not received yet.
+    pub fn missing_pieces(&self) -> Vec<u32> {
+        self.pieces
+            .iter()
+            .enumerate()
+            .filter(|(_, piece)| piece.is_none())
+            .map(|(index, _)| index as u32)
+            .collect()
+    }
+
+    /// Stores a piece of the metadata. When the last piece arrives, the metadata is assembled and checked against the info hash.
+    ///
+    /// It returns a `MetadataError` if:
+    /// - The index or the length of the piece is not valid for the size of the metadata.
+    /// - The metadata is complete and its hash does not match the info hash. The pieces are discarded.
+    pub fn piece_received(&mut self, index: u32, data: Vec<u8>) -> Result<(), MetadataError> {
+        let size = self.size.ok_or(MetadataError::InvalidPiece(index))?;
+        if index as usize >= self.pieces.len() {
+            return Err(MetadataError::InvalidPiece(index));
+        }
+        let start = index as usize * METADATA_PIECE_SIZE;
+        if data.len() != (size - start).min(METADATA_PIECE_SIZE) {
+            return Err(MetadataError::InvalidPiece(index));
+        }
+        self.pieces[index as usize] = Some(data);
+
+        if self.pieces.iter().all(|piece| piece.is_some()) {
+            let metadata: Vec<u8> = self.pieces.iter().flatten().flatten().copied().collect();
+            if Sha1::digest(&metadata).as_slice() != self.info_hash {
+                self.pieces = vec![None; self.pieces.len()];
+                return Err(MetadataError::HashDoesNotMatch);
+            }
+            self.metadata = Some(metadata);
+        }
+        Ok(())
+    }
+
+    /// Returns the metadata once all its pieces were received and verified.
+    pub fn metadata(&self) -> Option<&Vec<u8>> {
+        self.metadata.as_ref()
+    }
+}
+
+/// Handler of the `ut_metadata` extension (BEP 9), to send the info dict of a torrent to peers that only know its info hash, or to download it from them.
+///
+/// To create a new `UtMetadata` use `for_torrent()` to serve the metadata of a torrent, or `downloading()` to download it.
+pub struct UtMetadata {
+    metadata: Option<Vec<u8>>,
+    download: Option<Arc<Mutex<MetadataDownload>>>,
+}
+
+impl UtMetadata {
+    /// Creates a `UtMetadata` that serves the info dict of a torrent.
+    ///
+    /// If the bencoded info dict does not match the info hash of the torrent the requests are rejected, as the peer could not verify it.
+    pub fn for_torrent(torrent: &Torrent) -> Self {
+        let metadata = Bencode::encode(&torrent.info);
+        let matches_info_hash = torrent
+            .get_info_hash_as_bytes()
+            .is_ok_and(|info_hash| Sha1::digest(&metadata).as_slice() == info_hash);
+
+        Self {
+            metadata: matches_info_hash.then_some(metadata),
+            download: None,
+        }
+    }
+
+    /// Creates a `UtMetadata` that downloads the metadata from the peer, storing the pieces in the shared `MetadataDownload`.
+    pub fn downloading(download: Arc<Mutex<MetadataDownload>>) -> Self {
+        Self {
+            metadata: None,
+            download: Some(download),
+        }
+    }
+
+    fn handle_request(&self, piece: u32) -> MetadataMessage {
+        let metadata = match &self.metadata {
+            Some(metadata) => metadata,
+            None => return MetadataMessage::reject(piece),
+        };
+
+        let start = piece as usize * METADATA_PIECE_SIZE;
+        if start >= metadata.len() {
+            return MetadataMessage::reject(piece);
+        }
+        let end = (start + METADATA_PIECE_SIZE).min(metadata.len());
+        MetadataMessage::data(piece, metadata.len() as u64, metadata[start..end].to_vec())
+    }
+
+    fn handle_data(&self, message: MetadataMessage) -> Result<(), MetadataError> {
+        match &self.download {
+            Some(download) => download
+                .lock()
+                .map_err(|_| MetadataError::PoisonedDownloadLock)?
+                .piece_received(message.piece, message.data),
+            // Pieces we did not request are ignored.
+            None => Ok(()),
+        }
+    }
+}
+
+impl ExtensionHandler for UtMetadata {
+    fn name(&self) -> &str {
+        UT_METADATA
+    }
+
+    fn extend_handshake(&self, handshake: &mut ExtendedHandshake) {
+        if let Some(metadata) = &self.metadata {
+            handshake.metadata_size = Some(metadata.len() as u64);
+        }
+    }
+
+    /// Requests the missing pieces of the metadata if it is being downloaded and the peer announced its size.
+    fn handshake_received(&mut self, handshake: &ExtendedHandshake) -> Vec<Vec<u8>> {
+        let (download, size) = match (&self.download, handshake.metadata_size) {
+            (Some(download), Some(size)) => (download, size),
+            _ => return vec![],
+        };
+        let mut download = match download.lock() {
+            Ok(download) => download,
+            Err(_) => return vec![],
+        };
+        if download.set_size(size).is_err() {
+            return vec![];
+        }
+
+        download
+            .missing_pieces()
+            .into_iter()
+            .map(|piece| MetadataMessage::request(piece).as_bytes())
+            .collect()
+    }
+
+    fn message_received(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+        let to_extension_error = |err| ExtensionError::InvalidMessage(format!("{:?}", err));
+        let message = MetadataMessage::from_bytes(payload).map_err(to_extension_error)?;
+
+        match message.msg_type {
+            MetadataMessageType::Request => Ok(vec![self.handle_request(message.piece).as_bytes()]),
+            MetadataMessageType::Data => {
+                self.handle_data(message).map_err(to_extension_error)?;
+                Ok(vec![])
+            }
+            MetadataMessageType::Reject => Err(to_extension_error(MetadataError::RequestRejected(
+                message.piece,
+            ))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::torrent_parser::info::Info;
+
+    #[test]
+    fn test_message_as_bytes() {
+        assert_eq!(
+            MetadataMessage::request(0).as_bytes(),
+            b"d8:msg_typei0e5:piecei0ee"
+        );
+        assert_eq!(
+            MetadataMessage::data(1, 16390, b"abc".to_vec()).as_bytes(),
+            b"d8:msg_typei1e5:piecei1e10:total_sizei16390eeabc"
+        );
+    }
+
+    #[test]
+    fn test_message_from_bytes() {
+        let message =
+            MetadataMessage::from_bytes(b"d8:msg_typei1e5:piecei1e10:total_sizei16390eeabc")
+                .unwrap();
+
+        assert_eq!(message, MetadataMessage::data(1, 16390, b"abc".to_vec()));
+        assert_eq!(
+            MetadataMessage::from_bytes(b"d8:msg_typei7e5:piecei0ee"),
+            Err(MetadataError::UnknownMessageType(7))
+        );
+    }
+
+    #[test]
+    fn test_download_verifies_metadata() {
+        let metadata = vec![7; METADATA_PIECE_SIZE + 10];
+        let mut download = MetadataDownload::new(Sha1::digest(&metadata).to_vec());
+        download.set_size(metadata.len() as u64).unwrap();
+
+        assert_eq!(download.missing_pieces(), vec![0, 1]);
+        download
+            .piece_received(1, metadata[METADATA_PIECE_SIZE..].to_vec())
+            .unwrap();
+        assert_eq!(download.missing_pieces(), vec![0]);
+        assert_eq!(download.metadata(), None);
+
+        download
+            .piece_received(0, metadata[..METADATA_PIECE_SIZE].to_vec())
+            .unwrap();
+        assert_eq!(download.metadata(), Some(&metadata));
+    }
+
+    #[test]
+    fn test_download_discards_wrong_metadata() {
+        let mut download = MetadataDownload::new(vec![0; 20]);
+        download.set_size(3).unwrap();
+
+        assert_eq!(
+            download.piece_received(0, vec![1, 2]),
+            Err(MetadataError::InvalidPiece(0))
+        );
+        assert_eq!(
+            download.piece_received(0, vec![1, 2, 3]),
+            Err(MetadataError::HashDoesNotMatch)
+        );
+        assert_eq!(download.missing_pieces(), vec![0]);
+        assert_eq!(download.set_size(0), Err(MetadataError::InvalidSize(0)));
+    }
+
+    #[test]
+    fn test_download_from_seeding_extension() {
+        let torrent = create_test_torrent();
+        let mut seeder = UtMetadata::for_torrent(&torrent);
+        let download = Arc::new(Mutex::new(MetadataDownload::new(
+            torrent.get_info_hash_as_bytes().unwrap(),
+        )));
+        let mut leecher = UtMetadata::downloading(download.clone());
+
+        let mut handshake = ExtendedHandshake::default();
+        seeder.extend_handshake(&mut handshake);
+        let requests = leecher.handshake_received(&handshake);
+        assert_eq!(requests.len(), 1);
+
+        let responses = seeder.message_received(&requests[0]).unwrap();
+        leecher.message_received(&responses[0]).unwrap();
+
+        assert_eq!(
+            download.lock().unwrap().metadata(),
+            Some(&Bencode::encode(&torrent.info))
+        );
+    }
+
+    #[test]
+    fn test_reject_when_metadata_is_unknown() {
+        let mut leecher =
+            UtMetadata::downloading(Arc::new(Mutex::new(MetadataDownload::new(vec![0; 20]))));
+
+        let responses = leecher
+            .message_received(&MetadataMessage::request(0).as_bytes())
+            .unwrap();
+
+        assert_eq!(responses, vec![MetadataMessage::reject(0).as_bytes()]);
+        assert!(leecher
+            .message_received(&MetadataMessage::reject(0).as_bytes())
+            .is_err());
+    }
+
+    // Auxiliary functions
+
+    fn create_test_torrent() -> Torrent {
+        let info = Info {
+            length: 10,
+            name: "test_ut_metadata".to_string(),
+            piece_length: 5,
+            pieces: vec![0; 40],
+            files: None,
+        };
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info_hash: Torrent::create_info_hash(&info).unwrap(),
+            info,
+        }
+    }
+}
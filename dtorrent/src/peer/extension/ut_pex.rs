@@ -0,0 +1,383 @@
+use bencoder::bencode::{Bencode, ToBencode};
+use std::{
+    collections::{BTreeMap, HashSet},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+use super::registry::{ExtensionError, ExtensionHandler};
+use crate::{peer::bt_peer::BtPeer, torrent_handler::status::AtomicTorrentStatus};
+
+/// Name of the extension in the `m` dictionary of the extended handshake.
+pub const UT_PEX: &str = "ut_pex";
+
+/// Minimum time between the messages sent to a peer, as BEP 11 asks.
+const PEX_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Messages received from a peer sooner than this after the previous one are ignored.
+/// It is shorter than `PEX_INTERVAL` to tolerate some delay between messages.
+const MIN_RECEIVE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Maximum number of added and of dropped peers in a message, the rest are sent in the next ones.
+const MAX_PEX_PEERS: usize = 50;
+
+/// Posible `ut_pex` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PexError {
+    InvalidMessage,
+    InvalidField(String),
+}
+
+/// A message of the `ut_pex` extension (BEP 11), with the peers connected and disconnected since the previous message.
+///
+/// It is a bencoded dict with the peers in compact format, `added` and `dropped` for IPv4 peers and `added6` and `dropped6` for IPv6 peers.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct PexMessage {
+    pub added: Vec<SocketAddr>,
+    pub dropped: Vec<SocketAddr>,
+}
+
+impl PexMessage {
+    /// Parses the payload of a `ut_pex` message.
+    ///
+    /// It returns a `PexError` if:
+    /// - The payload is not a bencoded dict.
+    /// - A list of peers is not a string or its length is not a multiple of the compact peer length.
+    pub fn from_bytes(payload: &[u8]) -> Result<Self, PexError> {
+        let d = match Bencode::decode(payload).map_err(|_| PexError::InvalidMessage)? {
+            Bencode::BDict(d) => d,
+            _ => return Err(PexError::InvalidMessage),
+        };
+
+        let mut message = Self::default();
+        for (key, ipv6) in [("added", false), ("added6", true)] {
+            message
+                .added
+                .extend(Self::create_peers(d.get(key.as_bytes()), key, ipv6)?);
+        }
+        for (key, ipv6) in [("dropped", false), ("dropped6", true)] {
+            message
+                .dropped
+                .extend(Self::create_peers(d.get(key.as_bytes()), key, ipv6)?);
+        }
+        Ok(message)
+    }
+
+    /// Returns the payload of the message.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        Bencode::encode(self)
+    }
+
+    /// Parses a list of compact peers, 4 or 16 bytes of address and 2 bytes of port each.
+    fn create_peers(
+        peers: Option<&Bencode>,
+        key: &str,
+        ipv6: bool,
+    ) -> Result<Vec<SocketAddr>, PexError> {
+        let peers = match peers {
+            Some(Bencode::BString(peers)) => peers,
+            Some(_) => return Err(PexError::InvalidField(key.to_string())),
+            None => return Ok(vec![]),
+        };
+        let ip_length = if ipv6 { 16 } else { 4 };
+        if !peers.len().is_multiple_of(ip_length + 2) {
+            return Err(PexError::InvalidField(key.to_string()));
+        }
+
+        Ok(peers
+            .chunks_exact(ip_length + 2)
+            .map(|peer| {
+                let (ip, port) = peer.split_at(ip_length);
+                let ip = match <[u8; 16]>::try_from(ip) {
+                    Ok(ip) => IpAddr::V6(Ipv6Addr::from(ip)),
+                    Err(_) => IpAddr::V4(Ipv4Addr::new(ip[0], ip[1], ip[2], ip[3])),
+                };
+                SocketAddr::new(ip, u16::from_be_bytes([port[0], port[1]]))
+            })
+            .collect())
+    }
+
+    /// Returns the compact format of the peers with the given IP version.
+    fn compact_peers(peers: &[SocketAddr], ipv6: bool) -> Vec<u8> {
+        peers
+            .iter()
+            .filter(|peer| peer.is_ipv6() == ipv6)
+            .flat_map(|peer| {
+                let ip = match peer.ip() {
+                    IpAddr::V4(ip) => ip.octets().to_vec(),
+                    IpAddr::V6(ip) => ip.octets().to_vec(),
+                };
+                [ip, peer.port().to_be_bytes().to_vec()].concat()
+            })
+            .collect()
+    }
+}
+
+impl ToBencode for PexMessage {
+    fn to_bencode(&self) -> Bencode {
+        let mut d = BTreeMap::new();
+        for (key, ipv6) in [("added", false), ("added6", true)] {
+            let added = Self::compact_peers(&self.added, ipv6);
+            // No flags are known about the peers.
+            let flags = vec![0; added.len() / if ipv6 { 18 } else { 6 }];
+            d.insert(key.as_bytes().to_vec(), added.to_bencode());
+            d.insert(format!("{}.f", key).into_bytes(), flags.to_bencode());
+        }
+        for (key, ipv6) in [("dropped", false), ("dropped6", true)] {
+            d.insert(
+                key.as_bytes().to_vec(),
+                Self::compact_peers(&self.dropped, ipv6).to_bencode(),
+            );
+        }
+        Bencode::BDict(d)
+    }
+}
+
+/// Handler of the `ut_pex` extension in a peer session.
+///
+/// Every `PEX_INTERVAL` it sends to the peer the peers of the torrent that connected and disconnected since the previous message.
+/// Only the peers whose listen port is known are sent, so the receiver can connect to them.
+///
+/// The peers received are added to the discovered peers of the torrent status, for the torrent handler to connect to them.
+///
+/// To create a new `UtPex`, use UtPex::new(torrent_status, peer).
+pub struct UtPex {
+    torrent_status: Arc<AtomicTorrentStatus>,
+    peer: BtPeer,
+    sent_peers: HashSet<SocketAddr>,
+    last_sent: Option<Instant>,
+    last_received: Option<Instant>,
+}
+
+impl UtPex {
+    /// Creates a new `UtPex` for the session with the given peer of the torrent.
+    pub fn new(torrent_status: Arc<AtomicTorrentStatus>, peer: BtPeer) -> Self {
+        Self {
+            torrent_status,
+            peer,
+            sent_peers: HashSet::new(),
+            last_sent: None,
+            last_received: None,
+        }
+    }
+
+    /// Returns the listen addresses of the peers of the torrent, except the peer of this session.
+    fn connected_peers(&self) -> Option<HashSet<SocketAddr>> {
+        let connected_peers = self.torrent_status.get_connected_peers().ok()?;
+        Some(
+            connected_peers
+                .iter()
+                .filter(|(peer, _)| **peer != self.peer)
+                .filter_map(|(peer, status)| Some(SocketAddr::new(peer.ip, status.listen_port?)))
+                .collect(),
+        )
+    }
+}
+
+impl ExtensionHandler for UtPex {
+    fn name(&self) -> &str {
+        UT_PEX
+    }
+
+    fn message_received(&mut self, payload: &[u8]) -> Result<Vec<Vec<u8>>, ExtensionError> {
+        let message = PexMessage::from_bytes(payload)
+            .map_err(|err| ExtensionError::InvalidMessage(format!("{:?}", err)))?;
+
+        if self
+            .last_received
+            .is_some_and(|last_received| last_received.elapsed() < MIN_RECEIVE_INTERVAL)
+        {
+            return Ok(vec![]);
+        }
+        self.last_received = Some(Instant::now());
+
+        let peers = message
+            .added
+            .iter()
+            .take(MAX_PEX_PEERS)
+            .map(|addr| BtPeer::new(addr.ip(), addr.port().into()))
+            .collect();
+        self.torrent_status
+            .add_discovered_peers(peers)
+            .map_err(|err| ExtensionError::InvalidMessage(format!("{:?}", err)))?;
+        Ok(vec![])
+    }
+
+    /// Sends the peers connected and disconnected since the previous message, if `PEX_INTERVAL` passed.
+    fn tick(&mut self) -> Vec<Vec<u8>> {
+        if self
+            .last_sent
+            .is_some_and(|last_sent| last_sent.elapsed() < PEX_INTERVAL)
+        {
+            return vec![];
+        }
+        let connected_peers = match self.connected_peers() {
+            Some(connected_peers) => connected_peers,
+            None => return vec![],
+        };
+        self.last_sent = Some(Instant::now());
+
+        let message = PexMessage {
+            added: connected_peers
+                .difference(&self.sent_peers)
+                .take(MAX_PEX_PEERS)
+                .copied()
+                .collect(),
+            dropped: self
+                .sent_peers
+                .difference(&connected_peers)
+                .take(MAX_PEX_PEERS)
+                .copied()
+                .collect(),
+        };
+        if message.added.is_empty() && message.dropped.is_empty() {
+            return vec![];
+        }
+
+        self.sent_peers.extend(&message.added);
+        for peer in &message.dropped {
+            self.sent_peers.remove(peer);
+        }
+        vec![message.as_bytes()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::cfg::Cfg,
+        peer::peer_message::Bitfield,
+        peer::session_status::SessionStatus,
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+
+    #[test]
+    fn test_message_roundtrip() {
+        let message = PexMessage {
+            added: vec![
+                "10.0.0.1:6881".parse().unwrap(),
+                "[2001:db8::1]:6882".parse().unwrap(),
+            ],
+            dropped: vec!["10.0.0.2:6883".parse().unwrap()],
+        };
+
+        assert_eq!(PexMessage::from_bytes(&message.as_bytes()), Ok(message));
+    }
+
+    #[test]
+    fn test_message_from_bytes() {
+        let message =
+            PexMessage::from_bytes(b"d5:added6:\x0a\x00\x00\x01\x1a\xe17:added.f1:\x00e").unwrap();
+
+        assert_eq!(message.added, vec!["10.0.0.1:6881".parse().unwrap()]);
+        assert!(message.dropped.is_empty());
+        assert_eq!(
+            PexMessage::from_bytes(b"d5:added5:abcdee"),
+            Err(PexError::InvalidField("added".to_string()))
+        );
+        assert_eq!(PexMessage::from_bytes(b"le"), Err(PexError::InvalidMessage));
+    }
+
+    #[test]
+    fn test_added_and_dropped_peers_are_sent() {
+        let torrent_status = create_test_status("test_added_and_dropped_peers_are_sent");
+        let session_peer = create_test_peer(1, 6881);
+        connect_test_peer(&torrent_status, &session_peer, Some(6881));
+        connect_test_peer(&torrent_status, &create_test_peer(2, 6882), Some(6882));
+        connect_test_peer(&torrent_status, &create_test_peer(3, 50000), None);
+        let mut pex = UtPex::new(torrent_status.clone(), session_peer);
+
+        let messages = pex.tick();
+
+        assert_eq!(
+            messages,
+            vec![PexMessage {
+                added: vec!["127.0.0.2:6882".parse().unwrap()],
+                dropped: vec![],
+            }
+            .as_bytes()]
+        );
+
+        torrent_status
+            .peer_disconnected(&create_test_peer(2, 6882))
+            .unwrap();
+        assert!(pex.tick().is_empty());
+
+        pex.last_sent = Instant::now().checked_sub(PEX_INTERVAL);
+        assert_eq!(
+            pex.tick(),
+            vec![PexMessage {
+                added: vec![],
+                dropped: vec!["127.0.0.2:6882".parse().unwrap()],
+            }
+            .as_bytes()]
+        );
+    }
+
+    #[test]
+    fn test_received_peers_are_discovered() {
+        let torrent_status = create_test_status("test_received_peers_are_discovered");
+        let mut pex = UtPex::new(torrent_status.clone(), create_test_peer(1, 6881));
+        let first_message = PexMessage {
+            added: (0..MAX_PEX_PEERS as u16 + 10)
+                .map(|port| SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 7000 + port))
+                .collect(),
+            dropped: vec![],
+        };
+        let second_message = PexMessage {
+            added: vec!["10.0.0.1:6881".parse().unwrap()],
+            dropped: vec![],
+        };
+
+        assert!(pex
+            .message_received(&first_message.as_bytes())
+            .unwrap()
+            .is_empty());
+        pex.message_received(&second_message.as_bytes()).unwrap();
+
+        let discovered_peers = torrent_status.take_discovered_peers(100).unwrap();
+        assert_eq!(discovered_peers.len(), MAX_PEX_PEERS);
+        assert!(!discovered_peers.contains(&BtPeer::new("10.0.0.1".parse().unwrap(), 6881)));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_status(name: &str) -> Arc<AtomicTorrentStatus> {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            files: None,
+        };
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info,
+            info_hash: name.to_string(),
+        };
+        let (torrent_status, _) =
+            AtomicTorrentStatus::new(&torrent, Cfg::new("config.cfg").unwrap());
+        Arc::new(torrent_status)
+    }
+
+    fn create_test_peer(host: u8, port: i64) -> BtPeer {
+        BtPeer::new(IpAddr::V4(Ipv4Addr::new(127, 0, 0, host)), port)
+    }
+
+    fn connect_test_peer(
+        torrent_status: &AtomicTorrentStatus,
+        peer: &BtPeer,
+        listen_port: Option<u16>,
+    ) {
+        let mut status = SessionStatus::new(Bitfield::new(vec![]));
+        status.listen_port = listen_port;
+        torrent_status.peer_connecting();
+        torrent_status.peer_connected(peer).unwrap();
+        torrent_status
+            .update_peer_session_status(peer, &status)
+            .unwrap();
+    }
+}
@@ -16,18 +16,40 @@ pub struct Handshake {
 
 const PSTR: &str = "BitTorrent protocol";
 
+/// Byte and bit of the reserved field that announce support for the extension protocol (BEP 10).
+const EXTENSION_PROTOCOL_BYTE: usize = 5;
+const EXTENSION_PROTOCOL_BIT: u8 = 0x10;
+
+/// Byte and bit of the reserved field that announce support for the fast extension (BEP 6).
+const FAST_EXTENSION_BYTE: usize = 7;
+const FAST_EXTENSION_BIT: u8 = 0x04;
+
 impl Handshake {
-    /// Creates a new `Handshake` message.
+    /// Creates a new `Handshake` message, announcing support for the extension protocol and the fast extension.
     pub fn new(info_hash: Vec<u8>, peer_id: Vec<u8>) -> Self {
+        let mut reserved = [0; 8];
+        reserved[EXTENSION_PROTOCOL_BYTE] |= EXTENSION_PROTOCOL_BIT;
+        reserved[FAST_EXTENSION_BYTE] |= FAST_EXTENSION_BIT;
+
         Self {
             pstrlen: 19,
             pstr: PSTR.to_string(),
-            reserved: [0; 8],
+            reserved,
             info_hash,
             peer_id,
         }
     }
 
+    /// Returns true if the sender supports the extension protocol.
+    pub fn supports_extension_protocol(&self) -> bool {
+        self.reserved[EXTENSION_PROTOCOL_BYTE] & EXTENSION_PROTOCOL_BIT != 0
+    }
+
+    /// Returns true if the sender supports the fast extension.
+    pub fn supports_fast_extension(&self) -> bool {
+        self.reserved[FAST_EXTENSION_BYTE] & FAST_EXTENSION_BIT != 0
+    }
+
     /// Converts a `Handshake` message to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
         let mut bytes = vec![self.pstrlen];
@@ -83,7 +105,7 @@ mod tests {
         let expected_handshake_len = 68;
         let expected_pstrlen = 19;
         let expected_pstr = b"BitTorrent protocol".to_vec();
-        let expected_reserved = [0; 8];
+        let expected_reserved = [0, 0, 0, 0, 0, 0x10, 0, 0x04];
 
         let info_hash: Vec<u8> = (1..=20).collect();
         let peer_id: Vec<u8> = (21..=40).collect();
@@ -103,7 +125,7 @@ mod tests {
     fn test_from_bytes() {
         let expected_pstrlen = 19;
         let expected_pstr = "BitTorrent protocol";
-        let expected_reserved = [0; 8];
+        let expected_reserved = [0, 0, 0, 0, 0, 0x10, 0, 0x04];
 
         let info_hash: Vec<u8> = (1..=20).collect();
         let peer_id: Vec<u8> = (21..=40).collect();
@@ -117,5 +139,19 @@ mod tests {
         assert_eq!(handshake.reserved, expected_reserved);
         assert_eq!(handshake.info_hash, info_hash);
         assert_eq!(handshake.peer_id, peer_id);
+        assert!(handshake.supports_extension_protocol());
+        assert!(handshake.supports_fast_extension());
+    }
+
+    #[test]
+    fn test_without_extension_protocol() {
+        let mut bytes = Handshake::new(vec![0; 20], vec![0; 20]).as_bytes();
+        bytes[25] = 0;
+        bytes[27] = 0;
+
+        let handshake = Handshake::from_bytes(&bytes).unwrap();
+
+        assert!(!handshake.supports_extension_protocol());
+        assert!(!handshake.supports_fast_extension());
     }
 }
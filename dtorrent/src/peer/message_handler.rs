@@ -1,5 +1,5 @@
 use logger::logger_sender::LoggerSender;
-use std::{io::Write, net::TcpStream, sync::Arc};
+use std::{io::Write, sync::Arc};
 
 use crate::{
     torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError},
@@ -9,6 +9,7 @@ use crate::{
 use super::{
     handshake::Handshake,
     peer_message::{Bitfield, Message, MessageId, Request},
+    peer_stream::PeerStream,
 };
 
 #[derive(Debug)]
@@ -53,9 +54,36 @@ impl MessageHandler {
     }
 
     /// Handles a piece message received from the peer.
-    pub fn handle_piece(&mut self, message: Message) -> Vec<u8> {
+    ///
+    /// Returns the piece index, the offset of the block inside the piece and the block data.
+    pub fn handle_piece(&mut self, message: Message) -> (u32, u32, Vec<u8>) {
+        let mut index: [u8; 4] = [0; 4];
+        let mut begin: [u8; 4] = [0; 4];
+        index.copy_from_slice(&message.payload[0..4]);
+        begin.copy_from_slice(&message.payload[4..8]);
         let block = &message.payload[8..];
-        block.to_vec()
+        (
+            u32::from_be_bytes(index),
+            u32::from_be_bytes(begin),
+            block.to_vec(),
+        )
+    }
+
+    /// Handles a request, reject request or cancel message received from the peer.
+    ///
+    /// Returns the piece index, the offset of the block inside the piece and the block length.
+    pub fn handle_request(&mut self, message: Message) -> (u32, u32, u32) {
+        let mut index: [u8; 4] = [0; 4];
+        let mut begin: [u8; 4] = [0; 4];
+        let mut length: [u8; 4] = [0; 4];
+        index.copy_from_slice(&message.payload[0..4]);
+        begin.copy_from_slice(&message.payload[4..8]);
+        length.copy_from_slice(&message.payload[8..12]);
+        (
+            u32::from_be_bytes(index),
+            u32::from_be_bytes(begin),
+            u32::from_be_bytes(length),
+        )
     }
 
     // Returns the received piece index
@@ -74,7 +102,7 @@ impl MessageHandler {
         index: u32,
         begin: u32,
         block: &[u8],
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let mut payload = vec![];
         payload.extend(index.to_be_bytes());
@@ -91,14 +119,32 @@ impl MessageHandler {
     }
 
     /// Sends a unchoked message to the peer.
-    pub fn send_unchoked(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    pub fn send_unchoked(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
         let unchoked_msg = Message::new(MessageId::Unchoke, vec![]);
         self.send(stream, unchoked_msg)?;
         Ok(())
     }
 
+    /// Sends an extended message to the peer, the payload starts with the extended message ID.
+    pub fn send_extended(
+        &mut self,
+        payload: Vec<u8>,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let extended_msg = Message::new(MessageId::Extended, payload);
+        self.send(stream, extended_msg)?;
+        Ok(())
+    }
+
+    /// Sends a choke message to the peer.
+    pub fn send_choke(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
+        let choke_msg = Message::new(MessageId::Choke, vec![]);
+        self.send(stream, choke_msg)?;
+        Ok(())
+    }
+
     /// Sends a bitfield message to the peer.
-    pub fn send_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    pub fn send_bitfield(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
         let bitfield = self
             .torrent_status
             .get_bitfield()
@@ -109,13 +155,53 @@ impl MessageHandler {
         Ok(())
     }
 
+    /// Sends a have all message to the peer, instead of a bitfield with all the pieces.
+    pub fn send_have_all(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
+        let have_all_msg = Message::new(MessageId::HaveAll, vec![]);
+        self.send(stream, have_all_msg)?;
+        Ok(())
+    }
+
+    /// Sends a have none message to the peer, instead of a bitfield without pieces.
+    pub fn send_have_none(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
+        let have_none_msg = Message::new(MessageId::HaveNone, vec![]);
+        self.send(stream, have_none_msg)?;
+        Ok(())
+    }
+
+    /// Sends an allowed fast message to the peer, it can request the piece even while it is choked.
+    pub fn send_allowed_fast(
+        &mut self,
+        index: u32,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let allowed_fast_msg = Message::new(MessageId::AllowedFast, index.to_be_bytes().to_vec());
+        self.send(stream, allowed_fast_msg)?;
+        Ok(())
+    }
+
+    /// Sends a reject request message to the peer, for a request that will not be served.
+    pub fn send_reject_request(
+        &mut self,
+        index: u32,
+        begin: u32,
+        length: u32,
+        stream: &mut PeerStream,
+    ) -> Result<(), MessageHandlerError> {
+        let payload = Request::new(index, begin, length).as_bytes();
+
+        let reject_msg = Message::new(MessageId::RejectRequest, payload);
+        self.send(stream, reject_msg)?;
+        Ok(())
+    }
+
     /// Sends a request message to the peer.
     pub fn send_request(
         &self,
         index: u32,
         begin: u32,
         length: u32,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let payload = Request::new(index, begin, length).as_bytes();
 
@@ -125,7 +211,7 @@ impl MessageHandler {
     }
 
     /// Sends an interested message to the peer.
-    pub fn send_interested(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    pub fn send_interested(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
         let interested_msg = Message::new(MessageId::Interested, vec![]);
         self.send(stream, interested_msg)?;
         Ok(())
@@ -137,7 +223,7 @@ impl MessageHandler {
         index: u32,
         begin: u32,
         length: u32,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let mut payload = vec![];
         payload.extend(index.to_be_bytes());
@@ -155,7 +241,7 @@ impl MessageHandler {
     pub fn send_have(
         &mut self,
         index: u32,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), MessageHandlerError> {
         let mut payload = vec![];
         payload.extend(index.to_be_bytes());
@@ -167,7 +253,7 @@ impl MessageHandler {
     }
 
     /// Generic sending function.
-    fn send(&self, stream: &mut TcpStream, message: Message) -> Result<(), MessageHandlerError> {
+    fn send(&self, stream: &mut PeerStream, message: Message) -> Result<(), MessageHandlerError> {
         stream
             .write_all(&message.as_bytes())
             .map_err(|_| MessageHandlerError::MessageError(message.id))?;
@@ -180,7 +266,7 @@ impl MessageHandler {
     /// Sends a handshake to the peer.
     ///
     /// It returns an error if the handshake could not be sent or the handshake was not successful.
-    pub fn send_handshake(&mut self, stream: &mut TcpStream) -> Result<(), MessageHandlerError> {
+    pub fn send_handshake(&mut self, stream: &mut PeerStream) -> Result<(), MessageHandlerError> {
         let info_hash = self
             .torrent
             .get_info_hash_as_bytes()
@@ -0,0 +1,129 @@
+use std::io::{self, Read};
+
+use super::peer_message::{Message, MessageError, MessageId};
+use crate::torrent_handler::block_tracker::BLOCK_SIZE;
+
+/// Maximum length of a message, a piece message carries a single block.
+const MAX_MESSAGE_LENGTH: usize = BLOCK_SIZE as usize * 10;
+
+/// Bytes read from the stream at once.
+const READ_CHUNK_SIZE: usize = 32 * 1024;
+
+/// Posible `MessageReader` errors.
+#[derive(Debug)]
+pub enum MessageReaderError {
+    MessageLengthTooLong,
+    InvalidMessage(MessageError),
+}
+
+/// Incremental framing of the messages received from a peer.
+///
+/// The bytes are read as they arrive, without waiting for whole messages, and the messages are taken out once they are complete.
+/// This way a nonblocking stream can be read whenever it is readable.
+///
+/// To create a new `MessageReader`, use MessageReader::new().
+#[derive(Debug, Default)]
+pub struct MessageReader {
+    buffer: Vec<u8>,
+}
+
+impl MessageReader {
+    /// Creates a new `MessageReader` with nothing received.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads the bytes available in the stream, up to a chunk or `max` bytes if it is smaller.
+    ///
+    /// Returns the number of bytes read, 0 if the stream reached its end.
+    /// It returns the errors of the stream, `WouldBlock` if it is nonblocking and there is nothing to read.
+    pub fn read_from(&mut self, stream: &mut impl Read, max: usize) -> io::Result<usize> {
+        let mut chunk = [0; READ_CHUNK_SIZE];
+        let len = stream.read(&mut chunk[..max.min(READ_CHUNK_SIZE)])?;
+        self.buffer.extend_from_slice(&chunk[..len]);
+        Ok(len)
+    }
+
+    /// Takes the next complete message out of the received bytes, `None` if it did not arrive completely yet.
+    ///
+    /// Keep alive messages are returned as messages with the `KeepAlive` ID.
+    ///
+    /// It returns an error if the message is too long or invalid.
+    pub fn next_message(&mut self) -> Result<Option<Message>, MessageReaderError> {
+        if self.buffer.len() < 4 {
+            return Ok(None);
+        }
+        let len = u32::from_be_bytes([
+            self.buffer[0],
+            self.buffer[1],
+            self.buffer[2],
+            self.buffer[3],
+        ]) as usize;
+        if len > MAX_MESSAGE_LENGTH {
+            return Err(MessageReaderError::MessageLengthTooLong);
+        }
+        if self.buffer.len() < 4 + len {
+            return Ok(None);
+        }
+
+        let message = if len == 0 {
+            Message::new(MessageId::KeepAlive, vec![])
+        } else {
+            Message::from_bytes(&self.buffer[4..4 + len])
+                .map_err(MessageReaderError::InvalidMessage)?
+        };
+        self.buffer.drain(..4 + len);
+        Ok(Some(message))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_messages_split_across_reads() {
+        let mut bytes = Message::new(MessageId::Have, vec![0, 0, 0, 7]).as_bytes();
+        bytes.extend([0, 0, 0, 0]);
+        bytes.extend(Message::new(MessageId::Unchoke, vec![]).as_bytes());
+        let mut reader = MessageReader::new();
+
+        reader.read_from(&mut &bytes[..], 6).unwrap();
+        assert!(reader.next_message().unwrap().is_none());
+
+        reader.read_from(&mut &bytes[6..], usize::MAX).unwrap();
+        let have = reader.next_message().unwrap().unwrap();
+        assert_eq!(have.id, MessageId::Have);
+        assert_eq!(have.payload, vec![0, 0, 0, 7]);
+        assert_eq!(
+            reader.next_message().unwrap().unwrap().id,
+            MessageId::KeepAlive
+        );
+        assert_eq!(
+            reader.next_message().unwrap().unwrap().id,
+            MessageId::Unchoke
+        );
+        assert!(reader.next_message().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_messages() {
+        let mut reader = MessageReader::new();
+        reader
+            .read_from(&mut &[0xFF, 0, 0, 0][..], usize::MAX)
+            .unwrap();
+        assert!(matches!(
+            reader.next_message(),
+            Err(MessageReaderError::MessageLengthTooLong)
+        ));
+
+        let mut reader = MessageReader::new();
+        reader
+            .read_from(&mut &[0, 0, 0, 1, 99][..], usize::MAX)
+            .unwrap();
+        assert!(matches!(
+            reader.next_message(),
+            Err(MessageReaderError::InvalidMessage(_))
+        ));
+    }
+}
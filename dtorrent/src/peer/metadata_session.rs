@@ -0,0 +1,223 @@
+use std::{
+    io::{self, Read, Write},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use logger::logger_sender::LoggerSender;
+
+use crate::config::cfg::Cfg;
+
+use super::{
+    bt_peer::{BtPeer, BtPeerError},
+    encryption::mse::{self, MseError},
+    extension::{
+        extended_handshake::CLIENT_VERSION,
+        registry::{ExtensionError, ExtensionRegistry, EXTENDED_HANDSHAKE_ID},
+        ut_metadata::{MetadataDownload, UtMetadata, METADATA_PIECE_SIZE, UT_METADATA},
+    },
+    peer_message::{Message, MessageId},
+    peer_stream::PeerStream,
+};
+
+/// Largest message accepted from the peer, a piece of the metadata with its header, or a bitfield of a big torrent.
+const MAX_MESSAGE_LENGTH: u32 = METADATA_PIECE_SIZE as u32 * 4;
+
+/// Posible `MetadataSession` errors.
+#[derive(Debug)]
+pub enum MetadataSessionError {
+    CouldNotConnectToPeer(io::Error),
+    EncryptionError(MseError),
+    BtPeerError(BtPeerError),
+    InfoHashDoesNotMatch,
+    PeerIsOurself,
+    ExtensionProtocolNotSupported,
+    MetadataNotSupported,
+    ErrorReadingMessage(io::Error),
+    ErrorSendingMessage(io::Error),
+    MessageLengthTooLong,
+    ExtensionError(ExtensionError),
+    PoisonedDownloadLock,
+}
+
+/// A connection to a peer only to download the metadata of a torrent with the `ut_metadata` extension (BEP 9).
+///
+/// It is used for torrents added by a magnet link, before their info dict is known. The pieces of the metadata are stored in a
+/// `MetadataDownload` that can be shared by the sessions with several peers.
+///
+/// To create a new `MetadataSession`, use MetadataSession::new(peer, info_hash, download, config, logger_sender, client_peer_id).
+pub struct MetadataSession {
+    peer: BtPeer,
+    info_hash: Vec<u8>,
+    download: Arc<Mutex<MetadataDownload>>,
+    extensions: ExtensionRegistry,
+    config: Cfg,
+    logger_sender: LoggerSender,
+    client_peer_id: String,
+}
+
+impl MetadataSession {
+    /// Creates a new `MetadataSession` with a peer of the torrent with the given info hash.
+    pub fn new(
+        peer: BtPeer,
+        info_hash: Vec<u8>,
+        download: Arc<Mutex<MetadataDownload>>,
+        config: Cfg,
+        logger_sender: LoggerSender,
+        client_peer_id: String,
+    ) -> Self {
+        let mut extensions = ExtensionRegistry::new();
+        extensions.register(Box::new(UtMetadata::downloading(download.clone())));
+
+        Self {
+            peer,
+            info_hash,
+            download,
+            extensions,
+            config,
+            logger_sender,
+            client_peer_id,
+        }
+    }
+
+    /// Connects to the peer and downloads the metadata, returning it once it is complete and verified against the info hash.
+    ///
+    /// It returns an error if:
+    /// - The connection or the handshake with the peer failed.
+    /// - The peer does not support the extension protocol or the `ut_metadata` extension.
+    /// - The peer rejected a request or sent a piece that is not valid.
+    /// - The peer did not send a message before the read timeout.
+    pub fn fetch(&mut self) -> Result<Vec<u8>, MetadataSessionError> {
+        let mut stream = self.connect()?;
+        self.send_extended_handshake(&mut stream)?;
+
+        loop {
+            if let Some(metadata) = self.lock_download()?.metadata() {
+                return Ok(metadata.clone());
+            }
+            self.read_message(&mut stream)?;
+        }
+    }
+
+    fn connect(&mut self) -> Result<PeerStream, MetadataSessionError> {
+        let timeout = Duration::from_secs(self.config.read_write_seconds_timeout);
+        let mut stream = mse::connect(
+            self.peer.socket_addr(),
+            &self.info_hash,
+            self.config.encryption_mode,
+            timeout,
+            &None,
+        )
+        .map_err(|err| match err {
+            MseError::CouldNotConnect(err) => MetadataSessionError::CouldNotConnectToPeer(err),
+            err => MetadataSessionError::EncryptionError(err),
+        })?;
+
+        self.peer
+            .send_handshake(
+                &mut stream,
+                self.info_hash.clone(),
+                self.client_peer_id.clone(),
+            )
+            .map_err(MetadataSessionError::BtPeerError)?;
+        let info_hash = self
+            .peer
+            .receive_handshake(&mut stream)
+            .map_err(MetadataSessionError::BtPeerError)?;
+
+        if info_hash != self.info_hash {
+            return Err(MetadataSessionError::InfoHashDoesNotMatch);
+        }
+        if self.peer.peer_id.as_deref() == Some(self.client_peer_id.as_bytes()) {
+            return Err(MetadataSessionError::PeerIsOurself);
+        }
+        if !self.peer.supports_extension_protocol {
+            return Err(MetadataSessionError::ExtensionProtocolNotSupported);
+        }
+
+        self.logger_sender.info(&format!(
+            "IP: {} Handshake successful, fetching metadata",
+            self.peer.socket_addr()
+        ));
+        Ok(stream)
+    }
+
+    fn send_extended_handshake(&self, stream: &mut PeerStream) -> Result<(), MetadataSessionError> {
+        let mut handshake = self.extensions.handshake();
+        handshake.client_version = Some(CLIENT_VERSION.to_string());
+        handshake.port = Some(self.config.tcp_port);
+        handshake.your_ip = Some(self.peer.ip);
+
+        self.send_extended(
+            [vec![EXTENDED_HANDSHAKE_ID], handshake.as_bytes()].concat(),
+            stream,
+        )
+    }
+
+    fn send_extended(
+        &self,
+        payload: Vec<u8>,
+        stream: &mut PeerStream,
+    ) -> Result<(), MetadataSessionError> {
+        stream
+            .write_all(&Message::new(MessageId::Extended, payload).as_bytes())
+            .map_err(MetadataSessionError::ErrorSendingMessage)
+    }
+
+    /// Reads a message from the peer. Only extended messages are handled, the rest are ignored as we have no pieces to exchange yet.
+    fn read_message(&mut self, stream: &mut PeerStream) -> Result<(), MetadataSessionError> {
+        let mut length = [0; 4];
+        stream
+            .read_exact(&mut length)
+            .map_err(MetadataSessionError::ErrorReadingMessage)?;
+        let len = u32::from_be_bytes(length);
+
+        if len == 0 {
+            return Ok(());
+        }
+        if len > MAX_MESSAGE_LENGTH {
+            return Err(MetadataSessionError::MessageLengthTooLong);
+        }
+
+        let mut payload = vec![0; len as usize];
+        stream
+            .read_exact(&mut payload)
+            .map_err(MetadataSessionError::ErrorReadingMessage)?;
+
+        match Message::from_bytes(&payload) {
+            Ok(message) if message.id == MessageId::Extended => {
+                self.handle_extended(message, stream)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    fn handle_extended(
+        &mut self,
+        message: Message,
+        stream: &mut PeerStream,
+    ) -> Result<(), MetadataSessionError> {
+        let responses = self
+            .extensions
+            .handle_message(&message.payload)
+            .map_err(MetadataSessionError::ExtensionError)?;
+
+        if message.payload.first() == Some(&EXTENDED_HANDSHAKE_ID)
+            && (self.extensions.peer_extension_id(UT_METADATA).is_none()
+                || self.lock_download()?.size().is_none())
+        {
+            return Err(MetadataSessionError::MetadataNotSupported);
+        }
+
+        for response in responses {
+            self.send_extended(response, stream)?;
+        }
+        Ok(())
+    }
+
+    fn lock_download(&self) -> Result<MutexGuard<'_, MetadataDownload>, MetadataSessionError> {
+        self.download
+            .lock()
+            .map_err(|_| MetadataSessionError::PoisonedDownloadLock)
+    }
+}
@@ -1,6 +1,12 @@
+mod allowed_fast;
 pub mod bt_peer;
+pub mod encryption;
+pub mod extension;
 mod handshake;
 mod message_handler;
+pub mod message_reader;
+pub mod metadata_session;
 pub mod peer_message;
 pub mod peer_session;
+pub mod peer_stream;
 pub mod session_status;
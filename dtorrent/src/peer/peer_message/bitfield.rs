@@ -16,9 +16,14 @@ impl Bitfield {
     }
 
     /// Returns whether the bitfield has the piece with the given index.
+    ///
+    /// Indices past the end of the bitfield are not in it.
     pub fn has_piece(&self, index: u32) -> bool {
         let byte_index = (index / 8) as usize;
-        let byte = self.bitfield[byte_index];
+        let byte = match self.bitfield.get(byte_index) {
+            Some(byte) => *byte,
+            None => return false,
+        };
 
         let bit_index = 7 - (index % 8); // Gets the bit index in the byte (from the right)
 
@@ -28,6 +33,15 @@ impl Bitfield {
         bit != 0
     }
 
+    /// Creates a bitfield with all the pieces of a torrent with `pieces_count` pieces, the spare bits of the last byte are not set.
+    pub fn with_all_pieces(pieces_count: u32) -> Bitfield {
+        let mut bitfield = Self::new(vec![0; pieces_count.div_ceil(8) as usize]);
+        for index in 0..pieces_count {
+            bitfield.set_bit(index, true);
+        }
+        bitfield
+    }
+
     // Returns whether the bitfield has all the pieces.
     pub fn is_complete(&self) -> bool {
         self.bitfield.iter().all(|byte| *byte == 0b1111_1111)
@@ -74,9 +88,14 @@ impl Bitfield {
     }
 
     /// Sets the indexth bit to the given value.
+    ///
+    /// Indices past the end of the bitfield are ignored.
     pub fn set_bit(&mut self, index: u32, value: bool) {
         let byte_index = (index / 8) as usize;
-        let byte = self.bitfield[byte_index];
+        let byte = match self.bitfield.get(byte_index) {
+            Some(byte) => *byte,
+            None => return,
+        };
 
         let bit_index = 7 - (index % 8); // Gets the bit index in the byte (from the right)
         let bit = 1 << bit_index; // Shifts 1 to the left bit_index times
@@ -104,6 +123,13 @@ mod tests {
         assert!(bitfield.has_piece(4));
     }
 
+    #[test]
+    fn test_bitfield_with_all_pieces() {
+        let bitfield = Bitfield::with_all_pieces(10);
+
+        assert_eq!(bitfield.get_vec(), vec![0b11111111, 0b11000000]);
+    }
+
     #[test]
     fn test_bitfield_has_one_piece() {
         let bitfield = Bitfield::new(vec![0b00000000, 0b00000010, 0b00000000, 0b00000000]);
@@ -195,7 +221,7 @@ mod tests {
         let bitfield1 = Bitfield::new(vec![0b11111100, 0b11111111]);
         let bitfield2 = Bitfield::new(vec![0b11111100, 0b11111111]);
 
-        assert_eq!(bitfield2.diff(&bitfield1), vec![]);
+        assert_eq!(bitfield2.diff(&bitfield1), Vec::<usize>::new());
     }
 
     #[test]
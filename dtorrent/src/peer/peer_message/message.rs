@@ -12,6 +12,12 @@ pub enum MessageId {
     Piece = 7,
     Cancel = 8,
     Port = 9,
+    SuggestPiece = 13,
+    HaveAll = 14,
+    HaveNone = 15,
+    RejectRequest = 16,
+    AllowedFast = 17,
+    Extended = 20,
 }
 
 /// The message that is sent to the peer.
@@ -23,9 +29,11 @@ pub struct Message {
     pub payload: Vec<u8>,
 }
 
+/// Posible `Message` errors.
 #[derive(Debug)]
 pub enum MessageError {
     InvalidMessage,
+    InvalidPayloadLength(MessageId),
 }
 
 impl Message {
@@ -35,8 +43,12 @@ impl Message {
     }
 
     /// Parses a byte array into a `Message`.
+    ///
+    /// It returns a `MessageError` if:
+    /// - The message ID is not known.
+    /// - The payload does not have the length of the messages with that ID.
     pub fn from_bytes(payload: &[u8]) -> Result<Self, MessageError> {
-        let id = match payload[0] {
+        let id = match payload.first().ok_or(MessageError::InvalidMessage)? {
             0 => MessageId::Choke,
             1 => MessageId::Unchoke,
             2 => MessageId::Interested,
@@ -47,14 +59,19 @@ impl Message {
             7 => MessageId::Piece,
             8 => MessageId::Cancel,
             9 => MessageId::Port,
+            13 => MessageId::SuggestPiece,
+            14 => MessageId::HaveAll,
+            15 => MessageId::HaveNone,
+            16 => MessageId::RejectRequest,
+            17 => MessageId::AllowedFast,
+            20 => MessageId::Extended,
             _ => return Err(MessageError::InvalidMessage),
         };
 
-        let msg_payload = if payload.len() > 1 {
-            payload[1..].to_vec()
-        } else {
-            vec![]
-        };
+        let msg_payload = payload[1..].to_vec();
+        if !Self::is_valid_payload_length(&id, msg_payload.len()) {
+            return Err(MessageError::InvalidPayloadLength(id));
+        }
 
         Ok(Self {
             id,
@@ -62,6 +79,25 @@ impl Message {
         })
     }
 
+    /// Returns true if a payload of that length is valid for the message ID, the length of the bitfields is checked by the session.
+    fn is_valid_payload_length(id: &MessageId, length: usize) -> bool {
+        match id {
+            MessageId::KeepAlive
+            | MessageId::Choke
+            | MessageId::Unchoke
+            | MessageId::Interested
+            | MessageId::NotInterested
+            | MessageId::HaveAll
+            | MessageId::HaveNone => length == 0,
+            MessageId::Have | MessageId::SuggestPiece | MessageId::AllowedFast => length == 4,
+            MessageId::Request | MessageId::Cancel | MessageId::RejectRequest => length == 12,
+            MessageId::Piece => length >= 8,
+            MessageId::Port => length == 2,
+            MessageId::Bitfield => true,
+            MessageId::Extended => length >= 1,
+        }
+    }
+
     /// Converts a `Message` to a byte array.
     pub fn as_bytes(&self) -> Vec<u8> {
         let len = self.payload.len() + 1;
@@ -84,7 +120,7 @@ mod tests {
         let msg = Message::from_bytes(&payload).unwrap();
 
         assert_eq!(msg.id, MessageId::Unchoke);
-        assert_eq!(msg.payload, vec![]);
+        assert_eq!(msg.payload, Vec::<u8>::new());
     }
 
     #[test]
@@ -93,7 +129,54 @@ mod tests {
         let msg = Message::from_bytes(&payload).unwrap();
 
         assert_eq!(msg.id, MessageId::Interested);
-        assert_eq!(msg.payload, vec![]);
+        assert_eq!(msg.payload, Vec::<u8>::new());
+    }
+
+    #[test]
+    fn test_message_extended_from_bytes() {
+        let payload = [20, 0, b'd', b'e'];
+        let msg = Message::from_bytes(&payload).unwrap();
+
+        assert_eq!(msg.id, MessageId::Extended);
+        assert_eq!(msg.payload, vec![0, b'd', b'e']);
+    }
+
+    #[test]
+    fn test_message_fast_extension_from_bytes() {
+        assert_eq!(Message::from_bytes(&[14]).unwrap().id, MessageId::HaveAll);
+        assert_eq!(Message::from_bytes(&[15]).unwrap().id, MessageId::HaveNone);
+
+        let msg = Message::from_bytes(&[17, 0, 0, 0, 5]).unwrap();
+        assert_eq!(msg.id, MessageId::AllowedFast);
+        assert_eq!(msg.payload, vec![0, 0, 0, 5]);
+    }
+
+    #[test]
+    fn test_message_with_invalid_payload_length() {
+        assert!(matches!(
+            Message::from_bytes(&[4, 0, 0]),
+            Err(MessageError::InvalidPayloadLength(MessageId::Have))
+        ));
+        assert!(matches!(
+            Message::from_bytes(&[6, 0, 0, 0, 1, 0, 0, 0, 0]),
+            Err(MessageError::InvalidPayloadLength(MessageId::Request))
+        ));
+        assert!(matches!(
+            Message::from_bytes(&[7, 0, 0, 0, 1]),
+            Err(MessageError::InvalidPayloadLength(MessageId::Piece))
+        ));
+        assert!(matches!(
+            Message::from_bytes(&[16, 0, 0, 0, 1]),
+            Err(MessageError::InvalidPayloadLength(MessageId::RejectRequest))
+        ));
+        assert!(matches!(
+            Message::from_bytes(&[1, 0]),
+            Err(MessageError::InvalidPayloadLength(MessageId::Unchoke))
+        ));
+        assert!(matches!(
+            Message::from_bytes(&[]),
+            Err(MessageError::InvalidMessage)
+        ));
     }
 
     #[test]
@@ -108,7 +191,7 @@ mod tests {
 
         let len = 13u32.to_be_bytes();
         let msg_type = 6u8.to_be_bytes();
-        let mut expected = vec![];
+        let mut expected: Vec<u8> = vec![];
         expected.extend(&len);
         expected.extend(&msg_type);
         expected.extend(&payload);
@@ -124,7 +207,7 @@ mod tests {
 
         let len = 1u32.to_be_bytes();
         let msg_type = 2u8.to_be_bytes();
-        let mut expected = vec![];
+        let mut expected: Vec<u8> = vec![];
         expected.extend(&len);
         expected.extend(&msg_type);
 
@@ -39,7 +39,7 @@ mod tests {
 
         let bytes = request.as_bytes();
 
-        let mut expected = vec![];
+        let mut expected: Vec<u8> = vec![];
         expected.extend(&index.to_be_bytes());
         expected.extend(&begin.to_be_bytes());
         expected.extend(&length.to_be_bytes());
@@ -1,9 +1,10 @@
 use std::{
+    collections::{HashSet, VecDeque},
     fmt::Write,
-    io::{self, Read, Write as IOWrite},
-    net::TcpStream,
+    io,
+    net::IpAddr,
     sync::Arc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use chrono::{DateTime, Local};
@@ -11,19 +12,45 @@ use logger::logger_sender::LoggerSender;
 use sha1::{Digest, Sha1};
 
 use crate::{
+    bandwidth::rate_limiter::{RateLimiter, RateLimits, Rates},
     config::cfg::Cfg,
-    torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+    torrent_handler::{
+        block_tracker::{BlockRequest, BLOCK_SIZE},
+        status::{AtomicTorrentStatus, AtomicTorrentStatusError},
+    },
     torrent_parser::torrent::Torrent,
+    utp::socket::UtpSocket,
 };
 
 use super::{
+    allowed_fast::{allowed_fast_set, ALLOWED_FAST_SET_SIZE},
     bt_peer::{BtPeer, BtPeerError},
+    encryption::mse::{self, MseError},
+    extension::{
+        extended_handshake::CLIENT_VERSION,
+        registry::{ExtensionRegistry, EXTENDED_HANDSHAKE_ID},
+        ut_metadata::UtMetadata,
+        ut_pex::UtPex,
+    },
     message_handler::{MessageHandler, MessageHandlerError},
+    message_reader::{MessageReader, MessageReaderError},
     peer_message::{Bitfield, Message, MessageError, MessageId},
+    peer_stream::PeerStream,
     session_status::SessionStatus,
 };
 
-const BLOCK_SIZE: u32 = 16384;
+/// Bytes waiting to be sent to the peer above which its messages are not read, so a peer requesting faster than it receives does not fill our memory.
+pub const MAX_PENDING_WRITES: usize = 1024 * 1024;
+
+/// Number of outstanding requests a peer can send us, announced in the extended handshake.
+const MAX_QUEUED_REQUESTS: u32 = 250;
+
+/// Maximum length of a block a peer can request us, larger requests are rejected.
+const MAX_REQUEST_LENGTH: u32 = BLOCK_SIZE;
+
+/// Bytes a session transfers in each direction every time it is driven while there are rate limits,
+/// so the sessions waiting for bandwidth take it in turns instead of the first one taking it all.
+const BANDWIDTH_ROUND_SHARE: usize = 256 * 1024;
 
 #[derive(Debug)]
 pub enum PeerSessionError {
@@ -40,30 +67,72 @@ pub enum PeerSessionError {
     ErrorGettingBitfield(AtomicTorrentStatusError),
     ErrorGettingPiece(AtomicTorrentStatusError),
     ErrorGettingSessionsStatus(AtomicTorrentStatusError),
+    ErrorUpdatingPieceAvailability(AtomicTorrentStatusError),
+    ErrorRequestingBlocks(AtomicTorrentStatusError),
+    ErrorReceivingBlock(AtomicTorrentStatusError),
+    ErrorGettingChokingStatus(AtomicTorrentStatusError),
+    ErrorGettingAllowedFastSet,
+    EncryptionError(MseError),
+    InvalidInfoHash,
     PeerNotInterested,
     MessageHandlerError(MessageHandlerError),
     MessageError(MessageId),
     MessageLengthTooLong,
-    ErrorSettingStreamTimeout,
+    ErrorSettingNonblockingStream,
+    ErrorWritingMessage(io::Error),
     BtPeerError(BtPeerError),
     PeerIsOurself,
+    TorrentStopped,
 }
 
 /// A PeerSession represents a connection to a peer.
 ///
 /// It is used to send and receive messages from a peer.
+///
+/// Once connected, the session is a state machine driven by the `PeerReactor`: it never waits for the peer,
+/// it handles the messages as they arrive with `stream_readable()` and checks the choker and its timeout with `tick()`.
+/// We download from the peers we connect to, and only upload to the peers that connect to us.
+///
+/// With peers that support the fast extension (BEP 6), the pieces we have are sent with `Have All` or `Have None` when possible,
+/// the requests that will not be served are rejected, and each side can download the pieces of its allowed fast set while it is choked.
+///
+/// The messages of the peer are read and the blocks it requested are sent within the global and the torrent rate limits.
+/// When the limits stop the session it is throttled, and the reactor drives it again once there is bandwidth.
 pub struct PeerSession {
     torrent: Torrent,
     peer: BtPeer,
     bitfield: Bitfield,
     status: SessionStatus,
-    piece: Vec<u8>,
+    requested_blocks: Vec<BlockRequest>,
+    completed_piece: Option<(u32, Vec<u8>)>,
     torrent_status: Arc<AtomicTorrentStatus>,
-    current_piece: u32,
+    current_piece: Option<u32>,
     config: Cfg,
     logger_sender: LoggerSender,
     message_handler: MessageHandler,
+    extensions: ExtensionRegistry,
     client_peer_id: String,
+    /// Pieces the peer can request while we choke it.
+    allowed_fast_for_peer: HashSet<u32>,
+    /// Pieces we can request while the peer chokes us.
+    allowed_fast_from_peer: HashSet<u32>,
+    /// Socket used to connect to the peer over uTP before trying TCP.
+    utp: Option<Arc<UtpSocket>>,
+    reader: MessageReader,
+    /// Whether we download from the peer, only for the peers we connected to.
+    downloading: bool,
+    /// Whether an incoming leecher was ever interested in our pieces.
+    interest_received: bool,
+    last_message_at: Instant,
+    piece_started_at: DateTime<Local>,
+    piece_downloaded_size: u64,
+    upload_started_at: Option<DateTime<Local>>,
+    uploaded_size: u64,
+    /// Blocks requested by the peer, sent as the upload limits allow it.
+    requests_received: VecDeque<BlockRequest>,
+    global_limits: Arc<RateLimits>,
+    /// Whether the rate limits stopped the session before it read or sent everything it could.
+    throttled: bool,
 }
 
 impl PeerSession {
@@ -74,6 +143,7 @@ impl PeerSession {
         config: Cfg,
         logger_sender: LoggerSender,
         client_peer_id: String,
+        utp: Option<Arc<UtpSocket>>,
     ) -> Result<Self, PeerSessionError> {
         let our_bitfield = Bitfield::new(
             torrent_status
@@ -91,18 +161,38 @@ impl PeerSession {
 
         let pieces_count = torrent.total_pieces();
 
+        let mut extensions = ExtensionRegistry::new();
+        extensions.register(Box::new(UtMetadata::for_torrent(&torrent)));
+        extensions.register(Box::new(UtPex::new(torrent_status.clone(), peer.clone())));
+
         Ok(PeerSession {
             torrent,
             peer,
-            bitfield: Bitfield::new(vec![0; (pieces_count / 8) as usize]),
+            bitfield: Bitfield::new(vec![0; pieces_count.div_ceil(8) as usize]),
             status: SessionStatus::new(our_bitfield),
-            piece: vec![],
+            requested_blocks: vec![],
+            completed_piece: None,
             torrent_status,
-            current_piece: 0,
+            current_piece: None,
             config,
             logger_sender,
             message_handler,
+            extensions,
             client_peer_id,
+            allowed_fast_for_peer: HashSet::new(),
+            allowed_fast_from_peer: HashSet::new(),
+            utp,
+            reader: MessageReader::new(),
+            downloading: false,
+            interest_received: false,
+            last_message_at: Instant::now(),
+            piece_started_at: Local::now(),
+            piece_downloaded_size: 0,
+            upload_started_at: None,
+            uploaded_size: 0,
+            requests_received: VecDeque::new(),
+            global_limits: Arc::new(RateLimits::new(Rates::default())),
+            throttled: false,
         })
     }
 
@@ -112,120 +202,74 @@ impl PeerSession {
     /// Handshakes with an incoming leecher.
     pub fn handshake_incoming_leecher(
         &mut self,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
         self.message_handler
             .send_handshake(stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
 
         self.logger_sender.info(&format!(
-            "IP: {}:{} Handshake successful",
-            self.peer.ip, self.peer.port
+            "IP: {} Handshake successful",
+            self.peer.socket_addr()
         ));
 
-        self.message_handler
-            .send_bitfield(stream)
-            .map_err(PeerSessionError::MessageHandlerError)?;
+        self.send_have_pieces(stream)?;
 
-        self.logger_sender.info(&format!(
-            "IP: {}:{} Bitfield sent",
-            self.peer.ip, self.peer.port
-        ));
-
-        Ok(())
-    }
+        self.logger_sender
+            .info(&format!("IP: {} Bitfield sent", self.peer.socket_addr()));
 
-    pub fn unchoke_incoming_leecher(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
-        self.torrent_status
-            .peer_connected(&self.peer)
-            .map_err(PeerSessionError::ErrorConnectingToPeer)?;
-        match self.unchoke_incoming_leecher_wrap(stream) {
-            Ok(_) => Ok(()),
-            Err(e) => {
-                self.torrent_status
-                    .peer_disconnected(&self.peer)
-                    .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
-                Err(e)
-            }
-        }
+        self.send_extended_handshake(stream)
     }
 
-    /// Sends an unchoke message to the peer to start sending pieces.
-    pub fn unchoke_incoming_leecher_wrap(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
-        let mut id = self.read_message_from_stream(stream)?;
-        while id != MessageId::Interested {
-            // if we receive a `not interested` message, we close the connection.
-            if id == MessageId::NotInterested {
-                self.status.peer_interested = false;
-                // peer disconnected
-                return Err(PeerSessionError::PeerNotInterested);
-            }
-            // wait for the peer to send an interested message
-            id = self.read_message_from_stream(stream)?;
-        }
-
-        // Peer is interested
-        self.status.peer_interested = true;
-
-        self.message_handler
-            .send_unchoked(stream)
-            .map_err(PeerSessionError::MessageHandlerError)?;
-
-        self.status.peer_choked = false;
-
-        loop {
+    /// Sends the pieces we got since the last time to an incoming leecher, once it is interested.
+    fn update_incoming_leecher(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        if self.interest_received {
             self.update_bitfield(stream)?;
-
-            // TODO: Handle max connections.
-            self.read_message_from_stream(stream)?;
         }
+        Ok(())
     }
 
     /// ------------------------------------------------------------------------------------------------
     /// Downloading
 
-    /// Starts a connection to an outgoing seeder to start downloading pieces.
+    /// Connects to a seeder to download pieces from it, returning the stream once the handshakes are done.
+    ///
+    /// It blocks while connecting, the session is driven by the `PeerReactor` afterwards.
     ///
     /// It returns an error if:
     /// - The connection could not be established
     /// - The handshake was not successful
-    pub fn start_outgoing_seeder(&mut self) -> Result<(), PeerSessionError> {
-        let mut stream = match self.set_up_peer_session() {
-            Ok(stream) => stream,
-            Err(e) => {
-                self.torrent_status.peer_connecting_failed();
-                return Err(e);
+    pub fn connect_to_seeder(&mut self) -> Result<PeerStream, PeerSessionError> {
+        match self.set_up_peer_session() {
+            Ok(stream) => {
+                self.downloading = true;
+                // We connected to the port the peer listens on.
+                self.status.listen_port = u16::try_from(self.peer.port).ok();
+                Ok(stream)
             }
-        };
-
-        self.torrent_status
-            .peer_connected(&self.peer)
-            .map_err(PeerSessionError::ErrorConnectingToPeer)?;
-
-        match self.start_outgoing_seeder_wrap(&mut stream) {
-            Ok(_) => Ok(()),
             Err(e) => {
-                self.torrent_status
-                    .peer_disconnected(&self.peer)
-                    .map_err(PeerSessionError::ErrorDisconnectingFromPeer)?;
+                self.torrent_status.peer_connecting_failed();
                 Err(e)
             }
         }
     }
 
-    fn set_up_peer_session(&mut self) -> Result<TcpStream, PeerSessionError> {
-        let peer_socket = format!("{}:{}", self.peer.ip, self.peer.port);
-
-        let mut stream = TcpStream::connect(&peer_socket)
-            .map_err(|_| PeerSessionError::CouldNotConnectToPeer)?;
-
-        self.set_stream_timeouts(&mut stream)?;
+    fn set_up_peer_session(&mut self) -> Result<PeerStream, PeerSessionError> {
+        let info_hash = self
+            .torrent
+            .get_info_hash_as_bytes()
+            .map_err(|_| PeerSessionError::InvalidInfoHash)?;
+        let mut stream = mse::connect(
+            self.peer.socket_addr(),
+            &info_hash,
+            self.config.encryption_mode,
+            Duration::from_secs(self.config.read_write_seconds_timeout),
+            &self.utp,
+        )
+        .map_err(|err| match err {
+            MseError::CouldNotConnect(_) => PeerSessionError::CouldNotConnectToPeer,
+            err => PeerSessionError::EncryptionError(err),
+        })?;
 
         self.message_handler
             .send_handshake(&mut stream)
@@ -246,209 +290,184 @@ impl PeerSession {
             }
             None => (),
         }
+
+        // The fast extension requires telling the peer which pieces we have.
+        if self.peer.supports_fast_extension {
+            self.send_have_pieces(&mut stream)?;
+        }
+        self.send_extended_handshake(&mut stream)?;
         Ok(stream)
     }
 
-    fn start_outgoing_seeder_wrap(
-        &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<(), PeerSessionError> {
-        loop {
-            self.read_message_from_stream(stream)?;
+    /// Downloads pieces from the peer until it has no more pieces we need.
+    ///
+    /// It keeps up to `pipelining_size` block requests sent to the peer for the current piece, and the blocks of the piece can also be downloaded from other peers.
+    /// While we are choked, only the pieces of the allowed fast set of the peer are downloaded.
+    ///
+    /// It returns an error if the peer has no more pieces we need and it is not choking us.
+    fn update_download(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        self.check_current_piece()?;
 
-            if self.status.choked && !self.status.interested {
-                self.message_handler
-                    .send_interested(stream)
-                    .map_err(PeerSessionError::MessageHandlerError)?;
+        if self.status.choked && !self.status.interested {
+            self.message_handler
+                .send_interested(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
 
-                self.status.interested = true;
-            }
+            self.status.interested = true;
+        }
 
-            if !self.status.choked && self.status.interested {
-                self.request_pieces(stream)?;
-            }
+        if !self.status.interested || (self.status.choked && self.allowed_fast_from_peer.is_empty())
+        {
+            return Ok(());
         }
-    }
 
-    fn request_pieces(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+        self.send_cancels(stream)?;
         loop {
-            let piece_index = self
-                .torrent_status
-                .select_piece(&self.bitfield)
-                .map_err(PeerSessionError::ErrorSelectingPiece)?;
-
-            match piece_index {
-                Some(piece_index) => {
-                    self.current_piece = piece_index;
-                    match self.download_piece(stream, piece_index) {
-                        Ok(_) => {
-                            self.torrent_status
-                                .piece_downloaded(piece_index, &self.piece)
-                                .map_err(PeerSessionError::ErrorNotifyingPieceDownloaded)?;
-                        }
-                        Err(e) => {
-                            self.torrent_status
-                                .piece_aborted(piece_index)
-                                .map_err(PeerSessionError::ErrorAbortingPiece)?;
-
-                            return Err(e);
-                        }
-                    }
-                    if self.status.choked {
-                        // If we are choked, we need to wait for the peer to unchoke us.
-                        return Ok(());
+            let piece_index = match self.current_piece {
+                Some(piece_index) => piece_index,
+                None => match self.select_piece()? {
+                    Some(piece_index) => {
+                        self.current_piece = Some(piece_index);
+                        self.piece_started_at = Local::now();
+                        self.piece_downloaded_size = 0;
+                        piece_index
                     }
-                }
-                None => {
-                    return Err(PeerSessionError::NoPiecesLeftToDownloadInThisPeer);
-                }
+                    // If we are choked, we need to wait for the peer to unchoke us.
+                    None if self.status.choked => return Ok(()),
+                    None => return Err(PeerSessionError::NoPiecesLeftToDownloadInThisPeer),
+                },
             };
+
+            self.request_blocks(stream, piece_index)?;
+            if !self.requested_blocks.is_empty() {
+                return Ok(());
+            }
+            // The rest of the piece was requested to other peers.
+            self.current_piece = None;
         }
     }
 
-    /// Downloads a piece from the peer given the piece index.
-    fn download_piece(
+    /// Selects the next piece to download from the peer, among its allowed fast pieces if we are choked.
+    fn select_piece(&mut self) -> Result<Option<u32>, PeerSessionError> {
+        if self.status.choked {
+            let mut allowed_bitfield = Bitfield::new(vec![0; self.bitfield.get_vec().len()]);
+            for index in &self.allowed_fast_from_peer {
+                if self.bitfield.has_piece(*index) {
+                    allowed_bitfield.set_bit(*index, true);
+                }
+            }
+            self.torrent_status
+                .select_piece(&self.peer, &allowed_bitfield)
+                .map_err(PeerSessionError::ErrorSelectingPiece)
+        } else {
+            self.torrent_status
+                .select_piece(&self.peer, &self.bitfield)
+                .map_err(PeerSessionError::ErrorSelectingPiece)
+        }
+    }
+
+    /// Releases the current piece if it can not be downloaded from the peer anymore.
+    ///
+    /// The peer discards our requests when it chokes us, except those of allowed fast pieces.
+    /// The blocks already downloaded are kept, so the piece can be finished from another peer.
+    fn check_current_piece(&mut self) -> Result<(), PeerSessionError> {
+        let piece_index = match self.current_piece {
+            Some(piece_index) => piece_index,
+            None => return Ok(()),
+        };
+        let choked = self.status.choked && !self.allowed_fast_from_peer.contains(&piece_index);
+        if choked || !self.bitfield.has_piece(piece_index) {
+            self.requested_blocks.clear();
+            self.current_piece = None;
+            self.torrent_status
+                .release_piece(&self.peer, piece_index)
+                .map_err(PeerSessionError::ErrorAbortingPiece)?;
+        }
+        Ok(())
+    }
+
+    /// Validates and stores a piece completed by a block received from the peer.
+    fn piece_completed(
         &mut self,
-        stream: &mut TcpStream,
         piece_index: u32,
+        piece: Vec<u8>,
     ) -> Result<(), PeerSessionError> {
-        self.piece = vec![]; // reset piece
-
-        let entire_blocks_in_piece = self.download_with_pipeline(piece_index, stream)?;
-
-        self.check_last_piece_block(piece_index, entire_blocks_in_piece, stream)?;
-
-        self.validate_piece(&self.piece, piece_index)?;
+        self.requested_blocks.clear();
+        self.current_piece = None;
+
+        if let Err(e) = self.validate_piece(&piece, piece_index) {
+            self.torrent_status
+                .piece_aborted(piece_index)
+                .map_err(PeerSessionError::ErrorAbortingPiece)?;
+            return Err(e);
+        }
         self.logger_sender
             .info(&format!("Piece {} downloaded!", piece_index));
 
-        let remaining_pieces = self.torrent_status.downloaded_pieces();
+        let remaining_pieces = self.torrent_status.downloaded_pieces() + 1;
         println!(
             "*** Torrent: {} - Pieces downloaded: {} / {}",
             self.torrent.name(),
             remaining_pieces,
             self.torrent.total_pieces()
         );
-
-        Ok(())
-    }
-
-    /// Downloads a piece in 'chunks' of blocks.
-    ///
-    /// If the pipelinening size in the config is 5, then it will request 5 blocks and wait for those 5 blocks to be received.
-    ///
-    /// If there are less than 5 blocks left in the piece, it will request the remaining blocks and wait for those blocks to be received.
-    fn download_with_pipeline(
-        &mut self,
-        piece_index: u32,
-        stream: &mut TcpStream,
-    ) -> Result<u32, PeerSessionError> {
-        let entire_blocks_in_piece = self.complete_blocks_in_torrent_piece(piece_index);
-        let mut blocks_downloaded = 0;
-        while blocks_downloaded < entire_blocks_in_piece {
-            let remaining_blocks = entire_blocks_in_piece - blocks_downloaded;
-            let blocks_to_download = if remaining_blocks % self.config.pipelining_size == 0 {
-                self.config.pipelining_size
-            } else {
-                remaining_blocks
-            };
-
-            let download_start_time = Local::now();
-
-            // request blocks
-            for block in 0..blocks_to_download {
-                self.message_handler
-                    .send_request(
-                        piece_index,
-                        (block + blocks_downloaded) * BLOCK_SIZE,
-                        BLOCK_SIZE,
-                        stream,
-                    )
-                    .map_err(PeerSessionError::MessageHandlerError)?;
-            }
-
-            // If we are in the endgame phase, and we already downloaded all the blocks, we send a cancel message.
-            if self.torrent_status.is_finished() {
-                for block in 0..blocks_to_download {
-                    self.message_handler
-                        .send_cancel(
-                            piece_index,
-                            (block + blocks_downloaded) * BLOCK_SIZE,
-                            BLOCK_SIZE,
-                            stream,
-                        )
-                        .map_err(PeerSessionError::MessageHandlerError)?;
-                }
-            }
-
-            // Check that we receive a piece message.
-            // If we receive another message we handle it accordingly.
-            let mut current_blocks_downloaded = 0;
-            while current_blocks_downloaded < blocks_to_download {
-                if self.read_message_from_stream(stream)? == MessageId::Piece {
-                    current_blocks_downloaded += 1;
-                    blocks_downloaded += 1;
-                }
-            }
-            // Calculate download speed
-            let download_speed = self.calculate_kilobits_per_second(
-                download_start_time,
-                (blocks_to_download * BLOCK_SIZE).into(),
-            );
-            self.status.download_speed = download_speed;
-            self.update_peer_status()?;
-        }
-        Ok(entire_blocks_in_piece)
+        self.torrent_status
+            .piece_downloaded(piece_index, &piece)
+            .map_err(PeerSessionError::ErrorNotifyingPieceDownloaded)
     }
 
-    fn check_last_piece_block(
+    /// Requests blocks of the piece to the peer until there are `pipelining_size` requests waiting for a response.
+    fn request_blocks(
         &mut self,
+        stream: &mut PeerStream,
         piece_index: u32,
-        entire_blocks_in_piece: u32,
-        stream: &mut TcpStream,
     ) -> Result<(), PeerSessionError> {
-        let last_block_size = self.torrent.last_piece_size() % BLOCK_SIZE;
+        let mut pipelining_size = self.config.pipelining_size;
+        if let Some(request_queue) = self
+            .extensions
+            .peer_handshake()
+            .and_then(|handshake| handshake.request_queue)
+        {
+            pipelining_size = pipelining_size.min(request_queue);
+        }
 
-        let last_piece_index = self.torrent.total_pieces() - 1;
+        let max = (pipelining_size as usize).saturating_sub(self.requested_blocks.len());
+        if max == 0 {
+            return Ok(());
+        }
 
-        if last_block_size != 0 && piece_index == last_piece_index {
+        let blocks = self
+            .torrent_status
+            .request_blocks(&self.peer, piece_index, max)
+            .map_err(PeerSessionError::ErrorRequestingBlocks)?;
+        for block in &blocks {
             self.message_handler
-                .send_request(
-                    piece_index,
-                    entire_blocks_in_piece * BLOCK_SIZE,
-                    last_block_size,
-                    stream,
-                )
+                .send_request(block.index, block.begin, block.length, stream)
                 .map_err(PeerSessionError::MessageHandlerError)?;
-
-            while self.read_message_from_stream(stream)? != MessageId::Piece {
-                continue;
-            }
         }
+        self.requested_blocks.extend(blocks);
         Ok(())
     }
 
-    fn complete_blocks_in_torrent_piece(&self, piece_index: u32) -> u32 {
-        let last_piece_index = self.torrent.total_pieces() - 1;
-
-        if piece_index != last_piece_index {
-            self.torrent.piece_length() / BLOCK_SIZE
-        } else {
-            let last_piece_size = self.torrent.last_piece_size();
-
-            // If the last piece is multiple of the piece length, then is the same as the other pieces.
-            if last_piece_size == 0 {
-                self.torrent.piece_length() / BLOCK_SIZE
-            } else {
-                (last_piece_size as f64 / BLOCK_SIZE as f64).floor() as u32
-            }
+    /// Sends a cancel message for every requested block that was received from another peer.
+    fn send_cancels(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        let cancels = self
+            .torrent_status
+            .take_cancels(&self.peer)
+            .map_err(PeerSessionError::ErrorRequestingBlocks)?;
+        for cancel in cancels {
+            self.message_handler
+                .send_cancel(cancel.index, cancel.begin, cancel.length, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+            self.requested_blocks.retain(|block| *block != cancel);
         }
+        Ok(())
     }
 
     /// ------------------------------------------------------------------------------------------------
     /// Commons for download and upload
 
-    fn update_bitfield(&mut self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
+    fn update_bitfield(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
         let updated_bitfield = self
             .torrent_status
             .get_bitfield()
@@ -463,14 +482,77 @@ impl PeerSession {
         }
 
         self.status.bitfield = updated_bitfield;
+        Ok(())
+    }
 
-        let bitfield_msg = Message::new(MessageId::Bitfield, self.status.bitfield.get_vec());
-        stream
-            .write_all(&bitfield_msg.as_bytes())
-            .map_err(|_| PeerSessionError::MessageError(MessageId::Bitfield))?;
+    /// Tells the peer which pieces we have.
+    ///
+    /// If the peer supports the fast extension, a `Have All` or `Have None` message is sent instead of the bitfield when possible,
+    /// followed by the pieces of its allowed fast set.
+    fn send_have_pieces(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        if !self.peer.supports_fast_extension {
+            return self
+                .message_handler
+                .send_bitfield(stream)
+                .map_err(PeerSessionError::MessageHandlerError);
+        }
+
+        if self.torrent_status.is_finished() {
+            self.message_handler.send_have_all(stream)
+        } else if self.torrent_status.downloaded_pieces() == 0 {
+            self.message_handler.send_have_none(stream)
+        } else {
+            self.message_handler.send_bitfield(stream)
+        }
+        .map_err(PeerSessionError::MessageHandlerError)?;
+
+        // The canonical allowed fast set is only defined for IPv4 addresses.
+        let ip = match self.peer.ip {
+            IpAddr::V4(ip) => ip,
+            IpAddr::V6(_) => return Ok(()),
+        };
+        let info_hash = self
+            .torrent
+            .get_info_hash_as_bytes()
+            .map_err(|_| PeerSessionError::ErrorGettingAllowedFastSet)?;
+        for index in allowed_fast_set(
+            ip,
+            &info_hash,
+            self.torrent.total_pieces(),
+            ALLOWED_FAST_SET_SIZE,
+        ) {
+            self.message_handler
+                .send_allowed_fast(index, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+            self.allowed_fast_for_peer.insert(index);
+        }
         Ok(())
     }
 
+    /// Sends a `Choke` or `Unchoke` message if the choker changed its decision about the peer.
+    fn update_choking(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        let unchoked = self
+            .torrent_status
+            .is_peer_unchoked(&self.peer)
+            .map_err(PeerSessionError::ErrorGettingChokingStatus)?;
+        if unchoked != self.status.peer_choked {
+            return Ok(());
+        }
+
+        if unchoked {
+            self.message_handler
+                .send_unchoked(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        } else {
+            self.message_handler
+                .send_choke(stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+            self.discard_requests(stream)?;
+        }
+        self.status.peer_choked = !unchoked;
+        self.update_peer_status()
+    }
+
     fn calculate_kilobits_per_second(&self, start_time: DateTime<Local>, size: u64) -> f64 {
         let elapsed_time = Local::now().signed_duration_since(start_time);
         let elapsed_time_in_seconds = match elapsed_time.num_microseconds() {
@@ -487,51 +569,160 @@ impl PeerSession {
         Ok(())
     }
 
-    /// Reads & handles a message from the stream.
+    // ------------------------------------------------------------------------------------------------
+    // Events of the reactor
+
+    /// Starts the session once the handshakes are done, counting the peer as connected.
     ///
-    /// It returns an error if:
-    /// - The message could not be read
-    fn read_message_from_stream(
+    /// The session shares the global rate limits of the reactor besides the ones of its torrent.
+    /// The stream is switched to nonblocking mode, if it fails the session must still be closed.
+    pub fn start(
         &mut self,
-        stream: &mut TcpStream,
-    ) -> Result<MessageId, PeerSessionError> {
-        let mut length = [0; 4];
-
+        stream: &mut PeerStream,
+        global_limits: Arc<RateLimits>,
+    ) -> Result<(), PeerSessionError> {
+        self.global_limits = global_limits;
+        self.torrent_status
+            .peer_connected(&self.peer)
+            .map_err(PeerSessionError::ErrorConnectingToPeer)?;
         stream
-            .read_exact(&mut length)
-            .map_err(PeerSessionError::ErrorReadingMessage)?;
-        let len = u32::from_be_bytes(length);
+            .set_nonblocking()
+            .map_err(|_| PeerSessionError::ErrorSettingNonblockingStream)?;
+        self.last_message_at = Instant::now();
+        self.update_peer_status()?;
+        self.update(stream)
+    }
 
-        // TODO: solucionar el problema de que el peer puede mandar un mensaje de mas de 16393 bytes. Cuando esta mandando cualquiera.
-        // Issue: https://github.com/taller-1-fiuba-rust/22C1-La-Deymoneta/issues/101
-        // Ahora que en el server la iniciacion esta dentro del Ok() esta fallando en el handshake, mirar ahi tambien.
-        if len > BLOCK_SIZE * 10 {
-            return Err(PeerSessionError::MessageLengthTooLong);
+    /// Reads and handles the messages available in the nonblocking stream, as much as the download limits allow.
+    ///
+    /// The messages are not read while there are more than `MAX_PENDING_WRITES` bytes waiting to be sent to the peer.
+    ///
+    /// It returns an error if:
+    /// - The connection was closed or failed
+    /// - A message was invalid or could not be handled
+    pub fn stream_readable(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        self.throttled = false;
+        let mut read_size = 0;
+        while stream.pending_len() < MAX_PENDING_WRITES {
+            let max = match Self::available_bandwidth(&self.download_limiters()) {
+                Some(available) => available.min(BANDWIDTH_ROUND_SHARE.saturating_sub(read_size)),
+                None => usize::MAX,
+            };
+            if max == 0 {
+                self.throttled = true;
+                break;
+            }
+            match self.reader.read_from(stream, max) {
+                Ok(0) => {
+                    return Err(PeerSessionError::ErrorReadingMessage(
+                        io::ErrorKind::UnexpectedEof.into(),
+                    ))
+                }
+                Ok(len) => {
+                    self.download_limiters()
+                        .iter()
+                        .for_each(|limiter| limiter.consume(len));
+                    read_size += len;
+                    self.handle_received_messages(stream)?;
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(PeerSessionError::ErrorReadingMessage(err)),
+            }
         }
+        self.update(stream)
+    }
 
-        if len == 0 {
-            return Ok(MessageId::KeepAlive);
+    /// Checks the decisions of the choker and the messages of the extensions, called periodically by the reactor.
+    ///
+    /// It returns an error if the peer sent nothing during the read timeout.
+    pub fn tick(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        let timeout = Duration::from_secs(self.config.read_write_seconds_timeout);
+        if self.last_message_at.elapsed() > timeout {
+            return Err(PeerSessionError::ErrorReadingMessage(
+                io::ErrorKind::TimedOut.into(),
+            ));
         }
+        self.update(stream)
+    }
 
-        let mut payload = vec![0; (len) as usize];
+    /// Ends the session, releasing its pieces so they can be downloaded from other peers.
+    pub fn close(&mut self) -> Result<(), PeerSessionError> {
+        self.torrent_status
+            .peer_disconnected(&self.peer)
+            .map_err(PeerSessionError::ErrorDisconnectingFromPeer)
+    }
 
-        stream
-            .read_exact(&mut payload)
-            .map_err(PeerSessionError::ErrorReadingMessage)?;
+    /// Returns the peer of the session.
+    pub fn peer(&self) -> &BtPeer {
+        &self.peer
+    }
+
+    /// Returns true if the rate limits stopped the session, so it must be driven again even if the peer sends nothing.
+    pub fn is_throttled(&self) -> bool {
+        self.throttled
+    }
+
+    /// Sends the messages that do not answer a message of the peer: choking, extensions, blocks, haves and requests.
+    ///
+    /// It returns `TorrentStopped` if the torrent was stopped, so the reactor closes the session.
+    fn update(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        if self.torrent_status.is_stopped() {
+            return Err(PeerSessionError::TorrentStopped);
+        }
+        self.update_choking(stream)?;
+        self.send_extension_messages(stream)?;
+        self.serve_requests(stream)?;
+        if self.downloading {
+            self.update_download(stream)
+        } else {
+            self.update_incoming_leecher(stream)
+        }
+    }
 
-        let message =
-            Message::from_bytes(&payload).map_err(PeerSessionError::MessageDoesNotExist)?;
-        let id = message.id.clone();
+    /// Handles the messages that were received completely.
+    fn handle_received_messages(
+        &mut self,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        while let Some(message) = self.reader.next_message().map_err(|err| match err {
+            MessageReaderError::MessageLengthTooLong => PeerSessionError::MessageLengthTooLong,
+            MessageReaderError::InvalidMessage(err) => PeerSessionError::MessageDoesNotExist(err),
+        })? {
+            self.last_message_at = Instant::now();
+            let id = message.id.clone();
+            self.handle_message(message, stream)?;
+
+            match id {
+                MessageId::Interested => self.interest_received = true,
+                // An incoming leecher that is not interested has nothing to do with us.
+                MessageId::NotInterested if !self.downloading && !self.interest_received => {
+                    return Err(PeerSessionError::PeerNotInterested)
+                }
+                MessageId::Piece => {
+                    // Calculate download speed
+                    self.piece_downloaded_size += BLOCK_SIZE as u64;
+                    self.status.download_speed = self.calculate_kilobits_per_second(
+                        self.piece_started_at,
+                        self.piece_downloaded_size,
+                    );
+                    self.update_peer_status()?;
+                }
+                _ => {}
+            }
 
-        self.handle_message(message, stream)?;
-        Ok(id)
+            if let Some((piece_index, piece)) = self.completed_piece.take() {
+                self.piece_completed(piece_index, piece)?;
+            }
+        }
+        Ok(())
     }
 
     /// Handles a message received from the peer.
     fn handle_message(
         &mut self,
         message: Message,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
         match message.id {
             MessageId::Unchoke => {
@@ -540,76 +731,332 @@ impl PeerSession {
             MessageId::Choke => {
                 self.status.choked = true;
             }
+            MessageId::Interested => {
+                self.status.peer_interested = true;
+                self.update_peer_status()?;
+            }
+            MessageId::NotInterested => {
+                self.status.peer_interested = false;
+                self.update_peer_status()?;
+            }
             MessageId::Bitfield => {
                 self.bitfield = self.message_handler.handle_bitfield(message);
+                self.torrent_status
+                    .peer_bitfield_received(&self.peer, &self.bitfield)
+                    .map_err(PeerSessionError::ErrorUpdatingPieceAvailability)?;
             }
-            MessageId::Piece => {
-                let mut block = self.message_handler.handle_piece(message);
-                self.piece.append(&mut block);
-            }
+            MessageId::Piece => self.handle_piece(message)?,
             MessageId::Request => self.handle_request(message, stream)?,
             MessageId::Have => {
                 let index = self.message_handler.handle_have(message);
                 self.bitfield.set_bit(index as u32, true);
+                self.torrent_status
+                    .peer_has_piece(&self.peer, index)
+                    .map_err(PeerSessionError::ErrorUpdatingPieceAvailability)?;
             }
+            MessageId::Extended => self.handle_extended(message, stream)?,
+            MessageId::HaveAll => {
+                self.bitfield = Bitfield::with_all_pieces(self.torrent.total_pieces());
+                self.torrent_status
+                    .peer_bitfield_received(&self.peer, &self.bitfield)
+                    .map_err(PeerSessionError::ErrorUpdatingPieceAvailability)?;
+            }
+            MessageId::HaveNone => {
+                self.bitfield = Bitfield::new(vec![0; self.bitfield.get_vec().len()]);
+                self.torrent_status
+                    .peer_bitfield_received(&self.peer, &self.bitfield)
+                    .map_err(PeerSessionError::ErrorUpdatingPieceAvailability)?;
+            }
+            MessageId::AllowedFast => {
+                if message.payload.len() != 4 {
+                    return Err(PeerSessionError::MessageError(MessageId::AllowedFast));
+                }
+                let index = self.message_handler.handle_have(message);
+                if index < self.torrent.total_pieces() {
+                    self.allowed_fast_from_peer.insert(index);
+                }
+            }
+            MessageId::RejectRequest => self.handle_reject_request(message)?,
+            MessageId::Cancel => {
+                let (index, begin, length) = self.message_handler.handle_request(message);
+                self.requests_received.retain(|request| {
+                    request.index != index || request.begin != begin || request.length != length
+                });
+            }
+            // Suggestions are only advisory, the pieces are still selected rarest first.
+            MessageId::SuggestPiece => {}
             _ => {} // TODO: handle other messages,
         }
         Ok(())
     }
 
-    /// Sets read and write timeouts for the stream.
-    fn set_stream_timeouts(&self, stream: &mut TcpStream) -> Result<(), PeerSessionError> {
-        stream
-            .set_read_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
-            .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
+    /// Handles a request the peer will not serve.
+    ///
+    /// The block is released, so it can be requested again or downloaded from others.
+    /// The peer still has the piece, but it is no longer requested while we are choked.
+    fn handle_reject_request(&mut self, message: Message) -> Result<(), PeerSessionError> {
+        if message.payload.len() != 12 {
+            return Err(PeerSessionError::MessageError(MessageId::RejectRequest));
+        }
+        let (index, begin, length) = self.message_handler.handle_request(message);
+        let len = self.requested_blocks.len();
+        self.requested_blocks.retain(|request| {
+            request.index != index || request.begin != begin || request.length != length
+        });
+        if self.requested_blocks.len() != len {
+            self.allowed_fast_from_peer.remove(&index);
+            self.torrent_status
+                .release_block(&self.peer, index, begin)
+                .map_err(PeerSessionError::ErrorRequestingBlocks)?;
+        }
+        Ok(())
+    }
 
-        stream
-            .set_write_timeout(Some(Duration::from_secs(
-                self.config.read_write_seconds_timeout,
-            )))
-            .map_err(|_| PeerSessionError::ErrorSettingStreamTimeout)?;
+    /// Handles a block received from the peer, storing it in the torrent status.
+    fn handle_piece(&mut self, message: Message) -> Result<(), PeerSessionError> {
+        let (index, begin, block) = self.message_handler.handle_piece(message);
+        self.requested_blocks
+            .retain(|request| request.index != index || request.begin != begin);
+
+        if let Some(piece) = self
+            .torrent_status
+            .block_received(&self.peer, index, begin, &block)
+            .map_err(PeerSessionError::ErrorReceivingBlock)?
+        {
+            self.completed_piece = Some((index, piece));
+        }
+        Ok(())
+    }
+
+    /// Sends our extended handshake if the peer supports the extension protocol.
+    fn send_extended_handshake(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        if !self.peer.supports_extension_protocol {
+            return Ok(());
+        }
+
+        let mut handshake = self.extensions.handshake();
+        handshake.client_version = Some(CLIENT_VERSION.to_string());
+        handshake.port = Some(self.config.tcp_port);
+        handshake.request_queue = Some(MAX_QUEUED_REQUESTS);
+        handshake.your_ip = Some(self.peer.ip);
+
+        let payload = [vec![EXTENDED_HANDSHAKE_ID], handshake.as_bytes()].concat();
+        self.message_handler
+            .send_extended(payload, stream)
+            .map_err(PeerSessionError::MessageHandlerError)
+    }
+
+    /// Handles an extended message, dispatching it to the handler of its extension and sending its responses.
+    ///
+    /// Invalid extended messages are only logged, they do not end the session.
+    fn handle_extended(
+        &mut self,
+        message: Message,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        let responses = match self.extensions.handle_message(&message.payload) {
+            Ok(responses) => responses,
+            Err(err) => {
+                self.logger_sender.warn(&format!(
+                    "IP: {} Invalid extended message: {:?}",
+                    self.peer.socket_addr(),
+                    err
+                ));
+                return Ok(());
+            }
+        };
+
+        if message.payload.first() == Some(&EXTENDED_HANDSHAKE_ID) {
+            let (client_version, listen_port) = self
+                .extensions
+                .peer_handshake()
+                .map(|handshake| (handshake.client_version.clone(), handshake.port))
+                .unwrap_or_default();
+            let client_version = client_version.unwrap_or_default();
+            // The port of incoming peers is only known if they send it in the extended handshake.
+            if self.status.listen_port.is_none() && listen_port.is_some() {
+                self.status.listen_port = listen_port;
+                self.update_peer_status()?;
+            }
+            self.logger_sender.info(&format!(
+                "IP: {} Extended handshake received: {}",
+                self.peer.socket_addr(),
+                client_version
+            ));
+        }
+
+        for response in responses {
+            self.message_handler
+                .send_extended(response, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
         Ok(())
     }
 
-    /// Handles a piece message received from the peer.
+    /// Sends the messages that the extensions send on their own, like the peers of `ut_pex`.
+    fn send_extension_messages(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        for message in self.extensions.tick() {
+            self.message_handler
+                .send_extended(message, stream)
+                .map_err(PeerSessionError::MessageHandlerError)?;
+        }
+        Ok(())
+    }
+
+    /// Handles a request message received from the peer, queueing it to be sent as the upload limits allow it.
+    ///
+    /// Requests outside of the pieces of the torrent or longer than `MAX_REQUEST_LENGTH` are rejected.
     fn handle_request(
         &mut self,
         message: Message,
-        stream: &mut TcpStream,
+        stream: &mut PeerStream,
     ) -> Result<(), PeerSessionError> {
-        let mut index: [u8; 4] = [0; 4];
-        let mut begin: [u8; 4] = [0; 4];
-        let mut length: [u8; 4] = [0; 4];
-        index.copy_from_slice(&message.payload[0..4]);
-        begin.copy_from_slice(&message.payload[4..8]);
-        length.copy_from_slice(&message.payload[8..12]);
+        let (index, begin, length) = self.message_handler.handle_request(message);
+
+        if !self.is_valid_request(index, begin, length) {
+            return self.reject_request(index, begin, length, stream);
+        }
 
-        let index = u32::from_be_bytes(index);
-        let begin = u32::from_be_bytes(begin);
-        let length = u32::from_be_bytes(length);
+        // The requests of a choked peer are discarded, except those of its allowed fast pieces.
+        if self.status.peer_choked && !self.allowed_fast_for_peer.contains(&index) {
+            return self.reject_request(index, begin, length, stream);
+        }
+        if self.requests_received.len() >= MAX_QUEUED_REQUESTS as usize {
+            return self.reject_request(index, begin, length, stream);
+        }
 
-        let offset = index * self.torrent.piece_length() + begin;
+        self.requests_received.push_back(BlockRequest {
+            index,
+            begin,
+            length,
+        });
+        Ok(())
+    }
 
-        let upload_start_time = Local::now();
+    /// Returns true if the requested block is inside a piece of the torrent and is not longer than `MAX_REQUEST_LENGTH`.
+    fn is_valid_request(&self, index: u32, begin: u32, length: u32) -> bool {
+        index < self.torrent.total_pieces()
+            && length > 0
+            && length <= MAX_REQUEST_LENGTH
+            && begin as u64 + length as u64 <= self.torrent_status.piece_size(index)
+    }
+
+    /// Sends the blocks requested by the peer while the upload limits and the connection allow it.
+    ///
+    /// A block is sent whole if there is any bandwidth left, and the next blocks wait until its bytes are paid.
+    fn serve_requests(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        let mut sent_size = 0;
+        while !self.requests_received.is_empty() && stream.pending_len() < MAX_PENDING_WRITES {
+            if let Some(available) = Self::available_bandwidth(&self.upload_limiters()) {
+                if available == 0 || sent_size >= BANDWIDTH_ROUND_SHARE {
+                    self.throttled = true;
+                    break;
+                }
+            }
+            let request = match self.requests_received.pop_front() {
+                Some(request) => request,
+                None => break,
+            };
+            self.send_block(request, stream)?;
+            self.upload_limiters()
+                .iter()
+                .for_each(|limiter| limiter.consume(request.length as usize));
+            sent_size += request.length as usize;
+        }
+        Ok(())
+    }
+
+    /// Discards the requests of the peer after choking it, except those of its allowed fast pieces.
+    fn discard_requests(&mut self, stream: &mut PeerStream) -> Result<(), PeerSessionError> {
+        let (allowed, discarded): (VecDeque<BlockRequest>, VecDeque<BlockRequest>) = self
+            .requests_received
+            .drain(..)
+            .partition(|request| self.allowed_fast_for_peer.contains(&request.index));
+        self.requests_received = allowed;
+        for request in discarded {
+            self.reject_request(request.index, request.begin, request.length, stream)?;
+        }
+        Ok(())
+    }
 
-        let block = self
+    /// Sends a block requested by the peer, or rejects the request if we do not have its piece.
+    fn send_block(
+        &mut self,
+        request: BlockRequest,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        let BlockRequest {
+            index,
+            begin,
+            length,
+        } = request;
+        let offset = index as u64 * self.torrent.piece_length() as u64 + begin as u64;
+
+        let block = match self
             .torrent_status
-            .get_piece(index, offset as u64, length as usize)
-            .map_err(PeerSessionError::ErrorGettingPiece)?;
+            .get_piece(index, offset, length as usize)
+        {
+            Ok(block) => block,
+            Err(
+                AtomicTorrentStatusError::PieceWasNotFinished
+                | AtomicTorrentStatusError::InvalidPieceIndex,
+            ) => return self.reject_request(index, begin, length, stream),
+            Err(e) => return Err(PeerSessionError::ErrorGettingPiece(e)),
+        };
 
         self.message_handler
             .send_piece(index, begin, &block, stream)
             .map_err(PeerSessionError::MessageHandlerError)?;
 
-        // Calculate upload speed
-        let upload_speed = self.calculate_kilobits_per_second(upload_start_time, (length).into());
-        self.status.upload_speed = upload_speed;
+        // Calculate upload speed, the blocks are sent as the connection accepts them.
+        let upload_started_at = *self.upload_started_at.get_or_insert_with(Local::now);
+        self.uploaded_size += length as u64;
+        self.status.upload_speed =
+            self.calculate_kilobits_per_second(upload_started_at, self.uploaded_size);
         self.update_peer_status()?;
         Ok(())
     }
 
+    fn download_limiters(&self) -> [&RateLimiter; 2] {
+        [
+            &self.global_limits.download,
+            &self.torrent_status.rate_limits().download,
+        ]
+    }
+
+    fn upload_limiters(&self) -> [&RateLimiter; 2] {
+        [
+            &self.global_limits.upload,
+            &self.torrent_status.rate_limits().upload,
+        ]
+    }
+
+    /// Returns the bytes that can be transferred now within all the limiters, `None` if none of them is limited.
+    fn available_bandwidth(limiters: &[&RateLimiter]) -> Option<usize> {
+        limiters
+            .iter()
+            .filter_map(|limiter| limiter.available())
+            .min()
+    }
+
+    /// Tells the peer that a request will not be served, if it supports the fast extension.
+    ///
+    /// Other peers do not expect an answer, so the request is just discarded.
+    fn reject_request(
+        &mut self,
+        index: u32,
+        begin: u32,
+        length: u32,
+        stream: &mut PeerStream,
+    ) -> Result<(), PeerSessionError> {
+        if !self.peer.supports_fast_extension {
+            return Ok(());
+        }
+        self.message_handler
+            .send_reject_request(index, begin, length, stream)
+            .map_err(PeerSessionError::MessageHandlerError)
+    }
+
     /// Validates the downloaded piece.
     ///
     /// Checks the piece hash and compares it to the hash in the torrent file.
@@ -0,0 +1,315 @@
+use std::{
+    io::{self, Read, Write},
+    net::{SocketAddr, TcpStream},
+    os::unix::io::{AsRawFd, RawFd},
+    time::Duration,
+};
+
+use super::encryption::rc4::Rc4;
+use crate::utp::stream::UtpStream;
+
+/// Connection with a peer over TCP or over uTP.
+pub enum Transport {
+    Tcp(TcpStream),
+    Utp(UtpStream),
+}
+
+impl Transport {
+    /// Receives data without removing it from the queue.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.peek(buf),
+            Transport::Utp(stream) => stream.peek(buf),
+        }
+    }
+
+    /// Sets the read timeout of the connection.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.set_read_timeout(timeout),
+            Transport::Utp(stream) => {
+                stream.set_read_timeout(timeout);
+                Ok(())
+            }
+        }
+    }
+
+    /// Sets the write timeout of the connection.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.set_write_timeout(timeout),
+            Transport::Utp(stream) => {
+                stream.set_write_timeout(timeout);
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        match self {
+            Transport::Tcp(stream) => stream.peer_addr(),
+            Transport::Utp(stream) => stream.peer_addr(),
+        }
+    }
+
+    /// Makes the reads and writes return `WouldBlock` instead of waiting.
+    pub fn set_nonblocking(&self) -> io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.set_nonblocking(true),
+            Transport::Utp(stream) => {
+                stream.set_read_timeout(Some(Duration::ZERO));
+                stream.set_write_timeout(Some(Duration::ZERO));
+                Ok(())
+            }
+        }
+    }
+
+    /// Returns the file descriptor of the connection, to be polled for readiness.
+    ///
+    /// uTP connections have none, as they share the UDP socket.
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        match self {
+            Transport::Tcp(stream) => Some(stream.as_raw_fd()),
+            Transport::Utp(_) => None,
+        }
+    }
+}
+
+impl Read for Transport {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.read(buf),
+            Transport::Utp(stream) => stream.read(buf),
+        }
+    }
+}
+
+impl Write for Transport {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Transport::Tcp(stream) => stream.write(buf),
+            Transport::Utp(stream) => stream.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Transport::Tcp(stream) => stream.flush(),
+            Transport::Utp(stream) => stream.flush(),
+        }
+    }
+}
+
+/// Connection to a peer, plaintext or obfuscated with the RC4 keys negotiated with Message Stream Encryption.
+///
+/// It is read and written like the `Transport` it wraps, so the messages are encrypted and decrypted transparently.
+/// In nonblocking mode the writes are never partial: the data the connection does not accept yet is queued and sent by `send_pending()`.
+///
+/// To create a new `PeerStream`, use PeerStream::plaintext(stream), or the functions of the `mse` module to negotiate the encryption.
+pub struct PeerStream {
+    stream: Transport,
+    ciphers: Option<(Rc4, Rc4)>,
+    buffer: Vec<u8>,
+    nonblocking: bool,
+    pending: Vec<u8>,
+}
+
+impl PeerStream {
+    /// Wraps a plaintext connection.
+    pub fn plaintext(stream: Transport) -> Self {
+        Self::new(stream, None, vec![])
+    }
+
+    /// Wraps a connection with the ciphers used to encrypt and decrypt it, if any.
+    ///
+    /// The bytes of `buffer` were already received and decrypted, and are returned by the first reads.
+    pub fn new(stream: Transport, ciphers: Option<(Rc4, Rc4)>, buffer: Vec<u8>) -> Self {
+        Self {
+            stream,
+            ciphers,
+            buffer,
+            nonblocking: false,
+            pending: vec![],
+        }
+    }
+
+    /// Returns `true` if the connection is encrypted.
+    pub fn is_encrypted(&self) -> bool {
+        self.ciphers.is_some()
+    }
+
+    /// Receives data without removing it from the queue, like `TcpStream::peek`.
+    ///
+    /// The data is only meant to know if there is something to read, as it is not decrypted.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.buffer.is_empty() {
+            let len = buf.len().min(self.buffer.len());
+            buf[..len].copy_from_slice(&self.buffer[..len]);
+            return Ok(len);
+        }
+        self.stream.peek(buf)
+    }
+
+    /// Sets the read timeout of the connection.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_read_timeout(timeout)
+    }
+
+    /// Sets the write timeout of the connection.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        self.stream.set_write_timeout(timeout)
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        self.stream.peer_addr()
+    }
+
+    /// Switches the connection to nonblocking mode, where reads return `WouldBlock` if there is nothing to read and writes are queued.
+    pub fn set_nonblocking(&mut self) -> io::Result<()> {
+        self.stream.set_nonblocking()?;
+        self.nonblocking = true;
+        Ok(())
+    }
+
+    /// Returns the file descriptor of the connection, if it has one.
+    pub fn raw_fd(&self) -> Option<RawFd> {
+        self.stream.raw_fd()
+    }
+
+    /// Returns the number of bytes written in nonblocking mode that were not sent yet.
+    pub fn pending_len(&self) -> usize {
+        self.pending.len()
+    }
+
+    /// Sends as much of the queued data as the connection accepts without blocking.
+    ///
+    /// It returns an error if the connection failed.
+    pub fn send_pending(&mut self) -> io::Result<()> {
+        while !self.pending.is_empty() {
+            match self.stream.write(&self.pending) {
+                Ok(0) => return Err(io::ErrorKind::WriteZero.into()),
+                Ok(len) => {
+                    self.pending.drain(..len);
+                }
+                Err(err) if err.kind() == io::ErrorKind::WouldBlock => return Ok(()),
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Read for PeerStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.buffer.is_empty() {
+            let len = buf.len().min(self.buffer.len());
+            buf[..len].copy_from_slice(&self.buffer[..len]);
+            self.buffer.drain(..len);
+            return Ok(len);
+        }
+
+        let len = self.stream.read(buf)?;
+        if let Some((_, decryptor)) = &mut self.ciphers {
+            decryptor.apply(&mut buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+impl Write for PeerStream {
+    /// Writes the whole buffer, as the keystream can not go back if only a part of the encrypted data was sent.
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut data = buf.to_vec();
+        if let Some((encryptor, _)) = &mut self.ciphers {
+            encryptor.apply(&mut data);
+        }
+        if self.nonblocking {
+            self.pending.extend(data);
+            self.send_pending()?;
+        } else {
+            self.stream.write_all(&data)?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if self.nonblocking {
+            return self.send_pending();
+        }
+        self.stream.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{net::TcpListener, thread};
+
+    #[test]
+    fn test_encrypted_stream_round_trip() {
+        let (client, server) = create_test_connection();
+        let mut client = PeerStream::new(
+            Transport::Tcp(client),
+            Some((Rc4::new(b"a"), Rc4::new(b"b"))),
+            vec![],
+        );
+        let mut server = PeerStream::new(
+            Transport::Tcp(server),
+            Some((Rc4::new(b"b"), Rc4::new(b"a"))),
+            b"buffered ".to_vec(),
+        );
+
+        client.write_all(b"hello").unwrap();
+        server.write_all(b"world").unwrap();
+
+        let mut buffer = [0; 14];
+        server.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"buffered hello");
+        let mut buffer = [0; 5];
+        client.read_exact(&mut buffer).unwrap();
+        assert_eq!(&buffer, b"world");
+        assert!(client.is_encrypted());
+    }
+
+    #[test]
+    fn test_nonblocking_writes_are_queued() {
+        let (client, server) = create_test_connection();
+        let mut client = PeerStream::plaintext(Transport::Tcp(client));
+        let mut server = PeerStream::plaintext(Transport::Tcp(server));
+        client.set_nonblocking().unwrap();
+        let data: Vec<u8> = (0..8_000_000).map(|i| (i % 251) as u8).collect();
+
+        // The peer is not reading, so the data does not fit in the socket buffers.
+        assert_eq!(client.write(&data).unwrap(), data.len());
+        assert!(client.pending_len() > 0);
+        let mut buffer = [0; 1];
+        assert_eq!(
+            client.read(&mut buffer).unwrap_err().kind(),
+            io::ErrorKind::WouldBlock
+        );
+
+        let reader = thread::spawn(move || {
+            let mut received = vec![0; 8_000_000];
+            server.read_exact(&mut received).unwrap();
+            received
+        });
+        while client.pending_len() > 0 {
+            client.send_pending().unwrap();
+            thread::sleep(Duration::from_millis(1));
+        }
+
+        assert_eq!(reader.join().unwrap(), data);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_connection() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (server, _) = listener.accept().unwrap();
+        (client, server)
+    }
+}
@@ -14,6 +14,8 @@ pub struct SessionStatus {
     pub bitfield: Bitfield,
     pub download_speed: f64,
     pub upload_speed: f64,
+    /// The port where the other peer accepts connections, if known
+    pub listen_port: Option<u16>,
 }
 
 impl SessionStatus {
@@ -26,6 +28,7 @@ impl SessionStatus {
             bitfield,
             download_speed: 0.0,
             upload_speed: 0.0,
+            listen_port: None,
         }
     }
 }
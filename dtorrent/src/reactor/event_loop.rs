@@ -0,0 +1,291 @@
+use logger::logger_sender::LoggerSender;
+use mio::{unix::SourceFd, Events, Interest, Poll, Token, Waker};
+use std::{
+    collections::HashMap,
+    io,
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        mpsc::{self, Receiver, Sender, TryRecvError},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::peer_reactor::PeerReactorError;
+use crate::{
+    bandwidth::rate_limiter::RateLimits,
+    peer::{
+        peer_session::{PeerSession, PeerSessionError},
+        peer_stream::PeerStream,
+    },
+};
+
+/// Token of the waker, used to tell the event loop that there are new sessions.
+const WAKE_TOKEN: Token = Token(usize::MAX);
+
+/// Maximum time the event loop waits for events, as the uTP connections can not be polled and are read on every iteration,
+/// like the sessions throttled by the rate limits.
+const POLL_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Time between ticks of the sessions, when they check the choker and their timeouts.
+const TICK_INTERVAL: Duration = Duration::from_secs(1);
+
+const EVENTS_CAPACITY: usize = 1024;
+
+/// A session driven by the event loop, with its connection.
+struct Entry {
+    session: PeerSession,
+    stream: PeerStream,
+    interest: Interest,
+}
+
+/// Handle to send new sessions to an `EventLoop`.
+#[derive(Debug, Clone)]
+pub struct EventLoopHandle {
+    sender: Sender<(PeerSession, PeerStream)>,
+    waker: Arc<Waker>,
+}
+
+impl EventLoopHandle {
+    /// Hands a connected session to the event loop.
+    ///
+    /// It returns an error if the event loop stopped.
+    pub fn register(
+        &self,
+        session: PeerSession,
+        stream: PeerStream,
+    ) -> Result<(), PeerReactorError> {
+        self.sender
+            .send((session, stream))
+            .map_err(|_| PeerReactorError::EventLoopStopped)?;
+        self.waker.wake().map_err(PeerReactorError::PollError)
+    }
+}
+
+/// Thread that drives many peer sessions, polling their connections with epoll.
+///
+/// When a connection is readable the session reads and handles the messages that arrived, and the messages it sends are queued
+/// in its nonblocking stream and sent when the connection is writable.
+/// The sessions throttled by the rate limits are driven on every iteration, each time starting by a different one so they share the bandwidth.
+///
+/// To start a new `EventLoop`, use EventLoop::spawn(name, global_limits, logger_sender).
+pub struct EventLoop {
+    poll: Poll,
+    sessions: HashMap<Token, Entry>,
+    receiver: Receiver<(PeerSession, PeerStream)>,
+    next_token: usize,
+    iteration: usize,
+    global_limits: Arc<RateLimits>,
+    logger_sender: LoggerSender,
+}
+
+impl EventLoop {
+    /// Spawns the thread of a new event loop, returning the handle to send it sessions.
+    ///
+    /// It returns an error if the poll could not be created or the thread could not be spawned.
+    pub fn spawn(
+        name: String,
+        global_limits: Arc<RateLimits>,
+        logger_sender: LoggerSender,
+    ) -> Result<EventLoopHandle, PeerReactorError> {
+        let poll = Poll::new().map_err(PeerReactorError::PollError)?;
+        let waker =
+            Arc::new(Waker::new(poll.registry(), WAKE_TOKEN).map_err(PeerReactorError::PollError)?);
+        let (sender, receiver) = mpsc::channel();
+
+        let event_loop = Self {
+            poll,
+            sessions: HashMap::new(),
+            receiver,
+            next_token: 0,
+            iteration: 0,
+            global_limits,
+            logger_sender,
+        };
+        thread::Builder::new()
+            .name(name)
+            .spawn(move || event_loop.run())
+            .map_err(PeerReactorError::ThreadError)?;
+
+        Ok(EventLoopHandle { sender, waker })
+    }
+
+    fn run(mut self) {
+        let mut events = Events::with_capacity(EVENTS_CAPACITY);
+        let mut last_tick = Instant::now();
+        loop {
+            if let Err(err) = self.poll.poll(&mut events, Some(POLL_INTERVAL)) {
+                if err.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                self.logger_sender
+                    .error(&format!("The peer event loop stopped: {:?}", err));
+                return;
+            }
+
+            let mut ready = vec![];
+            for event in events.iter() {
+                if event.token() == WAKE_TOKEN {
+                    if !self.add_new_sessions() {
+                        return;
+                    }
+                } else {
+                    ready.push(event.token());
+                }
+            }
+
+            let tick = last_tick.elapsed() >= TICK_INTERVAL;
+            if tick {
+                last_tick = Instant::now();
+                ready = self.sessions.keys().copied().collect();
+            } else {
+                ready.extend(
+                    self.sessions
+                        .iter()
+                        .filter(|(_, entry)| {
+                            entry.stream.raw_fd().is_none() || entry.session.is_throttled()
+                        })
+                        .map(|(token, _)| *token),
+                );
+            }
+            ready.sort_unstable();
+            ready.dedup();
+            if !ready.is_empty() {
+                let first = self.iteration % ready.len();
+                ready.rotate_left(first);
+            }
+            self.iteration = self.iteration.wrapping_add(1);
+            for token in ready {
+                self.service(token, tick);
+            }
+        }
+    }
+
+    /// Starts the sessions sent to the event loop.
+    ///
+    /// Returns `false` if the handles were dropped and there are no sessions left, so the event loop can stop.
+    fn add_new_sessions(&mut self) -> bool {
+        loop {
+            match self.receiver.try_recv() {
+                Ok((session, stream)) => self.add_session(session, stream),
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => return !self.sessions.is_empty(),
+            }
+        }
+    }
+
+    fn add_session(&mut self, mut session: PeerSession, mut stream: PeerStream) {
+        let token = Token(self.next_token);
+        self.next_token = (self.next_token + 1) % WAKE_TOKEN.0;
+
+        if let Err(err) = session.start(&mut stream, self.global_limits.clone()) {
+            self.logger_sender.warn(&format!("{:?}", err));
+            if let Err(err) = session.close() {
+                self.logger_sender.warn(&format!("{:?}", err));
+            }
+            return;
+        }
+        if let Some(fd) = stream.raw_fd() {
+            if let Err(err) =
+                self.poll
+                    .registry()
+                    .register(&mut SourceFd(&fd), token, Interest::READABLE)
+            {
+                self.logger_sender.warn(&format!(
+                    "Could not poll the connection with a peer: {:?}",
+                    err
+                ));
+                if let Err(err) = session.close() {
+                    self.logger_sender.warn(&format!("{:?}", err));
+                }
+                return;
+            }
+        }
+
+        self.sessions.insert(
+            token,
+            Entry {
+                session,
+                stream,
+                interest: Interest::READABLE,
+            },
+        );
+        // Bytes received during the handshakes may be buffered already, without a readiness event.
+        self.service(token, false);
+    }
+
+    /// Reads the messages of a session and sends what it queued, closing it if it failed.
+    ///
+    /// A session that panics is closed too, so it does not stop the event loop and the other sessions.
+    fn service(&mut self, token: Token, tick: bool) {
+        let entry = match self.sessions.get_mut(&token) {
+            Some(entry) => entry,
+            None => return,
+        };
+        let result = match panic::catch_unwind(AssertUnwindSafe(|| Self::drive(entry, tick))) {
+            Ok(result) => result,
+            Err(_) => {
+                self.logger_sender
+                    .error("A peer session panicked, closing its connection.");
+                self.close(token);
+                return;
+            }
+        };
+        if let Err(err) = result.and_then(|_| self.update_interest(token)) {
+            self.logger_sender.warn(&format!("{:?}", err));
+            self.close(token);
+        }
+    }
+
+    fn drive(entry: &mut Entry, tick: bool) -> Result<(), PeerSessionError> {
+        if tick {
+            entry.session.tick(&mut entry.stream)?;
+        }
+        entry.session.stream_readable(&mut entry.stream)?;
+        entry
+            .stream
+            .send_pending()
+            .map_err(PeerSessionError::ErrorWritingMessage)
+    }
+
+    /// Polls the connection for writability only while there is data waiting to be sent.
+    fn update_interest(&mut self, token: Token) -> Result<(), PeerSessionError> {
+        let entry = match self.sessions.get_mut(&token) {
+            Some(entry) => entry,
+            None => return Ok(()),
+        };
+        let fd = match entry.stream.raw_fd() {
+            Some(fd) => fd,
+            None => return Ok(()),
+        };
+        let interest = if entry.stream.pending_len() > 0 {
+            Interest::READABLE | Interest::WRITABLE
+        } else {
+            Interest::READABLE
+        };
+        if interest != entry.interest {
+            self.poll
+                .registry()
+                .reregister(&mut SourceFd(&fd), token, interest)
+                .map_err(PeerSessionError::ErrorWritingMessage)?;
+            entry.interest = interest;
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, token: Token) {
+        let mut entry = match self.sessions.remove(&token) {
+            Some(entry) => entry,
+            None => return,
+        };
+        if let Some(fd) = entry.stream.raw_fd() {
+            let _ = self.poll.registry().deregister(&mut SourceFd(&fd));
+        }
+        // The last messages are sent if the connection accepts them right away.
+        let _ = entry.stream.send_pending();
+        if let Err(err) = entry.session.close() {
+            self.logger_sender.warn(&format!("{:?}", err));
+        }
+    }
+}
@@ -0,0 +1,2 @@
+mod event_loop;
+pub mod peer_reactor;
@@ -0,0 +1,152 @@
+use logger::logger_sender::LoggerSender;
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use super::event_loop::{EventLoop, EventLoopHandle};
+use crate::{
+    bandwidth::rate_limiter::RateLimits,
+    peer::{peer_session::PeerSession, peer_stream::PeerStream},
+};
+
+/// Number of event loops driving the peer sessions.
+pub const DEFAULT_EVENT_LOOPS: usize = 2;
+
+/// Number of threads connecting to peers at the same time.
+pub const DEFAULT_CONNECTORS: usize = 16;
+
+/// Posible `PeerReactor` errors.
+#[derive(Debug)]
+pub enum PeerReactorError {
+    PollError(io::Error),
+    ThreadError(io::Error),
+    EventLoopStopped,
+    ConnectorsStopped,
+    PoisonedConnectLock,
+}
+
+/// The event loops, with the next one that receives a session.
+#[derive(Debug, Clone)]
+struct EventLoops {
+    handles: Arc<Vec<EventLoopHandle>>,
+    next: Arc<AtomicUsize>,
+}
+
+impl EventLoops {
+    /// Hands a session to the event loops in turns, so they drive a similar number of sessions.
+    fn register(&self, session: PeerSession, stream: PeerStream) -> Result<(), PeerReactorError> {
+        let index = self.next.fetch_add(1, Ordering::Relaxed) % self.handles.len();
+        self.handles[index].register(session, stream)
+    }
+}
+
+/// Drives the peer sessions of all the torrents on a small fixed set of threads, instead of a thread per peer.
+///
+/// The connected sessions are spread among event loops that poll their connections and handle the messages as they arrive.
+/// Connecting to a peer and the encrypted handshake block, so they are done by a pool of connector threads before the session is handed to an event loop.
+/// All the sessions share the global rate limits.
+///
+/// To start a new `PeerReactor`, use PeerReactor::start(event_loops, connectors, global_limits, logger_sender).
+#[derive(Debug)]
+pub struct PeerReactor {
+    event_loops: EventLoops,
+    connect_sender: Mutex<Sender<PeerSession>>,
+}
+
+impl PeerReactor {
+    /// Spawns the event loops and the connector threads.
+    ///
+    /// It returns an error if the polls could not be created or the threads could not be spawned.
+    pub fn start(
+        event_loops: usize,
+        connectors: usize,
+        global_limits: Arc<RateLimits>,
+        logger_sender: LoggerSender,
+    ) -> Result<Arc<Self>, PeerReactorError> {
+        let handles = (0..event_loops.max(1))
+            .map(|i| {
+                EventLoop::spawn(
+                    format!("Peer event loop {}", i),
+                    global_limits.clone(),
+                    logger_sender.clone(),
+                )
+            })
+            .collect::<Result<Vec<EventLoopHandle>, PeerReactorError>>()?;
+        let event_loops = EventLoops {
+            handles: Arc::new(handles),
+            next: Arc::new(AtomicUsize::new(0)),
+        };
+
+        let (connect_sender, connect_receiver) = mpsc::channel();
+        let connect_receiver = Arc::new(Mutex::new(connect_receiver));
+        for i in 0..connectors.max(1) {
+            let connect_receiver = connect_receiver.clone();
+            let event_loops = event_loops.clone();
+            let logger_sender = logger_sender.clone();
+            thread::Builder::new()
+                .name(format!("Peer connector {}", i))
+                .spawn(move || Self::run_connector(&connect_receiver, &event_loops, &logger_sender))
+                .map_err(PeerReactorError::ThreadError)?;
+        }
+
+        Ok(Arc::new(Self {
+            event_loops,
+            connect_sender: Mutex::new(connect_sender),
+        }))
+    }
+
+    /// Queues a session to connect to its peer, it is driven by an event loop once connected.
+    ///
+    /// It returns an error if the connector threads stopped.
+    pub fn connect(&self, session: PeerSession) -> Result<(), PeerReactorError> {
+        self.connect_sender
+            .lock()
+            .map_err(|_| PeerReactorError::PoisonedConnectLock)?
+            .send(session)
+            .map_err(|_| PeerReactorError::ConnectorsStopped)
+    }
+
+    /// Hands a session whose handshakes are done to an event loop.
+    ///
+    /// It returns an error if the event loop stopped.
+    pub fn register(
+        &self,
+        session: PeerSession,
+        stream: PeerStream,
+    ) -> Result<(), PeerReactorError> {
+        self.event_loops.register(session, stream)
+    }
+
+    /// Connects the queued sessions one at a time, until the reactor is dropped.
+    fn run_connector(
+        connect_receiver: &Mutex<Receiver<PeerSession>>,
+        event_loops: &EventLoops,
+        logger_sender: &LoggerSender,
+    ) {
+        loop {
+            let session = match connect_receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+            let mut session = match session {
+                Ok(session) => session,
+                Err(_) => return,
+            };
+
+            match session.connect_to_seeder() {
+                Ok(stream) => {
+                    if let Err(err) = event_loops.register(session, stream) {
+                        logger_sender.error(&format!("{:?}", err));
+                    }
+                }
+                Err(err) => logger_sender.warn(&format!("{:?}", err)),
+            }
+        }
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::HashMap,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+/// Maximum size of the request line and the headers of a request.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+
+/// Maximum size of the body of a request, enough for any .torrent file.
+const MAX_BODY_SIZE: usize = 16 * 1024 * 1024;
+
+/// Posible `HttpRequest` errors.
+#[derive(Debug)]
+pub enum HttpRequestError {
+    IoError(io::Error),
+    InvalidRequestLine(String),
+    InvalidHeader(String),
+    InvalidContentLength(String),
+    TooLarge,
+}
+
+/// A HTTP/1.1 request received by the RPC server.
+///
+/// - `method`: the HTTP method, like `GET` or `POST`,
+/// - `path`: the path of the request, without its query,
+/// - `query`: the parameters of the query, as they were sent,
+/// - `headers`: the headers of the request, with their names in lowercase,
+/// - `body`: the body of the request, of `Content-Length` bytes.
+///
+/// To read a new `HttpRequest` from a stream, use HttpRequest::read(stream).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HttpRequest {
+    pub method: String,
+    pub path: String,
+    pub query: HashMap<String, String>,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl HttpRequest {
+    /// Reads a request from the stream, with its body.
+    ///
+    /// If the client expects it, `100 Continue` is written to the stream before reading the body.
+    ///
+    /// It returns a `HttpRequestError` if:
+    /// - The stream could not be read or written.
+    /// - The request line or a header are not valid.
+    /// - The `Content-Length` header is not a valid number.
+    /// - The headers or the body are too large.
+    pub fn read<S: Read + Write>(stream: S) -> Result<Self, HttpRequestError> {
+        let mut reader = BufReader::new(stream);
+        let mut request = Self::read_head(&mut reader)?;
+        request.read_body(&mut reader)?;
+        Ok(request)
+    }
+
+    /// Reads the request line and the headers of a request from the stream, leaving its body empty.
+    ///
+    /// The body can be read afterwards with `read_body()`, so requests can be rejected by their headers without reading it.
+    ///
+    /// It returns a `HttpRequestError` if:
+    /// - The stream could not be read.
+    /// - The request line or a header are not valid.
+    /// - The headers are too large.
+    pub fn read_head<S: Read>(reader: &mut BufReader<S>) -> Result<Self, HttpRequestError> {
+        let mut head = reader.take(MAX_HEAD_SIZE as u64);
+        let request_line = Self::read_line(&mut head)?;
+        let mut headers = HashMap::new();
+        loop {
+            let line = Self::read_line(&mut head)?;
+            if line.is_empty() {
+                break;
+            }
+            let (name, value) = line
+                .split_once(':')
+                .ok_or_else(|| HttpRequestError::InvalidHeader(line.clone()))?;
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+
+        let mut parts = request_line.split(' ');
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+                (method.to_string(), target)
+            }
+            _ => return Err(HttpRequestError::InvalidRequestLine(request_line.clone())),
+        };
+        let (path, query) = match target.split_once('?') {
+            Some((path, query)) => (path.to_string(), Self::parse_query(query)),
+            None => (target.to_string(), HashMap::new()),
+        };
+
+        Ok(Self {
+            method,
+            path,
+            query,
+            headers,
+            body: vec![],
+        })
+    }
+
+    /// Reads the body of a request whose head was read with `read_head()`, of `Content-Length` bytes.
+    ///
+    /// If the client expects it, `100 Continue` is written to the stream before reading the body.
+    ///
+    /// It returns a `HttpRequestError` if:
+    /// - The stream could not be read or written.
+    /// - The `Content-Length` header is not a valid number.
+    /// - The body is too large.
+    pub fn read_body<S: Read + Write>(
+        &mut self,
+        reader: &mut BufReader<S>,
+    ) -> Result<(), HttpRequestError> {
+        let content_length = match self.header("content-length") {
+            Some(length) => length
+                .parse::<usize>()
+                .map_err(|_| HttpRequestError::InvalidContentLength(length.to_string()))?,
+            None => 0,
+        };
+        if content_length > MAX_BODY_SIZE {
+            return Err(HttpRequestError::TooLarge);
+        }
+        if content_length > 0
+            && self
+                .header("expect")
+                .is_some_and(|expect| expect.eq_ignore_ascii_case("100-continue"))
+        {
+            let stream = reader.get_mut();
+            stream
+                .write_all(b"HTTP/1.1 100 Continue\r\n\r\n")
+                .and_then(|_| stream.flush())
+                .map_err(HttpRequestError::IoError)?;
+        }
+
+        // The body grows as it is received, so a client can not make us allocate more than it sends.
+        let mut body = Vec::new();
+        reader
+            .take(content_length as u64)
+            .read_to_end(&mut body)
+            .map_err(HttpRequestError::IoError)?;
+        if body.len() < content_length {
+            return Err(HttpRequestError::IoError(
+                io::ErrorKind::UnexpectedEof.into(),
+            ));
+        }
+        self.body = body;
+        Ok(())
+    }
+
+    /// Returns the value of a header, by its name in any case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .get(&name.to_lowercase())
+            .map(|value| value.as_str())
+    }
+
+    /// Returns the token of the `Authorization: Bearer <token>` header.
+    pub fn bearer_token(&self) -> Option<&str> {
+        self.header("authorization")?
+            .strip_prefix("Bearer ")
+            .map(|token| token.trim())
+    }
+
+    /// Reads a line ending with CRLF, without it.
+    fn read_line<R: BufRead>(reader: &mut io::Take<R>) -> Result<String, HttpRequestError> {
+        let mut line = String::new();
+        let bytes_read = reader
+            .read_line(&mut line)
+            .map_err(HttpRequestError::IoError)?;
+        if !line.ends_with('\n') {
+            return Err(match (bytes_read, reader.limit()) {
+                (_, 0) => HttpRequestError::TooLarge,
+                _ => HttpRequestError::IoError(io::ErrorKind::UnexpectedEof.into()),
+            });
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    fn parse_query(query: &str) -> HashMap<String, String> {
+        query
+            .split('&')
+            .filter(|param| !param.is_empty())
+            .map(|param| match param.split_once('=') {
+                Some((key, value)) => (key.to_string(), value.to_string()),
+                None => (param.to_string(), String::new()),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Cursor;
+
+    #[test]
+    fn test_read_get_request() {
+        let mut stream = TestStream::new(
+            b"GET /torrents/abcd?delete_data=true&verbose HTTP/1.1\r\nHost: localhost\r\nAuthorization: Bearer secret\r\n\r\n",
+        );
+
+        let request = HttpRequest::read(&mut stream).unwrap();
+
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.path, "/torrents/abcd");
+        assert_eq!(request.query.get("delete_data").unwrap(), "true");
+        assert_eq!(request.query.get("verbose").unwrap(), "");
+        assert_eq!(request.header("HOST"), Some("localhost"));
+        assert_eq!(request.bearer_token(), Some("secret"));
+        assert!(request.body.is_empty());
+    }
+
+    #[test]
+    fn test_read_request_with_body() {
+        let mut stream = TestStream::new(
+            b"POST /torrents HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\nhello",
+        );
+
+        let request = HttpRequest::read(&mut stream).unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert_eq!(request.body, b"hello");
+        assert_eq!(stream.output, b"HTTP/1.1 100 Continue\r\n\r\n");
+    }
+
+    #[test]
+    fn test_read_head_without_the_body() {
+        let mut reader = BufReader::new(TestStream::new(
+            b"POST /torrents HTTP/1.1\r\nContent-Length: 5\r\nExpect: 100-continue\r\n\r\nhello",
+        ));
+
+        let request = HttpRequest::read_head(&mut reader).unwrap();
+
+        assert_eq!(request.method, "POST");
+        assert!(request.body.is_empty());
+        assert!(reader.get_ref().output.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_requests() {
+        assert!(matches!(
+            HttpRequest::read(TestStream::new(b"GET /torrents\r\n\r\n")),
+            Err(HttpRequestError::InvalidRequestLine(_))
+        ));
+        assert!(matches!(
+            HttpRequest::read(TestStream::new(b"GET / HTTP/1.1\r\nNo colon\r\n\r\n")),
+            Err(HttpRequestError::InvalidHeader(_))
+        ));
+        assert!(matches!(
+            HttpRequest::read(TestStream::new(
+                b"POST / HTTP/1.1\r\nContent-Length: many\r\n\r\n"
+            )),
+            Err(HttpRequestError::InvalidContentLength(_))
+        ));
+        assert!(matches!(
+            HttpRequest::read(TestStream::new(
+                b"POST / HTTP/1.1\r\nContent-Length: 10\r\n\r\nshort"
+            )),
+            Err(HttpRequestError::IoError(_))
+        ));
+    }
+
+    #[test]
+    fn test_too_large_requests() {
+        let long_header = format!("GET / HTTP/1.1\r\nX: {}\r\n\r\n", "a".repeat(MAX_HEAD_SIZE));
+        assert!(matches!(
+            HttpRequest::read(TestStream::new(long_header.as_bytes())),
+            Err(HttpRequestError::TooLarge)
+        ));
+
+        let large_body = format!(
+            "POST / HTTP/1.1\r\nContent-Length: {}\r\n\r\n",
+            MAX_BODY_SIZE + 1
+        );
+        assert!(matches!(
+            HttpRequest::read(TestStream::new(large_body.as_bytes())),
+            Err(HttpRequestError::TooLarge)
+        ));
+    }
+
+    // Auxiliary functions
+
+    struct TestStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl TestStream {
+        fn new(input: &[u8]) -> Self {
+            Self {
+                input: Cursor::new(input.to_vec()),
+                output: Vec::new(),
+            }
+        }
+    }
+
+    impl Read for TestStream {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for TestStream {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+}
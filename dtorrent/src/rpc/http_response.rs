@@ -0,0 +1,95 @@
+use serde_json::{json, Value};
+
+/// The HTTP statuses answered by the RPC server.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HttpStatus {
+    Ok,
+    BadRequest,
+    Unauthorized,
+    NotFound,
+    MethodNotAllowed,
+    Conflict,
+    PayloadTooLarge,
+    InternalServerError,
+    ServiceUnavailable,
+}
+
+impl HttpStatus {
+    /// Returns the code and reason of the status, as written in the status line.
+    pub fn status_line(&self) -> &'static str {
+        match self {
+            Self::Ok => "200 OK",
+            Self::BadRequest => "400 Bad Request",
+            Self::Unauthorized => "401 Unauthorized",
+            Self::NotFound => "404 Not Found",
+            Self::MethodNotAllowed => "405 Method Not Allowed",
+            Self::Conflict => "409 Conflict",
+            Self::PayloadTooLarge => "413 Payload Too Large",
+            Self::InternalServerError => "500 Internal Server Error",
+            Self::ServiceUnavailable => "503 Service Unavailable",
+        }
+    }
+}
+
+/// A HTTP response of the RPC server, with a JSON body.
+///
+/// To create a new `HttpResponse`, use HttpResponse::json(status, value) or HttpResponse::error(status, message).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HttpResponse {
+    pub status: HttpStatus,
+    pub body: Value,
+}
+
+impl HttpResponse {
+    /// Creates a response with the value as its body.
+    pub fn json(status: HttpStatus, body: Value) -> Self {
+        Self { status, body }
+    }
+
+    /// Creates an error response, with a body like `{"error": message}`.
+    pub fn error(status: HttpStatus, message: &str) -> Self {
+        Self::json(status, json!({ "error": message }))
+    }
+
+    /// Returns the response as it is written to the stream, the connection is closed after it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let body = self.body.to_string();
+        let authenticate = match self.status {
+            HttpStatus::Unauthorized => "WWW-Authenticate: Bearer\r\n",
+            _ => "",
+        };
+        format!(
+            "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\n{}Connection: close\r\n\r\n{}",
+            self.status.status_line(),
+            body.len(),
+            authenticate,
+            body
+        )
+        .into_bytes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_response_bytes() {
+        let response = HttpResponse::json(HttpStatus::Ok, json!({ "result": "success" }));
+
+        assert_eq!(
+            String::from_utf8(response.to_bytes()).unwrap(),
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 20\r\nConnection: close\r\n\r\n{\"result\":\"success\"}"
+        );
+    }
+
+    #[test]
+    fn test_unauthorized_response_asks_for_a_token() {
+        let response = HttpResponse::error(HttpStatus::Unauthorized, "Invalid token");
+        let response = String::from_utf8(response.to_bytes()).unwrap();
+
+        assert!(response.starts_with("HTTP/1.1 401 Unauthorized\r\n"));
+        assert!(response.contains("WWW-Authenticate: Bearer\r\n"));
+        assert!(response.ends_with("{\"error\":\"Invalid token\"}"));
+    }
+}
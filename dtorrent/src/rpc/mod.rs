@@ -0,0 +1,4 @@
+pub mod http_request;
+pub mod http_response;
+pub mod rpc_request;
+pub mod rpc_server;
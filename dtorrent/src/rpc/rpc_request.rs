@@ -0,0 +1,431 @@
+use super::http_request::HttpRequest;
+use crate::{bandwidth::rate_limiter::Rates, torrent_handler::status::AtomicTorrentStatus};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::Duration;
+
+/// Posible `RpcRequest` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RpcRequestError {
+    NotFound(String),
+    MethodNotAllowed(String),
+    InvalidBody(String),
+}
+
+/// The limits of a torrent that can be changed through the RPC, the ones that are `None` are left as they are.
+///
+/// - `max_download_kbps` and `max_upload_kbps`: rate limits in kilobytes per second, 0 for unlimited,
+/// - `seed_ratio_limit`: share ratio at which the torrent stops seeding, 0 for no limit,
+/// - `seed_time_limit_minutes` and `seed_idle_limit_minutes`: minutes seeding, and minutes seeding without uploading, after which the torrent stops seeding, 0 for no limit.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TorrentLimits {
+    pub max_download_kbps: Option<u64>,
+    pub max_upload_kbps: Option<u64>,
+    pub seed_ratio_limit: Option<f64>,
+    pub seed_time_limit_minutes: Option<u64>,
+    pub seed_idle_limit_minutes: Option<u64>,
+}
+
+impl TorrentLimits {
+    /// Returns the current limits of the torrent.
+    pub fn of(torrent_status: &AtomicTorrentStatus) -> Self {
+        let rates = torrent_status.rate_limits().rates();
+        let seed_limits = torrent_status.seed_limits();
+        Self {
+            max_download_kbps: Some(rates.download / 1024),
+            max_upload_kbps: Some(rates.upload / 1024),
+            seed_ratio_limit: Some(seed_limits.ratio.unwrap_or(0.0)),
+            seed_time_limit_minutes: Some(Self::minutes(seed_limits.seeding_time)),
+            seed_idle_limit_minutes: Some(Self::minutes(seed_limits.idle_time)),
+        }
+    }
+
+    /// Changes the limits of the torrent that are set.
+    pub fn apply(&self, torrent_status: &AtomicTorrentStatus) {
+        let rate_limits = torrent_status.rate_limits();
+        let rates = rate_limits.rates();
+        rate_limits.set_rates(Rates {
+            download: self
+                .max_download_kbps
+                .map_or(rates.download, |kbps| kbps * 1024),
+            upload: self
+                .max_upload_kbps
+                .map_or(rates.upload, |kbps| kbps * 1024),
+        });
+
+        let mut seed_limits = torrent_status.seed_limits();
+        if let Some(ratio) = self.seed_ratio_limit {
+            seed_limits.ratio = Some(ratio).filter(|ratio| *ratio > 0.0);
+        }
+        if let Some(minutes) = self.seed_time_limit_minutes {
+            seed_limits.seeding_time = Self::duration(minutes);
+        }
+        if let Some(minutes) = self.seed_idle_limit_minutes {
+            seed_limits.idle_time = Self::duration(minutes);
+        }
+        torrent_status.set_seed_limits(seed_limits);
+    }
+
+    fn minutes(duration: Option<Duration>) -> u64 {
+        duration.map_or(0, |duration| duration.as_secs() / 60)
+    }
+
+    fn duration(minutes: u64) -> Option<Duration> {
+        Some(Duration::from_secs(minutes * 60)).filter(|_| minutes > 0)
+    }
+}
+
+/// The operations of the RPC, with the torrents identified by their info hash.
+///
+/// | Method | Path | Operation |
+/// |---|---|---|
+/// | GET | /torrents | `ListTorrents` |
+/// | POST | /torrents | `AddTorrentFile` with a .torrent file as body, or `AddMagnet` with a `{"magnet": link}` JSON body |
+/// | GET | /torrents/{info_hash} | `GetTorrent` |
+/// | DELETE | /torrents/{info_hash}?delete_data=true | `Remove` |
+/// | POST | /torrents/{info_hash}/pause | `Pause` |
+/// | POST | /torrents/{info_hash}/resume | `Resume` |
+/// | POST | /torrents/{info_hash}/recheck | `Recheck` |
+/// | GET | /torrents/{info_hash}/limits | `GetTorrentLimits` |
+/// | PUT | /torrents/{info_hash}/limits | `SetTorrentLimits` with a `TorrentLimits` JSON body |
+/// | PUT | /config | `SetSettings` with a JSON object of settings named like in the config file |
+///
+/// To create a new `RpcRequest`, use RpcRequest::from_http(request).
+#[derive(Debug, Clone, PartialEq)]
+pub enum RpcRequest {
+    ListTorrents,
+    GetTorrent(String),
+    AddTorrentFile(Vec<u8>),
+    AddMagnet(String),
+    Pause(String),
+    Resume(String),
+    Recheck(String),
+    Remove {
+        info_hash: String,
+        delete_data: bool,
+    },
+    GetTorrentLimits(String),
+    SetTorrentLimits {
+        info_hash: String,
+        limits: TorrentLimits,
+    },
+    SetSettings(Vec<(String, String)>),
+}
+
+impl RpcRequest {
+    /// Returns the operation asked by the HTTP request.
+    ///
+    /// It returns a `RpcRequestError` if:
+    /// - The path is not one of the RPC.
+    /// - The method is not allowed for the path.
+    /// - The body is not valid for the operation.
+    pub fn from_http(request: &HttpRequest) -> Result<Self, RpcRequestError> {
+        let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+        let method = request.method.as_str();
+
+        match (segments.as_slice(), method) {
+            (["torrents"], "GET") => Ok(Self::ListTorrents),
+            (["torrents"], "POST") => Self::add_request(request),
+            (["torrents", info_hash], "GET") => Ok(Self::GetTorrent(info_hash.to_string())),
+            (["torrents", info_hash], "DELETE") => Ok(Self::Remove {
+                info_hash: info_hash.to_string(),
+                delete_data: request
+                    .query
+                    .get("delete_data")
+                    .is_some_and(|value| value == "true" || value == "1"),
+            }),
+            (["torrents", info_hash, "pause"], "POST") => Ok(Self::Pause(info_hash.to_string())),
+            (["torrents", info_hash, "resume"], "POST") => Ok(Self::Resume(info_hash.to_string())),
+            (["torrents", info_hash, "recheck"], "POST") => {
+                Ok(Self::Recheck(info_hash.to_string()))
+            }
+            (["torrents", info_hash, "limits"], "GET") => {
+                Ok(Self::GetTorrentLimits(info_hash.to_string()))
+            }
+            (["torrents", info_hash, "limits"], "PUT") => Ok(Self::SetTorrentLimits {
+                info_hash: info_hash.to_string(),
+                limits: serde_json::from_slice(&request.body)
+                    .map_err(|error| RpcRequestError::InvalidBody(error.to_string()))?,
+            }),
+            (["config"], "PUT") => Self::settings_request(request),
+            (["torrents"], _)
+            | (["torrents", _], _)
+            | (["torrents", _, "pause" | "resume" | "recheck" | "limits"], _)
+            | (["config"], _) => Err(RpcRequestError::MethodNotAllowed(method.to_string())),
+            _ => Err(RpcRequestError::NotFound(request.path.clone())),
+        }
+    }
+
+    fn add_request(request: &HttpRequest) -> Result<Self, RpcRequestError> {
+        if request.body.is_empty() {
+            return Err(RpcRequestError::InvalidBody(
+                "Expected a .torrent file or a magnet link".to_string(),
+            ));
+        }
+        let is_json = request
+            .header("content-type")
+            .is_some_and(|content_type| content_type.starts_with("application/json"));
+        if !is_json {
+            return Ok(Self::AddTorrentFile(request.body.clone()));
+        }
+
+        let body: Value = serde_json::from_slice(&request.body)
+            .map_err(|error| RpcRequestError::InvalidBody(error.to_string()))?;
+        match body.get("magnet").and_then(Value::as_str) {
+            Some(link) => Ok(Self::AddMagnet(link.to_string())),
+            None => Err(RpcRequestError::InvalidBody(
+                "Expected a magnet link in the \"magnet\" field".to_string(),
+            )),
+        }
+    }
+
+    fn settings_request(request: &HttpRequest) -> Result<Self, RpcRequestError> {
+        let body: Value = serde_json::from_slice(&request.body)
+            .map_err(|error| RpcRequestError::InvalidBody(error.to_string()))?;
+        let settings = body.as_object().ok_or_else(|| {
+            RpcRequestError::InvalidBody("Expected an object of settings".to_string())
+        })?;
+
+        settings
+            .iter()
+            .map(|(name, value)| match value {
+                Value::String(value) => Ok((name.clone(), value.clone())),
+                Value::Number(_) | Value::Bool(_) => Ok((name.clone(), value.to_string())),
+                _ => Err(RpcRequestError::InvalidBody(format!(
+                    "Invalid value of setting {}",
+                    name
+                ))),
+            })
+            .collect::<Result<Vec<(String, String)>, RpcRequestError>>()
+            .map(Self::SetSettings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::cfg::Cfg,
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+    use std::collections::HashMap;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_torrent_requests() {
+        assert_eq!(
+            RpcRequest::from_http(&create_request("GET", "/torrents", &[], b"")),
+            Ok(RpcRequest::ListTorrents)
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("GET", "/torrents/abcd/", &[], b"")),
+            Ok(RpcRequest::GetTorrent("abcd".to_string()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("POST", "/torrents/abcd/pause", &[], b"")),
+            Ok(RpcRequest::Pause("abcd".to_string()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("POST", "/torrents/abcd/resume", &[], b"")),
+            Ok(RpcRequest::Resume("abcd".to_string()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("POST", "/torrents/abcd/recheck", &[], b"")),
+            Ok(RpcRequest::Recheck("abcd".to_string()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("DELETE", "/torrents/abcd", &[], b"")),
+            Ok(RpcRequest::Remove {
+                info_hash: "abcd".to_string(),
+                delete_data: false
+            })
+        );
+    }
+
+    #[test]
+    fn test_remove_request_deleting_data() {
+        let mut request = create_request("DELETE", "/torrents/abcd", &[], b"");
+        request
+            .query
+            .insert("delete_data".to_string(), "true".to_string());
+
+        assert_eq!(
+            RpcRequest::from_http(&request),
+            Ok(RpcRequest::Remove {
+                info_hash: "abcd".to_string(),
+                delete_data: true
+            })
+        );
+    }
+
+    #[test]
+    fn test_add_requests() {
+        assert_eq!(
+            RpcRequest::from_http(&create_request(
+                "POST",
+                "/torrents",
+                &[("Content-Type", "application/x-bittorrent")],
+                b"d8:announce"
+            )),
+            Ok(RpcRequest::AddTorrentFile(b"d8:announce".to_vec()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request(
+                "POST",
+                "/torrents",
+                &[("Content-Type", "application/json")],
+                br#"{"magnet": "magnet:?xt=urn:btih:abcd"}"#
+            )),
+            Ok(RpcRequest::AddMagnet(
+                "magnet:?xt=urn:btih:abcd".to_string()
+            ))
+        );
+        assert!(matches!(
+            RpcRequest::from_http(&create_request(
+                "POST",
+                "/torrents",
+                &[("Content-Type", "application/json")],
+                br#"{"link": "magnet:?xt=urn:btih:abcd"}"#
+            )),
+            Err(RpcRequestError::InvalidBody(_))
+        ));
+        assert!(matches!(
+            RpcRequest::from_http(&create_request("POST", "/torrents", &[], b"")),
+            Err(RpcRequestError::InvalidBody(_))
+        ));
+    }
+
+    #[test]
+    fn test_limits_and_settings_requests() {
+        assert_eq!(
+            RpcRequest::from_http(&create_request(
+                "PUT",
+                "/torrents/abcd/limits",
+                &[],
+                br#"{"max_upload_kbps": 50, "seed_ratio_limit": 2.0}"#
+            )),
+            Ok(RpcRequest::SetTorrentLimits {
+                info_hash: "abcd".to_string(),
+                limits: TorrentLimits {
+                    max_upload_kbps: Some(50),
+                    seed_ratio_limit: Some(2.0),
+                    ..Default::default()
+                }
+            })
+        );
+        assert!(matches!(
+            RpcRequest::from_http(&create_request(
+                "PUT",
+                "/torrents/abcd/limits",
+                &[],
+                br#"{"max_peers": 5}"#
+            )),
+            Err(RpcRequestError::InvalidBody(_))
+        ));
+        assert_eq!(
+            RpcRequest::from_http(&create_request(
+                "PUT",
+                "/config",
+                &[],
+                br#"{"DHT_ENABLED": false, "MAX_DOWNLOAD_KBPS": 100, "SEED_LIMIT_ACTION": "remove"}"#
+            )),
+            Ok(RpcRequest::SetSettings(vec![
+                ("DHT_ENABLED".to_string(), "false".to_string()),
+                ("MAX_DOWNLOAD_KBPS".to_string(), "100".to_string()),
+                ("SEED_LIMIT_ACTION".to_string(), "remove".to_string()),
+            ]))
+        );
+    }
+
+    #[test]
+    fn test_unknown_paths_and_methods() {
+        assert_eq!(
+            RpcRequest::from_http(&create_request("GET", "/peers", &[], b"")),
+            Err(RpcRequestError::NotFound("/peers".to_string()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("DELETE", "/torrents", &[], b"")),
+            Err(RpcRequestError::MethodNotAllowed("DELETE".to_string()))
+        );
+        assert_eq!(
+            RpcRequest::from_http(&create_request("GET", "/torrents/abcd/pause", &[], b"")),
+            Err(RpcRequestError::MethodNotAllowed("GET".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_apply_torrent_limits() {
+        let torrent_status = create_test_status("test_rpc_torrent_limits");
+        TorrentLimits {
+            max_download_kbps: Some(100),
+            seed_ratio_limit: Some(1.5),
+            seed_idle_limit_minutes: Some(30),
+            ..Default::default()
+        }
+        .apply(&torrent_status);
+
+        TorrentLimits {
+            max_upload_kbps: Some(20),
+            seed_idle_limit_minutes: Some(0),
+            ..Default::default()
+        }
+        .apply(&torrent_status);
+
+        assert_eq!(
+            TorrentLimits::of(&torrent_status),
+            TorrentLimits {
+                max_download_kbps: Some(100),
+                max_upload_kbps: Some(20),
+                seed_ratio_limit: Some(1.5),
+                seed_time_limit_minutes: Some(0),
+                seed_idle_limit_minutes: Some(0),
+            }
+        );
+    }
+
+    // Auxiliary functions
+
+    fn create_request(
+        method: &str,
+        path: &str,
+        headers: &[(&str, &str)],
+        body: &[u8],
+    ) -> HttpRequest {
+        HttpRequest {
+            method: method.to_string(),
+            path: path.to_string(),
+            query: HashMap::new(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_lowercase(), value.to_string()))
+                .collect(),
+            body: body.to_vec(),
+        }
+    }
+
+    fn create_test_status(name: &str) -> AtomicTorrentStatus {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 10,
+            pieces: vec![0; 20],
+            files: None,
+        };
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info_hash: Torrent::create_info_hash(&info).unwrap(),
+            info,
+        };
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.torrent_max_download_kbps = 0;
+        config.torrent_max_upload_kbps = 0;
+        config.seed_ratio_limit = 0.0;
+        config.seed_time_limit_minutes = 0;
+        config.seed_idle_limit_minutes = 0;
+        let (status, _) = AtomicTorrentStatus::new(&torrent, config);
+        status
+    }
+}
@@ -0,0 +1,315 @@
+use super::{
+    http_request::{HttpRequest, HttpRequestError},
+    http_response::{HttpResponse, HttpStatus},
+    rpc_request::{RpcRequest, RpcRequestError, TorrentLimits},
+};
+use crate::bt_client::{btclient::BtClient, btclient_error::BtClientError};
+use logger::logger_sender::LoggerSender;
+use serde_json::{json, Value};
+use std::{
+    io::{self, BufReader, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{self, Receiver, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+/// Maximum time to wait for a client to send its request.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Number of threads answering the requests.
+const WORKER_THREADS: usize = 4;
+
+/// Maximum number of accepted connections waiting for a free thread, the ones over it are answered as unavailable.
+const MAX_PENDING_CONNECTIONS: usize = 16;
+
+/// Struct for serving the JSON over HTTP RPC that controls a running `BtClient`.
+///
+/// Every request must send the token of the configuration in its `Authorization: Bearer` header, which is checked before reading its body.
+/// The connections are handled by a fixed number of threads, and each one is closed after answering its request.
+///
+/// To create a new `RpcServer`, use RpcServer::new(client, bind_address, token, logger_sender).
+pub struct RpcServer {
+    listener: TcpListener,
+    client: Arc<BtClient>,
+    token: Arc<String>,
+    logger_sender: LoggerSender,
+}
+
+impl RpcServer {
+    /// Creates a new `RpcServer` listening on the `ip:port` bind address.
+    ///
+    /// # Errors
+    /// - `io::Error` if the address could not be bound.
+    pub fn new(
+        client: Arc<BtClient>,
+        bind_address: &str,
+        token: String,
+        logger_sender: LoggerSender,
+    ) -> io::Result<Self> {
+        let listener = TcpListener::bind(bind_address)?;
+        Ok(Self {
+            listener,
+            client,
+            token: Arc::new(token),
+            logger_sender,
+        })
+    }
+
+    /// Handles the connections to the server forever.
+    pub fn serve(&self) {
+        if let Ok(address) = self.listener.local_addr() {
+            self.logger_sender
+                .info(&format!("Serving the RPC on http://{}", address));
+        }
+
+        let (connection_sender, connection_receiver) = mpsc::sync_channel(MAX_PENDING_CONNECTIONS);
+        let connection_receiver = Arc::new(Mutex::new(connection_receiver));
+        for i in 0..WORKER_THREADS {
+            let connection_receiver = connection_receiver.clone();
+            let client = self.client.clone();
+            let token = self.token.clone();
+            let logger = self.logger_sender.clone();
+
+            let builder = thread::Builder::new().name(format!("RPC worker {}", i));
+            let spawned = builder
+                .spawn(move || Self::run_worker(&connection_receiver, &client, &token, &logger));
+            if let Err(error) = spawned {
+                self.logger_sender.error(&format!(
+                    "An error occurred while trying to spawn a new thread for the RPC: {:?}",
+                    error
+                ));
+                return;
+            }
+        }
+
+        for stream in self.listener.incoming() {
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(error) => {
+                    self.logger_sender
+                        .warn(&format!("Couldn't accept a RPC connection: {:?}", error));
+                    continue;
+                }
+            };
+            match connection_sender.try_send(stream) {
+                Ok(()) => {}
+                Err(TrySendError::Full(mut stream)) => {
+                    self.logger_sender
+                        .warn("Too many RPC connections, rejecting a new one.");
+                    let response =
+                        HttpResponse::error(HttpStatus::ServiceUnavailable, "Too many connections");
+                    let _ = stream.write_all(&response.to_bytes());
+                }
+                Err(TrySendError::Disconnected(_)) => return,
+            }
+        }
+    }
+
+    /// Answers the connections received through the channel, one at a time, until the server stops.
+    fn run_worker(
+        connection_receiver: &Mutex<Receiver<TcpStream>>,
+        client: &BtClient,
+        token: &str,
+        logger: &LoggerSender,
+    ) {
+        loop {
+            let stream = match connection_receiver.lock() {
+                Ok(receiver) => receiver.recv(),
+                Err(_) => return,
+            };
+            let stream = match stream {
+                Ok(stream) => stream,
+                Err(_) => return,
+            };
+            if let Err(error) = Self::handle_connection(stream, client, token) {
+                logger.warn(&format!("Couldn't answer a RPC request: {:?}", error));
+            }
+        }
+    }
+
+    /// Reads a request and writes its response, the body is only read if the request has the token.
+    fn handle_connection(mut stream: TcpStream, client: &BtClient, token: &str) -> io::Result<()> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new(&mut stream);
+        let result = HttpRequest::read_head(&mut reader).and_then(|mut request| {
+            if Self::is_authorized(&request, token) {
+                request.read_body(&mut reader)?;
+            }
+            Ok(request)
+        });
+        let response = match result {
+            Ok(request) => Self::respond(client, token, &request),
+            Err(HttpRequestError::IoError(error)) => return Err(error),
+            Err(HttpRequestError::TooLarge) => {
+                HttpResponse::error(HttpStatus::PayloadTooLarge, "The request is too large")
+            }
+            Err(error) => HttpResponse::error(HttpStatus::BadRequest, &format!("{:?}", error)),
+        };
+        stream.write_all(&response.to_bytes())?;
+        stream.flush()
+    }
+
+    /// Answers an authorized request with the result of its operation.
+    fn respond(client: &BtClient, token: &str, request: &HttpRequest) -> HttpResponse {
+        if !Self::is_authorized(request, token) {
+            return HttpResponse::error(HttpStatus::Unauthorized, "Invalid or missing token");
+        }
+
+        let rpc_request = match RpcRequest::from_http(request) {
+            Ok(rpc_request) => rpc_request,
+            Err(RpcRequestError::NotFound(path)) => {
+                return HttpResponse::error(HttpStatus::NotFound, &format!("Unknown path {}", path))
+            }
+            Err(RpcRequestError::MethodNotAllowed(method)) => {
+                return HttpResponse::error(
+                    HttpStatus::MethodNotAllowed,
+                    &format!("Method {} not allowed", method),
+                )
+            }
+            Err(RpcRequestError::InvalidBody(message)) => {
+                return HttpResponse::error(HttpStatus::BadRequest, &message)
+            }
+        };
+
+        match Self::execute(client, rpc_request) {
+            Ok(body) => HttpResponse::json(HttpStatus::Ok, body),
+            Err(error) => Self::error_response(error),
+        }
+    }
+
+    fn execute(client: &BtClient, rpc_request: RpcRequest) -> Result<Value, BtClientError> {
+        let success = json!({ "result": "success" });
+        match rpc_request {
+            RpcRequest::ListTorrents => Ok(json!(client.torrent_stats())),
+            RpcRequest::GetTorrent(info_hash) => {
+                let info_hash = client.find_torrent(&info_hash)?.torrent.info_hash();
+                client
+                    .torrent_stats()
+                    .into_iter()
+                    .find(|stats| stats.info_hash == info_hash)
+                    .map(|stats| json!(stats))
+                    .ok_or(BtClientError::TorrentNotFound(info_hash))
+            }
+            RpcRequest::AddTorrentFile(contents) => {
+                client.add_torrent_file(&contents).map(|_| success)
+            }
+            RpcRequest::AddMagnet(link) => client.add_magnet(&link).map(|_| success),
+            RpcRequest::Pause(info_hash) => client.pause_torrent(&info_hash).map(|_| success),
+            RpcRequest::Resume(info_hash) => client.resume_torrent(&info_hash).map(|_| success),
+            RpcRequest::Recheck(info_hash) => client.recheck_torrent(&info_hash).map(|_| success),
+            RpcRequest::Remove {
+                info_hash,
+                delete_data,
+            } => client
+                .remove_torrent(&info_hash, delete_data)
+                .map(|_| success),
+            RpcRequest::GetTorrentLimits(info_hash) => {
+                let torrent_status = client.find_torrent(&info_hash)?;
+                Ok(json!(TorrentLimits::of(&torrent_status)))
+            }
+            RpcRequest::SetTorrentLimits { info_hash, limits } => {
+                let torrent_status = client.find_torrent(&info_hash)?;
+                limits.apply(&torrent_status);
+                Ok(json!(TorrentLimits::of(&torrent_status)))
+            }
+            RpcRequest::SetSettings(settings) => client.set_settings(&settings).map(|_| success),
+        }
+    }
+
+    fn error_response(error: BtClientError) -> HttpResponse {
+        match error {
+            BtClientError::TorrentNotFound(info_hash) => HttpResponse::error(
+                HttpStatus::NotFound,
+                &format!("Torrent {} not found", info_hash),
+            ),
+            BtClientError::TorrentAlreadyAdded(info_hash) => HttpResponse::error(
+                HttpStatus::Conflict,
+                &format!("Torrent {} was already added", info_hash),
+            ),
+            BtClientError::InvalidTorrent(message) | BtClientError::SettingError(message) => {
+                HttpResponse::error(HttpStatus::BadRequest, &message.message)
+            }
+            error => HttpResponse::error(HttpStatus::InternalServerError, &format!("{:?}", error)),
+        }
+    }
+
+    /// Returns true if the request has the token, compared in constant time so it can not be guessed by timing the answers.
+    fn is_authorized(request: &HttpRequest, token: &str) -> bool {
+        let request_token = match request.bearer_token() {
+            Some(request_token) => request_token.as_bytes(),
+            None => return false,
+        };
+        let token = token.as_bytes();
+        !token.is_empty()
+            && request_token.len() == token.len()
+            && request_token
+                .iter()
+                .zip(token)
+                .fold(0, |difference, (a, b)| difference | (a ^ b))
+                == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_requests_need_the_token() {
+        assert!(RpcServer::is_authorized(
+            &create_request(Some("Bearer secret")),
+            "secret"
+        ));
+        assert!(!RpcServer::is_authorized(
+            &create_request(Some("Bearer secreT")),
+            "secret"
+        ));
+        assert!(!RpcServer::is_authorized(
+            &create_request(Some("Bearer secret2")),
+            "secret"
+        ));
+        assert!(!RpcServer::is_authorized(
+            &create_request(Some("Basic secret")),
+            "secret"
+        ));
+        assert!(!RpcServer::is_authorized(&create_request(None), "secret"));
+        assert!(!RpcServer::is_authorized(
+            &create_request(Some("Bearer ")),
+            ""
+        ));
+    }
+
+    #[test]
+    fn test_client_errors_responses() {
+        assert_eq!(
+            RpcServer::error_response(BtClientError::TorrentNotFound("abcd".to_string())).status,
+            HttpStatus::NotFound
+        );
+        assert_eq!(
+            RpcServer::error_response(BtClientError::TorrentAlreadyAdded("abcd".to_string()))
+                .status,
+            HttpStatus::Conflict
+        );
+    }
+
+    // Auxiliary functions
+
+    fn create_request(authorization: Option<&str>) -> HttpRequest {
+        let mut headers = HashMap::new();
+        if let Some(authorization) = authorization {
+            headers.insert("authorization".to_string(), authorization.to_string());
+        }
+        HttpRequest {
+            method: "GET".to_string(),
+            path: "/torrents".to_string(),
+            query: HashMap::new(),
+            headers,
+            body: vec![],
+        }
+    }
+}
@@ -0,0 +1,17 @@
+use crate::torrent_parser::info_file::InfoFile;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct FileStats {
+    pub path: String,
+    pub length: u64,
+}
+
+impl FileStats {
+    pub fn for_file(file: &InfoFile) -> Self {
+        Self {
+            path: file.relative_path(),
+            length: file.length as u64,
+        }
+    }
+}
@@ -1,3 +1,5 @@
+pub mod file_stats;
 pub mod peer_stats;
 pub mod statistics_updater;
+pub mod stats_sink;
 pub mod torrent_stats;
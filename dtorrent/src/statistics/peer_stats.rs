@@ -1,6 +1,7 @@
 use crate::peer::{bt_peer::BtPeer, session_status::SessionStatus};
+use serde::Serialize;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct PeerStats {
     pub port: i64,
     pub ip: String,
@@ -17,7 +18,7 @@ impl PeerStats {
     pub fn for_peer(peer: BtPeer, session_status: SessionStatus) -> Self {
         Self {
             port: peer.port,
-            ip: peer.ip,
+            ip: peer.ip.to_string(),
             download_speed: session_status.download_speed,
             upload_speed: session_status.upload_speed,
             choked: session_status.choked,
@@ -1,47 +1,40 @@
-use super::torrent_stats::TorrentStats;
-use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
+use super::{stats_sink::StatsSink, torrent_stats::TorrentStats};
+use crate::torrent_handler::{status::AtomicTorrentStatusError, torrent_list::TorrentList};
 use core::time;
-use gtk::glib;
-use std::{sync::Arc, thread::sleep};
+use std::thread::sleep;
 
 #[derive(Debug)]
 pub enum StatisticsUpdaterError {
     SenderError,
     TorrentStatisticsError,
 }
-pub struct StatisticsUpdater {
-    torrent_status_list: Vec<Arc<AtomicTorrentStatus>>,
-    sender: glib::Sender<Vec<TorrentStats>>,
+pub struct StatisticsUpdater<S: StatsSink> {
+    torrent_list: TorrentList,
+    sink: S,
 }
 
-impl StatisticsUpdater {
-    pub fn new(
-        torrent_status_list: Vec<Arc<AtomicTorrentStatus>>,
-        sender: glib::Sender<Vec<TorrentStats>>,
-    ) -> StatisticsUpdater {
-        Self {
-            torrent_status_list,
-            sender,
-        }
+impl<S: StatsSink> StatisticsUpdater<S> {
+    pub fn new(torrent_list: TorrentList, sink: S) -> StatisticsUpdater<S> {
+        Self { torrent_list, sink }
     }
 
     pub fn run(&self) -> Result<(), StatisticsUpdaterError> {
         loop {
-            self.sender
-                .send(
+            self.sink
+                .send_stats(
                     self.torrent_statistics()
                         .map_err(|_| StatisticsUpdaterError::TorrentStatisticsError)?,
                 )
                 .map_err(|_| StatisticsUpdaterError::SenderError)?;
 
-            sleep(time::Duration::from_millis(300)); //Only update the UI every 300ms
+            sleep(time::Duration::from_millis(300)); //Only update the statistics every 300ms
         }
     }
 
     pub fn torrent_statistics(&self) -> Result<Vec<TorrentStats>, AtomicTorrentStatusError> {
         let mut statistics = Vec::new();
-        for torrent_status in &self.torrent_status_list {
-            statistics.push(TorrentStats::for_torrent(torrent_status)?);
+        for torrent_status in self.torrent_list.statuses() {
+            statistics.push(TorrentStats::for_torrent(&torrent_status)?);
         }
         Ok(statistics)
     }
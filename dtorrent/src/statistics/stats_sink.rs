@@ -0,0 +1,47 @@
+use super::torrent_stats::TorrentStats;
+use std::sync::mpsc;
+
+/// Posible `StatsSink` errors.
+#[derive(Debug)]
+pub enum StatsSinkError {
+    /// The receiving end of the statistics is gone.
+    Disconnected,
+}
+
+/// Where the `StatisticsUpdater` sends the statistics of the torrents, like the UI or the daemon.
+pub trait StatsSink: Send + 'static {
+    /// Sends the current statistics of every torrent of the client.
+    ///
+    /// # Errors
+    /// - `Disconnected` if nobody is receiving the statistics anymore.
+    fn send_stats(&self, stats: Vec<TorrentStats>) -> Result<(), StatsSinkError>;
+}
+
+impl StatsSink for mpsc::Sender<Vec<TorrentStats>> {
+    fn send_stats(&self, stats: Vec<TorrentStats>) -> Result<(), StatsSinkError> {
+        self.send(stats).map_err(|_| StatsSinkError::Disconnected)
+    }
+}
+
+#[cfg(feature = "gui")]
+impl StatsSink for gtk::glib::Sender<Vec<TorrentStats>> {
+    fn send_stats(&self, stats: Vec<TorrentStats>) -> Result<(), StatsSinkError> {
+        self.send(stats).map_err(|_| StatsSinkError::Disconnected)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_channel_sink() {
+        let (sender, receiver) = mpsc::channel();
+
+        sender.send_stats(vec![]).unwrap();
+        assert!(receiver.recv().unwrap().is_empty());
+
+        drop(receiver);
+        assert!(sender.send_stats(vec![]).is_err());
+    }
+}
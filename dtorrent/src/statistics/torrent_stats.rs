@@ -1,21 +1,25 @@
+use super::file_stats::FileStats;
 use super::peer_stats::PeerStats;
 use crate::torrent_handler::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
 use core::time;
+use serde::Serialize;
 use std::sync::Arc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct TorrentStats {
     pub torrent_name: String,
     pub info_hash: String,
-    pub length: u32,
+    pub length: u64,
     pub pieces_amount: u32,
     pub peers_amount: usize,
     pub downloaded_pieces_amount: usize,
     pub peers: Vec<PeerStats>,
+    pub files: Vec<FileStats>,
     pub total_peers: usize,
     pub download_speed: f64,
     pub upload_speed: f64,
     pub eta: String,
+    pub paused: bool,
 }
 
 impl TorrentStats {
@@ -40,10 +44,17 @@ impl TorrentStats {
             peers_amount: torrent_status.current_peers(),
             downloaded_pieces_amount: torrent_status.downloaded_pieces(),
             peers,
+            files: torrent
+                .info
+                .files()
+                .iter()
+                .map(FileStats::for_file)
+                .collect(),
             total_peers,
             download_speed: torrent_status.torrent_download_speed()?,
             upload_speed: torrent_status.torrent_upload_speed()?,
             eta: Self::format_eta(torrent_status)?,
+            paused: torrent_status.is_stopped(),
         })
     }
 
@@ -52,7 +63,7 @@ impl TorrentStats {
     ) -> Result<String, AtomicTorrentStatusError> {
         let down_speed = torrent_status.torrent_download_speed()? / 8_f64;
         let remaining_bytes =
-            torrent_status.remaining_pieces() as u32 * torrent_status.torrent.piece_length();
+            torrent_status.remaining_pieces() as u64 * torrent_status.torrent.piece_length() as u64;
 
         let remaining_kb = remaining_bytes / 1024;
 
@@ -0,0 +1,307 @@
+use super::manager::{file_paths, file_sections, ReadWithOffset, WriteWithOffset};
+use super::storage::{AllocationMode, Storage};
+use crate::torrent_parser::info::Info;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// Maximum number of file handles kept open by a `FileStorage`, the cache is emptied when it is reached.
+const MAX_OPEN_FILES: usize = 64;
+
+/// Size of the chunks of zeros written when a file is fully allocated.
+const ALLOCATION_CHUNK_SIZE: u64 = 1024 * 1024;
+
+/// `Storage` backend that stores the torrent in its files inside the download directory.
+///
+/// The file handles are cached, so consecutive blocks of the same file do not reopen it.
+/// A file opened for reading is reopened the first time it is written.
+///
+/// To create a new `FileStorage` use the method builder `new()`.
+#[derive(Debug)]
+pub struct FileStorage {
+    info: Info,
+    download_directory: String,
+    allocation: AllocationMode,
+    open_files: Mutex<HashMap<PathBuf, OpenFile>>,
+}
+
+#[derive(Debug)]
+struct OpenFile {
+    file: File,
+    writable: bool,
+}
+
+impl FileStorage {
+    /// Creates a new `FileStorage` for a torrent, storing its files inside the download directory.
+    pub fn new(info: Info, download_directory: &str, allocation: AllocationMode) -> Self {
+        Self {
+            info,
+            download_directory: download_directory.to_string(),
+            allocation,
+            open_files: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock_open_files(&self) -> io::Result<MutexGuard<'_, HashMap<PathBuf, OpenFile>>> {
+        self.open_files
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned open files lock"))
+    }
+
+    /// Returns the cached handle of a file, opening it if necessary.
+    ///
+    /// Files opened for writing are created, along with their directories, and allocated.
+    fn open_file<'a>(
+        &self,
+        open_files: &'a mut HashMap<PathBuf, OpenFile>,
+        path: &Path,
+        file_length: u64,
+        write: bool,
+    ) -> io::Result<&'a mut File> {
+        if open_files.len() >= MAX_OPEN_FILES && !open_files.contains_key(path) {
+            open_files.clear();
+        }
+
+        let open_file = match open_files.entry(path.to_path_buf()) {
+            Entry::Occupied(mut entry) => {
+                if write && !entry.get().writable {
+                    entry.insert(self.open_for_writing(path, file_length)?);
+                }
+                entry.into_mut()
+            }
+            Entry::Vacant(entry) => {
+                let open_file = if write {
+                    self.open_for_writing(path, file_length)?
+                } else {
+                    OpenFile {
+                        file: OpenOptions::new().read(true).open(path)?,
+                        writable: false,
+                    }
+                };
+                entry.insert(open_file)
+            }
+        };
+        Ok(&mut open_file.file)
+    }
+
+    fn open_for_writing(&self, path: &Path, file_length: u64) -> io::Result<OpenFile> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(path)?;
+        allocate_file(&mut file, file_length, self.allocation)?;
+
+        Ok(OpenFile {
+            file,
+            writable: true,
+        })
+    }
+}
+
+impl Storage for FileStorage {
+    fn write_block(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut open_files = self.lock_open_files()?;
+        for section in file_sections(&self.info, offset, data.len(), &self.download_directory)? {
+            let file = self.open_file(&mut open_files, &section.path, section.file_length, true)?;
+            file.write_all_at(
+                &data[section.range_offset..section.range_offset + section.length],
+                section.file_offset,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        // The range is checked before allocating the buffer, so an invalid length can not exhaust the memory.
+        let sections = file_sections(&self.info, offset, length, &self.download_directory)?;
+        let mut buffer = vec![0; length];
+        let mut open_files = self.lock_open_files()?;
+        for section in sections {
+            let file =
+                self.open_file(&mut open_files, &section.path, section.file_length, false)?;
+            file.read_exact_at(
+                &mut buffer[section.range_offset..section.range_offset + section.length],
+                section.file_offset,
+            )?;
+        }
+        Ok(buffer)
+    }
+
+    fn allocate(&self) -> io::Result<()> {
+        if self.allocation == AllocationMode::Lazy {
+            return Ok(());
+        }
+
+        let mut open_files = self.lock_open_files()?;
+        let paths = file_paths(&self.info, &self.download_directory);
+        for (file, path) in self.info.files().iter().zip(paths) {
+            self.open_file(&mut open_files, &path, file.length as u64, true)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        let open_files = self.lock_open_files()?;
+        for open_file in open_files.values().filter(|open_file| open_file.writable) {
+            open_file.file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+/// Grows a file up to its final length according to the allocation mode.
+///
+/// Files that are already as long as `file_length` are not modified.
+pub fn allocate_file(file: &mut File, file_length: u64, mode: AllocationMode) -> io::Result<()> {
+    let current_length = file.metadata()?.len();
+    if current_length >= file_length {
+        return Ok(());
+    }
+
+    match mode {
+        AllocationMode::Lazy => Ok(()),
+        AllocationMode::Sparse => file.set_len(file_length),
+        AllocationMode::Full => {
+            let zeros = vec![0; ALLOCATION_CHUNK_SIZE as usize];
+            file.seek(SeekFrom::Start(current_length))?;
+            let mut remaining = file_length - current_length;
+            while remaining > 0 {
+                let chunk_size = remaining.min(ALLOCATION_CHUNK_SIZE);
+                file.write_all(&zeros[..chunk_size as usize])?;
+                remaining -= chunk_size;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_manager::test_utils::TestTorrent;
+
+    #[test]
+    fn test_write_and_read_block_spanning_multiple_files() {
+        let torrent = TestTorrent::multi_file("test_file_storage_01");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        storage.write_block(0, b"Hello").unwrap();
+        storage.write_block(5, b", world!").unwrap();
+        let block = storage.read_block(3, 8).unwrap();
+
+        assert_eq!(block, b"lo, worl");
+    }
+
+    #[test]
+    fn test_read_block_of_missing_file() {
+        let torrent = TestTorrent::multi_file("test_file_storage_02");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        let io_error = storage.read_block(0, 5).unwrap_err();
+
+        assert_eq!(io_error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_write_after_read_reopens_file() {
+        let torrent = TestTorrent::multi_file("test_file_storage_03");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+        storage.write_block(0, b"Hello").unwrap();
+        let other_storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        other_storage.read_block(0, 5).unwrap();
+        other_storage.write_block(0, b"Jello").unwrap();
+        let block = storage.read_block(0, 5).unwrap();
+
+        assert_eq!(block, b"Jello");
+    }
+
+    #[test]
+    fn test_sparse_allocation() {
+        let torrent = TestTorrent::multi_file("test_file_storage_04");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Sparse,
+        );
+
+        storage.allocate().unwrap();
+
+        let lengths: Vec<u64> = torrent
+            .file_paths()
+            .iter()
+            .map(|path| fs::metadata(path).unwrap().len())
+            .collect();
+        assert_eq!(lengths, vec![5, 4, 4]);
+    }
+
+    #[test]
+    fn test_full_allocation_keeps_existing_data() {
+        let torrent = TestTorrent::multi_file("test_file_storage_05");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Full,
+        );
+        storage.write_block(0, b"Hi").unwrap();
+
+        storage.allocate().unwrap();
+
+        let first_file = fs::read(&torrent.file_paths()[0]).unwrap();
+        assert_eq!(first_file, b"Hi\0\0\0");
+    }
+
+    #[test]
+    fn test_lazy_allocation_does_not_create_files() {
+        let torrent = TestTorrent::multi_file("test_file_storage_06");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        storage.allocate().unwrap();
+
+        assert!(!torrent.path().exists());
+    }
+
+    #[test]
+    fn test_read_block_out_of_bounds() {
+        let torrent = TestTorrent::multi_file("test_file_storage_07");
+        let storage = FileStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        let too_long = storage.read_block(0, usize::MAX).unwrap_err();
+        let overflowing = storage.read_block(u64::MAX, 1).unwrap_err();
+
+        assert_eq!(too_long.kind(), io::ErrorKind::UnexpectedEof);
+        assert_eq!(overflowing.kind(), io::ErrorKind::UnexpectedEof);
+    }
+}
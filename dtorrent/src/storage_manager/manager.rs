@@ -1,9 +1,13 @@
+use super::file_storage::FileStorage;
+use super::storage::{AllocationMode, Storage};
 use crate::config::cfg::Cfg;
-use std::fs::{self, File, OpenOptions};
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::path::Path;
+use crate::torrent_parser::{info::Info, info_file::InfoFile};
+use std::fs::File;
+use std::io::{ErrorKind, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
 
-trait WriteWithOffset {
+/// Trait for writing a whole buffer at an offset of a file.
+pub trait WriteWithOffset {
     fn write_all_at(&mut self, buf: &[u8], offset: u64) -> Result<(), std::io::Error>;
 }
 
@@ -14,7 +18,8 @@ impl WriteWithOffset for File {
     }
 }
 
-trait ReadWithOffset {
+/// Trait for filling a whole buffer from an offset of a file.
+pub trait ReadWithOffset {
     fn read_exact_at(&mut self, buf: &mut [u8], offset: u64) -> Result<(), std::io::Error>;
 }
 
@@ -25,59 +30,146 @@ impl ReadWithOffset for File {
     }
 }
 
+/// A section of a file that overlaps a range of bytes of the torrent.
+#[derive(Debug, PartialEq)]
+pub struct FileSection {
+    pub path: PathBuf,
+    /// Final length of the file.
+    pub file_length: u64,
+    /// Offset inside the file where the section starts.
+    pub file_offset: u64,
+    /// Offset inside the requested range where the section starts.
+    pub range_offset: usize,
+    pub length: usize,
+}
+
+/// Returns the path where the torrent data is stored.
+///
+/// Single-file torrents are stored as `download_directory/name`, while multi-file torrents are stored inside the `download_directory/name` directory.
+pub fn torrent_path(info: &Info, download_directory: &str) -> PathBuf {
+    Path::new(download_directory).join(&info.name)
+}
+
+/// Returns the path of every file of the torrent.
+pub fn file_paths(info: &Info, download_directory: &str) -> Vec<PathBuf> {
+    info.files()
+        .iter()
+        .map(|file| file_path(info, file, download_directory))
+        .collect()
+}
+
+fn file_path(info: &Info, file: &InfoFile, download_directory: &str) -> PathBuf {
+    let path = torrent_path(info, download_directory);
+    if info.is_multi_file() {
+        file.path
+            .iter()
+            .fold(path, |path, component| path.join(component))
+    } else {
+        path
+    }
+}
+
+/// Maps a range of bytes of the torrent onto the files that contain it.
+///
+/// It returns an `UnexpectedEof` error if the range goes past the end of the torrent.
+pub fn file_sections(
+    info: &Info,
+    offset: u64,
+    length: usize,
+    download_directory: &str,
+) -> Result<Vec<FileSection>, std::io::Error> {
+    let end = match offset.checked_add(length as u64) {
+        Some(end) if end <= info.length as u64 => end,
+        _ => {
+            return Err(std::io::Error::new(
+                ErrorKind::UnexpectedEof,
+                format!(
+                    "Range of {} bytes at {} is out of the torrent bounds",
+                    length, offset
+                ),
+            ))
+        }
+    };
+
+    let mut sections = Vec::new();
+    let mut file_start = 0;
+    for file in info.files() {
+        let file_end = file_start + file.length as u64;
+
+        // Zero-length ranges still map to the file containing the offset.
+        if file_start < end.max(offset + 1) && offset < file_end {
+            let section_start = offset.max(file_start);
+            let section_end = end.min(file_end);
+            sections.push(FileSection {
+                path: file_path(info, &file, download_directory),
+                file_length: file.length as u64,
+                file_offset: section_start - file_start,
+                range_offset: (section_start - offset) as usize,
+                length: (section_end - section_start) as usize,
+            });
+        }
+        file_start = file_end;
+    }
+    Ok(sections)
+}
+
+/// Saves a piece to the files of the torrent.
+///
+/// If the piece spans multiple files, each part of it is written to the corresponding file.
+/// The files and directories are created if they don't exist.
+///
+/// It opens the files for a single piece, the torrent handlers use a `Storage` that keeps them open instead.
+///
+/// # Arguments
+/// * `info` - the info of the torrent the piece belongs to.
+/// * `piece` - the piece data.
+/// * `piece_offset` - integer specifying the offset in bytes from the start of the torrent.
+/// * `config` - the configuration of the application
 pub fn save_piece(
-    name: String,
+    info: &Info,
     piece: &[u8],
     piece_offset: u64,
     config: Cfg,
 ) -> Result<(), std::io::Error> {
-    let save_directory = config.download_directory;
-    if !Path::new(&save_directory).exists() {
-        fs::create_dir_all(save_directory.clone())?;
-    }
-    let mut file = OpenOptions::new()
-        .read(true)
-        .write(true)
-        .create(true)
-        .open(save_directory + "/" + &name)?;
-
-    file.write_all_at(piece, piece_offset)?;
-
-    Ok(())
+    FileStorage::new(
+        info.clone(),
+        &config.download_directory,
+        AllocationMode::Lazy,
+    )
+    .write_block(piece_offset, piece)
 }
 
-/// Retrieves a block of data from a file at a given offset.
+/// Retrieves a block of data of the torrent at a given offset.
+///
+/// If the block spans multiple files, it is read from each of them.
 ///
 /// # Arguments
-/// * `filename` - the name of the file to retrieve the data from.
-/// * `offset` - integer specifying the offset in bytes from the start of the file
+/// * `info` - the info of the torrent to retrieve the data from.
+/// * `offset` - integer specifying the offset in bytes from the start of the torrent.
 /// * `length` - integer specifying the requested length
 /// * `config` - the configuration of the application
 pub fn retrieve_block(
-    filename: String,
+    info: &Info,
     offset: u64,
     length: usize,
     config: Cfg,
 ) -> Result<Vec<u8>, std::io::Error> {
-    let file_directory = config.download_directory;
-
-    let mut file = OpenOptions::new()
-        .read(true)
-        .open(file_directory + "/" + &filename)?;
-
-    let mut buffer = vec![0; length];
-    file.read_exact_at(&mut buffer, offset)?;
-
-    Ok(buffer)
+    FileStorage::new(
+        info.clone(),
+        &config.download_directory,
+        AllocationMode::Lazy,
+    )
+    .read_block(offset, length)
 }
 
 #[cfg(test)]
 mod tests {
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::io::Write;
     use std::path::Path;
 
     use super::*;
+    use crate::storage_manager::test_utils::{create_multi_file_test_info, TestTorrent};
 
     const CONFIG_PATH: &str = "config.cfg";
 
@@ -93,7 +185,7 @@ mod tests {
         let offset = 0;
         let length = 5;
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(&create_test_info(filename), offset, length, config)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -118,7 +210,7 @@ mod tests {
         let offset = 4;
         let length = 7;
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(&create_test_info(filename), offset, length, config)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -143,7 +235,7 @@ mod tests {
         let offset = 0;
         let length = contents.len();
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(&create_test_info(filename), offset, length, config)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -168,7 +260,8 @@ mod tests {
         let offset = 0;
         let length = contents.len() + 1;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error =
+            retrieve_block(&create_test_info(filename), offset, length, config).unwrap_err();
 
         fs::remove_file(filepath).unwrap();
 
@@ -187,7 +280,8 @@ mod tests {
         let offset = 0;
         let length = contents.len() + 1;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error =
+            retrieve_block(&create_test_info(filename), offset, length, config).unwrap_err();
 
         fs::remove_file(filepath).unwrap();
 
@@ -206,7 +300,7 @@ mod tests {
         let offset = 0;
         let length = 0;
 
-        let block = retrieve_block(String::from(filename), offset, length, config)
+        let block = retrieve_block(&create_test_info(filename), offset, length, config)
             .map_err(|err| {
                 fs::remove_file(&filepath).unwrap();
                 err
@@ -228,7 +322,8 @@ mod tests {
         let offset = 0;
         let length = 6;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error =
+            retrieve_block(&create_test_info(filename), offset, length, config).unwrap_err();
 
         assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
     }
@@ -243,7 +338,8 @@ mod tests {
         let offset = 0;
         let length = 5;
 
-        let io_error = retrieve_block(String::from(filename), offset, length, config).unwrap_err();
+        let io_error =
+            retrieve_block(&create_test_info(filename), offset, length, config).unwrap_err();
 
         assert_eq!(io_error.kind(), std::io::ErrorKind::NotFound);
     }
@@ -265,7 +361,7 @@ mod tests {
 
         assert!(!Path::new(&path).exists());
         assert!(save_piece(
-            file_name,
+            &create_test_info(&file_name),
             &[0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8],
             0,
             config
@@ -286,7 +382,7 @@ mod tests {
         assert!(!Path::new(&path).exists());
 
         let content_to_write = vec![0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8];
-        assert!(save_piece(file_name, &content_to_write, 0, config).is_ok());
+        assert!(save_piece(&create_test_info(&file_name), &content_to_write, 0, config).is_ok());
         assert!(Path::new(&path).exists());
 
         read_file_and_assert_its_content_equals_expected_content(content_to_write, &path);
@@ -305,7 +401,7 @@ mod tests {
         File::create(&path).unwrap();
 
         let content_to_write = vec![0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8];
-        assert!(save_piece(file_name, &content_to_write, 0, config).is_ok());
+        assert!(save_piece(&create_test_info(&file_name), &content_to_write, 0, config).is_ok());
 
         read_file_and_assert_its_content_equals_expected_content(content_to_write, &path);
 
@@ -325,7 +421,7 @@ mod tests {
         file.write_all(&previous_content).unwrap();
 
         let content_to_write = vec![0x50u8, 0x65u8, 0x72u8, 0xF3u8, 0x6Eu8];
-        assert!(save_piece(file_name, &content_to_write, 5, config).is_ok());
+        assert!(save_piece(&create_test_info(&file_name), &content_to_write, 5, config).is_ok());
 
         read_file_and_assert_its_content_equals_expected_content(
             vec![
@@ -353,7 +449,7 @@ mod tests {
         file.write_all(&first_piece).unwrap();
         file.write_all_at(&third_piece, 7).unwrap();
 
-        assert!(save_piece(file_name, &second_piece, 4, config).is_ok());
+        assert!(save_piece(&create_test_info(&file_name), &second_piece, 4, config).is_ok());
 
         read_file_and_assert_its_content_equals_expected_content(
             vec![
@@ -365,6 +461,78 @@ mod tests {
         fs::remove_file(path).unwrap();
     }
 
+    // -------------------------------------------------------------------------------------
+
+    #[test]
+    fn save_piece_spanning_multiple_files() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = TestTorrent::multi_file("test_multi_file_01");
+
+        assert!(save_piece(&torrent.info, b"Hello, world!", 0, config).is_ok());
+
+        let paths = torrent.file_paths();
+        read_file_and_assert_its_content_equals_expected_content(
+            b"Hello".to_vec(),
+            paths[0].to_str().unwrap(),
+        );
+        read_file_and_assert_its_content_equals_expected_content(
+            b", wo".to_vec(),
+            paths[1].to_str().unwrap(),
+        );
+        read_file_and_assert_its_content_equals_expected_content(
+            b"rld!".to_vec(),
+            paths[2].to_str().unwrap(),
+        );
+    }
+
+    #[test]
+    fn retrieve_block_spanning_multiple_files() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = TestTorrent::multi_file("test_multi_file_02");
+
+        save_piece(&torrent.info, b"Hello, world!", 0, config.clone()).unwrap();
+
+        let block = retrieve_block(&torrent.info, 3, 8, config).unwrap();
+
+        assert_eq!("lo, worl".as_bytes(), &block[..]);
+    }
+
+    #[test]
+    fn retrieve_block_out_of_torrent_bounds() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let info = create_multi_file_test_info("test_multi_file_03");
+
+        let io_error = retrieve_block(&info, 10, 4, config).unwrap_err();
+
+        assert_eq!(io_error.kind(), std::io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn multi_file_torrent_is_saved_inside_its_directory() {
+        let info = create_multi_file_test_info("test_multi_file_04");
+
+        let paths = file_paths(&info, "downloads");
+
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("downloads/test_multi_file_04/a.txt"),
+                PathBuf::from("downloads/test_multi_file_04/dir/b.txt"),
+                PathBuf::from("downloads/test_multi_file_04/dir/c.txt"),
+            ]
+        );
+    }
+
+    fn create_test_info(name: &str) -> Info {
+        Info {
+            length: 100,
+            name: name.to_string(),
+            piece_length: 10,
+            pieces: vec![],
+            files: None,
+        }
+    }
+
     fn read_file_and_assert_its_content_equals_expected_content(
         expected_content: Vec<u8>,
         file_name: &str,
@@ -0,0 +1,91 @@
+use super::storage::Storage;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+/// `Storage` backend that keeps the whole torrent in memory, mostly useful for tests.
+///
+/// To create a new empty `MemoryStorage` use the method builder `new()`, or `with_data()` to start from existing data.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    data: Mutex<Vec<u8>>,
+}
+
+impl MemoryStorage {
+    /// Creates a new `MemoryStorage` for a torrent of `length` bytes, filled with zeros.
+    pub fn new(length: u64) -> Self {
+        Self::with_data(vec![0; length as usize])
+    }
+
+    /// Creates a new `MemoryStorage` containing the given torrent data.
+    pub fn with_data(data: Vec<u8>) -> Self {
+        Self {
+            data: Mutex::new(data),
+        }
+    }
+
+    fn lock_data(&self) -> io::Result<MutexGuard<'_, Vec<u8>>> {
+        self.data
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned data lock"))
+    }
+}
+
+/// Returns the range of the torrent data covered by a block, or an `UnexpectedEof` error if it goes past its end.
+fn block_range(data: &[u8], offset: u64, length: usize) -> io::Result<std::ops::Range<usize>> {
+    let start = offset as usize;
+    match start.checked_add(length) {
+        Some(end) if end <= data.len() => Ok(start..end),
+        _ => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!(
+                "Range {}..{} is out of the torrent bounds",
+                offset,
+                offset + length as u64
+            ),
+        )),
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn write_block(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut stored = self.lock_data()?;
+        let range = block_range(&stored, offset, data.len())?;
+        stored[range].copy_from_slice(data);
+        Ok(())
+    }
+
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let stored = self.lock_data()?;
+        let range = block_range(&stored, offset, length)?;
+        Ok(stored[range].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_and_read_block() {
+        let storage = MemoryStorage::new(13);
+
+        storage.write_block(0, b"Hello").unwrap();
+        storage.write_block(5, b", world!").unwrap();
+
+        assert_eq!(storage.read_block(3, 8).unwrap(), b"lo, worl");
+    }
+
+    #[test]
+    fn test_block_out_of_bounds() {
+        let storage = MemoryStorage::with_data(b"Hello".to_vec());
+
+        assert_eq!(
+            storage.read_block(3, 3).unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+        assert_eq!(
+            storage.write_block(5, b"!").unwrap_err().kind(),
+            io::ErrorKind::UnexpectedEof
+        );
+    }
+}
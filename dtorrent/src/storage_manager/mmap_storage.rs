@@ -0,0 +1,243 @@
+use super::file_storage::allocate_file;
+use super::manager::{file_paths, file_sections};
+use super::storage::{AllocationMode, Storage};
+use crate::torrent_parser::info::Info;
+use memmap2::MmapMut;
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+
+/// `Storage` backend that maps the files of the torrent inside the download directory into memory.
+///
+/// A file has to be as long as its final size to be mapped, so files are created sparse unless the allocation mode is `Full`.
+/// Files that are shorter than their final size, like the ones left by a lazily allocated `FileStorage`, are only mapped when they are written.
+///
+/// To create a new `MmapStorage` use the method builder `new()`.
+#[derive(Debug)]
+pub struct MmapStorage {
+    info: Info,
+    download_directory: String,
+    allocation: AllocationMode,
+    maps: Mutex<HashMap<PathBuf, MmapMut>>,
+}
+
+impl MmapStorage {
+    /// Creates a new `MmapStorage` for a torrent, storing its files inside the download directory.
+    pub fn new(info: Info, download_directory: &str, allocation: AllocationMode) -> Self {
+        Self {
+            info,
+            download_directory: download_directory.to_string(),
+            allocation,
+            maps: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn lock_maps(&self) -> io::Result<MutexGuard<'_, HashMap<PathBuf, MmapMut>>> {
+        self.maps
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned memory maps lock"))
+    }
+
+    /// Returns the memory map of a file, mapping it if necessary.
+    ///
+    /// Files mapped for writing are created, along with their directories, and allocated.
+    fn map_file<'a>(
+        &self,
+        maps: &'a mut HashMap<PathBuf, MmapMut>,
+        path: &Path,
+        file_length: u64,
+        write: bool,
+    ) -> io::Result<&'a mut MmapMut> {
+        match maps.entry(path.to_path_buf()) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                if write {
+                    if let Some(parent) = path.parent() {
+                        fs::create_dir_all(parent)?;
+                    }
+                }
+                let mut file = OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .create(write)
+                    .truncate(false)
+                    .open(path)?;
+
+                if write {
+                    let mode = match self.allocation {
+                        AllocationMode::Full => AllocationMode::Full,
+                        _ => AllocationMode::Sparse,
+                    };
+                    allocate_file(&mut file, file_length, mode)?;
+                } else if file.metadata()?.len() < file_length {
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        format!("File {:?} is shorter than its final size", path),
+                    ));
+                }
+
+                // SAFETY: the file is only modified through this map while the storage exists.
+                let map = unsafe { MmapMut::map_mut(&file)? };
+                Ok(entry.insert(map))
+            }
+        }
+    }
+}
+
+impl Storage for MmapStorage {
+    fn write_block(&self, offset: u64, data: &[u8]) -> io::Result<()> {
+        let mut maps = self.lock_maps()?;
+        for section in file_sections(&self.info, offset, data.len(), &self.download_directory)? {
+            // Empty files can not be mapped, they are only created.
+            if section.length == 0 {
+                if section.file_length == 0 {
+                    create_empty_file(&section.path)?;
+                }
+                continue;
+            }
+            let map = self.map_file(&mut maps, &section.path, section.file_length, true)?;
+            let start = section.file_offset as usize;
+            map[start..start + section.length].copy_from_slice(
+                &data[section.range_offset..section.range_offset + section.length],
+            );
+        }
+        Ok(())
+    }
+
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>> {
+        let sections = file_sections(&self.info, offset, length, &self.download_directory)?;
+        let mut buffer = vec![0; length];
+        let mut maps = self.lock_maps()?;
+        for section in sections.iter().filter(|section| section.length > 0) {
+            let map = self.map_file(&mut maps, &section.path, section.file_length, false)?;
+            let start = section.file_offset as usize;
+            buffer[section.range_offset..section.range_offset + section.length]
+                .copy_from_slice(&map[start..start + section.length]);
+        }
+        Ok(buffer)
+    }
+
+    fn allocate(&self) -> io::Result<()> {
+        let mut maps = self.lock_maps()?;
+        let paths = file_paths(&self.info, &self.download_directory);
+        for (file, path) in self.info.files().iter().zip(paths) {
+            // Empty files can not be mapped, they are only created.
+            if file.length > 0 {
+                self.map_file(&mut maps, &path, file.length as u64, true)?;
+            } else {
+                create_empty_file(&path)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> io::Result<()> {
+        self.lock_maps()?.values().try_for_each(|map| map.flush())
+    }
+}
+
+/// Creates an empty file of the torrent, along with its directories.
+fn create_empty_file(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map(|_| ())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage_manager::test_utils::TestTorrent;
+    use crate::torrent_parser::info_file::InfoFile;
+
+    #[test]
+    fn test_write_and_read_block_spanning_multiple_files() {
+        let torrent = TestTorrent::multi_file("test_mmap_storage_01");
+        let storage = MmapStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        storage.write_block(0, b"Hello, world!").unwrap();
+        storage.flush().unwrap();
+        let block = storage.read_block(3, 8).unwrap();
+        let last_file = fs::read(&torrent.file_paths()[2]).unwrap();
+
+        assert_eq!(block, b"lo, worl");
+        assert_eq!(last_file, b"rld!");
+    }
+
+    #[test]
+    fn test_read_block_of_missing_file() {
+        let torrent = TestTorrent::multi_file("test_mmap_storage_02");
+        let storage = MmapStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        let io_error = storage.read_block(0, 5).unwrap_err();
+
+        assert_eq!(io_error.kind(), io::ErrorKind::NotFound);
+    }
+
+    #[test]
+    fn test_write_creates_files_with_final_size() {
+        let torrent = TestTorrent::multi_file("test_mmap_storage_03");
+        let storage = MmapStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        storage.write_block(0, b"Hi").unwrap();
+
+        let first_file = fs::read(&torrent.file_paths()[0]).unwrap();
+        assert_eq!(first_file, b"Hi\0\0\0");
+    }
+
+    #[test]
+    fn test_write_and_read_block_spanning_an_empty_file() {
+        let torrent = TestTorrent::new(Info {
+            length: 9,
+            name: "test_mmap_storage_04".to_string(),
+            piece_length: 10,
+            pieces: vec![],
+            files: Some(vec![
+                InfoFile {
+                    length: 5,
+                    path: vec!["a.txt".to_string()],
+                },
+                InfoFile {
+                    length: 0,
+                    path: vec!["empty.txt".to_string()],
+                },
+                InfoFile {
+                    length: 4,
+                    path: vec!["b.txt".to_string()],
+                },
+            ]),
+        });
+        let storage = MmapStorage::new(
+            torrent.info.clone(),
+            &torrent.download_directory,
+            AllocationMode::Lazy,
+        );
+
+        storage.write_block(0, b"Hello, wo").unwrap();
+        let block = storage.read_block(3, 4).unwrap();
+        let empty_file = fs::metadata(&torrent.file_paths()[1]).unwrap();
+
+        assert_eq!(block, b"lo, ");
+        assert_eq!(empty_file.len(), 0);
+    }
+}
@@ -1 +1,9 @@
+pub mod file_storage;
 pub mod manager;
+pub mod memory_storage;
+pub mod mmap_storage;
+pub mod resume_data;
+pub mod storage;
+
+#[cfg(test)]
+mod test_utils;
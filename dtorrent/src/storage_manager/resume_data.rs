@@ -0,0 +1,267 @@
+use super::manager::file_paths;
+use crate::config::cfg::Cfg;
+use crate::torrent_parser::info::Info;
+use bencoder::bencode::{Bencode, BencodeError, ToBencode};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Directory inside the download directory where the resume files are stored.
+const RESUME_DIRECTORY: &str = ".resume";
+
+/// Value stored as the modification time of a file that does not exist.
+const MISSING_FILE_MTIME: i64 = -1;
+
+/// `ResumeData` struct containing the state of a torrent that is stored between runs of the client.
+///
+/// - `bitfield`: the pieces that were downloaded and verified,
+/// - `downloaded`: number of bytes downloaded from peers,
+/// - `uploaded`: number of bytes uploaded to peers,
+/// - `file_mtimes`: modification time in nanoseconds of each file of the torrent when the resume data was saved.
+///
+/// The resume data can only be trusted if the files were not modified since it was saved, see `matches_files()`.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ResumeData {
+    pub bitfield: Vec<u8>,
+    pub downloaded: u64,
+    pub uploaded: u64,
+    pub file_mtimes: Vec<i64>,
+}
+
+/// Posible `ResumeData` errors.
+#[derive(Debug)]
+pub enum ResumeDataError {
+    ReadError(io::Error),
+    DecodeError(BencodeError),
+    NotADict,
+    MissingBitfield,
+    InvalidCounter,
+    InvalidFileMtimes,
+}
+
+impl ResumeData {
+    /// Builds a new `ResumeData` from a bencoded dict.
+    ///
+    /// It returns an `ResumeDataError` if:
+    /// - The bencoded resume data is not a dict.
+    /// - The bitfield is missing.
+    /// - A counter is not a positive number.
+    /// - The file modification times are not a list of numbers.
+    pub fn from(bencode: &Bencode) -> Result<ResumeData, ResumeDataError> {
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(ResumeDataError::NotADict),
+        };
+
+        let mut bitfield = None;
+        let mut downloaded = 0;
+        let mut uploaded = 0;
+        let mut file_mtimes = Vec::new();
+
+        for (k, v) in d.iter() {
+            if k == b"bitfield" {
+                bitfield = match v {
+                    Bencode::BString(s) => Some(s.clone()),
+                    _ => return Err(ResumeDataError::MissingBitfield),
+                };
+            } else if k == b"downloaded" {
+                downloaded = Self::create_counter(v)?;
+            } else if k == b"uploaded" {
+                uploaded = Self::create_counter(v)?;
+            } else if k == b"file mtimes" {
+                file_mtimes = Self::create_file_mtimes(v)?;
+            }
+        }
+
+        Ok(ResumeData {
+            bitfield: bitfield.ok_or(ResumeDataError::MissingBitfield)?,
+            downloaded,
+            uploaded,
+            file_mtimes,
+        })
+    }
+
+    fn create_counter(bencode: &Bencode) -> Result<u64, ResumeDataError> {
+        match bencode {
+            Bencode::BNumber(n) if *n >= 0 => Ok(*n as u64),
+            _ => Err(ResumeDataError::InvalidCounter),
+        }
+    }
+
+    fn create_file_mtimes(bencode: &Bencode) -> Result<Vec<i64>, ResumeDataError> {
+        let list = match bencode {
+            Bencode::BList(list) => list,
+            _ => return Err(ResumeDataError::InvalidFileMtimes),
+        };
+
+        list.iter()
+            .map(|mtime| match mtime {
+                Bencode::BNumber(n) => Ok(*n),
+                _ => Err(ResumeDataError::InvalidFileMtimes),
+            })
+            .collect()
+    }
+
+    /// Loads the resume data of a torrent from its resume file.
+    ///
+    /// It returns `None` if the torrent has no resume file.
+    ///
+    /// It returns an `ResumeDataError` if the resume file could not be read or is invalid.
+    pub fn load(info_hash: &str, config: &Cfg) -> Result<Option<ResumeData>, ResumeDataError> {
+        let contents = match fs::read(resume_path(info_hash, config)) {
+            Ok(contents) => contents,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(ResumeDataError::ReadError(err)),
+        };
+
+        let bencode = Bencode::decode(&contents).map_err(ResumeDataError::DecodeError)?;
+        Self::from(&bencode).map(Some)
+    }
+
+    /// Saves the resume data of a torrent to its resume file.
+    ///
+    /// The data is written to a temporary file first, so a crash while saving does not leave a corrupted resume file.
+    pub fn save(&self, info_hash: &str, config: &Cfg) -> io::Result<()> {
+        let path = resume_path(info_hash, config);
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let tmp_path = path.with_extension("resume.tmp");
+        fs::write(&tmp_path, Bencode::encode(self))?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Deletes the resume file of a torrent, if there is one.
+    pub fn delete(info_hash: &str, config: &Cfg) -> io::Result<()> {
+        match fs::remove_file(resume_path(info_hash, config)) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns true if the files of the torrent were not modified since the resume data was saved.
+    pub fn matches_files(&self, info: &Info, config: &Cfg) -> bool {
+        self.file_mtimes == file_mtimes(info, config)
+    }
+}
+
+impl ToBencode for ResumeData {
+    fn to_bencode(&self) -> Bencode {
+        let mut resume_data = BTreeMap::new();
+        resume_data.insert(b"bitfield".to_vec(), self.bitfield.to_bencode());
+        resume_data.insert(b"downloaded".to_vec(), self.downloaded.to_bencode());
+        resume_data.insert(b"uploaded".to_vec(), self.uploaded.to_bencode());
+        resume_data.insert(
+            b"file mtimes".to_vec(),
+            Bencode::BList(
+                self.file_mtimes
+                    .iter()
+                    .map(|m| Bencode::BNumber(*m))
+                    .collect(),
+            ),
+        );
+        Bencode::BDict(resume_data)
+    }
+}
+
+/// Returns the path of the resume file of a torrent.
+pub fn resume_path(info_hash: &str, config: &Cfg) -> PathBuf {
+    Path::new(&config.download_directory)
+        .join(RESUME_DIRECTORY)
+        .join(format!("{}.resume", info_hash))
+}
+
+/// Returns the modification time in nanoseconds of each file of the torrent, or `MISSING_FILE_MTIME` for the files that do not exist.
+pub fn file_mtimes(info: &Info, config: &Cfg) -> Vec<i64> {
+    file_paths(info, &config.download_directory)
+        .iter()
+        .map(|path| {
+            fs::metadata(path)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+                .map(|duration| duration.as_nanos() as i64)
+                .unwrap_or(MISSING_FILE_MTIME)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_save_and_load() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let info_hash = "test_resume_data_save_and_load";
+        let resume_data = ResumeData {
+            bitfield: vec![0b1010_0000],
+            downloaded: 100,
+            uploaded: 50,
+            file_mtimes: vec![1234, MISSING_FILE_MTIME],
+        };
+
+        resume_data.save(info_hash, &config).unwrap();
+        let loaded = ResumeData::load(info_hash, &config).unwrap();
+        ResumeData::delete(info_hash, &config).unwrap();
+
+        assert_eq!(loaded, Some(resume_data));
+        assert!(!resume_path(info_hash, &config).exists());
+    }
+
+    #[test]
+    fn test_load_without_resume_file() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+
+        let loaded = ResumeData::load("test_resume_data_missing", &config).unwrap();
+
+        assert_eq!(loaded, None);
+    }
+
+    #[test]
+    fn test_from_without_bitfield() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"downloaded".to_vec(), Bencode::BNumber(100));
+
+        assert!(matches!(
+            ResumeData::from(&Bencode::BDict(dict)),
+            Err(ResumeDataError::MissingBitfield)
+        ));
+    }
+
+    #[test]
+    fn test_matches_files() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let info = create_test_info("test_resume_data_matches_files");
+        let path = Path::new(&config.download_directory).join(&info.name);
+        fs::write(&path, b"data").unwrap();
+
+        let resume_data = ResumeData {
+            bitfield: vec![0],
+            downloaded: 0,
+            uploaded: 0,
+            file_mtimes: file_mtimes(&info, &config),
+        };
+        assert!(resume_data.matches_files(&info, &config));
+
+        fs::remove_file(&path).unwrap();
+        assert!(!resume_data.matches_files(&info, &config));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_info(name: &str) -> Info {
+        Info {
+            length: 4,
+            name: name.to_string(),
+            piece_length: 4,
+            pieces: vec![],
+            files: None,
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use super::{file_storage::FileStorage, memory_storage::MemoryStorage, mmap_storage::MmapStorage};
+use crate::config::cfg::Cfg;
+use crate::torrent_parser::info::Info;
+use std::fmt::Debug;
+use std::io;
+use std::str::FromStr;
+
+/// Trait for the places where the data of a torrent can be stored.
+///
+/// Offsets are in bytes from the start of the torrent, each backend maps them onto its own layout.
+pub trait Storage: Debug + Send + Sync {
+    /// Writes a block of data of the torrent at a given offset.
+    fn write_block(&self, offset: u64, data: &[u8]) -> io::Result<()>;
+
+    /// Reads a block of data of the torrent at a given offset.
+    ///
+    /// It returns an `UnexpectedEof` error if the block goes past the end of the torrent or of the stored data.
+    fn read_block(&self, offset: u64, length: usize) -> io::Result<Vec<u8>>;
+
+    /// Reserves the space of the whole torrent, according to the `AllocationMode` of the backend.
+    fn allocate(&self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Makes sure the data written so far reached the underlying storage.
+    fn flush(&self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Available `Storage` backends.
+///
+/// - `File`: files in the download directory, keeping their handles open between reads and writes,
+/// - `Mmap`: files in the download directory mapped into memory,
+/// - `Memory`: a buffer in memory, nothing is written to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StorageBackend {
+    File,
+    Mmap,
+    Memory,
+}
+
+/// How the files of a torrent are allocated before their data is downloaded.
+///
+/// - `Lazy`: files are created and grown as pieces are written,
+/// - `Sparse`: files are created with their final size without reserving disk space,
+/// - `Full`: files are filled with zeros up to their final size, so the disk space is reserved upfront.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocationMode {
+    Lazy,
+    Sparse,
+    Full,
+}
+
+impl FromStr for StorageBackend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "file" => Ok(StorageBackend::File),
+            "mmap" => Ok(StorageBackend::Mmap),
+            "memory" => Ok(StorageBackend::Memory),
+            _ => Err(format!("Invalid storage backend: {}", s)),
+        }
+    }
+}
+
+impl FromStr for AllocationMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "lazy" => Ok(AllocationMode::Lazy),
+            "sparse" => Ok(AllocationMode::Sparse),
+            "full" => Ok(AllocationMode::Full),
+            _ => Err(format!("Invalid allocation mode: {}", s)),
+        }
+    }
+}
+
+/// Creates the `Storage` of a torrent using the backend and allocation mode of the configuration.
+pub fn create_storage(info: &Info, config: &Cfg) -> Box<dyn Storage> {
+    match config.storage_backend {
+        StorageBackend::File => Box::new(FileStorage::new(
+            info.clone(),
+            &config.download_directory,
+            config.file_allocation,
+        )),
+        StorageBackend::Mmap => Box::new(MmapStorage::new(
+            info.clone(),
+            &config.download_directory,
+            config.file_allocation,
+        )),
+        StorageBackend::Memory => Box::new(MemoryStorage::new(info.length as u64)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_storage_backend_from_str() {
+        assert_eq!("file".parse(), Ok(StorageBackend::File));
+        assert_eq!("MMAP".parse(), Ok(StorageBackend::Mmap));
+        assert_eq!("memory".parse(), Ok(StorageBackend::Memory));
+        assert!("disk".parse::<StorageBackend>().is_err());
+    }
+
+    #[test]
+    fn test_allocation_mode_from_str() {
+        assert_eq!("lazy".parse(), Ok(AllocationMode::Lazy));
+        assert_eq!("Sparse".parse(), Ok(AllocationMode::Sparse));
+        assert_eq!("full".parse(), Ok(AllocationMode::Full));
+        assert!("all".parse::<AllocationMode>().is_err());
+    }
+}
@@ -0,0 +1,78 @@
+use super::manager::{file_paths, torrent_path};
+use crate::config::cfg::Cfg;
+use crate::torrent_parser::{info::Info, info_file::InfoFile};
+use std::fs;
+use std::path::PathBuf;
+
+const CONFIG_PATH: &str = "config.cfg";
+
+/// A torrent whose files are stored in the download directory of the config file while a test runs.
+///
+/// The data of the torrent is deleted when it is dropped, even if the test fails.
+///
+/// To create a new `TestTorrent`, use TestTorrent::multi_file(name) or TestTorrent::new(info).
+pub struct TestTorrent {
+    pub info: Info,
+    pub download_directory: String,
+}
+
+impl TestTorrent {
+    /// Creates a `TestTorrent` storing the torrent of the given info.
+    pub fn new(info: Info) -> Self {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        Self {
+            info,
+            download_directory: config.download_directory,
+        }
+    }
+
+    /// Creates a `TestTorrent` with the files of `create_multi_file_test_info()`.
+    pub fn multi_file(name: &str) -> Self {
+        Self::new(create_multi_file_test_info(name))
+    }
+
+    /// Returns the paths of the files of the torrent.
+    pub fn file_paths(&self) -> Vec<PathBuf> {
+        file_paths(&self.info, &self.download_directory)
+    }
+
+    /// Returns the path of the data of the torrent.
+    pub fn path(&self) -> PathBuf {
+        torrent_path(&self.info, &self.download_directory)
+    }
+}
+
+impl Drop for TestTorrent {
+    fn drop(&mut self) {
+        let path = self.path();
+        let _ = if path.is_dir() {
+            fs::remove_dir_all(path)
+        } else {
+            fs::remove_file(path)
+        };
+    }
+}
+
+/// Creates a torrent with three files of 5, 4 and 4 bytes: `a.txt`, `dir/b.txt` and `dir/c.txt`.
+pub fn create_multi_file_test_info(name: &str) -> Info {
+    Info {
+        length: 13,
+        name: name.to_string(),
+        piece_length: 10,
+        pieces: vec![],
+        files: Some(vec![
+            InfoFile {
+                length: 5,
+                path: vec!["a.txt".to_string()],
+            },
+            InfoFile {
+                length: 4,
+                path: vec!["dir".to_string(), "b.txt".to_string()],
+            },
+            InfoFile {
+                length: 4,
+                path: vec!["dir".to_string(), "c.txt".to_string()],
+            },
+        ]),
+    }
+}
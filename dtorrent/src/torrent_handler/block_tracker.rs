@@ -0,0 +1,445 @@
+use crate::peer::bt_peer::BtPeer;
+use std::collections::{HashMap, HashSet};
+
+/// Size of the blocks in which the pieces are requested to the peers.
+pub const BLOCK_SIZE: u32 = 16384;
+
+/// A block of a piece, as it is sent in `Request` and `Cancel` messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRequest {
+    pub index: u32,
+    pub begin: u32,
+    pub length: u32,
+}
+
+/// Possible states of a block.
+#[derive(Debug, PartialEq, Eq)]
+enum BlockStatus {
+    Missing,
+    Requested(HashSet<BtPeer>),
+    Received,
+}
+
+/// The blocks of a piece that is being downloaded and the data received so far.
+#[derive(Debug)]
+struct PieceBlocks {
+    blocks: Vec<BlockStatus>,
+    data: Vec<u8>,
+}
+
+impl PieceBlocks {
+    fn new(piece_size: u32) -> Self {
+        let blocks_count = piece_size.div_ceil(BLOCK_SIZE) as usize;
+        Self {
+            blocks: (0..blocks_count).map(|_| BlockStatus::Missing).collect(),
+            data: vec![0; piece_size as usize],
+        }
+    }
+
+    fn block_length(&self, block: usize) -> u32 {
+        let begin = block * BLOCK_SIZE as usize;
+        (self.data.len() - begin).min(BLOCK_SIZE as usize) as u32
+    }
+
+    fn is_complete(&self) -> bool {
+        self.blocks
+            .iter()
+            .all(|status| *status == BlockStatus::Received)
+    }
+
+    fn is_requested(&self) -> bool {
+        self.blocks
+            .iter()
+            .any(|status| matches!(status, BlockStatus::Requested(_)))
+    }
+
+    fn received_bytes(&self) -> usize {
+        (0..self.blocks.len())
+            .filter(|block| self.blocks[*block] == BlockStatus::Received)
+            .map(|block| self.block_length(block) as usize)
+            .sum()
+    }
+
+    /// Returns true if the piece has a block that is not received nor requested to the peer.
+    fn has_blocks_for(&self, peer: &BtPeer) -> bool {
+        self.blocks.iter().any(|status| match status {
+            BlockStatus::Missing => true,
+            BlockStatus::Requested(peers) => !peers.contains(peer),
+            BlockStatus::Received => false,
+        })
+    }
+}
+
+/// Keeps the blocks of the pieces being downloaded, which peers each block was requested to, and the `Cancel` messages each peer has to send.
+///
+/// Several peers can download blocks of the same piece. In endgame, blocks already requested to other peers are requested again,
+/// and when one of them arrives the duplicated requests are cancelled.
+///
+/// The received blocks of a piece are kept until the piece is finished or discarded, even if no peer is downloading it.
+#[derive(Debug, Default)]
+pub struct BlockTracker {
+    pieces: HashMap<u32, PieceBlocks>,
+    cancels: HashMap<BtPeer, Vec<BlockRequest>>,
+}
+
+impl BlockTracker {
+    /// Creates a new empty `BlockTracker`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Requests up to `max` blocks of a piece of `piece_size` bytes to a peer.
+    ///
+    /// Missing blocks are requested first. In endgame, blocks already requested to other peers are requested too.
+    pub fn request_blocks(
+        &mut self,
+        peer: &BtPeer,
+        index: u32,
+        piece_size: u32,
+        max: usize,
+        endgame: bool,
+    ) -> Vec<BlockRequest> {
+        let piece = self
+            .pieces
+            .entry(index)
+            .or_insert_with(|| PieceBlocks::new(piece_size));
+
+        let mut selected: Vec<usize> = (0..piece.blocks.len())
+            .filter(|block| piece.blocks[*block] == BlockStatus::Missing)
+            .take(max)
+            .collect();
+        if endgame {
+            let duplicated: Vec<usize> = (0..piece.blocks.len())
+                .filter(|block| match &piece.blocks[*block] {
+                    BlockStatus::Requested(peers) => !peers.contains(peer),
+                    _ => false,
+                })
+                .take(max - selected.len())
+                .collect();
+            selected.extend(duplicated);
+        }
+
+        selected
+            .into_iter()
+            .map(|block| {
+                match &mut piece.blocks[block] {
+                    BlockStatus::Requested(peers) => {
+                        peers.insert(peer.clone());
+                    }
+                    status => *status = BlockStatus::Requested(HashSet::from([peer.clone()])),
+                }
+                BlockRequest {
+                    index,
+                    begin: block as u32 * BLOCK_SIZE,
+                    length: piece.block_length(block),
+                }
+            })
+            .collect()
+    }
+
+    /// Stores a block received from a peer, and queues a `Cancel` for every other peer the block was requested to.
+    ///
+    /// Blocks that were not requested to the peer, including the ones already received, are ignored.
+    ///
+    /// Returns the data of the piece if this block completed it.
+    pub fn block_received(
+        &mut self,
+        peer: &BtPeer,
+        index: u32,
+        begin: u32,
+        data: &[u8],
+    ) -> Option<Vec<u8>> {
+        let piece = self.pieces.get_mut(&index)?;
+        let block = (begin / BLOCK_SIZE) as usize;
+        if !begin.is_multiple_of(BLOCK_SIZE)
+            || block >= piece.blocks.len()
+            || data.len() != piece.block_length(block) as usize
+        {
+            return None;
+        }
+
+        match &piece.blocks[block] {
+            BlockStatus::Requested(peers) if peers.contains(peer) => {}
+            _ => return None,
+        }
+        if let BlockStatus::Requested(peers) =
+            std::mem::replace(&mut piece.blocks[block], BlockStatus::Received)
+        {
+            let request = BlockRequest {
+                index,
+                begin,
+                length: data.len() as u32,
+            };
+            for other_peer in peers.into_iter().filter(|other| other != peer) {
+                self.cancels.entry(other_peer).or_default().push(request);
+            }
+        }
+        piece.data[begin as usize..begin as usize + data.len()].copy_from_slice(data);
+
+        if piece.is_complete() {
+            Some(piece.data.clone())
+        } else {
+            None
+        }
+    }
+
+    /// Returns the `Cancel` messages a peer has to send, because the blocks were received from other peers.
+    pub fn take_cancels(&mut self, peer: &BtPeer) -> Vec<BlockRequest> {
+        self.cancels.remove(peer).unwrap_or_default()
+    }
+
+    /// Removes the requests of a piece made to a peer, the blocks become missing if they were not requested to other peers.
+    ///
+    /// Returns true if no other peer is downloading the piece.
+    pub fn release_piece(&mut self, peer: &BtPeer, index: u32) -> bool {
+        let piece = match self.pieces.get_mut(&index) {
+            Some(piece) => piece,
+            None => return true,
+        };
+        for status in piece.blocks.iter_mut() {
+            if let BlockStatus::Requested(peers) = status {
+                peers.remove(peer);
+                if peers.is_empty() {
+                    *status = BlockStatus::Missing;
+                }
+            }
+        }
+        !piece.is_requested()
+    }
+
+    /// Removes the request of a block made to a peer, the block becomes missing if it was not requested to other peers.
+    pub fn release_block(&mut self, peer: &BtPeer, index: u32, begin: u32) {
+        let status = match self
+            .pieces
+            .get_mut(&index)
+            .and_then(|piece| piece.blocks.get_mut((begin / BLOCK_SIZE) as usize))
+        {
+            Some(status) => status,
+            None => return,
+        };
+        if let BlockStatus::Requested(peers) = status {
+            peers.remove(peer);
+            if peers.is_empty() {
+                *status = BlockStatus::Missing;
+            }
+        }
+    }
+
+    /// Removes the requests made to a peer that disconnected and its pending `Cancel` messages.
+    pub fn remove_peer(&mut self, peer: &BtPeer) {
+        let indices: Vec<u32> = self.pieces.keys().copied().collect();
+        for index in indices {
+            self.release_piece(peer, index);
+        }
+        self.cancels.remove(peer);
+    }
+
+    /// Discards the blocks of a piece.
+    pub fn remove_piece(&mut self, index: u32) {
+        self.pieces.remove(&index);
+    }
+
+    /// Returns true if a piece has blocks that can be requested to a peer in endgame.
+    pub fn has_blocks_for(&self, peer: &BtPeer, index: u32) -> bool {
+        self.pieces
+            .get(&index)
+            .is_none_or(|piece| piece.has_blocks_for(peer))
+    }
+
+    /// Returns the number of bytes received of each piece that has received blocks.
+    pub fn received_bytes(&self) -> HashMap<u32, usize> {
+        self.pieces
+            .iter()
+            .map(|(index, piece)| (*index, piece.received_bytes()))
+            .filter(|(_, received)| *received > 0)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const PIECE_SIZE: u32 = BLOCK_SIZE * 2 + 10;
+
+    #[test]
+    fn test_request_missing_blocks() {
+        let mut tracker = BlockTracker::new();
+        let peer = create_test_peer(1);
+
+        let first = tracker.request_blocks(&peer, 0, PIECE_SIZE, 2, false);
+        let second = tracker.request_blocks(&peer, 0, PIECE_SIZE, 2, false);
+        let third = tracker.request_blocks(&peer, 0, PIECE_SIZE, 2, false);
+
+        assert_eq!(
+            first,
+            vec![
+                BlockRequest {
+                    index: 0,
+                    begin: 0,
+                    length: BLOCK_SIZE
+                },
+                BlockRequest {
+                    index: 0,
+                    begin: BLOCK_SIZE,
+                    length: BLOCK_SIZE
+                }
+            ]
+        );
+        assert_eq!(
+            second,
+            vec![BlockRequest {
+                index: 0,
+                begin: BLOCK_SIZE * 2,
+                length: 10
+            }]
+        );
+        assert!(third.is_empty());
+    }
+
+    #[test]
+    fn test_endgame_requests_blocks_of_other_peers() {
+        let mut tracker = BlockTracker::new();
+        let first_peer = create_test_peer(1);
+        let second_peer = create_test_peer(2);
+        tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 3, false);
+
+        assert!(tracker
+            .request_blocks(&second_peer, 0, PIECE_SIZE, 3, false)
+            .is_empty());
+        assert_eq!(
+            tracker
+                .request_blocks(&second_peer, 0, PIECE_SIZE, 3, true)
+                .len(),
+            3
+        );
+        assert!(!tracker.has_blocks_for(&second_peer, 0));
+    }
+
+    #[test]
+    fn test_block_received_cancels_duplicates_and_completes_piece() {
+        let mut tracker = BlockTracker::new();
+        let first_peer = create_test_peer(1);
+        let second_peer = create_test_peer(2);
+        tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 3, false);
+        tracker.request_blocks(&second_peer, 0, PIECE_SIZE, 3, true);
+
+        let block = vec![1; BLOCK_SIZE as usize];
+        assert!(tracker.block_received(&first_peer, 0, 0, &block).is_none());
+        assert!(tracker.block_received(&second_peer, 0, 0, &block).is_none());
+        assert!(tracker
+            .block_received(&second_peer, 0, BLOCK_SIZE, &block)
+            .is_none());
+        let piece = tracker
+            .block_received(&first_peer, 0, BLOCK_SIZE * 2, &[2; 10])
+            .unwrap();
+
+        assert_eq!(piece.len(), PIECE_SIZE as usize);
+        assert_eq!(piece[PIECE_SIZE as usize - 1], 2);
+        assert_eq!(
+            tracker.take_cancels(&second_peer),
+            vec![
+                BlockRequest {
+                    index: 0,
+                    begin: 0,
+                    length: BLOCK_SIZE
+                },
+                BlockRequest {
+                    index: 0,
+                    begin: BLOCK_SIZE * 2,
+                    length: 10
+                }
+            ]
+        );
+        assert_eq!(
+            tracker.take_cancels(&first_peer),
+            vec![BlockRequest {
+                index: 0,
+                begin: BLOCK_SIZE,
+                length: BLOCK_SIZE
+            }]
+        );
+        assert!(tracker.take_cancels(&first_peer).is_empty());
+    }
+
+    #[test]
+    fn test_invalid_blocks_are_ignored() {
+        let mut tracker = BlockTracker::new();
+        let peer = create_test_peer(1);
+        tracker.request_blocks(&peer, 0, PIECE_SIZE, 3, false);
+
+        assert!(tracker.block_received(&peer, 1, 0, &[0; 10]).is_none());
+        assert!(tracker.block_received(&peer, 0, 5, &[0; 10]).is_none());
+        assert!(tracker
+            .block_received(&peer, 0, BLOCK_SIZE * 2, &[0; 11])
+            .is_none());
+        assert!(tracker.received_bytes().is_empty());
+    }
+
+    #[test]
+    fn test_unrequested_blocks_are_ignored() {
+        let mut tracker = BlockTracker::new();
+        let first_peer = create_test_peer(1);
+        let second_peer = create_test_peer(2);
+        tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 1, false);
+
+        let block = vec![1; BLOCK_SIZE as usize];
+        assert!(tracker
+            .block_received(&first_peer, 0, BLOCK_SIZE, &block)
+            .is_none());
+        assert!(tracker.block_received(&second_peer, 0, 0, &block).is_none());
+        assert!(tracker.received_bytes().is_empty());
+
+        let requests = tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 3, false);
+        assert_eq!(requests[0].begin, BLOCK_SIZE);
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn test_release_piece_keeps_received_blocks() {
+        let mut tracker = BlockTracker::new();
+        let first_peer = create_test_peer(1);
+        let second_peer = create_test_peer(2);
+        tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 1, false);
+        tracker.request_blocks(&second_peer, 0, PIECE_SIZE, 1, false);
+        tracker.block_received(&first_peer, 0, 0, &vec![1; BLOCK_SIZE as usize]);
+
+        assert!(!tracker.release_piece(&first_peer, 0));
+        assert!(tracker.release_piece(&second_peer, 0));
+
+        assert_eq!(
+            tracker.received_bytes().get(&0),
+            Some(&(BLOCK_SIZE as usize))
+        );
+        let requests = tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 3, false);
+        assert_eq!(requests[0].begin, BLOCK_SIZE);
+        assert_eq!(requests.len(), 2);
+    }
+
+    #[test]
+    fn test_release_block() {
+        let mut tracker = BlockTracker::new();
+        let first_peer = create_test_peer(1);
+        let second_peer = create_test_peer(2);
+        tracker.request_blocks(&first_peer, 0, PIECE_SIZE, 3, false);
+
+        tracker.release_block(&first_peer, 0, BLOCK_SIZE);
+
+        let requests = tracker.request_blocks(&second_peer, 0, PIECE_SIZE, 3, false);
+        assert_eq!(
+            requests,
+            vec![BlockRequest {
+                index: 0,
+                begin: BLOCK_SIZE,
+                length: BLOCK_SIZE
+            }]
+        );
+    }
+
+    // Auxiliary functions
+
+    fn create_test_peer(id: u8) -> BtPeer {
+        BtPeer::new(IpAddr::V4(Ipv4Addr::new(192, 0, 0, id)), 6881)
+    }
+}
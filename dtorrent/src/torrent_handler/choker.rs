@@ -0,0 +1,249 @@
+use super::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
+use crate::peer::{bt_peer::BtPeer, session_status::SessionStatus};
+use logger::logger_sender::LoggerSender;
+use rand::{prelude::IteratorRandom, Rng};
+use std::{
+    cmp::Ordering,
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    thread::sleep,
+    time::Duration,
+};
+
+/// Time between evaluations of the peers that are unchoked.
+const CHOKE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Number of evaluations an optimistic unchoke lasts, 30 seconds.
+const OPTIMISTIC_UNCHOKE_ROUNDS: u32 = 3;
+
+/// Struct for deciding which peers of a torrent are allowed to download from us, following the tit-for-tat algorithm.
+///
+/// Every `CHOKE_INTERVAL` the interested peers that upload to us the fastest are unchoked, or the ones we upload to the fastest when seeding.
+/// Besides them, a random interested peer is optimistically unchoked, so new peers get a chance to show their upload rate.
+///
+/// The decisions are stored in the `AtomicTorrentStatus`, and each peer session sends the `Choke` and `Unchoke` messages.
+///
+/// To create a new `Choker`, use Choker::new(torrent_status, upload_slots, logger_sender).
+#[derive(Debug)]
+pub struct Choker {
+    torrent_status: Arc<AtomicTorrentStatus>,
+    upload_slots: usize,
+    optimistic_unchoke: Option<BtPeer>,
+    round: u32,
+    logger_sender: LoggerSender,
+}
+
+impl Choker {
+    /// Creates a new `Choker` from the status of a torrent, the number of peers unchoked by upload rate and a logger sender.
+    pub fn new(
+        torrent_status: Arc<AtomicTorrentStatus>,
+        upload_slots: usize,
+        logger_sender: LoggerSender,
+    ) -> Self {
+        Self {
+            torrent_status,
+            upload_slots,
+            optimistic_unchoke: None,
+            round: 0,
+            logger_sender,
+        }
+    }
+
+    /// Evaluates the peers to unchoke every `CHOKE_INTERVAL`, until the torrent is removed.
+    pub fn run(&mut self) {
+        let mut rng = rand::thread_rng();
+        while !self.torrent_status.is_removed() {
+            if let Err(err) = self.choke_round(&mut rng) {
+                self.logger_sender
+                    .warn(&format!("Could not update choked peers: {:?}", err));
+            }
+            sleep(CHOKE_INTERVAL);
+        }
+    }
+
+    /// Chooses the peers that are unchoked until the next round and stores them in the torrent status.
+    ///
+    /// # Errors
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    /// - `PoisonedUnchokedPeersLock` if the lock on the `unchoked_peers` field is poisoned.
+    pub fn choke_round<R: Rng>(&mut self, rng: &mut R) -> Result<(), AtomicTorrentStatusError> {
+        let peers = self.torrent_status.get_connected_peers()?;
+        let unchoked_peers =
+            self.select_unchoked_peers(&peers, self.torrent_status.is_finished(), rng);
+        self.torrent_status.set_unchoked_peers(unchoked_peers)
+    }
+
+    /// Returns the `upload_slots` interested peers with the best rate, plus the optimistic unchoke.
+    ///
+    /// The optimistic unchoke is replaced every `OPTIMISTIC_UNCHOKE_ROUNDS`, or earlier if it is no longer interested or it got one of the regular slots.
+    fn select_unchoked_peers<R: Rng>(
+        &mut self,
+        peers: &HashMap<BtPeer, SessionStatus>,
+        seeding: bool,
+        rng: &mut R,
+    ) -> HashSet<BtPeer> {
+        let rate = |status: &SessionStatus| {
+            if seeding {
+                status.upload_speed
+            } else {
+                status.download_speed
+            }
+        };
+
+        let mut interested: Vec<(&BtPeer, &SessionStatus)> = peers
+            .iter()
+            .filter(|(_, status)| status.peer_interested)
+            .collect();
+        interested
+            .sort_by(|(_, a), (_, b)| rate(b).partial_cmp(&rate(a)).unwrap_or(Ordering::Equal));
+
+        let mut unchoked_peers: HashSet<BtPeer> = interested
+            .iter()
+            .take(self.upload_slots)
+            .map(|(peer, _)| (*peer).clone())
+            .collect();
+
+        let keep_optimistic_unchoke = !self.round.is_multiple_of(OPTIMISTIC_UNCHOKE_ROUNDS)
+            && self.optimistic_unchoke.as_ref().is_some_and(|peer| {
+                !unchoked_peers.contains(peer)
+                    && interested
+                        .iter()
+                        .any(|(interested_peer, _)| *interested_peer == peer)
+            });
+        if !keep_optimistic_unchoke {
+            self.optimistic_unchoke = interested
+                .iter()
+                .map(|(peer, _)| *peer)
+                .filter(|peer| !unchoked_peers.contains(*peer))
+                .choose(rng)
+                .cloned();
+        }
+        self.round += 1;
+
+        if let Some(peer) = &self.optimistic_unchoke {
+            unchoked_peers.insert(peer.clone());
+        }
+        unchoked_peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::cfg::Cfg,
+        peer::peer_message::Bitfield,
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+    use logger::logger_receiver::Logger;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_unchoke_fastest_uploaders_while_downloading() {
+        let mut choker = create_test_choker("test_unchoke_fastest_uploaders_while_downloading", 2);
+        let peers = create_test_peers(&[(1, 10.0, 0.0), (2, 30.0, 0.0), (3, 20.0, 0.0)]);
+
+        let unchoked_peers = choker.select_unchoked_peers(&peers, false, &mut rand::thread_rng());
+
+        assert_eq!(unchoked_peers.len(), 3);
+        assert!(unchoked_peers.contains(&create_test_peer(2)));
+        assert!(unchoked_peers.contains(&create_test_peer(3)));
+        assert_eq!(choker.optimistic_unchoke, Some(create_test_peer(1)));
+        remove_test_logs("test_unchoke_fastest_uploaders_while_downloading");
+    }
+
+    #[test]
+    fn test_unchoke_fastest_downloaders_while_seeding() {
+        let mut choker = create_test_choker("test_unchoke_fastest_downloaders_while_seeding", 1);
+        let peers = create_test_peers(&[(1, 0.0, 10.0), (2, 30.0, 0.0)]);
+
+        let unchoked_peers = choker.select_unchoked_peers(&peers, true, &mut rand::thread_rng());
+
+        assert_eq!(unchoked_peers.len(), 2);
+        assert_eq!(choker.optimistic_unchoke, Some(create_test_peer(2)));
+        remove_test_logs("test_unchoke_fastest_downloaders_while_seeding");
+    }
+
+    #[test]
+    fn test_not_interested_peers_stay_choked() {
+        let mut choker = create_test_choker("test_not_interested_peers_stay_choked", 4);
+        let mut peers = create_test_peers(&[(1, 10.0, 0.0), (2, 20.0, 0.0)]);
+        if let Some(status) = peers.get_mut(&create_test_peer(2)) {
+            status.peer_interested = false;
+        }
+
+        let unchoked_peers = choker.select_unchoked_peers(&peers, false, &mut rand::thread_rng());
+
+        assert_eq!(unchoked_peers, HashSet::from([create_test_peer(1)]));
+        assert_eq!(choker.optimistic_unchoke, None);
+        remove_test_logs("test_not_interested_peers_stay_choked");
+    }
+
+    #[test]
+    fn test_optimistic_unchoke_rotates_every_three_rounds() {
+        let mut choker =
+            create_test_choker("test_optimistic_unchoke_rotates_every_three_rounds", 0);
+        let peers = create_test_peers(&[(1, 0.0, 0.0), (2, 0.0, 0.0), (3, 0.0, 0.0)]);
+        let mut rng = rand::thread_rng();
+
+        let mut optimistic_unchokes = HashSet::new();
+        for _ in 0..30 {
+            choker.select_unchoked_peers(&peers, false, &mut rng);
+            let first = choker.optimistic_unchoke.clone();
+            choker.select_unchoked_peers(&peers, false, &mut rng);
+            choker.select_unchoked_peers(&peers, false, &mut rng);
+
+            assert_eq!(choker.optimistic_unchoke, first);
+            optimistic_unchokes.insert(first);
+        }
+
+        assert!(optimistic_unchokes.len() > 1);
+        remove_test_logs("test_optimistic_unchoke_rotates_every_three_rounds");
+    }
+
+    // Auxiliary functions
+
+    fn create_test_choker(name: &str, upload_slots: usize) -> Choker {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info: Info {
+                length: 10,
+                name: name.to_string(),
+                piece_length: 1,
+                pieces: vec![],
+                files: None,
+            },
+            info_hash: "info_hash".to_string(),
+        };
+        let (torrent_status, _) = AtomicTorrentStatus::new(&torrent, config);
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+
+        Choker::new(Arc::new(torrent_status), upload_slots, logger.new_sender())
+    }
+
+    fn remove_test_logs(name: &str) {
+        std::fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    fn create_test_peer(id: u8) -> BtPeer {
+        BtPeer::new(IpAddr::V4(Ipv4Addr::new(192, 0, 0, id)), 6881)
+    }
+
+    /// Creates interested peers from tuples of id, download speed and upload speed.
+    fn create_test_peers(peers: &[(u8, f64, f64)]) -> HashMap<BtPeer, SessionStatus> {
+        peers
+            .iter()
+            .map(|(id, download_speed, upload_speed)| {
+                let mut status = SessionStatus::new(Bitfield::new(vec![]));
+                status.peer_interested = true;
+                status.download_speed = *download_speed;
+                status.upload_speed = *upload_speed;
+                (create_test_peer(*id), status)
+            })
+            .collect()
+    }
+}
@@ -1,15 +1,19 @@
 use super::status::{AtomicTorrentStatus, AtomicTorrentStatusError};
 use crate::{
     config::cfg::Cfg,
+    dht::{node::DhtNode, node_id::NodeId},
     peer::{
         bt_peer::BtPeer,
         peer_session::{PeerSession, PeerSessionError},
     },
+    reactor::peer_reactor::{PeerReactor, PeerReactorError},
     torrent_parser::torrent::Torrent,
     tracker::{
+        http::query_params::AnnounceEvent,
         tracker_handler::{TrackerHandler, TrackerHandlerError},
         tracker_response::TrackerResponse,
     },
+    utp::socket::UtpSocket,
 };
 use logger::logger_sender::LoggerSender;
 use std::{
@@ -18,12 +22,30 @@ use std::{
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-/// Struct for handling the torrent download.
+/// Minimum time between regular announces, used when the tracker asks for a shorter interval.
+const MIN_ANNOUNCE_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Time between checks of the torrent status while waiting for the next announce.
+const ANNOUNCE_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Intervals in seconds between announces when the peers only come from the DHT, as there is no tracker telling us.
+const DHT_ANNOUNCE_INTERVAL: i64 = 15 * 60;
+const DHT_MIN_ANNOUNCE_INTERVAL: i64 = 5 * 60;
+
+/// Time between saves of the resume data while the torrent runs, so a crash does not lose the progress since it started.
+const RESUME_DATA_SAVE_INTERVAL: Duration = Duration::from_secs(5 * 60);
+
+/// Struct for handling the torrent download, and its seeding once it is finished.
 ///
-/// To create a new `TorrentHandler`, use TorrentHandler::new(torrent, config, logger_sender).
+/// The peers are obtained from the trackers of the torrent and, if there is a `DhtNode`, also from the DHT.
+/// With a DHT the torrent can be downloaded even if none of its trackers work.
+/// If there is a `UtpSocket`, the peers are connected over uTP when they support it.
+/// The peer sessions are driven by the `PeerReactor` shared by all the torrents.
+///
+/// To create a new `TorrentHandler`, use TorrentHandler::new(torrent, config, logger_sender, client_peer_id, dht, utp, reactor).
 #[derive(Debug)]
 pub struct TorrentHandler {
     torrent: Torrent,
@@ -32,6 +54,10 @@ pub struct TorrentHandler {
     torrent_status: Arc<AtomicTorrentStatus>,
     torrent_status_receiver: Receiver<usize>,
     client_peer_id: String,
+    dht: Option<Arc<DhtNode>>,
+    utp: Option<Arc<UtpSocket>>,
+    reactor: Arc<PeerReactor>,
+    resume_data_saved_at: Instant,
 }
 
 /// Posible torrent handler errors.
@@ -40,16 +66,20 @@ pub enum TorrentHandlerError {
     TrackerError(TrackerHandlerError),
     TorrentStatusError(AtomicTorrentStatusError),
     PeerSessionError(PeerSessionError),
+    ReactorError(PeerReactorError),
     TorrentStatusRecvError(mpsc::RecvError),
 }
 
 impl TorrentHandler {
-    /// Creates a new `TorrentHandler` from a torrent, a config and a logger sender.
+    /// Creates a new `TorrentHandler` from a torrent, a config and a logger sender, that also searches peers in the DHT and connects over uTP if they are given.
     pub fn new(
         torrent: Torrent,
         config: Cfg,
         logger_sender: LoggerSender,
         client_peer_id: String,
+        dht: Option<Arc<DhtNode>>,
+        utp: Option<Arc<UtpSocket>>,
+        reactor: Arc<PeerReactor>,
     ) -> Self {
         let (torrent_status, torrent_status_receiver) =
             AtomicTorrentStatus::new(&torrent, config.clone());
@@ -61,89 +91,411 @@ impl TorrentHandler {
             logger_sender,
             torrent_status_receiver,
             client_peer_id,
+            dht,
+            utp,
+            reactor,
+            resume_data_saved_at: Instant::now(),
         }
     }
 
     /// Starts the torrent download.
     ///
-    /// First it connects to the tracker and gets the peers. Then it connects to each peer and starts the download.
+    /// First it restores the pieces already downloaded, from the resume file or by hash-checking the existing data.
+    ///
+    /// Then it announces the `started` event to the tracker and gets the peers. Then it connects to each peer and starts the download.
+    /// If there is a DHT, the torrent is also announced in it and its peers are added to the ones of the tracker.
+    ///
+    /// The tracker is announced again after its `interval`, or after its `min interval` if more peers are needed.
+    /// When the download finishes the `completed` event is announced.
+    ///
+    /// A finished torrent, or one that was already complete, keeps being announced as a seeder while the leechers connect through the `BtServer`,
+    /// until it reaches one of its seeding limits. The `stopped` event is announced before returning.
+    ///
+    /// The resume data is saved every `RESUME_DATA_SAVE_INTERVAL` while it runs, when the download finishes and before returning.
     ///
     /// # Errors
     ///
-    /// - `TrackerErr` if there was a problem connecting to the tracker or getting the peers.
+    /// - `TrackerErr` if there was a problem connecting to the tracker or getting the first peers, and there is no DHT to get them from.
     /// - `TorrentStatusError` if there was a problem using the `Torrent Status`.
     /// - `TorrentStatusRecvError` if there was a problem receiving from the receiver of `Torrent Status`.
     pub fn handle(&mut self) -> Result<(), TorrentHandlerError> {
-        let tracker_handler = TrackerHandler::new(
+        let mut tracker_handler = match TrackerHandler::new(
             self.torrent.clone(),
             self.config.tcp_port.into(),
             self.client_peer_id.clone(),
-        )
-        .map_err(TorrentHandlerError::TrackerError)?;
-        self.logger_sender.info("Connected to tracker.");
+        ) {
+            Ok(tracker_handler) => {
+                self.logger_sender.info("Connected to tracker.");
+                Some(tracker_handler)
+            }
+            Err(err) if self.dht.is_some() => {
+                self.logger_sender.warn(&format!(
+                    "Could not connect to tracker, using only the DHT: {:?}",
+                    err
+                ));
+                None
+            }
+            Err(err) => return Err(TorrentHandlerError::TrackerError(err)),
+        };
+
+        self.torrent_status
+            .verify_existing_data()
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        let already_finished = self.torrent_status.is_finished();
+        if already_finished {
+            self.logger_sender
+                .info("Torrent data already downloaded, seeding.");
+        } else {
+            self.torrent_status
+                .allocate_storage()
+                .map_err(TorrentHandlerError::TorrentStatusError)?;
+        }
+
+        let tracker_response = self.announce(&mut tracker_handler, AnnounceEvent::Started)?;
+        self.torrent_status.set_announcing(true);
+        let result = if already_finished {
+            self.seed_until_limit(&mut tracker_handler, tracker_response)
+        } else {
+            self.download_and_seed(&mut tracker_handler, tracker_response)
+        };
+        self.announce_last_event(&mut tracker_handler, AnnounceEvent::Stopped);
+        self.torrent_status.set_announcing(false);
+
+        self.save_resume_data();
+        result
+    }
+
+    /// Saves the resume data of the torrent, a failure is only logged.
+    fn save_resume_data(&mut self) {
+        if let Err(err) = self.torrent_status.save_resume_data() {
+            self.logger_sender
+                .warn(&format!("Could not save resume data: {:?}", err));
+        }
+        self.resume_data_saved_at = Instant::now();
+    }
+
+    /// Saves the resume data if it was saved more than `RESUME_DATA_SAVE_INTERVAL` ago.
+    fn save_resume_data_if_due(&mut self) {
+        if self.resume_data_saved_at.elapsed() >= RESUME_DATA_SAVE_INTERVAL {
+            self.save_resume_data();
+        }
+    }
+
+    /// Gets the status of the torrent.
+    pub fn status(&self) -> Arc<AtomicTorrentStatus> {
+        self.torrent_status.clone()
+    }
 
-        while !self.torrent_status.is_finished() {
-            let peer_list = self.get_peers_list(&tracker_handler)?;
+    /// Connects to the peers of the tracker response and announces again, until the download finishes or the torrent is stopped.
+    fn announce_until_finished(
+        &mut self,
+        tracker_handler: &mut Option<TrackerHandler>,
+        mut tracker_response: TrackerResponse,
+    ) -> Result<(), TorrentHandlerError> {
+        loop {
+            let announce_time = Instant::now();
             self.logger_sender.info("Tracker peer list obtained.");
 
-            // Start connection with each peer
-            for peer in peer_list {
-                let current_peers = self.torrent_status.all_current_peers();
-
-                // If we reached the maximum number of simultaneous peers, wait until the status tells us that one disconnected.
-                if current_peers >= self.config.max_peers_per_torrent as usize {
-                    // This while loop is done to prevent creating more peers than allowed when multiple peers are disconnected at the same time.
-                    self.torrent_status_receiver
-                        .recv()
-                        .map_err(TorrentHandlerError::TorrentStatusRecvError)?;
-                    while self
-                        .torrent_status_receiver
-                        .recv_timeout(Duration::from_nanos(1))
-                        .is_ok()
-                    {
-                        continue;
-                    }
+            self.connect_to_peers(tracker_response.peers.clone())?;
+            self.wait_for_next_announce(&tracker_response, announce_time)?;
+
+            if self.torrent_status.is_finished() || self.torrent_status.is_stopped() {
+                return Ok(());
+            }
+            tracker_response = self.reannounce(tracker_handler, &tracker_response)?;
+        }
+    }
+
+    /// Downloads the torrent and, once it finishes, announces the `completed` event and seeds it.
+    fn download_and_seed(
+        &mut self,
+        tracker_handler: &mut Option<TrackerHandler>,
+        tracker_response: TrackerResponse,
+    ) -> Result<(), TorrentHandlerError> {
+        self.announce_until_finished(tracker_handler, tracker_response)?;
+        if !self.torrent_status.is_finished() {
+            return Ok(());
+        }
+        self.logger_sender.info("Torrent download finished.");
+        self.save_resume_data();
+        let tracker_response = self.announce_completed(tracker_handler);
+        self.seed_until_limit(tracker_handler, tracker_response)
+    }
+
+    /// Announces the torrent again after each `interval` of the tracker, until it reaches one of its seeding limits or it is stopped.
+    ///
+    /// The seeding time counts from the start of the seeding, and the idle time from the last time something was uploaded.
+    /// When a limit is reached the torrent is stopped, and the `BtClient` pauses or removes it according to the action of the limits.
+    fn seed_until_limit(
+        &mut self,
+        tracker_handler: &mut Option<TrackerHandler>,
+        mut tracker_response: TrackerResponse,
+    ) -> Result<(), TorrentHandlerError> {
+        let seeding_start = Instant::now();
+        let mut uploaded_bytes = self.torrent_status.uploaded_bytes();
+        let mut last_upload = Instant::now();
+        loop {
+            let announce_time = Instant::now();
+            let interval = Self::announce_interval(tracker_response.interval);
+
+            while announce_time.elapsed() < interval {
+                if self.torrent_status.is_stopped() {
+                    return Ok(());
+                }
+                if self.torrent_status.uploaded_bytes() != uploaded_bytes {
+                    uploaded_bytes = self.torrent_status.uploaded_bytes();
+                    last_upload = Instant::now();
                 }
-                if self.torrent_status.is_finished() {
-                    break;
+                let seed_limit = self.torrent_status.seed_limits().reached(
+                    self.torrent_status.share_ratio(),
+                    seeding_start.elapsed(),
+                    last_upload.elapsed(),
+                );
+                if let Some(seed_limit) = seed_limit {
+                    self.logger_sender
+                        .info(&format!("Seeding limit reached: {:?}.", seed_limit));
+                    self.torrent_status.stop_at_seed_limit();
+                    return Ok(());
                 }
+                self.save_resume_data_if_due();
+                thread::sleep(ANNOUNCE_POLL_INTERVAL);
+            }
+            tracker_response = self.reannounce(tracker_handler, &tracker_response)?;
+        }
+    }
 
-                let connected_peers = self
-                    .torrent_status
-                    .get_connected_peers()
-                    .map_err(TorrentHandlerError::TorrentStatusError)?;
+    /// Starts a connection with each peer of the list, up to the maximum number of simultaneous peers.
+    fn connect_to_peers(&mut self, peer_list: Vec<BtPeer>) -> Result<(), TorrentHandlerError> {
+        for peer in peer_list {
+            let current_peers = self.torrent_status.all_current_peers();
 
-                // Avoid connecting to the same peer twice.
-                if connected_peers.contains_key(&peer) {
+            // If we reached the maximum number of simultaneous peers, wait until the status tells us that one disconnected.
+            if current_peers >= self.config.max_peers_per_torrent as usize {
+                // This while loop is done to prevent creating more peers than allowed when multiple peers are disconnected at the same time.
+                self.torrent_status_receiver
+                    .recv()
+                    .map_err(TorrentHandlerError::TorrentStatusRecvError)?;
+                while self
+                    .torrent_status_receiver
+                    .recv_timeout(Duration::from_nanos(1))
+                    .is_ok()
+                {
                     continue;
                 }
+            }
+            if self.torrent_status.is_finished() || self.torrent_status.is_stopped() {
+                break;
+            }
 
-                let current_peers = self.torrent_status.all_current_peers();
-                if current_peers < self.config.max_peers_per_torrent as usize {
-                    self.connect_to_peer(peer)?;
-                }
+            let connected_peers = self
+                .torrent_status
+                .get_connected_peers()
+                .map_err(TorrentHandlerError::TorrentStatusError)?;
+
+            // Avoid connecting to the same peer twice.
+            if connected_peers.contains_key(&peer) {
+                continue;
+            }
+
+            let current_peers = self.torrent_status.all_current_peers();
+            if current_peers < self.config.max_peers_per_torrent as usize {
+                self.connect_to_peer(peer)?;
             }
         }
-        self.logger_sender.info("Torrent download finished.");
         Ok(())
     }
 
-    /// Gets the status of the torrent.
-    pub fn status(&self) -> Arc<AtomicTorrentStatus> {
-        self.torrent_status.clone()
+    /// Blocks until the tracker can be announced again, connecting meanwhile to the peers discovered by the peer sessions.
+    ///
+    /// That is after the `interval` of the tracker response, or after its `min interval` if there are less peers than the maximum.
+    /// It returns earlier if the download finishes or the torrent is stopped.
+    fn wait_for_next_announce(
+        &mut self,
+        tracker_response: &TrackerResponse,
+        announce_time: Instant,
+    ) -> Result<(), TorrentHandlerError> {
+        let interval = Self::announce_interval(tracker_response.interval);
+        let min_interval = tracker_response
+            .min_interval
+            .map(Self::announce_interval)
+            .unwrap_or(interval);
+
+        while !self.torrent_status.is_finished() && !self.torrent_status.is_stopped() {
+            let elapsed = announce_time.elapsed();
+            let needs_peers = self.torrent_status.all_current_peers()
+                < self.config.max_peers_per_torrent as usize;
+
+            if elapsed >= interval || (needs_peers && elapsed >= min_interval) {
+                return Ok(());
+            }
+            if needs_peers {
+                self.connect_to_discovered_peers()?;
+            }
+            self.save_resume_data_if_due();
+            thread::sleep(ANNOUNCE_POLL_INTERVAL);
+        }
+        Ok(())
+    }
+
+    /// Connects to the peers discovered by the peer sessions, like the ones received with `ut_pex`, up to the maximum number of simultaneous peers.
+    fn connect_to_discovered_peers(&mut self) -> Result<(), TorrentHandlerError> {
+        let free_slots = (self.config.max_peers_per_torrent as usize)
+            .saturating_sub(self.torrent_status.all_current_peers());
+        let peers = self
+            .torrent_status
+            .take_discovered_peers(free_slots)
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+        self.connect_to_peers(peers)
+    }
+
+    /// Converts an interval in seconds received from the tracker to a `Duration`, with `MIN_ANNOUNCE_INTERVAL` as lower bound.
+    fn announce_interval(seconds: i64) -> Duration {
+        Duration::from_secs(seconds.max(0) as u64).max(MIN_ANNOUNCE_INTERVAL)
     }
 
-    fn get_peers_list(
+    /// Announces an event with the current counters of the torrent and updates the total number of peers.
+    ///
+    /// The `started` and regular announces are also made in the DHT, adding its peers to the response.
+    /// If there is a DHT, a failure of the tracker is only logged and the peers of the DHT are returned.
+    fn announce(
         &self,
-        tracker_handler: &TrackerHandler,
-    ) -> Result<Vec<BtPeer>, TorrentHandlerError> {
-        let tracker_response = tracker_handler
-            .get_peers_list()
-            .map_err(TorrentHandlerError::TrackerError)?;
+        tracker_handler: &mut Option<TrackerHandler>,
+        event: AnnounceEvent,
+    ) -> Result<TrackerResponse, TorrentHandlerError> {
+        let left = self
+            .torrent_status
+            .left_bytes()
+            .map_err(TorrentHandlerError::TorrentStatusError)?;
+
+        let tracker_result = match tracker_handler {
+            Some(tracker_handler) => tracker_handler.get_peers_list(
+                event,
+                self.torrent_status.uploaded_bytes(),
+                self.torrent_status.downloaded_bytes(),
+                left,
+            ),
+            None => Ok(Self::empty_tracker_response()),
+        };
+        let mut tracker_response = match tracker_result {
+            Ok(tracker_response) => tracker_response,
+            Err(err) if self.dht.is_some() => {
+                self.logger_sender.warn(&format!(
+                    "Could not announce to tracker, using only the DHT: {:?}",
+                    err
+                ));
+                Self::empty_tracker_response()
+            }
+            Err(err) => return Err(TorrentHandlerError::TrackerError(err)),
+        };
+
+        if matches!(event, AnnounceEvent::Started | AnnounceEvent::None) {
+            for peer in self.announce_to_dht() {
+                if !tracker_response.peers.contains(&peer) {
+                    tracker_response.peers.push(peer);
+                }
+            }
+        }
 
         self.update_total_peers(&tracker_response);
 
-        Ok(tracker_response.peers)
+        Ok(tracker_response)
+    }
+
+    /// Announces the torrent in the DHT and returns the peers found there, or no peers if there is no DHT.
+    fn announce_to_dht(&self) -> Vec<BtPeer> {
+        let dht = match &self.dht {
+            Some(dht) => dht,
+            None => return vec![],
+        };
+        let info_hash = match self
+            .torrent
+            .get_info_hash_as_bytes()
+            .ok()
+            .and_then(|info_hash| NodeId::from_bytes(&info_hash))
+        {
+            Some(info_hash) => info_hash,
+            None => return vec![],
+        };
+
+        let peers: Vec<BtPeer> = match dht.announce(info_hash, self.config.tcp_port) {
+            Ok(addrs) => addrs
+                .into_iter()
+                .map(|addr| BtPeer::new(addr.ip(), addr.port().into()))
+                .collect(),
+            Err(err) => {
+                self.logger_sender
+                    .warn(&format!("Could not announce to the DHT: {:?}", err));
+                return vec![];
+            }
+        };
+        self.logger_sender
+            .info(&format!("DHT peer list obtained: {} peers.", peers.len()));
+        peers
+    }
+
+    /// Response used when the peers only come from the DHT.
+    fn empty_tracker_response() -> TrackerResponse {
+        TrackerResponse {
+            interval: DHT_ANNOUNCE_INTERVAL,
+            min_interval: Some(DHT_MIN_ANNOUNCE_INTERVAL),
+            tracker_id: None,
+            complete: 0,
+            incomplete: 0,
+            peers: vec![],
+        }
+    }
+
+    /// Makes a regular announce, after the interval of the last response.
+    ///
+    /// A failure of the tracker is only logged: the current peers are kept, and the tracker is announced again after the last interval.
+    fn reannounce(
+        &self,
+        tracker_handler: &mut Option<TrackerHandler>,
+        last_response: &TrackerResponse,
+    ) -> Result<TrackerResponse, TorrentHandlerError> {
+        match self.announce(tracker_handler, AnnounceEvent::None) {
+            Err(TorrentHandlerError::TrackerError(err)) => {
+                self.logger_sender.warn(&format!(
+                    "Could not announce to tracker, retrying in {} seconds: {:?}",
+                    last_response.interval, err
+                ));
+                Ok(TrackerResponse {
+                    interval: last_response.interval,
+                    min_interval: None,
+                    tracker_id: None,
+                    complete: last_response.complete,
+                    incomplete: last_response.incomplete,
+                    peers: vec![],
+                })
+            }
+            result => result,
+        }
+    }
+
+    /// Announces the `completed` event, returning the response used to keep seeding.
+    ///
+    /// A failure is only logged, and the tracker is announced again after the interval used for the DHT.
+    fn announce_completed(&self, tracker_handler: &mut Option<TrackerHandler>) -> TrackerResponse {
+        match self.announce(tracker_handler, AnnounceEvent::Completed) {
+            Ok(tracker_response) => tracker_response,
+            Err(err) => {
+                self.logger_sender
+                    .warn(&format!("Could not announce Completed event: {:?}", err));
+                Self::empty_tracker_response()
+            }
+        }
+    }
+
+    /// Announces the `stopped` event, a failure is only logged since the torrent handler is finishing anyway.
+    fn announce_last_event(
+        &self,
+        tracker_handler: &mut Option<TrackerHandler>,
+        event: AnnounceEvent,
+    ) {
+        if let Err(err) = self.announce(tracker_handler, event) {
+            self.logger_sender
+                .warn(&format!("Could not announce {:?} event: {:?}", event, err));
+        }
     }
 
     /// Updates the torrent status with the number of total peers.
@@ -161,41 +513,146 @@ impl TorrentHandler {
         }
     }
 
+    /// Queues a session with the peer in the reactor, which connects to it and drives the download.
     fn connect_to_peer(&mut self, peer: BtPeer) -> Result<(), TorrentHandlerError> {
         self.torrent_status.peer_connecting();
-        let peer_name = format!("{}:{}", peer.ip, peer.port);
 
-        let mut peer_session = PeerSession::new(
-            peer.clone(),
+        let peer_session = PeerSession::new(
+            peer,
             self.torrent.clone(),
             self.torrent_status.clone(),
             self.config.clone(),
             self.logger_sender.clone(),
             self.client_peer_id.clone(),
+            self.utp.clone(),
         )
         .map_err(TorrentHandlerError::PeerSessionError)?;
 
-        let builder = thread::Builder::new().name(format!(
-            "Torrent: {} / Peer: {}",
-            self.torrent.info.name, peer_name
-        ));
-        let peer_logger_sender = self.logger_sender.clone();
+        if let Err(err) = self.reactor.connect(peer_session) {
+            self.torrent_status.peer_connecting_failed();
+            return Err(TorrentHandlerError::ReactorError(err));
+        }
+        Ok(())
+    }
+}
 
-        let join = builder.spawn(move || match peer_session.start_outgoing_seeder() {
-            Ok(_) => (),
-            Err(err) => {
-                peer_logger_sender.warn(&format!("{:?}", err));
-            }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bandwidth::rate_limiter::{RateLimits, Rates},
+        storage_manager::resume_data::ResumeData,
+        torrent_parser::info::Info,
+    };
+    use logger::logger_receiver::Logger;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+    };
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_reannounce_when_the_tracker_fails_once() {
+        let name = "test_reannounce_when_the_tracker_fails_once";
+        let tracker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tracker.local_addr().unwrap().port();
+        let join = thread::spawn(move || {
+            // The first announce fails, as the connection is closed without a response.
+            drop(tracker.accept().unwrap());
+            let (mut stream, _) = tracker.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let size = stream.read(&mut buffer).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\n\r\nd8:intervali600e5:peerslee")
+                .unwrap();
+            String::from_utf8_lossy(&buffer[..size]).to_string()
         });
-        match join {
-            Ok(_) => (),
-            Err(err) => {
-                self.logger_sender.error(&format!("{:?}", err));
-                self.torrent_status
-                    .peer_disconnected(&peer)
-                    .map_err(TorrentHandlerError::TorrentStatusError)?;
-            }
+
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let torrent = create_test_torrent(name, &format!("http://127.0.0.1:{}/announce", port));
+        let handler = create_test_handler(&torrent, logger.new_sender());
+        let mut tracker_handler =
+            Some(TrackerHandler::new(torrent, 6881, "DTorrent:00000000001".to_string()).unwrap());
+        let last_response = TrackerResponse {
+            interval: 900,
+            min_interval: Some(60),
+            tracker_id: None,
+            complete: 2,
+            incomplete: 3,
+            peers: vec![BtPeer::new("127.0.0.1".parse().unwrap(), 6882)],
+        };
+
+        let retry_response = handler
+            .reannounce(&mut tracker_handler, &last_response)
+            .unwrap();
+        let tracker_response = handler
+            .reannounce(&mut tracker_handler, &last_response)
+            .unwrap();
+        let request = join.join().unwrap();
+
+        assert!(request.contains("info_hash="));
+        assert_eq!(retry_response.interval, 900);
+        assert_eq!(retry_response.min_interval, None);
+        assert!(retry_response.peers.is_empty());
+        assert_eq!(tracker_response.interval, 600);
+        std::fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    #[test]
+    fn test_save_resume_data_if_due() {
+        let name = "test_save_resume_data_if_due";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let torrent = create_test_torrent(name, "http://127.0.0.1:1/announce");
+        let mut handler = create_test_handler(&torrent, logger.new_sender());
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        ResumeData::delete(&torrent.info_hash, &config).unwrap();
+
+        handler.save_resume_data_if_due();
+        let saved_before_interval = ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .is_some();
+        handler.resume_data_saved_at = Instant::now() - RESUME_DATA_SAVE_INTERVAL;
+        handler.save_resume_data_if_due();
+        let saved_after_interval = ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .is_some();
+
+        ResumeData::delete(&torrent.info_hash, &config).unwrap();
+        std::fs::remove_dir_all(format!("./{}", name)).unwrap();
+        assert!(!saved_before_interval);
+        assert!(saved_after_interval);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_torrent(name: &str, announce: &str) -> Torrent {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 10,
+            pieces: vec![0; 20],
+            files: None,
+        };
+        Torrent {
+            announce_url: announce.to_string(),
+            announce_list: vec![],
+            info_hash: Torrent::create_info_hash(&info).unwrap(),
+            info,
         }
-        Ok(())
+    }
+
+    fn create_test_handler(torrent: &Torrent, logger_sender: LoggerSender) -> TorrentHandler {
+        let global_limits = Arc::new(RateLimits::new(Rates::default()));
+        let reactor = PeerReactor::start(1, 1, global_limits, logger_sender.clone()).unwrap();
+        TorrentHandler::new(
+            torrent.clone(),
+            Cfg::new(CONFIG_PATH).unwrap(),
+            logger_sender,
+            "DTorrent:00000000001".to_string(),
+            None,
+            None,
+            reactor,
+        )
     }
 }
@@ -0,0 +1,282 @@
+use crate::{
+    config::cfg::Cfg,
+    dht::{node::DhtNode, node_id::NodeId},
+    peer::{
+        bt_peer::BtPeer, extension::ut_metadata::MetadataDownload,
+        metadata_session::MetadataSession,
+    },
+    torrent_parser::{
+        magnet::{Magnet, MagnetError},
+        torrent::Torrent,
+    },
+    tracker::{
+        http::query_params::AnnounceEvent,
+        tracker_handler::{TrackerHandler, TrackerHandlerError},
+    },
+};
+use logger::logger_sender::LoggerSender;
+use std::sync::{Arc, Mutex};
+
+/// Posible `MetadataFetcher` errors.
+#[derive(Debug)]
+pub enum MetadataFetcherError {
+    TrackerError(TrackerHandlerError),
+    NoPeerSentTheMetadata,
+    MagnetError(MagnetError),
+}
+
+/// Struct for getting the `Torrent` of a magnet link, downloading its info dict from the peers of the torrent.
+///
+/// The peers are asked for the metadata one at a time, keeping the pieces already received, until the metadata is complete and matches the info hash.
+/// If there is a `DhtNode` the peers are also searched in the DHT, so magnet links without trackers work too.
+///
+/// To create a new `MetadataFetcher`, use MetadataFetcher::new(magnet, config, logger_sender, client_peer_id, dht).
+#[derive(Debug)]
+pub struct MetadataFetcher {
+    magnet: Magnet,
+    config: Cfg,
+    logger_sender: LoggerSender,
+    client_peer_id: String,
+    dht: Option<Arc<DhtNode>>,
+}
+
+impl MetadataFetcher {
+    /// Creates a new `MetadataFetcher` for a magnet link.
+    pub fn new(
+        magnet: Magnet,
+        config: Cfg,
+        logger_sender: LoggerSender,
+        client_peer_id: String,
+        dht: Option<Arc<DhtNode>>,
+    ) -> Self {
+        Self {
+            magnet,
+            config,
+            logger_sender,
+            client_peer_id,
+            dht,
+        }
+    }
+
+    /// Gets peers from the trackers of the magnet link and the DHT, and fetches the metadata from them.
+    ///
+    /// # Errors
+    /// - `TrackerError` if the magnet link has no valid trackers or none of them answered, and there is no DHT.
+    /// - `NoPeerSentTheMetadata` if no peer sent the whole metadata.
+    /// - `MagnetError` if the metadata is not a valid info dict.
+    pub fn fetch(&self) -> Result<Torrent, MetadataFetcherError> {
+        let mut peers = match self.tracker_peers() {
+            Ok(peers) => peers,
+            Err(err) if self.dht.is_some() => {
+                self.logger_sender.warn(&format!(
+                    "Could not get peers of {} from trackers, using only the DHT: {:?}",
+                    self.magnet.name(),
+                    err
+                ));
+                vec![]
+            }
+            Err(err) => return Err(err),
+        };
+        for peer in self.dht_peers() {
+            if !peers.contains(&peer) {
+                peers.push(peer);
+            }
+        }
+
+        self.fetch_from_peers(peers)
+    }
+
+    /// Gets the peers from the trackers of the magnet link.
+    fn tracker_peers(&self) -> Result<Vec<BtPeer>, MetadataFetcherError> {
+        let mut tracker_handler = TrackerHandler::new(
+            self.magnet.placeholder_torrent(),
+            self.config.tcp_port.into(),
+            self.client_peer_id.clone(),
+        )
+        .map_err(MetadataFetcherError::TrackerError)?;
+
+        // The size of the torrent is unknown until the metadata arrives, any amount left tells the tracker we are leeching.
+        let tracker_response = tracker_handler
+            .get_peers_list(AnnounceEvent::None, 0, 0, 1)
+            .map_err(MetadataFetcherError::TrackerError)?;
+
+        Ok(tracker_response.peers)
+    }
+
+    /// Gets the peers of the torrent from the DHT, or no peers if there is no DHT.
+    fn dht_peers(&self) -> Vec<BtPeer> {
+        match (
+            &self.dht,
+            NodeId::from_bytes(&self.magnet.info_hash_as_bytes()),
+        ) {
+            (Some(dht), Some(info_hash)) => match dht.get_peers(info_hash) {
+                Ok(addrs) => addrs
+                    .into_iter()
+                    .map(|addr| BtPeer::new(addr.ip(), addr.port().into()))
+                    .collect(),
+                Err(err) => {
+                    self.logger_sender.warn(&format!(
+                        "Could not get peers of {} from the DHT: {:?}",
+                        self.magnet.name(),
+                        err
+                    ));
+                    vec![]
+                }
+            },
+            _ => vec![],
+        }
+    }
+
+    /// Fetches the metadata from the given peers, trying them in order.
+    ///
+    /// # Errors
+    /// - `NoPeerSentTheMetadata` if no peer sent the whole metadata.
+    /// - `MagnetError` if the metadata is not a valid info dict.
+    pub fn fetch_from_peers(&self, peers: Vec<BtPeer>) -> Result<Torrent, MetadataFetcherError> {
+        let download = Arc::new(Mutex::new(MetadataDownload::new(
+            self.magnet.info_hash_as_bytes(),
+        )));
+
+        for peer in peers {
+            let peer_addr = peer.socket_addr();
+            let mut session = MetadataSession::new(
+                peer,
+                self.magnet.info_hash_as_bytes(),
+                download.clone(),
+                self.config.clone(),
+                self.logger_sender.clone(),
+                self.client_peer_id.clone(),
+            );
+
+            match session.fetch() {
+                Ok(metadata) => {
+                    self.logger_sender.info(&format!(
+                        "Metadata of {} received from {}",
+                        self.magnet.name(),
+                        peer_addr
+                    ));
+                    return self
+                        .magnet
+                        .clone()
+                        .into_torrent(&metadata)
+                        .map_err(MetadataFetcherError::MagnetError);
+                }
+                Err(err) => self.logger_sender.warn(&format!(
+                    "IP: {} Could not fetch metadata: {:?}",
+                    peer_addr, err
+                )),
+            }
+        }
+        Err(MetadataFetcherError::NoPeerSentTheMetadata)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bandwidth::rate_limiter::{RateLimits, Rates},
+        bt_server::server::BtServer,
+        reactor::peer_reactor::PeerReactor,
+        torrent_handler::{status::AtomicTorrentStatus, torrent_list::TorrentList},
+        torrent_parser::info::Info,
+    };
+    use logger::logger_receiver::Logger;
+    use std::{
+        net::{IpAddr, Ipv4Addr},
+        thread,
+        time::Duration,
+    };
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_fetch_metadata_from_another_client() {
+        let name = "test_fetch_metadata_from_another_client";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let torrent = create_test_torrent(name);
+        let port = 16881;
+        start_test_server(&torrent, port, logger.new_sender());
+
+        let magnet = Magnet::parse(&format!("magnet:?xt=urn:btih:{}", torrent.info_hash)).unwrap();
+        let fetcher = MetadataFetcher::new(
+            magnet,
+            Cfg::new(CONFIG_PATH).unwrap(),
+            logger.new_sender(),
+            "DTorrent:00000000002".to_string(),
+            None,
+        );
+        let peers = vec![BtPeer::new(IpAddr::V4(Ipv4Addr::LOCALHOST), port as i64)];
+
+        let fetched_torrent = fetcher.fetch_from_peers(peers).unwrap();
+
+        assert_eq!(fetched_torrent.info, torrent.info);
+        assert_eq!(fetched_torrent.info_hash, torrent.info_hash);
+        std::fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    #[test]
+    fn test_fetch_metadata_without_peers() {
+        let name = "test_fetch_metadata_without_peers";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let magnet =
+            Magnet::parse("magnet:?xt=urn:btih:2c6b6858d61da9543d4231a71db4b1c9264b0685").unwrap();
+        let fetcher = MetadataFetcher::new(
+            magnet,
+            Cfg::new(CONFIG_PATH).unwrap(),
+            logger.new_sender(),
+            "DTorrent:00000000002".to_string(),
+            None,
+        );
+
+        assert!(matches!(
+            fetcher.fetch_from_peers(vec![]),
+            Err(MetadataFetcherError::NoPeerSentTheMetadata)
+        ));
+        assert!(matches!(
+            fetcher.fetch(),
+            Err(MetadataFetcherError::TrackerError(_))
+        ));
+        std::fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    // Auxiliary functions
+
+    fn create_test_torrent(name: &str) -> Torrent {
+        let info = Info {
+            length: 40,
+            name: name.to_string(),
+            piece_length: 20,
+            pieces: vec![1; 40],
+            files: None,
+        };
+        Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info_hash: Torrent::create_info_hash(&info).unwrap(),
+            info,
+        }
+    }
+
+    /// Starts a `BtServer` seeding the metadata of the torrent, like another client would.
+    fn start_test_server(torrent: &Torrent, port: u16, logger_sender: LoggerSender) {
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.tcp_port = port;
+        let (torrent_status, _) = AtomicTorrentStatus::new(torrent, config.clone());
+        let torrent_list = TorrentList::new();
+        torrent_list.add(Arc::new(torrent_status));
+        let global_limits = Arc::new(RateLimits::new(Rates::default()));
+        let reactor = PeerReactor::start(1, 1, global_limits, logger_sender.clone()).unwrap();
+        let mut server = BtServer::new(
+            torrent_list,
+            config,
+            logger_sender,
+            "DTorrent:00000000001".to_string(),
+            None,
+            reactor,
+        );
+
+        thread::spawn(move || server.init());
+        thread::sleep(Duration::from_millis(200));
+    }
+}
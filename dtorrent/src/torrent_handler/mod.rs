@@ -1,2 +1,9 @@
+pub mod block_tracker;
+pub mod choker;
 pub mod handler;
+pub mod metadata_fetcher;
+pub mod piece_picker;
+pub mod scrape_updater;
+pub mod seed_limits;
 pub mod status;
+pub mod torrent_list;
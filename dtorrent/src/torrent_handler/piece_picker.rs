@@ -0,0 +1,217 @@
+use crate::peer::{bt_peer::BtPeer, peer_message::Bitfield};
+use rand::{prelude::IteratorRandom, Rng};
+use std::collections::HashMap;
+
+/// Keeps how many connected peers have each piece of a torrent.
+///
+/// The pieces of each peer come from its `Bitfield` message and the `Have` messages it sends afterwards,
+/// and they stop counting when the peer disconnects.
+///
+/// To create a new `PieceAvailability` use the method builder `new()`.
+#[derive(Debug)]
+pub struct PieceAvailability {
+    counts: Vec<usize>,
+    peers_bitfields: HashMap<BtPeer, Bitfield>,
+}
+
+impl PieceAvailability {
+    /// Creates a new `PieceAvailability` for a torrent with `total_pieces` pieces that no peer has yet.
+    pub fn new(total_pieces: u32) -> Self {
+        Self {
+            counts: vec![0; total_pieces as usize],
+            peers_bitfields: HashMap::new(),
+        }
+    }
+
+    /// Returns the number of connected peers that have a piece.
+    pub fn availability(&self, index: u32) -> usize {
+        self.counts.get(index as usize).copied().unwrap_or(0)
+    }
+
+    /// Sets the pieces of a peer, replacing the ones it had before.
+    pub fn peer_bitfield(&mut self, peer: &BtPeer, bitfield: &Bitfield) {
+        self.peer_disconnected(peer);
+        for (index, count) in self.counts.iter_mut().enumerate() {
+            if bitfield.has_piece(index as u32) {
+                *count += 1;
+            }
+        }
+        self.peers_bitfields.insert(peer.clone(), bitfield.clone());
+    }
+
+    /// Adds a piece announced by a peer with a `Have` message.
+    ///
+    /// Pieces the peer already had and invalid indices are ignored.
+    pub fn peer_has_piece(&mut self, peer: &BtPeer, index: u32) {
+        let bitfield_length = self.counts.len().div_ceil(8);
+        let bitfield = self
+            .peers_bitfields
+            .entry(peer.clone())
+            .or_insert_with(|| Bitfield::new(vec![0; bitfield_length]));
+
+        if let Some(count) = self.counts.get_mut(index as usize) {
+            if !bitfield.has_piece(index) {
+                bitfield.set_bit(index, true);
+                *count += 1;
+            }
+        }
+    }
+
+    /// Removes the pieces of a peer that disconnected.
+    pub fn peer_disconnected(&mut self, peer: &BtPeer) {
+        if let Some(bitfield) = self.peers_bitfields.remove(peer) {
+            for (index, count) in self.counts.iter_mut().enumerate() {
+                if bitfield.has_piece(index as u32) {
+                    *count = count.saturating_sub(1);
+                }
+            }
+        }
+    }
+}
+
+/// Chooses the next piece to download among the `candidates`.
+///
+/// Pieces that were partially downloaded are finished first, starting with the most complete one.
+/// `received_bytes` has the number of bytes already downloaded of those pieces.
+/// Otherwise the rarest piece is chosen, breaking ties randomly so peers do not all download the same pieces.
+pub fn pick_piece<R: Rng>(
+    candidates: &[u32],
+    availability: &PieceAvailability,
+    received_bytes: &HashMap<u32, usize>,
+    rng: &mut R,
+) -> Option<u32> {
+    let most_complete_partial = candidates
+        .iter()
+        .filter_map(|index| {
+            received_bytes
+                .get(index)
+                .map(|received| (*index, *received))
+        })
+        .max_by_key(|(_, downloaded)| *downloaded);
+    if let Some((index, _)) = most_complete_partial {
+        return Some(index);
+    }
+
+    let rarest = candidates
+        .iter()
+        .map(|index| availability.availability(*index))
+        .min()?;
+    candidates
+        .iter()
+        .filter(|index| availability.availability(**index) == rarest)
+        .copied()
+        .choose(rng)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::{IpAddr, Ipv4Addr};
+
+    #[test]
+    fn test_availability_from_bitfields_and_haves() {
+        let mut availability = PieceAvailability::new(10);
+        let first_peer = create_test_peer(1);
+        let second_peer = create_test_peer(2);
+
+        availability.peer_bitfield(&first_peer, &Bitfield::new(vec![0b1100_0000, 0]));
+        availability.peer_bitfield(&second_peer, &Bitfield::new(vec![0b1000_0000, 0]));
+        availability.peer_has_piece(&second_peer, 9);
+        availability.peer_has_piece(&second_peer, 9);
+
+        assert_eq!(availability.availability(0), 2);
+        assert_eq!(availability.availability(1), 1);
+        assert_eq!(availability.availability(2), 0);
+        assert_eq!(availability.availability(9), 1);
+    }
+
+    #[test]
+    fn test_availability_replacing_bitfield_and_disconnecting() {
+        let mut availability = PieceAvailability::new(10);
+        let peer = create_test_peer(1);
+
+        availability.peer_bitfield(&peer, &Bitfield::new(vec![0b1100_0000, 0]));
+        availability.peer_bitfield(&peer, &Bitfield::new(vec![0b0010_0000, 0]));
+        assert_eq!(availability.availability(0), 0);
+        assert_eq!(availability.availability(2), 1);
+
+        availability.peer_disconnected(&peer);
+        assert_eq!(availability.availability(2), 0);
+    }
+
+    #[test]
+    fn test_have_without_bitfield() {
+        let mut availability = PieceAvailability::new(10);
+
+        availability.peer_has_piece(&create_test_peer(1), 3);
+        availability.peer_has_piece(&create_test_peer(1), 10);
+
+        assert_eq!(availability.availability(3), 1);
+    }
+
+    #[test]
+    fn test_pick_rarest_piece() {
+        let mut availability = PieceAvailability::new(4);
+        availability.peer_bitfield(&create_test_peer(1), &Bitfield::new(vec![0b1111_0000]));
+        availability.peer_bitfield(&create_test_peer(2), &Bitfield::new(vec![0b1101_0000]));
+        availability.peer_bitfield(&create_test_peer(3), &Bitfield::new(vec![0b0100_0000]));
+
+        let index = pick_piece(
+            &[0, 1, 2, 3],
+            &availability,
+            &HashMap::new(),
+            &mut rand::thread_rng(),
+        );
+
+        assert_eq!(index, Some(2));
+    }
+
+    #[test]
+    fn test_pick_breaks_ties_randomly() {
+        let availability = PieceAvailability::new(4);
+        let mut rng = rand::thread_rng();
+
+        let mut picked = [false; 4];
+        for _ in 0..200 {
+            let index =
+                pick_piece(&[0, 1, 2, 3], &availability, &HashMap::new(), &mut rng).unwrap();
+            picked[index as usize] = true;
+        }
+
+        assert_eq!(picked, [true; 4]);
+    }
+
+    #[test]
+    fn test_pick_partial_pieces_first() {
+        let mut availability = PieceAvailability::new(4);
+        availability.peer_bitfield(&create_test_peer(1), &Bitfield::new(vec![0b0110_0000]));
+        let mut partial_pieces = HashMap::new();
+        partial_pieces.insert(1, 10);
+        partial_pieces.insert(2, 20);
+        partial_pieces.insert(3, 30);
+
+        let index = pick_piece(
+            &[0, 1, 2],
+            &availability,
+            &partial_pieces,
+            &mut rand::thread_rng(),
+        );
+
+        assert_eq!(index, Some(2));
+    }
+
+    #[test]
+    fn test_pick_without_candidates() {
+        let availability = PieceAvailability::new(4);
+
+        let index = pick_piece(&[], &availability, &HashMap::new(), &mut rand::thread_rng());
+
+        assert_eq!(index, None);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_peer(id: u8) -> BtPeer {
+        BtPeer::new(IpAddr::V4(Ipv4Addr::new(192, 0, 0, id)), 6881)
+    }
+}
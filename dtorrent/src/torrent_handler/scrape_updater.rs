@@ -0,0 +1,197 @@
+use super::{status::AtomicTorrentStatus, torrent_list::TorrentList};
+use crate::tracker::{
+    scrape_response::ScrapeResponse,
+    tracker_handler::{TrackerHandler, TrackerHandlerError},
+};
+use logger::logger_sender::LoggerSender;
+use std::{collections::HashMap, sync::Arc, thread::sleep, time::Duration};
+
+/// Time between scrapes of the torrents that are not being announced.
+const SCRAPE_INTERVAL: Duration = Duration::from_secs(15 * 60);
+
+/// Struct for keeping the total number of peers of the torrents that are not being announced up to date, like queued or seeding ones.
+///
+/// The torrents are grouped by their main tracker, so several of them are scraped with a single request.
+///
+/// To create a new `ScrapeUpdater`, use ScrapeUpdater::new(torrent_list, client_port, client_peer_id, logger_sender).
+#[derive(Debug)]
+pub struct ScrapeUpdater {
+    torrent_list: TorrentList,
+    client_port: u32,
+    client_peer_id: String,
+    logger_sender: LoggerSender,
+}
+
+impl ScrapeUpdater {
+    /// Creates a new `ScrapeUpdater` from the list of torrents of the client, the client port, the client peer id and a logger sender.
+    pub fn new(
+        torrent_list: TorrentList,
+        client_port: u32,
+        client_peer_id: String,
+        logger_sender: LoggerSender,
+    ) -> Self {
+        Self {
+            torrent_list,
+            client_port,
+            client_peer_id,
+            logger_sender,
+        }
+    }
+
+    /// Scrapes the torrents that are not being announced every `SCRAPE_INTERVAL`.
+    pub fn run(&self) {
+        loop {
+            self.scrape_idle_torrents();
+            sleep(SCRAPE_INTERVAL);
+        }
+    }
+
+    /// Scrapes the torrents that are not being announced and updates their total number of peers.
+    ///
+    /// A failed scrape is only logged, the torrents are scraped again in the next run.
+    pub fn scrape_idle_torrents(&self) {
+        for torrent_status_group in self.idle_torrents_by_tracker().into_values() {
+            match self.scrape(&torrent_status_group) {
+                Ok(scrape_response) => {
+                    Self::update_total_peers(&torrent_status_group, &scrape_response)
+                }
+                Err(err) => self
+                    .logger_sender
+                    .warn(&format!("Could not scrape tracker: {:?}", err)),
+            }
+        }
+    }
+
+    /// Groups the torrents that are not being announced by the first tracker of their first tier.
+    fn idle_torrents_by_tracker(&self) -> HashMap<String, Vec<Arc<AtomicTorrentStatus>>> {
+        let mut groups: HashMap<String, Vec<Arc<AtomicTorrentStatus>>> = HashMap::new();
+
+        for torrent_status in self.torrent_list.statuses() {
+            if torrent_status.is_announcing() {
+                continue;
+            }
+            let main_tracker = torrent_status
+                .torrent
+                .announce_tiers()
+                .into_iter()
+                .flatten()
+                .next()
+                .unwrap_or_default();
+            groups.entry(main_tracker).or_default().push(torrent_status);
+        }
+        groups
+    }
+
+    fn scrape(
+        &self,
+        torrent_status_group: &[Arc<AtomicTorrentStatus>],
+    ) -> Result<ScrapeResponse, TrackerHandlerError> {
+        let info_hashes: Vec<String> = torrent_status_group
+            .iter()
+            .map(|torrent_status| torrent_status.torrent.info_hash())
+            .collect();
+
+        let mut tracker_handler = TrackerHandler::new(
+            torrent_status_group[0].torrent.clone(),
+            self.client_port,
+            self.client_peer_id.clone(),
+        )?;
+        tracker_handler.scrape(&info_hashes)
+    }
+
+    fn update_total_peers(
+        torrent_status_group: &[Arc<AtomicTorrentStatus>],
+        scrape_response: &ScrapeResponse,
+    ) {
+        for torrent_status in torrent_status_group {
+            let info_hash = torrent_status.torrent.info_hash().to_lowercase();
+            if let Some(file) = scrape_response.files.get(&info_hash) {
+                torrent_status.update_total_peers(file.complete as usize, file.incomplete as usize);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{config::cfg::Cfg, torrent_parser::info::Info, torrent_parser::torrent::Torrent};
+    use logger::logger_receiver::Logger;
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_scrape_idle_torrents() {
+        let tracker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tracker.local_addr().unwrap().port();
+        let join = thread::spawn(move || {
+            let (mut stream, _) = tracker.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let size = stream.read(&mut buffer).unwrap();
+            let mut response = b"HTTP/1.1 200 OK\r\n\r\nd5:filesd20:".to_vec();
+            response.extend([0xaa; 20]);
+            response.extend(b"d8:completei5e10:downloadedi50e10:incompletei10ee20:");
+            response.extend([0xbb; 20]);
+            response.extend(b"d8:completei1e10:downloadedi2e10:incompletei3eeee");
+            stream.write_all(&response).unwrap();
+            String::from_utf8_lossy(&buffer[..size]).to_string()
+        });
+
+        let announce = format!("http://127.0.0.1:{}/announce", port);
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let idle_status = create_test_status(&announce, &"aa".repeat(20), config.clone());
+        let other_idle_status = create_test_status(&announce, &"bb".repeat(20), config.clone());
+        let announcing_status = create_test_status(&announce, &"cc".repeat(20), config);
+        announcing_status.set_announcing(true);
+
+        let logger = Logger::new("./test_scrape_idle_torrents", 1000).unwrap();
+        let torrent_list = TorrentList::new();
+        torrent_list.add(idle_status.clone());
+        torrent_list.add(other_idle_status.clone());
+        torrent_list.add(announcing_status.clone());
+        let scrape_updater = ScrapeUpdater::new(
+            torrent_list,
+            6969,
+            "LA_DEYMONETA_PAPA!!!".to_string(),
+            logger.new_sender(),
+        );
+        scrape_updater.scrape_idle_torrents();
+        let request = join.join().unwrap();
+
+        assert_eq!(request.matches("info_hash=").count(), 2);
+        assert_eq!(idle_status.get_total_peers(), (5, 10));
+        assert_eq!(other_idle_status.get_total_peers(), (1, 3));
+        assert_eq!(announcing_status.get_total_peers(), (0, 0));
+        std::fs::remove_dir_all("./test_scrape_idle_torrents").unwrap();
+    }
+
+    // Auxiliary functions
+
+    fn create_test_status(
+        announce: &str,
+        info_hash: &str,
+        config: Cfg,
+    ) -> Arc<AtomicTorrentStatus> {
+        let info = Info {
+            length: 10,
+            name: "test_scrape_idle_torrents".to_string(),
+            piece_length: 1,
+            pieces: vec![],
+            files: None,
+        };
+
+        let torrent = Torrent {
+            announce_url: announce.to_string(),
+            announce_list: vec![],
+            info,
+            info_hash: info_hash.to_string(),
+        };
+        let (status, _) = AtomicTorrentStatus::new(&torrent, config);
+        Arc::new(status)
+    }
+}
@@ -0,0 +1,143 @@
+use crate::config::cfg::Cfg;
+use std::{str::FromStr, time::Duration};
+
+/// What is done with a torrent once it reaches one of its seeding limits.
+///
+/// - `Pause`: the torrent is paused, and seeds again if it is resumed,
+/// - `Remove`: the torrent is removed from the client, its data is kept.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SeedLimitAction {
+    #[default]
+    Pause,
+    Remove,
+}
+
+impl FromStr for SeedLimitAction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "pause" => Ok(SeedLimitAction::Pause),
+            "remove" => Ok(SeedLimitAction::Remove),
+            _ => Err(format!("Invalid seed limit action: {}", s)),
+        }
+    }
+}
+
+/// The seeding limits a torrent can reach.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeedLimit {
+    Ratio,
+    SeedingTime,
+    IdleTime,
+}
+
+/// Conditions that stop the seeding of a finished torrent, a limit of `None` is never reached.
+///
+/// - `ratio`: bytes uploaded over bytes downloaded,
+/// - `seeding_time`: time seeding since the torrent handler started to seed,
+/// - `idle_time`: time seeding without uploading anything.
+///
+/// To create a new `SeedLimits`, use SeedLimits::new(config).
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct SeedLimits {
+    pub ratio: Option<f64>,
+    pub seeding_time: Option<Duration>,
+    pub idle_time: Option<Duration>,
+    pub action: SeedLimitAction,
+}
+
+impl SeedLimits {
+    /// Creates the seeding limits of the configuration, where a limit of 0 means no limit.
+    pub fn new(config: &Cfg) -> Self {
+        let minutes =
+            |minutes: u64| Some(Duration::from_secs(minutes * 60)).filter(|_| minutes > 0);
+        Self {
+            ratio: Some(config.seed_ratio_limit).filter(|ratio| *ratio > 0.0),
+            seeding_time: minutes(config.seed_time_limit_minutes),
+            idle_time: minutes(config.seed_idle_limit_minutes),
+            action: config.seed_limit_action,
+        }
+    }
+
+    /// Returns the first limit reached by a torrent with the given share ratio, time seeding and time without uploading.
+    pub fn reached(
+        &self,
+        ratio: f64,
+        seeding_time: Duration,
+        idle_time: Duration,
+    ) -> Option<SeedLimit> {
+        if self.ratio.is_some_and(|limit| ratio >= limit) {
+            Some(SeedLimit::Ratio)
+        } else if self.seeding_time.is_some_and(|limit| seeding_time >= limit) {
+            Some(SeedLimit::SeedingTime)
+        } else if self.idle_time.is_some_and(|limit| idle_time >= limit) {
+            Some(SeedLimit::IdleTime)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_limits_of_the_configuration() {
+        let mut config = Cfg::new(CONFIG_PATH).unwrap();
+        config.seed_ratio_limit = 1.5;
+        config.seed_time_limit_minutes = 0;
+        config.seed_idle_limit_minutes = 30;
+        config.seed_limit_action = SeedLimitAction::Remove;
+
+        let limits = SeedLimits::new(&config);
+
+        assert_eq!(limits.ratio, Some(1.5));
+        assert_eq!(limits.seeding_time, None);
+        assert_eq!(limits.idle_time, Some(Duration::from_secs(30 * 60)));
+        assert_eq!(limits.action, SeedLimitAction::Remove);
+    }
+
+    #[test]
+    fn test_reached_limits() {
+        let limits = SeedLimits {
+            ratio: Some(2.0),
+            seeding_time: Some(minutes(60)),
+            idle_time: Some(minutes(10)),
+            action: SeedLimitAction::Pause,
+        };
+
+        assert_eq!(limits.reached(1.0, minutes(5), minutes(1)), None);
+        assert_eq!(
+            limits.reached(2.0, minutes(5), minutes(1)),
+            Some(SeedLimit::Ratio)
+        );
+        assert_eq!(
+            limits.reached(1.0, minutes(60), minutes(1)),
+            Some(SeedLimit::SeedingTime)
+        );
+        assert_eq!(
+            limits.reached(1.0, minutes(5), minutes(10)),
+            Some(SeedLimit::IdleTime)
+        );
+    }
+
+    #[test]
+    fn test_no_limits_are_never_reached() {
+        let limits = SeedLimits::default();
+
+        assert_eq!(
+            limits.reached(100.0, minutes(10_000), minutes(10_000)),
+            None
+        );
+    }
+
+    // Auxiliary functions
+
+    fn minutes(minutes: u64) -> Duration {
+        Duration::from_secs(minutes * 60)
+    }
+}
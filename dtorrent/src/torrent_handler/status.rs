@@ -1,29 +1,52 @@
+use super::{
+    block_tracker::{BlockRequest, BlockTracker},
+    piece_picker::{pick_piece, PieceAvailability},
+    seed_limits::SeedLimits,
+};
 use crate::{
+    bandwidth::rate_limiter::{RateLimits, Rates},
     config::cfg::Cfg,
     peer::{bt_peer::BtPeer, peer_message::Bitfield, session_status::SessionStatus},
-    storage_manager::manager::{retrieve_block, save_piece},
+    storage_manager::{
+        manager::torrent_path,
+        resume_data::{file_mtimes, ResumeData},
+        storage::{create_storage, Storage},
+    },
     torrent_parser::torrent::Torrent,
 };
 use rand::{self, prelude::IteratorRandom};
+use sha1::{Digest, Sha1};
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet, VecDeque},
+    fs, io,
+    path::{Path, PathBuf},
     sync::{
-        atomic::{AtomicUsize, Ordering},
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
         mpsc::{sync_channel, Receiver, SyncSender},
-        {Mutex, MutexGuard},
+        {Mutex, MutexGuard, PoisonError},
     },
 };
 
+/// Maximum number of discovered peers waiting to be connected.
+const MAX_DISCOVERED_PEERS: usize = 200;
+
 /// A Struct that represents the current status of a torrent.
 ///
 /// It contains the following information:
 ///
 /// - The current number of peers that are downloading the torrent.
 /// - The current state of the pieces of the torrent.
+/// - How many connected peers have each piece.
+/// - The blocks of the pieces being downloaded, shared by all the peer sessions of the torrent.
+/// - The number of bytes downloaded and uploaded, as they are reported to the tracker.
+/// - The peers discovered by the peer sessions, like the ones received with `ut_pex`, waiting for the torrent handler to connect to them.
+/// - The download and upload rate limits of the torrent, shared by its peer sessions.
+/// - The limits that stop the seeding of the torrent once it is finished.
 ///
 /// It is `Atomic`, meaning that it can be accessed from multiple threads at the same time.
 ///
-/// To create a new `AtomicTorrentStatus`, use the `new()` method.
+/// To create a new `AtomicTorrentStatus`, use the `new()` method. The torrent data is stored in the `Storage` backend of the configuration,
+/// unless a different one is passed to `with_storage()`.
 ///
 /// The `new()` method also returns a `Receiver` that can be used to know when a peer disconnects. This is useful if there is a limit for how many peers can be created,
 /// so the thread can be blocked until the status notifies that a peer has disconnected.
@@ -31,16 +54,29 @@ use std::{
 pub struct AtomicTorrentStatus {
     pub torrent: Torrent, //TODO: resolver encqapsulamiento en statistics.rs
     pieces_status: Mutex<HashMap<u32, PieceStatus>>,
+    piece_availability: Mutex<PieceAvailability>,
+    block_tracker: Mutex<BlockTracker>,
     current_peers: AtomicUsize,
     config: Cfg,
+    storage: Box<dyn Storage>,
     torrent_status_sender: SyncSender<usize>,
     sessions_status: Mutex<HashMap<BtPeer, SessionStatus>>,
+    unchoked_peers: Mutex<HashSet<BtPeer>>,
     finished_pieces: AtomicUsize,
     downloading_pieces: AtomicUsize,
     free_pieces: AtomicUsize,
     total_seeders_count: AtomicUsize,
     total_leechers_count: AtomicUsize,
     all_current_peers: AtomicUsize,
+    downloaded_bytes: AtomicU64,
+    uploaded_bytes: AtomicU64,
+    stopped: AtomicBool,
+    removed: AtomicBool,
+    announcing: AtomicBool,
+    discovered_peers: Mutex<VecDeque<BtPeer>>,
+    rate_limits: RateLimits,
+    seed_limits: Mutex<SeedLimits>,
+    seed_limit_reached: AtomicBool,
 }
 
 /// Possible states of a piece.
@@ -57,12 +93,20 @@ pub enum AtomicTorrentStatusError {
     PoisonedPiecesStatusLock,
     PoisonedCurrentPeersLock,
     PoisonedSessionsStatusLock,
+    PoisonedPieceAvailabilityLock,
+    PoisonedBlockTrackerLock,
+    PoisonedUnchokedPeersLock,
+    PoisonedDiscoveredPeersLock,
     InvalidPieceIndex,
     NoPeersConnected,
     PieceWasNotDownloading,
     SavePieceError(std::io::Error),
     RetrievingPieceError(std::io::Error),
     PieceWasNotFinished,
+    SaveResumeDataError(std::io::Error),
+    AllocateStorageError(std::io::Error),
+    DeleteDataError(std::io::Error),
+    DataOutsideDownloadDirectory(PathBuf),
 }
 
 impl AtomicTorrentStatus {
@@ -71,6 +115,18 @@ impl AtomicTorrentStatus {
     /// Returns a tuple with the `AtomicTorrentStatus` and a channel `Receiver` that can be used optionally to receive when a peer disconects from the torrent status.
     /// The value sent on the channel is the current number of peers connected.
     pub fn new(torrent: &Torrent, config: Cfg) -> (Self, Receiver<usize>) {
+        let storage = create_storage(&torrent.info, &config);
+        Self::with_storage(torrent, config, storage)
+    }
+
+    /// Creates a new `AtomicTorrentStatus` from a `Torrent` and a `config`, that stores the torrent data in the given `Storage`.
+    ///
+    /// Returns the same tuple as `new()`.
+    pub fn with_storage(
+        torrent: &Torrent,
+        config: Cfg,
+        storage: Box<dyn Storage>,
+    ) -> (Self, Receiver<usize>) {
         let mut pieces_status: HashMap<u32, PieceStatus> = HashMap::new();
         let sessions_status: HashMap<BtPeer, SessionStatus> = HashMap::new();
 
@@ -78,6 +134,11 @@ impl AtomicTorrentStatus {
             sync_channel((config.max_peers_per_torrent * 100) as usize);
 
         let total_pieces = torrent.total_pieces();
+        let rates = Rates::from_kbps(
+            config.torrent_max_download_kbps,
+            config.torrent_max_upload_kbps,
+        );
+        let seed_limits = SeedLimits::new(&config);
 
         for index in 0..total_pieces {
             pieces_status.insert(index as u32, PieceStatus::Free);
@@ -87,16 +148,29 @@ impl AtomicTorrentStatus {
             Self {
                 torrent: torrent.clone(),
                 pieces_status: Mutex::new(pieces_status),
+                piece_availability: Mutex::new(PieceAvailability::new(total_pieces)),
+                block_tracker: Mutex::new(BlockTracker::new()),
                 current_peers: AtomicUsize::new(0),
                 config,
+                storage,
                 torrent_status_sender,
                 sessions_status: Mutex::new(sessions_status),
+                unchoked_peers: Mutex::new(HashSet::new()),
                 finished_pieces: AtomicUsize::new(0),
                 downloading_pieces: AtomicUsize::new(0),
                 free_pieces: AtomicUsize::new(total_pieces as usize),
                 total_seeders_count: AtomicUsize::new(0),
                 total_leechers_count: AtomicUsize::new(0),
                 all_current_peers: AtomicUsize::new(0),
+                downloaded_bytes: AtomicU64::new(0),
+                uploaded_bytes: AtomicU64::new(0),
+                stopped: AtomicBool::new(false),
+                removed: AtomicBool::new(false),
+                announcing: AtomicBool::new(false),
+                discovered_peers: Mutex::new(VecDeque::new()),
+                rate_limits: RateLimits::new(rates),
+                seed_limits: Mutex::new(seed_limits),
+                seed_limit_reached: AtomicBool::new(false),
             },
             torrent_status_receiver,
         )
@@ -122,6 +196,288 @@ impl AtomicTorrentStatus {
         self.finished_pieces.load(Ordering::Relaxed)
     }
 
+    /// Returns the number of bytes of verified pieces downloaded from peers.
+    pub fn downloaded_bytes(&self) -> u64 {
+        self.downloaded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes uploaded to peers.
+    pub fn uploaded_bytes(&self) -> u64 {
+        self.uploaded_bytes.load(Ordering::Relaxed)
+    }
+
+    /// Returns the rate limits of the torrent, they can be changed while it is downloading.
+    pub fn rate_limits(&self) -> &RateLimits {
+        &self.rate_limits
+    }
+
+    /// Returns the seeding limits of the torrent.
+    pub fn seed_limits(&self) -> SeedLimits {
+        *self.lock_seed_limits()
+    }
+
+    /// Changes the seeding limits of the torrent, which start as the ones of the configuration.
+    pub fn set_seed_limits(&self, seed_limits: SeedLimits) {
+        *self.lock_seed_limits() = seed_limits;
+    }
+
+    /// Returns the share ratio of the torrent, the bytes uploaded over the bytes downloaded.
+    ///
+    /// If nothing was downloaded, like when the data was already in the download directory, the length of the torrent is used instead.
+    pub fn share_ratio(&self) -> f64 {
+        let downloaded = match self.downloaded_bytes() {
+            0 => self.torrent.length(),
+            downloaded => downloaded,
+        };
+        self.uploaded_bytes() as f64 / downloaded.max(1) as f64
+    }
+
+    /// Stops the torrent because it reached one of its seeding limits.
+    pub fn stop_at_seed_limit(&self) {
+        self.seed_limit_reached.store(true, Ordering::Relaxed);
+        self.stop();
+    }
+
+    /// Returns true if the torrent was stopped because it reached one of its seeding limits.
+    pub fn is_seed_limit_reached(&self) -> bool {
+        self.seed_limit_reached.load(Ordering::Relaxed)
+    }
+
+    /// Returns the number of bytes of the pieces that are not downloaded yet.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    pub fn left_bytes(&self) -> Result<u64, AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+        Ok(pieces_status
+            .iter()
+            .filter(|(_, status)| **status != PieceStatus::Finished)
+            .map(|(index, _)| self.piece_size(*index))
+            .sum())
+    }
+
+    /// Returns the size in bytes of a piece, the last one can be smaller than the others.
+    pub fn piece_size(&self, index: u32) -> u64 {
+        let last_piece_size = self.torrent.last_piece_size();
+        if index == self.torrent.total_pieces() - 1 && last_piece_size != 0 {
+            last_piece_size as u64
+        } else {
+            self.torrent.piece_length() as u64
+        }
+    }
+
+    /// Asks the torrent handler to stop, it announces the `stopped` event to the tracker before finishing.
+    ///
+    /// The peer sessions of the torrent are closed, and no new ones are accepted until it is resumed.
+    pub fn stop(&self) {
+        self.stopped.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns true if the torrent was asked to stop.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed)
+    }
+
+    /// Allows a stopped torrent to run again, unless it was removed.
+    ///
+    /// A torrent stopped by its seeding limits seeds again until it reaches them.
+    pub fn resume(&self) {
+        if !self.is_removed() {
+            self.seed_limit_reached.store(false, Ordering::Relaxed);
+            self.stopped.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// Stops the torrent for good, it can not be resumed anymore.
+    pub fn remove(&self) {
+        self.removed.store(true, Ordering::Relaxed);
+        self.stop();
+    }
+
+    /// Returns true if the torrent was removed.
+    pub fn is_removed(&self) -> bool {
+        self.removed.load(Ordering::Relaxed)
+    }
+
+    /// Deletes the downloaded data of the torrent and its resume file.
+    ///
+    /// It should only be called once the torrent handler finished and the peer sessions were closed, or they could write the data again.
+    ///
+    /// The data is only deleted if it is inside the download directory, as the name of the torrent may come from a peer.
+    ///
+    /// # Errors
+    /// - `DeleteDataError` if the data or the resume file could not be deleted.
+    /// - `DataOutsideDownloadDirectory` if the path of the data is not strictly inside the download directory.
+    pub fn delete_data(&self) -> Result<(), AtomicTorrentStatusError> {
+        let result = match self.data_path_to_delete()? {
+            Some(path) if path.is_dir() => fs::remove_dir_all(path),
+            Some(path) => fs::remove_file(path),
+            None => Ok(()),
+        };
+        match result {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => {
+                return Err(AtomicTorrentStatusError::DeleteDataError(err))
+            }
+            _ => (),
+        }
+        ResumeData::delete(&self.torrent.info_hash, &self.config)
+            .map_err(AtomicTorrentStatusError::DeleteDataError)
+    }
+
+    /// Returns the canonical path of the data of the torrent, `None` if there is no data.
+    ///
+    /// It returns `DataOutsideDownloadDirectory` if the path is the download directory itself or it is outside of it.
+    fn data_path_to_delete(&self) -> Result<Option<PathBuf>, AtomicTorrentStatusError> {
+        let canonicalize = |path: &Path| match fs::canonicalize(path) {
+            Ok(path) => Ok(Some(path)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(AtomicTorrentStatusError::DeleteDataError(err)),
+        };
+        let path = torrent_path(&self.torrent.info, &self.config.download_directory);
+        let (download_directory, canonical_path) = match (
+            canonicalize(Path::new(&self.config.download_directory))?,
+            canonicalize(&path)?,
+        ) {
+            (Some(download_directory), Some(canonical_path)) => {
+                (download_directory, canonical_path)
+            }
+            _ => return Ok(None),
+        };
+        if canonical_path == download_directory || !canonical_path.starts_with(&download_directory)
+        {
+            return Err(AtomicTorrentStatusError::DataOutsideDownloadDirectory(path));
+        }
+        Ok(Some(canonical_path))
+    }
+
+    /// Restores the state of the pieces from the data already in the download directory.
+    ///
+    /// If the torrent has a resume file and its files were not modified since it was saved, the pieces and counters are restored from it.
+    /// Otherwise every piece is hash-checked with `force_recheck()`.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `SaveResumeDataError` if the resume file could not be saved after a recheck.
+    pub fn verify_existing_data(&self) -> Result<(), AtomicTorrentStatusError> {
+        let resume_data = ResumeData::load(&self.torrent.info_hash, &self.config)
+            .ok()
+            .flatten()
+            .filter(|resume_data| {
+                resume_data.bitfield.len() == (self.torrent.total_pieces() as usize).div_ceil(8)
+                    && resume_data.matches_files(&self.torrent.info, &self.config)
+            });
+
+        match resume_data {
+            Some(resume_data) => {
+                let bitfield = Bitfield::new(resume_data.bitfield);
+                self.set_pieces_status(|index| bitfield.has_piece(index))?;
+                self.downloaded_bytes
+                    .store(resume_data.downloaded, Ordering::Relaxed);
+                self.uploaded_bytes
+                    .store(resume_data.uploaded, Ordering::Relaxed);
+                Ok(())
+            }
+            None => self.force_recheck(),
+        }
+    }
+
+    /// Hash-checks every piece against the data in the download directory, and saves the result in the resume file.
+    ///
+    /// Pieces that are being downloaded are not checked.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `SaveResumeDataError` if the resume file could not be saved.
+    pub fn force_recheck(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.set_pieces_status(|index| self.piece_is_valid(index))?;
+        self.save_resume_data()
+    }
+
+    /// Saves the pieces status, the counters and the modification time of the files in the resume file of the torrent.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `SaveResumeDataError` if the resume file could not be saved.
+    pub fn save_resume_data(&self) -> Result<(), AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+        self.storage
+            .flush()
+            .map_err(AtomicTorrentStatusError::SaveResumeDataError)?;
+        let resume_data = ResumeData {
+            bitfield: Bitfield::from(&pieces_status).bitfield,
+            downloaded: self.downloaded_bytes(),
+            uploaded: self.uploaded_bytes(),
+            file_mtimes: file_mtimes(&self.torrent.info, &self.config),
+        };
+        resume_data
+            .save(&self.torrent.info_hash, &self.config)
+            .map_err(AtomicTorrentStatusError::SaveResumeDataError)
+    }
+
+    /// Reserves the space of the torrent data, according to the allocation mode of the configuration.
+    ///
+    /// # Errors
+    /// - `AllocateStorageError` if the storage could not be allocated.
+    pub fn allocate_storage(&self) -> Result<(), AtomicTorrentStatusError> {
+        self.storage
+            .allocate()
+            .map_err(AtomicTorrentStatusError::AllocateStorageError)
+    }
+
+    /// Sets every piece that is not being downloaded as finished or free, and updates the pieces counters.
+    fn set_pieces_status<F>(&self, is_finished: F) -> Result<(), AtomicTorrentStatusError>
+    where
+        F: Fn(u32) -> bool,
+    {
+        let mut pieces_status = self.lock_pieces_status()?;
+        for (index, status) in pieces_status.iter_mut() {
+            if *status == PieceStatus::Downloading {
+                continue;
+            }
+            *status = if is_finished(*index) {
+                PieceStatus::Finished
+            } else {
+                PieceStatus::Free
+            };
+        }
+
+        let count = |status| pieces_status.values().filter(|s| **s == status).count();
+        self.finished_pieces
+            .store(count(PieceStatus::Finished), Ordering::Relaxed);
+        self.free_pieces
+            .store(count(PieceStatus::Free), Ordering::Relaxed);
+        Ok(())
+    }
+
+    /// Returns true if the stored data of a piece matches its hash.
+    fn piece_is_valid(&self, index: u32) -> bool {
+        let start = index as usize * 20;
+        let expected_hash = match self.torrent.info.pieces.get(start..start + 20) {
+            Some(hash) => hash,
+            None => return false,
+        };
+
+        match self.storage.read_block(
+            index as u64 * self.torrent.piece_length() as u64,
+            self.piece_size(index) as usize,
+        ) {
+            Ok(piece) => Sha1::digest(&piece).as_slice() == expected_hash,
+            Err(_) => false,
+        }
+    }
+
+    /// Sets whether the torrent handler is announcing the torrent to its tracker, between the `started` and `stopped` events.
+    pub fn set_announcing(&self, announcing: bool) {
+        self.announcing.store(announcing, Ordering::Relaxed);
+    }
+
+    /// Returns true if the torrent handler is announcing the torrent to its tracker.
+    ///
+    /// The total number of peers of torrents that are not being announced, like queued or seeding ones, is updated by scraping the tracker.
+    pub fn is_announcing(&self) -> bool {
+        self.announcing.load(Ordering::Relaxed)
+    }
+
     /// Adds a new peer to the current number of peers.
     ///
     /// # Errors
@@ -143,6 +499,9 @@ impl AtomicTorrentStatus {
     /// # Errors
     /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
     /// - `NoPeersConnected` if there are no peers connected.
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
+    /// - `PoisonedUnchokedPeersLock` if the lock on the `unchoked_peers` field is poisoned.
     pub fn peer_disconnected(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
         let mut peer_status = self.lock_session_status()?;
         if self.current_peers.load(Ordering::Relaxed) == 0 {
@@ -152,6 +511,9 @@ impl AtomicTorrentStatus {
         self.all_current_peers.fetch_sub(1, Ordering::Relaxed);
 
         peer_status.remove(peer);
+        self.lock_piece_availability()?.peer_disconnected(peer);
+        self.release_peer_pieces(peer)?;
+        self.lock_unchoked_peers()?.remove(peer);
 
         self.notify_peer_disconnected();
         Ok(())
@@ -225,6 +587,47 @@ impl AtomicTorrentStatus {
         Ok(self.lock_session_status()?.clone())
     }
 
+    /// Adds peers to the queue of peers to connect to, skipping the ones that are already connected or queued.
+    ///
+    /// Once the queue has `MAX_DISCOVERED_PEERS` peers the new ones are dropped, they will be discovered again later.
+    ///
+    /// # Errors
+    /// - `PoisonedSessionsStatusLock` if the lock on the `session_status` field is poisoned.
+    /// - `PoisonedDiscoveredPeersLock` if the lock on the `discovered_peers` field is poisoned.
+    pub fn add_discovered_peers(&self, peers: Vec<BtPeer>) -> Result<(), AtomicTorrentStatusError> {
+        let sessions_status = self.lock_session_status()?;
+        let mut discovered_peers = self.lock_discovered_peers()?;
+
+        for peer in peers {
+            if discovered_peers.len() >= MAX_DISCOVERED_PEERS {
+                break;
+            }
+            // Incoming peers are connected from another port, the one they listen on is in their session status.
+            let connected = sessions_status.iter().any(|(connected_peer, status)| {
+                *connected_peer == peer
+                    || (connected_peer.ip == peer.ip
+                        && status.listen_port.map(i64::from) == Some(peer.port))
+            });
+            if !connected && !discovered_peers.contains(&peer) {
+                discovered_peers.push_back(peer);
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes and returns up to `count` peers of the queue of discovered peers, the oldest first.
+    ///
+    /// # Errors
+    /// - `PoisonedDiscoveredPeersLock` if the lock on the `discovered_peers` field is poisoned.
+    pub fn take_discovered_peers(
+        &self,
+        count: usize,
+    ) -> Result<Vec<BtPeer>, AtomicTorrentStatusError> {
+        let mut discovered_peers = self.lock_discovered_peers()?;
+        let count = count.min(discovered_peers.len());
+        Ok(discovered_peers.drain(..count).collect())
+    }
+
     /// Returns the current download speed of the torrent in kilobits per second.
     ///
     /// # Errors
@@ -249,39 +652,105 @@ impl AtomicTorrentStatus {
             .sum())
     }
 
+    /// Sets the peers that are allowed to download from us, chosen by the `Choker`.
+    ///
+    /// # Errors
+    /// - `PoisonedUnchokedPeersLock` if the lock on the `unchoked_peers` field is poisoned.
+    pub fn set_unchoked_peers(
+        &self,
+        peers: HashSet<BtPeer>,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        *self.lock_unchoked_peers()? = peers;
+        Ok(())
+    }
+
+    /// Returns true if a peer is allowed to download from us.
+    ///
+    /// # Errors
+    /// - `PoisonedUnchokedPeersLock` if the lock on the `unchoked_peers` field is poisoned.
+    pub fn is_peer_unchoked(&self, peer: &BtPeer) -> Result<bool, AtomicTorrentStatusError> {
+        Ok(self.lock_unchoked_peers()?.contains(peer))
+    }
+
+    /// Sets the pieces that a connected peer has, from its `Bitfield` message.
+    ///
+    /// # Errors
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    pub fn peer_bitfield_received(
+        &self,
+        peer: &BtPeer,
+        bitfield: &Bitfield,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_piece_availability()?
+            .peer_bitfield(peer, bitfield);
+        Ok(())
+    }
+
+    /// Adds a piece that a connected peer announced with a `Have` message.
+    ///
+    /// # Errors
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    pub fn peer_has_piece(
+        &self,
+        peer: &BtPeer,
+        index: u32,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_piece_availability()?.peer_has_piece(peer, index);
+        Ok(())
+    }
+
+    /// Returns the number of connected peers that have a piece.
+    ///
+    /// # Errors
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    pub fn piece_availability(&self, index: u32) -> Result<usize, AtomicTorrentStatusError> {
+        Ok(self.lock_piece_availability()?.availability(index))
+    }
+
     /// Returns the index of a piece that can be downloaded from a peer `Bitfield` passed by parameter.
     ///
+    /// Pieces whose download was interrupted are finished first, then the rarest pieces among the connected peers are chosen.
+    ///
+    /// When there are no free pieces left the 'EndGame' starts, and a piece being downloaded from other peers is chosen
+    /// if it has blocks that were not requested to this peer yet.
+    ///
     /// If none of the pieces can be downloaded, returns `None`.
     ///
     /// # Errors
     /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedPieceAvailabilityLock` if the lock on the `piece_availability` field is poisoned.
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
     pub fn select_piece(
         &self,
+        peer: &BtPeer,
         bitfield: &Bitfield,
     ) -> Result<Option<u32>, AtomicTorrentStatusError> {
         let mut pieces_status = self.lock_pieces_status()?;
+        let block_tracker = self.lock_block_tracker()?;
 
-        // If there are no free pieces do the 'EndGame' strategy, otherwise do the normal piece selection.
-        let index = if pieces_status
-            .values()
-            .filter(|status| **status == PieceStatus::Free)
-            .count()
-            == 0
-        {
-            pieces_status
-                .clone()
+        if Self::is_endgame(&pieces_status) {
+            return Ok(pieces_status
                 .iter()
-                .filter(|(_, status)| **status == PieceStatus::Downloading)
+                .filter(|(index, status)| {
+                    **status == PieceStatus::Downloading
+                        && bitfield.has_piece(**index)
+                        && block_tracker.has_blocks_for(peer, **index)
+                })
                 .choose(&mut rand::thread_rng())
-                .map(|(index, _)| *index)
-        } else {
-            pieces_status
-                .clone()
-                .iter()
-                .filter(|(_, status)| **status == PieceStatus::Free)
-                .find(|(index, _)| bitfield.has_piece(**index))
-                .map(|(index, _)| *index)
-        };
+                .map(|(index, _)| *index));
+        }
+
+        let candidates: Vec<u32> = pieces_status
+            .iter()
+            .filter(|(index, status)| **status == PieceStatus::Free && bitfield.has_piece(**index))
+            .map(|(index, _)| *index)
+            .collect();
+        let index = pick_piece(
+            &candidates,
+            &*self.lock_piece_availability()?,
+            &block_tracker.received_bytes(),
+            &mut rand::thread_rng(),
+        );
 
         Ok(match index {
             Some(index) => {
@@ -294,6 +763,121 @@ impl AtomicTorrentStatus {
         })
     }
 
+    /// Returns true if there are no free pieces left, so the pieces being downloaded can be requested to several peers.
+    fn is_endgame(pieces_status: &HashMap<u32, PieceStatus>) -> bool {
+        !pieces_status
+            .values()
+            .any(|status| *status == PieceStatus::Free)
+    }
+
+    /// Requests up to `max` blocks of a piece obtained from `select_piece` to a peer.
+    ///
+    /// The blocks that were not requested to any peer are returned first. In 'EndGame' the blocks requested to other peers are returned too.
+    /// An empty vector means there is nothing left to request of the piece to this peer.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
+    pub fn request_blocks(
+        &self,
+        peer: &BtPeer,
+        index: u32,
+        max: usize,
+    ) -> Result<Vec<BlockRequest>, AtomicTorrentStatusError> {
+        let pieces_status = self.lock_pieces_status()?;
+        if pieces_status.get(&index) != Some(&PieceStatus::Downloading) {
+            return Ok(vec![]);
+        }
+
+        Ok(self.lock_block_tracker()?.request_blocks(
+            peer,
+            index,
+            self.piece_size(index) as u32,
+            max,
+            Self::is_endgame(&pieces_status),
+        ))
+    }
+
+    /// Stores a block of a piece received from a peer.
+    ///
+    /// The other peers the block was requested to have to cancel it, see `take_cancels()`.
+    ///
+    /// Returns the data of the piece if this block completed it, it has to be checked and passed to `piece_downloaded()` or `piece_aborted()`.
+    ///
+    /// # Errors
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
+    pub fn block_received(
+        &self,
+        peer: &BtPeer,
+        index: u32,
+        begin: u32,
+        block: &[u8],
+    ) -> Result<Option<Vec<u8>>, AtomicTorrentStatusError> {
+        Ok(self
+            .lock_block_tracker()?
+            .block_received(peer, index, begin, block))
+    }
+
+    /// Returns the requests a peer has to cancel, because the blocks were already received from other peers.
+    ///
+    /// # Errors
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
+    pub fn take_cancels(
+        &self,
+        peer: &BtPeer,
+    ) -> Result<Vec<BlockRequest>, AtomicTorrentStatusError> {
+        Ok(self.lock_block_tracker()?.take_cancels(peer))
+    }
+
+    /// Stops downloading a piece from a peer, keeping the blocks already received.
+    ///
+    /// If no other peer is downloading the piece it becomes free again, so it can be finished later.
+    ///
+    /// # Errors
+    /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
+    pub fn release_piece(&self, peer: &BtPeer, index: u32) -> Result<(), AtomicTorrentStatusError> {
+        let mut pieces_status = self.lock_pieces_status()?;
+        let released = self.lock_block_tracker()?.release_piece(peer, index);
+        if released && pieces_status.get(&index) == Some(&PieceStatus::Downloading) {
+            pieces_status.insert(index, PieceStatus::Free);
+            self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
+            self.free_pieces.fetch_add(1, Ordering::Relaxed);
+        }
+        Ok(())
+    }
+
+    /// Stops requesting a block to a peer, for example because the peer rejected the request.
+    ///
+    /// The piece is still downloaded by the peer, but the block can be requested to others.
+    ///
+    /// # Errors
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
+    pub fn release_block(
+        &self,
+        peer: &BtPeer,
+        index: u32,
+        begin: u32,
+    ) -> Result<(), AtomicTorrentStatusError> {
+        self.lock_block_tracker()?.release_block(peer, index, begin);
+        Ok(())
+    }
+
+    /// Releases every piece requested to a peer and drops its pending cancels.
+    fn release_peer_pieces(&self, peer: &BtPeer) -> Result<(), AtomicTorrentStatusError> {
+        let downloading: Vec<u32> = self
+            .lock_pieces_status()?
+            .iter()
+            .filter(|(_, status)| **status == PieceStatus::Downloading)
+            .map(|(index, _)| *index)
+            .collect();
+        for index in downloading {
+            self.release_piece(peer, index)?;
+        }
+        self.lock_block_tracker()?.remove_peer(peer);
+        Ok(())
+    }
+
     /// Saves a downlaoded piece to the disk.
     ///
     /// # Errors
@@ -301,6 +885,7 @@ impl AtomicTorrentStatus {
     /// - `InvalidPieceIndex` if the piece index is invalid.
     /// - `PieceWasNotDownloading` if the piece was not downloading.
     /// - `SavePieceError` if the piece could not be saved.
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
     pub fn piece_downloaded(
         &self,
         index: u32,
@@ -315,17 +900,16 @@ impl AtomicTorrentStatus {
             }
             None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
         }
-        save_piece(
-            self.torrent.info.name.clone(),
-            piece,
-            (index * self.torrent.info.piece_length as u32) as u64,
-            self.config.clone(),
-        )
-        .map_err(AtomicTorrentStatusError::SavePieceError)?;
+        self.storage
+            .write_block(index as u64 * self.torrent.info.piece_length as u64, piece)
+            .map_err(AtomicTorrentStatusError::SavePieceError)?;
 
         piece_status.insert(index, PieceStatus::Finished);
+        self.lock_block_tracker()?.remove_piece(index);
         self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
         self.finished_pieces.fetch_add(1, Ordering::Relaxed);
+        self.downloaded_bytes
+            .fetch_add(piece.len() as u64, Ordering::Relaxed);
         Ok(())
     }
 
@@ -352,23 +936,24 @@ impl AtomicTorrentStatus {
             None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
         }
 
-        retrieve_block(
-            self.torrent.info.name.clone(),
-            offset,
-            length,
-            self.config.clone(),
-        )
-        .map_err(AtomicTorrentStatusError::RetrievingPieceError)
+        let block = self
+            .storage
+            .read_block(offset, length)
+            .map_err(AtomicTorrentStatusError::RetrievingPieceError)?;
+        self.uploaded_bytes
+            .fetch_add(block.len() as u64, Ordering::Relaxed);
+        Ok(block)
     }
 
-    /// Aborts a piece download.
+    /// Aborts a piece download, discarding the blocks received.
     ///
-    /// This must be called when a piece obteined from `select_piece` can not longer be downloaded.
+    /// This must be called when a piece obteined from `select_piece` can not longer be downloaded, or its data does not match its hash.
     ///
     /// # Errors
     /// - `PoisonedPiecesStatusLock` if the lock on the `pieces_status` field is poisoned.
     /// - `InvalidPieceIndex` if the piece index is invalid.
     /// - `PieceWasNotDownloading` if the piece was not downloading.
+    /// - `PoisonedBlockTrackerLock` if the lock on the `block_tracker` field is poisoned.
     pub fn piece_aborted(&self, index: u32) -> Result<(), AtomicTorrentStatusError> {
         let mut piece_status = self.lock_pieces_status()?;
         match piece_status.get(&index) {
@@ -380,6 +965,7 @@ impl AtomicTorrentStatus {
             None => return Err(AtomicTorrentStatusError::InvalidPieceIndex),
         }
         piece_status.insert(index, PieceStatus::Free);
+        self.lock_block_tracker()?.remove_piece(index);
         self.downloading_pieces.fetch_sub(1, Ordering::Relaxed);
         self.free_pieces.fetch_add(1, Ordering::Relaxed);
         Ok(())
@@ -394,6 +980,13 @@ impl AtomicTorrentStatus {
         Ok(Bitfield::from(&pieces_status))
     }
 
+    /// The seeding limits are always valid, even if a thread panicked while changing them.
+    fn lock_seed_limits(&self) -> MutexGuard<'_, SeedLimits> {
+        self.seed_limits
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+    }
+
     fn lock_pieces_status(
         &self,
     ) -> Result<MutexGuard<HashMap<u32, PieceStatus>>, AtomicTorrentStatusError> {
@@ -402,6 +995,36 @@ impl AtomicTorrentStatus {
             .map_err(|_| AtomicTorrentStatusError::PoisonedPiecesStatusLock)
     }
 
+    fn lock_piece_availability(
+        &self,
+    ) -> Result<MutexGuard<'_, PieceAvailability>, AtomicTorrentStatusError> {
+        self.piece_availability
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedPieceAvailabilityLock)
+    }
+
+    fn lock_unchoked_peers(
+        &self,
+    ) -> Result<MutexGuard<'_, HashSet<BtPeer>>, AtomicTorrentStatusError> {
+        self.unchoked_peers
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedUnchokedPeersLock)
+    }
+
+    fn lock_block_tracker(&self) -> Result<MutexGuard<'_, BlockTracker>, AtomicTorrentStatusError> {
+        self.block_tracker
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedBlockTrackerLock)
+    }
+
+    fn lock_discovered_peers(
+        &self,
+    ) -> Result<MutexGuard<'_, VecDeque<BtPeer>>, AtomicTorrentStatusError> {
+        self.discovered_peers
+            .lock()
+            .map_err(|_| AtomicTorrentStatusError::PoisonedDiscoveredPeersLock)
+    }
+
     fn lock_session_status(
         &self,
     ) -> Result<MutexGuard<HashMap<BtPeer, SessionStatus>>, AtomicTorrentStatusError> {
@@ -413,8 +1036,10 @@ impl AtomicTorrentStatus {
 
 #[cfg(test)]
 mod tests {
-    use std::{fs, sync::Arc, thread};
+    use std::{sync::Arc, thread};
 
+    use crate::storage_manager::memory_storage::MemoryStorage;
+    use crate::torrent_handler::block_tracker::BLOCK_SIZE;
     use crate::torrent_parser::info::Info;
 
     use super::*;
@@ -437,7 +1062,10 @@ mod tests {
         let status = create_status_whitout_receiver(&torrent, config.clone());
         for _ in 0..(torrent.info.length / torrent.info.piece_length) {
             let index = status
-                .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                .select_piece(
+                    &create_test_peer("192.0.0.1"),
+                    &Bitfield::new(vec![0b11111111, 0b11111111]),
+                )
                 .unwrap()
                 .unwrap();
             status.piece_downloaded(index as u32, &[]).unwrap();
@@ -462,7 +1090,7 @@ mod tests {
     #[test]
     fn test_peer_connected() {
         let torrent = create_test_torrent("test_peer_connected");
-        let peer = create_test_peer("192.0".to_string());
+        let peer = create_test_peer("192.0.0.1");
 
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
@@ -473,7 +1101,7 @@ mod tests {
     #[test]
     fn test_peer_disconnected() {
         let torrent = create_test_torrent("test_peer_disconnected");
-        let peer = create_test_peer("192.0".to_string());
+        let peer = create_test_peer("192.0.0.1");
 
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
@@ -486,7 +1114,7 @@ mod tests {
     #[test]
     fn test_peer_disconnected_error() {
         let torrent = create_test_torrent("test_peer_disconnected_error");
-        let peer = create_test_peer("192.0".to_string());
+        let peer = create_test_peer("192.0.0.1");
 
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
@@ -500,7 +1128,10 @@ mod tests {
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
             .unwrap()
             .unwrap();
         assert_eq!(
@@ -516,11 +1147,134 @@ mod tests {
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
-            .select_piece(&Bitfield::new(vec![0b00000000, 0b00000000]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b00000000, 0b00000000]),
+            )
             .unwrap();
         assert!(index.is_none());
     }
 
+    #[test]
+    fn test_select_rarest_piece() {
+        let torrent = create_test_torrent("test_select_rarest_piece");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        status
+            .peer_bitfield_received(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11000000]),
+            )
+            .unwrap();
+        status
+            .peer_bitfield_received(
+                &create_test_peer("192.0.0.2"),
+                &Bitfield::new(vec![0b11011111, 0b11000000]),
+            )
+            .unwrap();
+
+        let index = status
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11000000]),
+            )
+            .unwrap();
+
+        assert_eq!(index, Some(2));
+    }
+
+    #[test]
+    fn test_peer_disconnected_removes_its_pieces() {
+        let torrent = create_test_torrent("test_peer_disconnected_removes_its_pieces");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let peer = create_test_peer("192.0.0.1");
+        status.peer_connected(&peer).unwrap();
+        status
+            .peer_bitfield_received(&peer, &Bitfield::new(vec![0b10000000, 0]))
+            .unwrap();
+        status.peer_has_piece(&peer, 9).unwrap();
+        assert_eq!(status.piece_availability(0).unwrap(), 1);
+        assert_eq!(status.piece_availability(9).unwrap(), 1);
+
+        status.peer_disconnected(&peer).unwrap();
+
+        assert_eq!(status.piece_availability(0).unwrap(), 0);
+        assert_eq!(status.piece_availability(9).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_partial_piece_is_selected_first() {
+        let mut torrent = create_test_torrent("test_partial_piece_is_selected_first");
+        torrent.info.piece_length = BLOCK_SIZE as i64 * 2;
+        torrent.info.length = torrent.info.piece_length * 10;
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let peer = create_test_peer("192.0.0.1");
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+        let index = status.select_piece(&peer, &bitfield).unwrap().unwrap();
+        status.request_blocks(&peer, index, 5).unwrap();
+        let block = vec![0; BLOCK_SIZE as usize];
+        assert!(status
+            .block_received(&peer, index, 0, &block)
+            .unwrap()
+            .is_none());
+        status.release_piece(&peer, index).unwrap();
+        assert_eq!(status.downloading_pieces(), 0);
+
+        assert_eq!(status.select_piece(&peer, &bitfield).unwrap(), Some(index));
+    }
+
+    #[test]
+    fn test_endgame_shares_pieces_between_peers() {
+        let torrent = create_test_torrent("test_endgame_shares_pieces_between_peers");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let first_peer = create_test_peer("192.0.0.1");
+        let second_peer = create_test_peer("192.0.0.2");
+        let bitfield = Bitfield::new(vec![0b11111111, 0b11000000]);
+        for _ in 0..torrent.total_pieces() {
+            let index = status
+                .select_piece(&first_peer, &bitfield)
+                .unwrap()
+                .unwrap();
+            assert_eq!(
+                status.request_blocks(&first_peer, index, 5).unwrap().len(),
+                1
+            );
+        }
+
+        let index = status
+            .select_piece(&second_peer, &bitfield)
+            .unwrap()
+            .unwrap();
+        let requests = status.request_blocks(&second_peer, index, 5).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert!(status
+            .select_piece(&first_peer, &Bitfield::new(vec![0, 0]))
+            .unwrap()
+            .is_none());
+
+        let piece = status.block_received(&second_peer, index, 0, &[7]).unwrap();
+        assert_eq!(piece, Some(vec![7]));
+        assert_eq!(
+            status.take_cancels(&first_peer).unwrap(),
+            vec![BlockRequest {
+                index,
+                begin: 0,
+                length: 1
+            }]
+        );
+        assert!(status
+            .block_received(&first_peer, index, 0, &[7])
+            .unwrap()
+            .is_none());
+    }
+
     #[test]
     fn test_piece_downloaded() {
         let torrent = create_test_torrent("test_piece_downloaded");
@@ -528,7 +1282,10 @@ mod tests {
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
             .unwrap()
             .unwrap();
         status.piece_downloaded(index as u32, &[]).unwrap();
@@ -543,6 +1300,31 @@ mod tests {
         .unwrap();
     }
 
+    #[test]
+    fn test_piece_downloaded_with_memory_storage() {
+        let torrent = create_test_torrent("test_piece_downloaded_with_memory_storage");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let storage = MemoryStorage::new(torrent.info.length as u64);
+        let (status, _) =
+            AtomicTorrentStatus::with_storage(&torrent, config.clone(), Box::new(storage));
+        let index = status
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(index, &[7]).unwrap();
+
+        assert_eq!(status.get_piece(index, index as u64, 1).unwrap(), vec![7]);
+        assert!(!std::path::Path::new(&format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .exists());
+    }
+
     #[test]
     fn test_piece_aborted() {
         let torrent = create_test_torrent("test_piece_aborted");
@@ -550,7 +1332,10 @@ mod tests {
         let config = Cfg::new(CONFIG_PATH).unwrap();
         let status = create_status_whitout_receiver(&torrent, config.clone());
         let index = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
             .unwrap()
             .unwrap();
         status.piece_aborted(index).unwrap();
@@ -580,7 +1365,7 @@ mod tests {
 
         for _ in 0..10 {
             let status_cloned = status.clone();
-            let peer = create_test_peer("192.0".to_string());
+            let peer = create_test_peer("192.0.0.1");
             let join = thread::spawn(move || status_cloned.peer_connected(&peer).unwrap());
             joins.push(join);
         }
@@ -602,7 +1387,10 @@ mod tests {
             let status_cloned = status.clone();
             let join = thread::spawn(move || {
                 let index = status_cloned
-                    .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+                    .select_piece(
+                        &create_test_peer("192.0.0.1"),
+                        &Bitfield::new(vec![0b11111111, 0b11111111]),
+                    )
                     .unwrap()
                     .unwrap();
                 status_cloned.piece_downloaded(index, &[]).unwrap();
@@ -652,7 +1440,10 @@ mod tests {
         let remaining_starting_pieces = status.remaining_pieces();
 
         let index = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
             .unwrap()
             .unwrap();
         status.piece_downloaded(index, &[]).unwrap();
@@ -673,7 +1464,10 @@ mod tests {
         let status = create_status_whitout_receiver(&torrent, config.clone());
 
         let _ = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
             .unwrap()
             .unwrap();
 
@@ -687,7 +1481,10 @@ mod tests {
         let status = create_status_whitout_receiver(&torrent, config.clone());
 
         let index = status
-            .select_piece(&Bitfield::new(vec![0b11111111, 0b11111111]))
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
             .unwrap()
             .unwrap();
         status.piece_downloaded(index, &[]).unwrap();
@@ -703,7 +1500,7 @@ mod tests {
     #[test]
     fn test_torrent_status_channel() {
         let torrent = create_test_torrent("test_torrent_status_channel");
-        let peer = create_test_peer("192.0".to_string());
+        let peer = create_test_peer("192.0.0.1");
 
         let (status, receiver) = AtomicTorrentStatus::new(&torrent, Cfg::new(CONFIG_PATH).unwrap());
         status.peer_connecting();
@@ -717,8 +1514,8 @@ mod tests {
     #[test]
     fn test_torrent_download_speed() {
         let torrent = create_test_torrent("test_torrent_download_speed");
-        let peer1 = create_test_peer("192.0".to_string());
-        let peer2 = create_test_peer("932.0".to_string());
+        let peer1 = create_test_peer("192.0.0.1");
+        let peer2 = create_test_peer("93.2.0.1");
 
         let mut peer_session1 = create_test_peer_session_status();
         peer_session1.download_speed = 1500.0;
@@ -742,8 +1539,8 @@ mod tests {
     #[test]
     fn test_torrent_upload_speed() {
         let torrent = create_test_torrent("test_torrent_upload_speed");
-        let peer1 = create_test_peer("192.0".to_string());
-        let peer2 = create_test_peer("932.0".to_string());
+        let peer1 = create_test_peer("192.0.0.1");
+        let peer2 = create_test_peer("93.2.0.1");
 
         let mut peer_session1 = create_test_peer_session_status();
         peer_session1.upload_speed = 100.0;
@@ -764,6 +1561,265 @@ mod tests {
         assert_eq!(status.torrent_upload_speed().unwrap(), 300.0);
     }
 
+    #[test]
+    fn test_left_bytes_of_torrent_larger_than_4_gib() {
+        let mut torrent = create_test_torrent("test_left_bytes_of_torrent_larger_than_4_gib");
+        torrent.info.piece_length = 256 * 1024;
+        torrent.info.length = 5 * 1024 * 1024 * 1024 + 5;
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        assert_eq!(status.left_bytes().unwrap(), 5_368_709_125);
+    }
+
+    #[test]
+    fn test_piece_size() {
+        let mut torrent = create_test_torrent("test_piece_size");
+        torrent.info.piece_length = 4;
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+
+        assert_eq!(status.piece_size(0), 4);
+        assert_eq!(status.piece_size(1), 4);
+        assert_eq!(status.piece_size(2), 2);
+    }
+
+    #[test]
+    fn test_transfer_counters() {
+        let torrent = create_test_torrent("test_transfer_counters");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        assert_eq!(status.left_bytes().unwrap(), 10);
+
+        let index = status
+            .select_piece(
+                &create_test_peer("192.0.0.1"),
+                &Bitfield::new(vec![0b11111111, 0b11111111]),
+            )
+            .unwrap()
+            .unwrap();
+        status.piece_downloaded(index, &[1]).unwrap();
+        status.get_piece(index, index as u64, 1).unwrap();
+
+        assert_eq!(status.downloaded_bytes(), 1);
+        assert_eq!(status.uploaded_bytes(), 1);
+        assert_eq!(status.left_bytes().unwrap(), 9);
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+    }
+
+    #[test]
+    fn test_stop() {
+        let torrent = create_test_torrent("test_stop");
+
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        assert!(!status.is_stopped());
+
+        status.stop();
+
+        assert!(status.is_stopped());
+    }
+
+    #[test]
+    fn test_resume() {
+        let torrent = create_test_torrent("test_resume");
+        let status = create_status_whitout_receiver(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+
+        status.stop();
+        status.resume();
+
+        assert!(!status.is_stopped());
+    }
+
+    #[test]
+    fn test_resume_after_seed_limit() {
+        let torrent = create_test_torrent("test_resume_after_seed_limit");
+        let status = create_status_whitout_receiver(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+
+        status.stop_at_seed_limit();
+        assert!(status.is_stopped());
+        assert!(status.is_seed_limit_reached());
+
+        status.resume();
+        assert!(!status.is_seed_limit_reached());
+    }
+
+    #[test]
+    fn test_share_ratio() {
+        let torrent = create_test_torrent("test_share_ratio");
+        let status = create_status_whitout_receiver(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+        status.uploaded_bytes.store(15, Ordering::Relaxed);
+
+        assert_eq!(status.share_ratio(), 1.5);
+
+        status.downloaded_bytes.store(5, Ordering::Relaxed);
+        assert_eq!(status.share_ratio(), 3.0);
+    }
+
+    #[test]
+    fn test_removed_torrent_can_not_be_resumed() {
+        let torrent = create_test_torrent("test_remove");
+        let status = create_status_whitout_receiver(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+
+        status.remove();
+        status.resume();
+
+        assert!(status.is_removed());
+        assert!(status.is_stopped());
+    }
+
+    #[test]
+    fn test_delete_data() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = create_test_torrent_with_data("test_delete_data", b"abcd", &config);
+        let path = format!("{}/{}", config.download_directory, torrent.info.name);
+        fs::write(&path, b"abcd").unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+        status.save_resume_data().unwrap();
+
+        status.delete_data().unwrap();
+
+        assert!(!Path::new(&path).exists());
+        assert!(ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .is_none());
+        assert!(status.delete_data().is_ok());
+    }
+
+    #[test]
+    fn test_delete_data_outside_the_download_directory() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        fs::create_dir_all(&config.download_directory).unwrap();
+
+        for name in ["", ".", "..", "/tmp"] {
+            let mut torrent =
+                create_test_torrent("test_delete_data_outside_the_download_directory");
+            torrent.info.name = name.to_string();
+            let status = create_status_whitout_receiver(&torrent, config.clone());
+
+            assert!(matches!(
+                status.delete_data(),
+                Err(AtomicTorrentStatusError::DataOutsideDownloadDirectory(_))
+            ));
+        }
+        assert!(Path::new(&config.download_directory).is_dir());
+    }
+
+    #[test]
+    fn test_force_recheck() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = create_test_torrent_with_data("test_force_recheck", b"abcd", &config);
+        fs::write(
+            format!("{}/{}", config.download_directory, torrent.info.name),
+            b"abcx",
+        )
+        .unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+
+        status.force_recheck().unwrap();
+
+        assert_eq!(status.downloaded_pieces(), 1);
+        assert_eq!(status.remaining_pieces(), 1);
+        assert_eq!(status.left_bytes().unwrap(), 2);
+        let resume_data = ResumeData::load(&torrent.info_hash, &config)
+            .unwrap()
+            .unwrap();
+        assert_eq!(resume_data.bitfield, vec![0b1000_0000]);
+        remove_test_data(&torrent, &config);
+    }
+
+    #[test]
+    fn test_verify_existing_data_uses_resume_file() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = create_test_torrent_with_data("test_verify_resume", b"abcd", &config);
+        fs::write(
+            format!("{}/{}", config.download_directory, torrent.info.name),
+            b"abcd",
+        )
+        .unwrap();
+        ResumeData {
+            bitfield: vec![0b1100_0000],
+            downloaded: 4,
+            uploaded: 8,
+            file_mtimes: file_mtimes(&torrent.info, &config),
+        }
+        .save(&torrent.info_hash, &config)
+        .unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+
+        status.verify_existing_data().unwrap();
+
+        assert!(status.is_finished());
+        assert_eq!(status.downloaded_bytes(), 4);
+        assert_eq!(status.uploaded_bytes(), 8);
+        remove_test_data(&torrent, &config);
+    }
+
+    #[test]
+    fn test_verify_existing_data_rechecks_modified_files() {
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let torrent = create_test_torrent_with_data("test_verify_modified", b"abcd", &config);
+        fs::write(
+            format!("{}/{}", config.download_directory, torrent.info.name),
+            b"xbcd",
+        )
+        .unwrap();
+        ResumeData {
+            bitfield: vec![0b1100_0000],
+            downloaded: 4,
+            uploaded: 8,
+            file_mtimes: vec![0],
+        }
+        .save(&torrent.info_hash, &config)
+        .unwrap();
+        let status = create_status_whitout_receiver(&torrent, config.clone());
+
+        status.verify_existing_data().unwrap();
+
+        assert!(!status.is_finished());
+        assert_eq!(status.downloaded_pieces(), 1);
+        assert_eq!(status.downloaded_bytes(), 0);
+        remove_test_data(&torrent, &config);
+    }
+
+    #[test]
+    fn test_discovered_peers() {
+        let torrent = create_test_torrent("test_discovered_peers");
+        let config = Cfg::new(CONFIG_PATH).unwrap();
+        let status = create_status_whitout_receiver(&torrent, config);
+        let mut incoming_peer_status = create_test_peer_session_status();
+        incoming_peer_status.listen_port = Some(6881);
+        status
+            .update_peer_session_status(&create_test_peer("127.0.0.1"), &incoming_peer_status)
+            .unwrap();
+
+        status
+            .add_discovered_peers(vec![
+                BtPeer::new("127.0.0.1".parse().unwrap(), 6881),
+                BtPeer::new("127.0.0.2".parse().unwrap(), 6881),
+                BtPeer::new("127.0.0.3".parse().unwrap(), 6881),
+                BtPeer::new("127.0.0.2".parse().unwrap(), 6881),
+            ])
+            .unwrap();
+
+        assert_eq!(
+            status.take_discovered_peers(1).unwrap(),
+            vec![BtPeer::new("127.0.0.2".parse().unwrap(), 6881)]
+        );
+        assert_eq!(
+            status.take_discovered_peers(5).unwrap(),
+            vec![BtPeer::new("127.0.0.3".parse().unwrap(), 6881)]
+        );
+        assert!(status.take_discovered_peers(5).unwrap().is_empty());
+    }
+
     // Auxiliary functions
 
     fn create_test_torrent(name: &str) -> Torrent {
@@ -772,21 +1828,58 @@ mod tests {
             name: name.to_string(),
             piece_length: 1,
             pieces: vec![],
+            files: None,
         };
 
         Torrent {
             announce_url: "announce".to_string(),
+            announce_list: vec![],
             info,
             info_hash: "info_hash".to_string(),
         }
     }
 
-    fn create_test_peer(ip: String) -> BtPeer {
+    /// Creates a torrent with pieces of 2 bytes whose hashes match the given data.
+    fn create_test_torrent_with_data(name: &str, data: &[u8], config: &Cfg) -> Torrent {
+        let pieces = data
+            .chunks(2)
+            .flat_map(|piece| Sha1::digest(piece).to_vec())
+            .collect();
+        let info = Info {
+            length: data.len() as i64,
+            name: name.to_string(),
+            piece_length: 2,
+            pieces,
+            files: None,
+        };
+
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info,
+            info_hash: name.to_string(),
+        };
+        ResumeData::delete(&torrent.info_hash, config).unwrap();
+        torrent
+    }
+
+    fn remove_test_data(torrent: &Torrent, config: &Cfg) {
+        fs::remove_file(format!(
+            "{}/{}",
+            config.download_directory, torrent.info.name
+        ))
+        .unwrap();
+        ResumeData::delete(&torrent.info_hash, config).unwrap();
+    }
+
+    fn create_test_peer(ip: &str) -> BtPeer {
         BtPeer {
             peer_id: Some(vec![0x00]),
-            ip: ip,
+            ip: ip.parse().unwrap(),
             port: 0,
             info_hash: None,
+            supports_extension_protocol: false,
+            supports_fast_extension: false,
         }
     }
 
@@ -0,0 +1,141 @@
+use super::status::AtomicTorrentStatus;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+
+/// The list of the torrents of the client, shared by the threads that must see the torrents added or removed while it is running.
+///
+/// The torrents are kept in the order they were added, and are identified by their info hash.
+///
+/// To create a new `TorrentList`, use TorrentList::new().
+#[derive(Debug, Clone, Default)]
+pub struct TorrentList {
+    statuses: Arc<Mutex<Vec<Arc<AtomicTorrentStatus>>>>,
+}
+
+impl TorrentList {
+    /// Creates an empty `TorrentList`.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the status of a torrent at the end of the list.
+    pub fn add(&self, torrent_status: Arc<AtomicTorrentStatus>) {
+        self.lock_statuses().push(torrent_status);
+    }
+
+    /// Removes the torrent with the given info hash from the list, returning its status if it was in it.
+    pub fn remove(&self, info_hash: &str) -> Option<Arc<AtomicTorrentStatus>> {
+        let mut statuses = self.lock_statuses();
+        let index = statuses
+            .iter()
+            .position(|torrent_status| Self::has_info_hash(torrent_status, info_hash))?;
+        Some(statuses.remove(index))
+    }
+
+    /// Returns the status of the torrent with the given info hash, as an hex string.
+    pub fn find(&self, info_hash: &str) -> Option<Arc<AtomicTorrentStatus>> {
+        self.lock_statuses()
+            .iter()
+            .find(|torrent_status| Self::has_info_hash(torrent_status, info_hash))
+            .cloned()
+    }
+
+    /// Returns the status of the torrent with the given info hash, as bytes like it is received in a handshake.
+    pub fn find_by_bytes(&self, info_hash: &[u8]) -> Option<Arc<AtomicTorrentStatus>> {
+        self.lock_statuses()
+            .iter()
+            .find(|torrent_status| {
+                torrent_status
+                    .torrent
+                    .get_info_hash_as_bytes()
+                    .is_ok_and(|bytes| bytes == info_hash)
+            })
+            .cloned()
+    }
+
+    /// Returns the status of every torrent of the list, in the order they were added.
+    pub fn statuses(&self) -> Vec<Arc<AtomicTorrentStatus>> {
+        self.lock_statuses().clone()
+    }
+
+    fn has_info_hash(torrent_status: &AtomicTorrentStatus, info_hash: &str) -> bool {
+        torrent_status
+            .torrent
+            .info_hash()
+            .eq_ignore_ascii_case(info_hash)
+    }
+
+    /// The list is always valid, even if a thread panicked while changing it.
+    fn lock_statuses(&self) -> MutexGuard<'_, Vec<Arc<AtomicTorrentStatus>>> {
+        self.statuses.lock().unwrap_or_else(PoisonError::into_inner)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        config::cfg::Cfg,
+        torrent_parser::{info::Info, torrent::Torrent},
+    };
+
+    const CONFIG_PATH: &str = "config.cfg";
+
+    #[test]
+    fn test_torrents_are_found_by_info_hash() {
+        let list = TorrentList::new();
+        let first = create_test_status("test_torrent_list_01");
+        let second = create_test_status("test_torrent_list_02");
+        list.add(first.clone());
+        list.add(second.clone());
+
+        let info_hash = second.torrent.info_hash();
+        let info_hash_bytes = second.torrent.get_info_hash_as_bytes().unwrap();
+
+        assert!(Arc::ptr_eq(&list.find(&info_hash).unwrap(), &second));
+        assert!(Arc::ptr_eq(
+            &list.find(&info_hash.to_uppercase()).unwrap(),
+            &second
+        ));
+        assert!(Arc::ptr_eq(
+            &list.find_by_bytes(&info_hash_bytes).unwrap(),
+            &second
+        ));
+        assert!(list.find("0000").is_none());
+    }
+
+    #[test]
+    fn test_removed_torrents_are_no_longer_listed() {
+        let list = TorrentList::new();
+        let first = create_test_status("test_torrent_list_03");
+        let second = create_test_status("test_torrent_list_04");
+        list.add(first.clone());
+        list.add(second.clone());
+
+        let removed = list.remove(&first.torrent.info_hash()).unwrap();
+
+        assert!(Arc::ptr_eq(&removed, &first));
+        assert!(list.remove(&first.torrent.info_hash()).is_none());
+        assert_eq!(list.statuses().len(), 1);
+        assert!(Arc::ptr_eq(&list.statuses()[0], &second));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_status(name: &str) -> Arc<AtomicTorrentStatus> {
+        let info = Info {
+            length: 10,
+            name: name.to_string(),
+            piece_length: 10,
+            pieces: vec![0; 20],
+            files: None,
+        };
+        let torrent = Torrent {
+            announce_url: "announce".to_string(),
+            announce_list: vec![],
+            info_hash: Torrent::create_info_hash(&info).unwrap(),
+            info,
+        };
+        let (status, _) = AtomicTorrentStatus::new(&torrent, Cfg::new(CONFIG_PATH).unwrap());
+        Arc::new(status)
+    }
+}
@@ -2,22 +2,31 @@ use std::collections::BTreeMap;
 
 use bencoder::bencode::{Bencode, ToBencode};
 
+use super::info_file::{is_valid_path_component, FromInfoFileError, InfoFile};
+
+/// `Info` struct containing the info dict of a torrent.
+///
+/// For multi-file torrents `files` contains the list of files and `length` is the sum of their lengths.
+/// For single-file torrents `files` is `None`.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Info {
     pub length: i64,
     pub name: String,
     pub piece_length: i64,
     pub pieces: Vec<u8>,
+    pub files: Option<Vec<InfoFile>>,
 }
 
 #[derive(Debug, PartialEq)]
 pub enum FromInfoError {
     MissingLength,
     MissingName,
+    InvalidName,
     MissingPieceLength,
     MissingPieces,
     NotADict,
-    MultipleFilesNotSupported,
+    InvalidFiles(FromInfoFileError),
+    MissingFiles,
 }
 
 impl Info {
@@ -26,6 +35,7 @@ impl Info {
         let mut length = 0;
         let mut piece_length = 0;
         let mut pieces = Vec::new();
+        let mut files = None;
 
         let d = match bencode {
             Bencode::BDict(s) => s,
@@ -42,18 +52,42 @@ impl Info {
             } else if k == b"pieces" {
                 pieces = Info::create_pieces(v)?;
             } else if k == b"files" {
-                return Err(FromInfoError::MultipleFilesNotSupported);
+                files = Some(Info::create_files(v)?);
             }
         }
 
+        // In multi-file torrents the length is the sum of the length of every file.
+        if let Some(files) = &files {
+            length = files.iter().map(|file| file.length).sum();
+        }
+
         Ok(Info {
             length,
             name,
             piece_length,
             pieces,
+            files,
         })
     }
 
+    /// Returns the files of the torrent.
+    ///
+    /// For single-file torrents it returns only one file, named as the torrent.
+    pub fn files(&self) -> Vec<InfoFile> {
+        match &self.files {
+            Some(files) => files.clone(),
+            None => vec![InfoFile {
+                length: self.length,
+                path: vec![self.name.clone()],
+            }],
+        }
+    }
+
+    /// Returns true if the torrent has a list of files instead of a single file.
+    pub fn is_multi_file(&self) -> bool {
+        self.files.is_some()
+    }
+
     fn create_name(bencode: &Bencode) -> Result<String, FromInfoError> {
         let c = match bencode {
             &Bencode::BString(ref s) => s,
@@ -65,6 +99,11 @@ impl Info {
             Err(_) => return Err(FromInfoError::MissingName),
         };
 
+        // The name is used as the file or directory of the torrent inside the download directory.
+        if !is_valid_path_component(&name) {
+            return Err(FromInfoError::InvalidName);
+        }
+
         Ok(name)
     }
 
@@ -91,12 +130,30 @@ impl Info {
         };
         Ok(c.to_vec())
     }
+
+    fn create_files(bencode: &Bencode) -> Result<Vec<InfoFile>, FromInfoError> {
+        let list = match bencode {
+            Bencode::BList(list) => list,
+            _ => return Err(FromInfoError::MissingFiles),
+        };
+
+        if list.is_empty() {
+            return Err(FromInfoError::MissingFiles);
+        }
+
+        list.iter()
+            .map(|file| InfoFile::from(file).map_err(FromInfoError::InvalidFiles))
+            .collect()
+    }
 }
 
 impl ToBencode for Info {
     fn to_bencode(&self) -> Bencode {
         let mut info = BTreeMap::new();
-        info.insert(b"length".to_vec(), self.length.to_bencode());
+        match &self.files {
+            Some(files) => info.insert(b"files".to_vec(), files.to_bencode()),
+            None => info.insert(b"length".to_vec(), self.length.to_bencode()),
+        };
         info.insert(b"name".to_vec(), self.name.to_bencode());
         info.insert(b"piece length".to_vec(), self.piece_length.to_bencode());
         info.insert(b"pieces".to_vec(), self.pieces.to_bencode());
@@ -115,7 +172,8 @@ mod tests {
         assert_eq!(info.length, 0);
         assert_eq!(info.name, String::new());
         assert_eq!(info.piece_length, 0);
-        assert_eq!(info.pieces, Vec::new());
+        assert_eq!(info.pieces, Vec::<u8>::new());
+        assert_eq!(info.files, None);
     }
 
     #[test]
@@ -140,10 +198,116 @@ mod tests {
         info.insert(b"name".to_vec(), Bencode::BString(b"test1".to_vec()));
         info.insert(b"piece length".to_vec(), Bencode::BNumber(2));
         info.insert(b"pieces".to_vec(), Bencode::BString(b"test2".to_vec()));
+        info.insert(
+            b"files".to_vec(),
+            Bencode::BList(vec![
+                build_file_bencode(3, vec![b"a.txt".to_vec()]),
+                build_file_bencode(5, vec![b"dir".to_vec(), b"b.txt".to_vec()]),
+            ]),
+        );
+        let bencode = Bencode::BDict(info);
+
+        let response = Info::from(&bencode).unwrap();
+        assert_eq!(response.length, 8);
+        assert!(response.is_multi_file());
+
+        let files = response.files();
+        assert_eq!(files.len(), 2);
+        assert_eq!(files[0].length, 3);
+        assert_eq!(files[0].relative_path(), "a.txt");
+        assert_eq!(files[1].length, 5);
+        assert_eq!(files[1].relative_path(), "dir/b.txt");
+    }
+
+    #[test]
+    fn test_from_info_with_empty_files() {
+        let mut info = BTreeMap::new();
+        info.insert(b"name".to_vec(), Bencode::BString(b"test1".to_vec()));
         info.insert(b"files".to_vec(), Bencode::BList(vec![]));
         let bencode = Bencode::BDict(info);
 
         let response = Info::from(&bencode).unwrap_err();
-        assert_eq!(response, FromInfoError::MultipleFilesNotSupported);
+        assert_eq!(response, FromInfoError::MissingFiles);
+    }
+
+    #[test]
+    fn test_single_file_info_files() {
+        let mut info = BTreeMap::new();
+        info.insert(b"length".to_vec(), Bencode::BNumber(1));
+        info.insert(b"name".to_vec(), Bencode::BString(b"test1".to_vec()));
+        let bencode = Bencode::BDict(info);
+
+        let response = Info::from(&bencode).unwrap();
+        assert!(!response.is_multi_file());
+        assert_eq!(
+            response.files(),
+            vec![InfoFile {
+                length: 1,
+                path: vec!["test1".to_string()]
+            }]
+        );
+    }
+
+    #[test]
+    fn test_multiple_files_to_bencode() {
+        let files = Bencode::BList(vec![build_file_bencode(3, vec![b"a.txt".to_vec()])]);
+
+        let mut info = BTreeMap::new();
+        info.insert(b"files".to_vec(), files.clone());
+        info.insert(b"name".to_vec(), Bencode::BString(b"test1".to_vec()));
+        info.insert(b"piece length".to_vec(), Bencode::BNumber(2));
+        info.insert(b"pieces".to_vec(), Bencode::BString(b"test2".to_vec()));
+        let bencode = Bencode::BDict(info);
+
+        let response = Info::from(&bencode).unwrap();
+        assert_eq!(response.to_bencode(), bencode);
+    }
+
+    #[test]
+    fn test_from_info_with_empty_name() {
+        assert_eq!(info_with_name(b""), Err(FromInfoError::InvalidName));
+    }
+
+    #[test]
+    fn test_from_info_with_current_directory_name() {
+        assert_eq!(info_with_name(b"."), Err(FromInfoError::InvalidName));
+    }
+
+    #[test]
+    fn test_from_info_with_parent_directory_name() {
+        assert_eq!(info_with_name(b".."), Err(FromInfoError::InvalidName));
+    }
+
+    #[test]
+    fn test_from_info_with_path_separator_in_name() {
+        assert_eq!(info_with_name(b"dir/file"), Err(FromInfoError::InvalidName));
+        assert_eq!(
+            info_with_name(b"dir\\file"),
+            Err(FromInfoError::InvalidName)
+        );
+    }
+
+    #[test]
+    fn test_from_info_with_absolute_name() {
+        assert_eq!(info_with_name(b"/tmp"), Err(FromInfoError::InvalidName));
+    }
+
+    // Auxiliary functions
+
+    fn info_with_name(name: &[u8]) -> Result<Info, FromInfoError> {
+        let mut info = BTreeMap::new();
+        info.insert(b"length".to_vec(), Bencode::BNumber(1));
+        info.insert(b"name".to_vec(), Bencode::BString(name.to_vec()));
+        Info::from(&Bencode::BDict(info))
+    }
+
+    fn build_file_bencode(length: i64, path: Vec<Vec<u8>>) -> Bencode {
+        let mut file = BTreeMap::new();
+        file.insert(b"length".to_vec(), Bencode::BNumber(length));
+        file.insert(
+            b"path".to_vec(),
+            Bencode::BList(path.into_iter().map(Bencode::BString).collect()),
+        );
+        Bencode::BDict(file)
     }
 }
@@ -0,0 +1,182 @@
+use std::{collections::BTreeMap, path::Path};
+
+use bencoder::bencode::{Bencode, ToBencode};
+
+/// `InfoFile` struct containing the information of one of the files of a multi-file torrent.
+///
+/// - `length`: length of the file in bytes,
+/// - `path`: list of path components, the last one being the file name.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct InfoFile {
+    pub length: i64,
+    pub path: Vec<String>,
+}
+
+/// Posible `InfoFile` errors.
+#[derive(Debug, PartialEq)]
+pub enum FromInfoFileError {
+    MissingLength,
+    MissingPath,
+    InvalidPath,
+    NotADict,
+}
+
+/// Returns true if `component` can be used as a single component of a path inside the download directory.
+///
+/// Empty components, `.`, `..`, absolute paths and components with a path separator are not valid, as they could
+/// be used to write outside of the torrent directory.
+pub(crate) fn is_valid_path_component(component: &str) -> bool {
+    !component.is_empty()
+        && component != "."
+        && component != ".."
+        && !component.contains('/')
+        && !component.contains('\\')
+        && !Path::new(component).is_absolute()
+}
+
+impl InfoFile {
+    /// Builds a new `InfoFile` from a bencoded dict of the `files` list.
+    ///
+    /// It returns an `FromInfoFileError` if:
+    /// - The bencoded file is not a dict.
+    /// - The length is missing or is not a number.
+    /// - The path is missing or is not a list of strings.
+    /// - A path component is empty, `.` or `..`, or contains a path separator.
+    pub fn from(bencode: &Bencode) -> Result<InfoFile, FromInfoFileError> {
+        let mut length = None;
+        let mut path = None;
+
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(FromInfoFileError::NotADict),
+        };
+
+        for (k, v) in d.iter() {
+            if k == b"length" {
+                length = Some(InfoFile::create_length(v)?);
+            } else if k == b"path" {
+                path = Some(InfoFile::create_path(v)?);
+            }
+        }
+
+        Ok(InfoFile {
+            length: length.ok_or(FromInfoFileError::MissingLength)?,
+            path: path.ok_or(FromInfoFileError::MissingPath)?,
+        })
+    }
+
+    fn create_length(bencode: &Bencode) -> Result<i64, FromInfoFileError> {
+        match bencode {
+            Bencode::BNumber(n) if *n >= 0 => Ok(*n),
+            _ => Err(FromInfoFileError::MissingLength),
+        }
+    }
+
+    fn create_path(bencode: &Bencode) -> Result<Vec<String>, FromInfoFileError> {
+        let list = match bencode {
+            Bencode::BList(list) => list,
+            _ => return Err(FromInfoFileError::MissingPath),
+        };
+
+        if list.is_empty() {
+            return Err(FromInfoFileError::MissingPath);
+        }
+
+        let mut path = Vec::new();
+        for component in list {
+            let component = match component {
+                Bencode::BString(s) => {
+                    String::from_utf8(s.to_vec()).map_err(|_| FromInfoFileError::InvalidPath)?
+                }
+                _ => return Err(FromInfoFileError::MissingPath),
+            };
+
+            if !is_valid_path_component(&component) {
+                return Err(FromInfoFileError::InvalidPath);
+            }
+            path.push(component);
+        }
+
+        Ok(path)
+    }
+
+    /// Returns the path of the file relative to the torrent directory.
+    pub fn relative_path(&self) -> String {
+        self.path.join("/")
+    }
+}
+
+impl ToBencode for InfoFile {
+    fn to_bencode(&self) -> Bencode {
+        let mut file = BTreeMap::new();
+        file.insert(b"length".to_vec(), self.length.to_bencode());
+        file.insert(b"path".to_vec(), self.path.to_bencode());
+        Bencode::BDict(file)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_info_file() {
+        let bencode = build_file_bencode(10, vec![b"dir".to_vec(), b"file.txt".to_vec()]);
+
+        let file = InfoFile::from(&bencode).unwrap();
+
+        assert_eq!(file.length, 10);
+        assert_eq!(file.path, vec!["dir".to_string(), "file.txt".to_string()]);
+        assert_eq!(file.relative_path(), "dir/file.txt");
+    }
+
+    #[test]
+    fn test_from_info_file_missing_length() {
+        let mut file = BTreeMap::new();
+        file.insert(
+            b"path".to_vec(),
+            Bencode::BList(vec![Bencode::BString(b"file.txt".to_vec())]),
+        );
+
+        let err = InfoFile::from(&Bencode::BDict(file)).unwrap_err();
+
+        assert_eq!(err, FromInfoFileError::MissingLength);
+    }
+
+    #[test]
+    fn test_from_info_file_empty_path() {
+        let bencode = build_file_bencode(10, vec![]);
+
+        let err = InfoFile::from(&bencode).unwrap_err();
+
+        assert_eq!(err, FromInfoFileError::MissingPath);
+    }
+
+    #[test]
+    fn test_from_info_file_parent_directory_in_path() {
+        let bencode = build_file_bencode(10, vec![b"..".to_vec(), b"file.txt".to_vec()]);
+
+        let err = InfoFile::from(&bencode).unwrap_err();
+
+        assert_eq!(err, FromInfoFileError::InvalidPath);
+    }
+
+    #[test]
+    fn test_info_file_to_bencode() {
+        let bencode = build_file_bencode(10, vec![b"dir".to_vec(), b"file.txt".to_vec()]);
+
+        let file = InfoFile::from(&bencode).unwrap();
+
+        assert_eq!(file.to_bencode(), bencode);
+    }
+
+    fn build_file_bencode(length: i64, path: Vec<Vec<u8>>) -> Bencode {
+        let mut file = BTreeMap::new();
+        file.insert(b"length".to_vec(), Bencode::BNumber(length));
+        file.insert(
+            b"path".to_vec(),
+            Bencode::BList(path.into_iter().map(Bencode::BString).collect()),
+        );
+        Bencode::BDict(file)
+    }
+}
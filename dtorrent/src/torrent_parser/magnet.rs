@@ -0,0 +1,305 @@
+use std::fmt::Write;
+
+use bencoder::bencode::{Bencode, BencodeError};
+use sha1::{Digest, Sha1};
+
+use super::{
+    info::{FromInfoError, Info},
+    torrent::Torrent,
+};
+
+const MAGNET_PREFIX: &str = "magnet:?";
+const INFO_HASH_URN: &str = "urn:btih:";
+const BASE32_ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+
+/// `Magnet` struct containing the information of a magnet link (BEP 9).
+///
+/// - `info_hash`: the info hash of the `xt` parameter, as a lowercase hex string.
+/// - `display_name`: the `dn` parameter, if present.
+/// - `trackers`: the urls of the `tr` parameters.
+///
+/// The info dict of the torrent is not part of the link, it has to be fetched from peers with the `ut_metadata` extension.
+///
+/// To create a new `Magnet` use the method builder `parse()`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Magnet {
+    pub info_hash: String,
+    pub display_name: Option<String>,
+    pub trackers: Vec<String>,
+}
+
+/// Posible `Magnet` errors.
+#[derive(Debug, PartialEq)]
+pub enum MagnetError {
+    NotAMagnetLink,
+    MissingInfoHash,
+    InvalidInfoHash,
+    InvalidEncoding,
+    InfoHashDoesNotMatch,
+    DecodeError(BencodeError),
+    FromInfoError(FromInfoError),
+}
+
+impl Magnet {
+    /// Parses a `magnet:?xt=urn:btih:...` link.
+    ///
+    /// The info hash can be encoded in hex (40 characters) or in base32 (32 characters). Unknown parameters are ignored.
+    ///
+    /// It returns a `MagnetError` if:
+    /// - The link does not start with `magnet:?`.
+    /// - The link has no `xt` parameter with a BitTorrent info hash.
+    /// - The info hash is not valid.
+    /// - A parameter is not correctly percent-encoded.
+    pub fn parse(link: &str) -> Result<Self, MagnetError> {
+        let params = link
+            .trim()
+            .strip_prefix(MAGNET_PREFIX)
+            .ok_or(MagnetError::NotAMagnetLink)?;
+
+        let mut info_hash = None;
+        let mut display_name = None;
+        let mut trackers = Vec::new();
+
+        for param in params.split('&') {
+            let (key, value) = match param.split_once('=') {
+                Some((key, value)) => (key, percent_decode(value)?),
+                None => continue,
+            };
+
+            match key {
+                "xt" => {
+                    if let Some(hash) = value.strip_prefix(INFO_HASH_URN) {
+                        info_hash = Some(Self::create_info_hash(hash)?);
+                    }
+                }
+                "dn" => display_name = Some(value),
+                "tr" => trackers.push(value),
+                _ => (),
+            }
+        }
+
+        Ok(Self {
+            info_hash: info_hash.ok_or(MagnetError::MissingInfoHash)?,
+            display_name,
+            trackers,
+        })
+    }
+
+    fn create_info_hash(hash: &str) -> Result<String, MagnetError> {
+        let bytes = match hash.len() {
+            40 if hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+                Torrent::decode_hex(hash).map_err(|_| MagnetError::InvalidInfoHash)?
+            }
+            32 => base32_decode(hash)?,
+            _ => return Err(MagnetError::InvalidInfoHash),
+        };
+
+        let mut hex_string = String::with_capacity(bytes.len() * 2);
+        for b in bytes {
+            write!(&mut hex_string, "{:02x}", b).map_err(|_| MagnetError::InvalidInfoHash)?;
+        }
+        Ok(hex_string)
+    }
+
+    /// Returns the info hash of the magnet link as a byte array.
+    pub fn info_hash_as_bytes(&self) -> Vec<u8> {
+        // The info hash was validated when the link was parsed.
+        Torrent::decode_hex(&self.info_hash).unwrap_or_default()
+    }
+
+    /// Returns the name of the torrent to show while its metadata is unknown, the display name or the info hash.
+    pub fn name(&self) -> String {
+        self.display_name
+            .clone()
+            .unwrap_or_else(|| self.info_hash.clone())
+    }
+
+    /// Returns a `Torrent` without info dict, only useful for announcing to the trackers of the magnet link to get peers.
+    pub fn placeholder_torrent(&self) -> Torrent {
+        Torrent {
+            announce_url: self.trackers.first().cloned().unwrap_or_default(),
+            announce_list: self.announce_list(),
+            info: Info {
+                length: 0,
+                name: self.name(),
+                piece_length: 0,
+                pieces: vec![],
+                files: None,
+            },
+            info_hash: self.info_hash.clone(),
+        }
+    }
+
+    /// Builds the `Torrent` of the magnet link from the bencoded info dict fetched from peers.
+    ///
+    /// It returns a `MagnetError` if:
+    /// - The SHA-1 hash of the metadata does not match the info hash of the link.
+    /// - The metadata is not a valid info dict.
+    pub fn into_torrent(self, metadata: &[u8]) -> Result<Torrent, MagnetError> {
+        if Sha1::digest(metadata).as_slice() != self.info_hash_as_bytes() {
+            return Err(MagnetError::InfoHashDoesNotMatch);
+        }
+
+        let bencode = Bencode::decode(metadata).map_err(MagnetError::DecodeError)?;
+        let info = Info::from(&bencode).map_err(MagnetError::FromInfoError)?;
+
+        Ok(Torrent {
+            announce_url: self.trackers.first().cloned().unwrap_or_default(),
+            announce_list: self.announce_list(),
+            info,
+            info_hash: self.info_hash,
+        })
+    }
+
+    /// Each tracker of the link is its own tier, so all of them are tried.
+    fn announce_list(&self) -> Vec<Vec<String>> {
+        self.trackers
+            .iter()
+            .map(|tracker| vec![tracker.clone()])
+            .collect()
+    }
+}
+
+/// Decodes a percent-encoded parameter of the link, where `+` also stands for a space.
+fn percent_decode(value: &str) -> Result<String, MagnetError> {
+    let mut bytes = Vec::with_capacity(value.len());
+    let mut iter = value.bytes();
+
+    while let Some(b) = iter.next() {
+        match b {
+            b'%' => {
+                let hex = [
+                    iter.next().ok_or(MagnetError::InvalidEncoding)?,
+                    iter.next().ok_or(MagnetError::InvalidEncoding)?,
+                ];
+                let hex = std::str::from_utf8(&hex).map_err(|_| MagnetError::InvalidEncoding)?;
+                bytes.push(u8::from_str_radix(hex, 16).map_err(|_| MagnetError::InvalidEncoding)?);
+            }
+            b'+' => bytes.push(b' '),
+            _ => bytes.push(b),
+        }
+    }
+
+    String::from_utf8(bytes).map_err(|_| MagnetError::InvalidEncoding)
+}
+
+/// Decodes a base32 string (RFC 4648, without padding).
+fn base32_decode(value: &str) -> Result<Vec<u8>, MagnetError> {
+    let mut bytes = Vec::with_capacity(value.len() * 5 / 8);
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+
+    for c in value.to_ascii_uppercase().bytes() {
+        let digit = BASE32_ALPHABET
+            .iter()
+            .position(|x| *x == c)
+            .ok_or(MagnetError::InvalidInfoHash)?;
+        buffer = (buffer << 5) | digit as u32;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+        }
+    }
+    Ok(bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bencoder::bencode::ToBencode;
+
+    #[test]
+    fn test_parse_magnet() {
+        let link = "magnet:?xt=urn:btih:2C6B6858D61DA9543D4231A71DB4B1C9264B0685&dn=ubuntu+22.04&tr=http%3A%2F%2Ftracker.example.com%3A6969%2Fannounce&tr=udp%3A%2F%2Ftracker.example.org%3A1337";
+
+        let magnet = Magnet::parse(link).unwrap();
+
+        assert_eq!(magnet.info_hash, "2c6b6858d61da9543d4231a71db4b1c9264b0685");
+        assert_eq!(magnet.display_name, Some("ubuntu 22.04".to_string()));
+        assert_eq!(
+            magnet.trackers,
+            vec![
+                "http://tracker.example.com:6969/announce".to_string(),
+                "udp://tracker.example.org:1337".to_string()
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_base32_info_hash() {
+        let link = "magnet:?xt=urn:btih:FRVWQWGWDWUVIPKCGGTR3NFRZETEWBUF";
+
+        let magnet = Magnet::parse(link).unwrap();
+
+        assert_eq!(magnet.info_hash, "2c6b6858d61da9543d4231a71db4b1c9264b0685");
+        assert_eq!(magnet.name(), magnet.info_hash);
+        assert!(magnet.trackers.is_empty());
+    }
+
+    #[test]
+    fn test_parse_invalid_magnets() {
+        assert_eq!(
+            Magnet::parse("http://example.com"),
+            Err(MagnetError::NotAMagnetLink)
+        );
+        assert_eq!(
+            Magnet::parse("magnet:?dn=name"),
+            Err(MagnetError::MissingInfoHash)
+        );
+        assert_eq!(
+            Magnet::parse("magnet:?xt=urn:btih:1234"),
+            Err(MagnetError::InvalidInfoHash)
+        );
+        assert_eq!(
+            Magnet::parse("magnet:?xt=urn:btih:2c6b6858d61da9543d4231a71db4b1c9264b0685&dn=%4"),
+            Err(MagnetError::InvalidEncoding)
+        );
+    }
+
+    #[test]
+    fn test_into_torrent() {
+        let info = create_test_info();
+        let metadata = Bencode::encode(&info);
+        let info_hash = Torrent::create_info_hash(&info).unwrap();
+        let link = format!(
+            "magnet:?xt=urn:btih:{}&tr=http://tracker/announce",
+            info_hash
+        );
+
+        let torrent = Magnet::parse(&link)
+            .unwrap()
+            .into_torrent(&metadata)
+            .unwrap();
+
+        assert_eq!(torrent.info, info);
+        assert_eq!(torrent.info_hash, info_hash);
+        assert_eq!(
+            torrent.announce_tiers(),
+            vec![vec!["http://tracker/announce".to_string()]]
+        );
+    }
+
+    #[test]
+    fn test_into_torrent_with_wrong_metadata() {
+        let metadata = Bencode::encode(&create_test_info());
+        let link = "magnet:?xt=urn:btih:2c6b6858d61da9543d4231a71db4b1c9264b0685";
+
+        let result = Magnet::parse(link).unwrap().into_torrent(&metadata);
+
+        assert_eq!(result, Err(MagnetError::InfoHashDoesNotMatch));
+    }
+
+    // Auxiliary functions
+
+    fn create_test_info() -> Info {
+        let info = Info {
+            length: 10,
+            name: "test_magnet".to_string(),
+            piece_length: 5,
+            pieces: vec![0; 40],
+            files: None,
+        };
+        Info::from(&info.to_bencode()).unwrap()
+    }
+}
@@ -1,3 +1,5 @@
 pub mod info;
+pub mod info_file;
+pub mod magnet;
 pub mod parser;
 pub mod torrent;
@@ -29,7 +29,17 @@ impl TorrentParser {
             Err(e) => return Err(ParseError::IoError(e)),
         };
 
-        let bencode = match Bencode::decode(&buffer) {
+        TorrentParser::parse_bytes(&buffer)
+    }
+
+    /// Given the contents of a torrent file, like an uploaded one, it parses them and returns a Torrent struct.
+    ///
+    /// # Errors
+    ///
+    /// * `ParseError::BencodeError` - An error occurred while parsing the bencode
+    /// * `ParseError::FromTorrentError` - An error occurred while creating the Torrent struct
+    pub fn parse_bytes(buffer: &[u8]) -> Result<Torrent, ParseError> {
+        let bencode = match Bencode::decode(buffer) {
             Ok(bencode) => bencode,
             Err(e) => return Err(ParseError::BencodeError(e)),
         };
@@ -58,6 +68,18 @@ mod tests {
     use super::*;
     use std::{fs, io::Write};
 
+    #[test]
+    fn test_parse_torrent_bytes() {
+        let contents =
+            b"d8:announce35:https://torrent.ubuntu.com/announce4:infod6:lengthi3654957056e4:name30:ubuntu-22.04-desktop-amd64.iso12:piece lengthi262144e6:pieces20:BC07C06A9DBC07C06A9Dee";
+
+        let torrent = TorrentParser::parse_bytes(contents).unwrap();
+
+        assert_eq!(torrent.announce_url, "https://torrent.ubuntu.com/announce");
+        assert_eq!(torrent.info.name, "ubuntu-22.04-desktop-amd64.iso");
+        assert!(TorrentParser::parse_bytes(b"not a torrent").is_err());
+    }
+
     #[test]
     fn test_parse_torrent() {
         let filepath = "./test_parse_torrent.torrent";
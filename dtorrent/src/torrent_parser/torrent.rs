@@ -7,9 +7,13 @@ use bencoder::bencode::{Bencode, ToBencode};
 
 use super::info::{FromInfoError, Info};
 
+/// `Torrent` struct containing the information of a .torrent file.
+///
+/// `announce_list` contains the tiers of trackers of the `announce-list` key (BEP 12), it is empty if the torrent does not have one.
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Torrent {
     pub announce_url: String,
+    pub announce_list: Vec<Vec<String>>,
     pub info: Info,
     pub info_hash: String,
 }
@@ -17,6 +21,7 @@ pub struct Torrent {
 #[derive(Debug, PartialEq)]
 pub enum FromTorrentError {
     MissingAnnounce,
+    InvalidAnnounceList,
     MissingInfo,
     FromInfoError(FromInfoError),
     InfoHashError,
@@ -26,6 +31,7 @@ pub enum FromTorrentError {
 impl Torrent {
     pub fn from(bencode: Bencode) -> Result<Torrent, FromTorrentError> {
         let mut announce_url = String::new();
+        let mut announce_list = Vec::new();
         let mut info: Option<Info> = None;
 
         let d = match bencode {
@@ -36,13 +42,19 @@ impl Torrent {
         for (k, v) in d.iter() {
             if k == b"announce" {
                 announce_url = Torrent::create_announce(v)?;
+            } else if k == b"announce-list" {
+                announce_list = Torrent::create_announce_list(v)?;
             } else if k == b"info" {
                 info = Some(Torrent::create_info(v)?);
             }
         }
 
+        // Torrents with an announce-list may not have the announce key.
         if announce_url.is_empty() {
-            return Err(FromTorrentError::MissingAnnounce);
+            announce_url = match announce_list.first().and_then(|tier| tier.first()) {
+                Some(url) => url.clone(),
+                None => return Err(FromTorrentError::MissingAnnounce),
+            };
         }
 
         let info = match info {
@@ -54,6 +66,7 @@ impl Torrent {
 
         Ok(Torrent {
             announce_url,
+            announce_list,
             info,
             info_hash,
         })
@@ -73,6 +86,38 @@ impl Torrent {
         Ok(announce_url)
     }
 
+    /// Parses the `announce-list` key, a list of tiers where each tier is a list of tracker urls.
+    ///
+    /// Empty tiers are discarded.
+    fn create_announce_list(bencode: &Bencode) -> Result<Vec<Vec<String>>, FromTorrentError> {
+        let tiers = match bencode {
+            Bencode::BList(tiers) => tiers,
+            _ => return Err(FromTorrentError::InvalidAnnounceList),
+        };
+
+        let mut announce_list = Vec::new();
+        for tier in tiers {
+            let urls = match tier {
+                Bencode::BList(urls) => urls,
+                _ => return Err(FromTorrentError::InvalidAnnounceList),
+            };
+
+            let mut tier_urls = Vec::new();
+            for url in urls {
+                match Torrent::create_announce(url) {
+                    Ok(url) => tier_urls.push(url),
+                    Err(_) => return Err(FromTorrentError::InvalidAnnounceList),
+                }
+            }
+
+            if !tier_urls.is_empty() {
+                announce_list.push(tier_urls);
+            }
+        }
+
+        Ok(announce_list)
+    }
+
     fn create_info(bencode: &Bencode) -> Result<Info, FromTorrentError> {
         let info = match Info::from(bencode) {
             Ok(x) => x,
@@ -103,7 +148,8 @@ impl Torrent {
         Self::decode_hex(self.info_hash.as_str())
     }
 
-    fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
+    /// Decodes an hex string into bytes.
+    pub fn decode_hex(s: &str) -> Result<Vec<u8>, ParseIntError> {
         (0..s.len())
             .step_by(2)
             .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
@@ -120,9 +166,9 @@ impl Torrent {
         self.info.piece_length as u32
     }
 
-    /// Returns the length in bytes of the torrent.
-    pub fn length(&self) -> u32 {
-        self.info.length as u32
+    /// Returns the length in bytes of the torrent, for multi-file torrents the sum of the length of its files.
+    pub fn length(&self) -> u64 {
+        self.info.length.max(0) as u64
     }
 
     /// Returns the number of pieces of the torrent.
@@ -132,18 +178,33 @@ impl Torrent {
 
     /// Returns the size of the last piece of the torrent.
     pub fn last_piece_size(&self) -> u32 {
-        self.info.length as u32 % self.info.piece_length as u32
+        (self.length() % self.piece_length() as u64) as u32
     }
 
     pub fn info_hash(&self) -> String {
         self.info_hash.clone()
     }
+
+    /// Returns the tiers of trackers of the torrent.
+    ///
+    /// If the torrent has an `announce-list` it is used and the `announce` key is ignored, as BEP 12 describes.
+    /// Otherwise there is only one tier with the `announce` url.
+    pub fn announce_tiers(&self) -> Vec<Vec<String>> {
+        if self.announce_list.is_empty() {
+            vec![vec![self.announce_url.clone()]]
+        } else {
+            self.announce_list.clone()
+        }
+    }
 }
 
 impl ToBencode for Torrent {
     fn to_bencode(&self) -> Bencode {
         let mut m = BTreeMap::new();
         m.insert(b"announce_url".to_vec(), self.announce_url.to_bencode());
+        if !self.announce_list.is_empty() {
+            m.insert(b"announce-list".to_vec(), self.announce_list.to_bencode());
+        }
         m.insert(b"info".to_vec(), self.info.to_bencode());
         Bencode::BDict(m)
     }
@@ -228,6 +289,77 @@ mod tests {
         assert_eq!(actual_err, expected_err);
     }
 
+    #[test]
+    fn test_from_torrent_with_announce_list() {
+        let announce = String::from("http://example.com/announce");
+        let info_bencode = build_info_bencode(10, b"example".to_vec(), 20, b"test".to_vec());
+        let mut dict = match build_torrent_bencode(announce.clone().into_bytes(), info_bencode) {
+            Bencode::BDict(dict) => dict,
+            _ => unreachable!(),
+        };
+        dict.insert(
+            b"announce-list".to_vec(),
+            build_announce_list_bencode(vec![
+                vec!["http://tier1-a.com/announce", "http://tier1-b.com/announce"],
+                vec![],
+                vec!["udp://tier2.com:80"],
+            ]),
+        );
+
+        let torrent = Torrent::from(Bencode::BDict(dict)).unwrap();
+
+        assert_eq!(torrent.announce_url, announce);
+        assert_eq!(
+            torrent.announce_tiers(),
+            vec![
+                vec![
+                    "http://tier1-a.com/announce".to_string(),
+                    "http://tier1-b.com/announce".to_string()
+                ],
+                vec!["udp://tier2.com:80".to_string()],
+            ]
+        );
+    }
+
+    #[test]
+    fn test_from_torrent_with_announce_list_and_without_announce() {
+        let info_bencode = build_info_bencode(10, b"example".to_vec(), 20, b"test".to_vec());
+        let mut dict = BTreeMap::new();
+        dict.insert(b"info".to_vec(), Bencode::BDict(info_bencode));
+        dict.insert(
+            b"announce-list".to_vec(),
+            build_announce_list_bencode(vec![vec!["http://tier1.com/announce"]]),
+        );
+
+        let torrent = Torrent::from(Bencode::BDict(dict)).unwrap();
+
+        assert_eq!(torrent.announce_url, "http://tier1.com/announce");
+    }
+
+    #[test]
+    fn test_from_torrent_invalid_announce_list() {
+        let info_bencode = build_info_bencode(10, b"example".to_vec(), 20, b"test".to_vec());
+        let mut dict = BTreeMap::new();
+        dict.insert(b"info".to_vec(), Bencode::BDict(info_bencode));
+        dict.insert(
+            b"announce-list".to_vec(),
+            Bencode::BList(vec![Bencode::BString(b"http://tier1.com".to_vec())]),
+        );
+
+        let actual_err = Torrent::from(Bencode::BDict(dict)).unwrap_err();
+
+        assert_eq!(actual_err, FromTorrentError::InvalidAnnounceList);
+    }
+
+    #[test]
+    fn test_announce_tiers_without_announce_list() {
+        let torrent = build_test_torrent();
+        assert_eq!(
+            torrent.announce_tiers(),
+            vec![vec!["http://example.com/announce".to_string()]]
+        );
+    }
+
     #[test]
     fn test_get_info_hash_as_bytes() {
         let info_hash = String::from("2c6b6858d61da9543d4231a71db4b1c9264b0685");
@@ -237,11 +369,13 @@ mod tests {
 
         let torrent = Torrent {
             announce_url: String::from("http://example.com/announce"),
+            announce_list: vec![],
             info: Info {
                 length: 10,
                 name: String::from("example"),
                 piece_length: 20,
                 pieces: String::from("test").into_bytes(),
+                files: None,
             },
             info_hash,
         };
@@ -279,6 +413,17 @@ mod tests {
         assert_eq!(torrent.last_piece_size(), 5);
     }
 
+    #[test]
+    fn test_torrent_larger_than_4_gib() {
+        let mut torrent = build_test_torrent();
+        torrent.info.piece_length = 256 * 1024;
+        torrent.info.length = 5 * 1024 * 1024 * 1024 + 5;
+
+        assert_eq!(torrent.length(), 5_368_709_125);
+        assert_eq!(torrent.total_pieces(), 20_481);
+        assert_eq!(torrent.last_piece_size(), 5);
+    }
+
     fn build_info_bencode(
         length: i64,
         name: Vec<u8>,
@@ -303,14 +448,31 @@ mod tests {
         Bencode::BDict(dict)
     }
 
+    fn build_announce_list_bencode(tiers: Vec<Vec<&str>>) -> Bencode {
+        Bencode::BList(
+            tiers
+                .into_iter()
+                .map(|tier| {
+                    Bencode::BList(
+                        tier.into_iter()
+                            .map(|url| Bencode::BString(url.as_bytes().to_vec()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        )
+    }
+
     fn build_test_torrent() -> Torrent {
         Torrent {
             announce_url: String::from("http://example.com/announce"),
+            announce_list: vec![],
             info: Info {
                 length: 105,
                 name: String::from("example"),
                 piece_length: 10,
                 pieces: String::from("test").into_bytes(),
+                files: None,
             },
             info_hash: "info_hash".to_string(),
         }
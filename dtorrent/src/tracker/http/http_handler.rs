@@ -2,12 +2,16 @@ use native_tls::Error;
 use native_tls::HandshakeError;
 use native_tls::TlsConnector;
 use std::io::Error as IOError;
-use std::io::{Read, Write};
-use std::net::TcpStream;
+use std::io::{ErrorKind, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
 
 use super::query_params::QueryParams;
 use super::url_parser::TrackerUrl;
 
+/// Timeout for connecting, reading and writing to a tracker, so an unresponsive tracker can be skipped.
+const TRACKER_TIMEOUT: Duration = Duration::from_secs(10);
+
 /// `HttpHandler` struct to make **HTTP** requests.
 ///
 /// To create a new `HttpHandler` use the method builder `new()`.
@@ -18,7 +22,7 @@ use super::url_parser::TrackerUrl;
 #[derive(Debug)]
 pub struct HttpHandler {
     tracker_url: TrackerUrl,
-    query_params: QueryParams,
+    query: String,
 }
 
 /// Posible `HttpHandler` errors
@@ -46,10 +50,12 @@ pub enum TlsStreamConnectError {
 impl HttpHandler {
     /// Builds a new `HttpHandler` from a **TrackerUrl** and a **QueryParams** passed by paramaters.
     pub fn new(tracker_url: TrackerUrl, query_params: QueryParams) -> Self {
-        Self {
-            tracker_url,
-            query_params,
-        }
+        Self::with_query(tracker_url, query_params.build())
+    }
+
+    /// Builds a new `HttpHandler` from a **TrackerUrl** and an already built **query** string, like the one of a scrape request.
+    pub fn with_query(tracker_url: TrackerUrl, query: String) -> Self {
+        Self { tracker_url, query }
     }
 
     /// Makes a **HTTPS** request to the tracker url.
@@ -99,21 +105,33 @@ impl HttpHandler {
 
     fn connect_tcp_stream(&self) -> Result<TcpStream, HttpHandlerError> {
         let connect_url = format!("{}:{}", self.tracker_url.host, self.tracker_url.port);
-        match TcpStream::connect(connect_url) {
-            Ok(stream) => Ok(stream),
-            Err(err) => Err(HttpHandlerError::TcpStreamConnectError(err)),
+        let addrs = connect_url
+            .to_socket_addrs()
+            .map_err(HttpHandlerError::TcpStreamConnectError)?;
+
+        let mut last_error = IOError::new(ErrorKind::NotFound, "Tracker host has no addresses");
+        for addr in addrs {
+            match TcpStream::connect_timeout(&addr, TRACKER_TIMEOUT) {
+                Ok(stream) => {
+                    stream
+                        .set_read_timeout(Some(TRACKER_TIMEOUT))
+                        .map_err(HttpHandlerError::TcpStreamConnectError)?;
+                    stream
+                        .set_write_timeout(Some(TRACKER_TIMEOUT))
+                        .map_err(HttpHandlerError::TcpStreamConnectError)?;
+                    return Ok(stream);
+                }
+                Err(err) => last_error = err,
+            }
         }
+        Err(HttpHandlerError::TcpStreamConnectError(last_error))
     }
 
     fn request_and_decode<A>(&self, mut stream: A) -> Result<Vec<u8>, HttpHandlerError>
     where
         A: Write + Read,
     {
-        let query_params = self.query_params.build();
-        let mut request = format!(
-            "GET /{}{} HTTP/1.1",
-            self.tracker_url.endpoint, query_params
-        );
+        let mut request = format!("GET /{}{} HTTP/1.1", self.tracker_url.endpoint, self.query);
         request.push_str("\r\n");
         request.push_str("Host: ");
         request.push_str(self.tracker_url.host.as_str());
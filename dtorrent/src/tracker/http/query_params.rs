@@ -1,20 +1,50 @@
 use url_encoder::url_encoder::encode;
 
+/// Events that can be sent to the tracker in an announce.
+///
+/// Their values are the ones used by the **UDP** tracker protocol.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum AnnounceEvent {
+    None = 0,
+    Completed = 1,
+    Started = 2,
+    Stopped = 3,
+}
+
+impl AnnounceEvent {
+    /// Returns the value of the `event` query param, or `None` for a regular announce.
+    pub fn as_query_value(&self) -> Option<&'static str> {
+        match self {
+            AnnounceEvent::None => None,
+            AnnounceEvent::Completed => Some("completed"),
+            AnnounceEvent::Started => Some("started"),
+            AnnounceEvent::Stopped => Some("stopped"),
+        }
+    }
+}
+
 /// `QueryParams` struct containing the query parameters information.
 ///
-/// To create a new `TrackerResponse` use the method builder `new()`.
+/// To create a new `QueryParams` use the method builder `new()`, and set the counters and event of the announce in its fields.
 ///
 /// To build the Query params string use the method 'build()'.
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct QueryParams {
-    info_hash: String,
-    client_port: u32,
-    info_length: i64,
-    client_peer_id: String,
+    pub info_hash: String,
+    pub client_port: u32,
+    pub client_peer_id: String,
+    pub uploaded: u64,
+    pub downloaded: u64,
+    pub left: u64,
+    pub event: AnnounceEvent,
+    pub key: u32,
+    pub tracker_id: Option<String>,
 }
 
 impl QueryParams {
-    /// Creates a new `QueryParams` from an **info_hash**, **client_port** and **info_lenght** passed by parameters.
+    /// Creates a new `QueryParams` from an **info_hash**, **client_port**, **info_lenght** and **client_peer_id** passed by parameters.
+    ///
+    /// It is a `started` announce with nothing downloaded nor uploaded.
     pub fn new(
         info_hash: String,
         client_port: u32,
@@ -24,20 +54,49 @@ impl QueryParams {
         QueryParams {
             info_hash,
             client_port,
-            info_length,
             client_peer_id,
+            uploaded: 0,
+            downloaded: 0,
+            left: info_length as u64,
+            event: AnnounceEvent::Started,
+            key: 0,
+            tracker_id: None,
         }
     }
 
     /// Builds the QueryParams string and returns it.
     pub fn build(&self) -> String {
-        format!(
-            "?info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&event=started",
+        let mut query = format!(
+            "?info_hash={}&peer_id={}&port={}&uploaded={}&downloaded={}&left={}&compact=1&key={:08x}",
             encode(self.info_hash.as_str()),
             self.client_peer_id,
             self.client_port,
-            self.info_length
-        )
+            self.uploaded,
+            self.downloaded,
+            self.left,
+            self.key
+        );
+
+        if let Some(event) = self.event.as_query_value() {
+            query.push_str(&format!("&event={}", event));
+        }
+        if let Some(tracker_id) = &self.tracker_id {
+            let hex_tracker_id: String = tracker_id
+                .bytes()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            query.push_str(&format!("&trackerid={}", encode(&hex_tracker_id)));
+        }
+        query
+    }
+
+    /// Builds the query string of a scrape request for the given info hashes (as hex strings) and returns it.
+    pub fn build_scrape(info_hashes: &[String]) -> String {
+        let params: Vec<String> = info_hashes
+            .iter()
+            .map(|info_hash| format!("info_hash={}", encode(info_hash.as_str())))
+            .collect();
+        format!("?{}", params.join("&"))
     }
 }
 
@@ -57,7 +116,7 @@ mod tests {
         assert_eq!(
             query_params.build(),
             format!(
-                "?info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&event=started",
+                "?info_hash={}&peer_id={}&port={}&uploaded=0&downloaded=0&left={}&compact=1&key=00000000&event=started",
                 encode(info_hash.as_str()),
                 peer_id,
                 client_port,
@@ -65,4 +124,49 @@ mod tests {
             )
         );
     }
+
+    #[test]
+    fn test_query_params_build_with_counters_and_tracker_id() {
+        let info_hash = "2c6b6858d61da9543d4231a71db4b1c9264b0685".to_string();
+        let peer_id = "LA_DEYMONETA_PAPA!!!".to_string();
+        let mut query_params = QueryParams::new(info_hash.clone(), 6969, 100, peer_id.clone());
+        query_params.uploaded = 10;
+        query_params.downloaded = 20;
+        query_params.left = 80;
+        query_params.event = AnnounceEvent::None;
+        query_params.key = 0xdeadbeef;
+        query_params.tracker_id = Some("a b".to_string());
+
+        assert_eq!(
+            query_params.build(),
+            format!(
+                "?info_hash={}&peer_id={}&port=6969&uploaded=10&downloaded=20&left=80&compact=1&key=deadbeef&trackerid=%61%20%62",
+                encode(info_hash.as_str()),
+                peer_id
+            )
+        );
+    }
+
+    #[test]
+    fn test_query_params_build_scrape() {
+        let info_hashes = vec!["2c6b".to_string(), "f834".to_string()];
+
+        assert_eq!(
+            QueryParams::build_scrape(&info_hashes),
+            "?info_hash=%2c%6b&info_hash=%f8%34"
+        );
+    }
+
+    #[test]
+    fn test_query_params_build_completed_and_stopped() {
+        let info_hash = "2c6b6858d61da9543d4231a71db4b1c9264b0685".to_string();
+        let peer_id = "LA_DEYMONETA_PAPA!!!".to_string();
+        let mut query_params = QueryParams::new(info_hash, 6969, 100, peer_id);
+
+        query_params.event = AnnounceEvent::Completed;
+        assert!(query_params.build().ends_with("&event=completed"));
+
+        query_params.event = AnnounceEvent::Stopped;
+        assert!(query_params.build().ends_with("&event=stopped"));
+    }
 }
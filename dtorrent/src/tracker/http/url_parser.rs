@@ -1,7 +1,7 @@
 /// `TrackerUrl` struct containing a tracker url information.
 ///
 /// To create a new `TrackerUrl` use the method builder `parse()`.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct TrackerUrl {
     pub protocol: ConnectionProtocol,
     pub host: String,
@@ -10,10 +10,11 @@ pub struct TrackerUrl {
 }
 
 /// Posible `TrackerUrl` Connection Protocol values.
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub enum ConnectionProtocol {
     Http,
     Https,
+    Udp,
 }
 
 /// Posible `TrackerUrl` Errors.
@@ -33,11 +34,12 @@ impl TrackerUrl {
     /// - the url format is invalid.
     /// - The url connection protocol is unsupported.
     /// - the url port number is not a number.
+    /// - the url is an **UDP** url without a port number.
     pub fn parse(url: &str) -> Result<Self, TrackerUrlError> {
         let (url_without_protocol, protocol) = Self::identify_and_remove_protocol(url)?;
 
         let (url_without_endpoint, endpoint) =
-            Self::identify_and_remove_endpoint(&url_without_protocol)?;
+            Self::identify_and_remove_endpoint(&url_without_protocol, &protocol)?;
 
         let host = Self::identify_host(&url_without_endpoint)?;
 
@@ -47,6 +49,8 @@ impl TrackerUrl {
             match protocol {
                 ConnectionProtocol::Https => 443,
                 ConnectionProtocol::Http => 80,
+                // UDP trackers do not have a default port.
+                ConnectionProtocol::Udp => return Err(TrackerUrlError::InvalidTrackerURL),
             }
         };
 
@@ -58,6 +62,28 @@ impl TrackerUrl {
         })
     }
 
+    /// Returns the scrape url of the tracker, derived from its announce url.
+    ///
+    /// For **HTTP** trackers the last path component of the endpoint must start with `announce`, which is replaced by `scrape`.
+    /// Otherwise the tracker does not support scraping and it returns `None`.
+    /// **UDP** trackers always support scraping with the same url.
+    pub fn scrape_url(&self) -> Option<TrackerUrl> {
+        if self.protocol == ConnectionProtocol::Udp {
+            return Some(self.clone());
+        }
+
+        let (path, last_component) = match self.endpoint.rsplit_once('/') {
+            Some((path, last_component)) => (format!("{}/", path), last_component),
+            None => (String::new(), self.endpoint.as_str()),
+        };
+        let suffix = last_component.strip_prefix("announce")?;
+
+        Some(TrackerUrl {
+            endpoint: format!("{}scrape{}", path, suffix),
+            ..self.clone()
+        })
+    }
+
     fn identify_and_remove_protocol(
         url: &str,
     ) -> Result<(String, ConnectionProtocol), TrackerUrlError> {
@@ -69,6 +95,8 @@ impl TrackerUrl {
                     ConnectionProtocol::Http
                 } else if protocol_name == "https" {
                     ConnectionProtocol::Https
+                } else if protocol_name == "udp" {
+                    ConnectionProtocol::Udp
                 } else {
                     return Err(TrackerUrlError::UnsupportedConnectionProtocol);
                 }
@@ -82,7 +110,10 @@ impl TrackerUrl {
         }
     }
 
-    fn identify_and_remove_endpoint(url: &str) -> Result<(String, String), TrackerUrlError> {
+    fn identify_and_remove_endpoint(
+        url: &str,
+        protocol: &ConnectionProtocol,
+    ) -> Result<(String, String), TrackerUrlError> {
         let mut splitted_url = url.split('/');
 
         let url_without_endpoint = match splitted_url.next() {
@@ -92,6 +123,10 @@ impl TrackerUrl {
 
         match splitted_url.next() {
             Some(endpoint) => Ok((url_without_endpoint.to_string(), endpoint.to_string())),
+            // UDP urls do not need an endpoint since it is not used by the protocol.
+            None if *protocol == ConnectionProtocol::Udp => {
+                Ok((url_without_endpoint.to_string(), String::new()))
+            }
             None => Err(TrackerUrlError::InvalidTrackerURL),
         }
     }
@@ -162,9 +197,73 @@ mod tests {
         assert_eq!("ann", parsed_tracker_url.endpoint);
     }
 
+    #[test]
+    fn test_scrape_url() {
+        let tracker_url = TrackerUrl::parse("http://www.example.org:1337/announce").unwrap();
+
+        let scrape_url = tracker_url.scrape_url().unwrap();
+
+        assert_eq!("scrape", scrape_url.endpoint);
+        assert_eq!("www.example.org", scrape_url.host);
+        assert_eq!(1337, scrape_url.port);
+    }
+
+    #[test]
+    fn test_scrape_url_keeps_suffix() {
+        let tracker_url = TrackerUrl::parse("http://www.example.org/announce.php").unwrap();
+
+        assert_eq!("scrape.php", tracker_url.scrape_url().unwrap().endpoint);
+    }
+
+    #[test]
+    fn test_scrape_url_not_supported() {
+        let tracker_url = TrackerUrl::parse("http://www.example.org/ann").unwrap();
+
+        assert_eq!(None, tracker_url.scrape_url());
+    }
+
+    #[test]
+    fn test_udp_scrape_url() {
+        let tracker_url = TrackerUrl::parse("udp://tracker.example.org:1337").unwrap();
+
+        assert_eq!(Some(tracker_url.clone()), tracker_url.scrape_url());
+    }
+
+    #[test]
+    fn test_udp_with_endpoint() {
+        let url = String::from("udp://tracker.example.org:1337/announce");
+        let parsed_tracker_url = TrackerUrl::parse(&url).unwrap();
+
+        assert_eq!(ConnectionProtocol::Udp, parsed_tracker_url.protocol);
+        assert_eq!("tracker.example.org", parsed_tracker_url.host);
+        assert_eq!(1337, parsed_tracker_url.port);
+        assert_eq!("announce", parsed_tracker_url.endpoint);
+    }
+
+    #[test]
+    fn test_udp_without_endpoint() {
+        let url = String::from("udp://tracker.example.org:1337");
+        let parsed_tracker_url = TrackerUrl::parse(&url).unwrap();
+
+        assert_eq!(ConnectionProtocol::Udp, parsed_tracker_url.protocol);
+        assert_eq!("tracker.example.org", parsed_tracker_url.host);
+        assert_eq!(1337, parsed_tracker_url.port);
+        assert_eq!("", parsed_tracker_url.endpoint);
+    }
+
+    #[test]
+    fn test_udp_without_port() {
+        let url = String::from("udp://tracker.example.org/announce");
+
+        assert_eq!(
+            TrackerUrl::parse(&url),
+            Err(TrackerUrlError::InvalidTrackerURL)
+        );
+    }
+
     #[test]
     fn test_invalid_protocol() {
-        let url = String::from("udp://www.example.org:1337/ann");
+        let url = String::from("wss://www.example.org:1337/ann");
 
         assert_eq!(
             TrackerUrl::parse(&url),
@@ -1,3 +1,5 @@
 pub mod http;
+pub mod scrape_response;
 pub mod tracker_handler;
 pub mod tracker_response;
+pub mod udp;
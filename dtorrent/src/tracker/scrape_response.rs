@@ -0,0 +1,191 @@
+use std::collections::HashMap;
+
+use bencoder::bencode::{Bencode, BencodeError};
+
+/// `ScrapeResponse` struct containing a tracker scrape response.
+///
+/// `files` maps the info hash (as an hex string) of each scraped torrent to its statistics.
+///
+/// To create a new `ScrapeResponse` from an **HTTP** tracker response use the method builder `from()`.
+#[derive(Debug, Default)]
+pub struct ScrapeResponse {
+    pub files: HashMap<String, ScrapeFile>,
+}
+
+/// Statistics of a single torrent in a scrape response.
+///
+/// - `complete`: number of peers with the entire file (seeders),
+/// - `downloaded`: number of times the tracker registered a completion,
+/// - `incomplete`: number of non-seeder peers (leechers).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScrapeFile {
+    pub complete: i64,
+    pub downloaded: i64,
+    pub incomplete: i64,
+}
+
+/// Posible `ScrapeResponse` errors.
+#[derive(Debug)]
+pub enum FromScrapeResponseError {
+    DecodeResponseError(BencodeError),
+    FailureReason(String),
+    InvalidFiles,
+    InvalidFile,
+    NotADict,
+}
+
+impl ScrapeResponse {
+    /// Builds a new `ScrapeResponse` decoding a bencoded Vec<u8> cointaining the tracker's scrape response.
+    ///
+    /// It returns an `FromScrapeResponseError` if:
+    /// - There was a problem decoding the response.
+    /// - The bencoded response is not a dict.
+    /// - The tracker responded with a failure reason.
+    /// - The `files` dict or one of its entries is invalid.
+    pub fn from(response: Vec<u8>) -> Result<ScrapeResponse, FromScrapeResponseError> {
+        let decoded_res = match Bencode::decode(&response) {
+            Ok(decoded_res) => decoded_res,
+            Err(err) => return Err(FromScrapeResponseError::DecodeResponseError(err)),
+        };
+
+        let d = match decoded_res {
+            Bencode::BDict(d) => d,
+            _ => return Err(FromScrapeResponseError::NotADict),
+        };
+
+        let mut files = HashMap::new();
+        for (k, v) in d.iter() {
+            if k == b"files" {
+                files = Self::create_files(v)?;
+            } else if k == b"failure reason" {
+                let reason = match v {
+                    Bencode::BString(s) => String::from_utf8_lossy(s).to_string(),
+                    _ => String::new(),
+                };
+                return Err(FromScrapeResponseError::FailureReason(reason));
+            }
+        }
+
+        Ok(ScrapeResponse { files })
+    }
+
+    fn create_files(
+        bencode: &Bencode,
+    ) -> Result<HashMap<String, ScrapeFile>, FromScrapeResponseError> {
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(FromScrapeResponseError::InvalidFiles),
+        };
+
+        let mut files = HashMap::new();
+        for (info_hash, file) in d.iter() {
+            let hex_info_hash: String = info_hash
+                .iter()
+                .map(|byte| format!("{:02x}", byte))
+                .collect();
+            files.insert(hex_info_hash, Self::create_file(file)?);
+        }
+        Ok(files)
+    }
+
+    fn create_file(bencode: &Bencode) -> Result<ScrapeFile, FromScrapeResponseError> {
+        let d = match bencode {
+            Bencode::BDict(d) => d,
+            _ => return Err(FromScrapeResponseError::InvalidFile),
+        };
+
+        let mut file = ScrapeFile {
+            complete: 0,
+            downloaded: 0,
+            incomplete: 0,
+        };
+        for (k, v) in d.iter() {
+            let value = match v {
+                Bencode::BNumber(n) => *n,
+                _ => continue,
+            };
+            if k == b"complete" {
+                file.complete = value;
+            } else if k == b"downloaded" {
+                file.downloaded = value;
+            } else if k == b"incomplete" {
+                file.incomplete = value;
+            }
+        }
+        Ok(file)
+    }
+
+    /// Adds the statistics of another `ScrapeResponse` to this one.
+    pub fn merge(&mut self, other: ScrapeResponse) {
+        self.files.extend(other.files);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_from_scrape_response() {
+        let mut file = BTreeMap::new();
+        file.insert(b"complete".to_vec(), Bencode::BNumber(5));
+        file.insert(b"downloaded".to_vec(), Bencode::BNumber(50));
+        file.insert(b"incomplete".to_vec(), Bencode::BNumber(10));
+
+        let mut files = BTreeMap::new();
+        files.insert(vec![0x2c; 20], Bencode::BDict(file));
+
+        let mut dict = BTreeMap::new();
+        dict.insert(b"files".to_vec(), Bencode::BDict(files));
+
+        let response = ScrapeResponse::from(Bencode::encode(&dict)).unwrap();
+
+        assert_eq!(
+            response.files.get(&"2c".repeat(20)),
+            Some(&ScrapeFile {
+                complete: 5,
+                downloaded: 50,
+                incomplete: 10
+            })
+        );
+    }
+
+    #[test]
+    fn test_from_scrape_response_without_files() {
+        let dict: BTreeMap<Vec<u8>, Bencode> = BTreeMap::new();
+
+        let response = ScrapeResponse::from(Bencode::encode(&dict)).unwrap();
+
+        assert!(response.files.is_empty());
+    }
+
+    #[test]
+    fn test_from_scrape_response_failure_reason() {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"failure reason".to_vec(),
+            Bencode::BString(b"scrape disabled".to_vec()),
+        );
+
+        let response = ScrapeResponse::from(Bencode::encode(&dict));
+
+        assert!(matches!(
+            response,
+            Err(FromScrapeResponseError::FailureReason(reason)) if reason == "scrape disabled"
+        ));
+    }
+
+    #[test]
+    fn test_from_scrape_response_invalid_files() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"files".to_vec(), Bencode::BNumber(1));
+
+        let response = ScrapeResponse::from(Bencode::encode(&dict));
+
+        assert!(matches!(
+            response,
+            Err(FromScrapeResponseError::InvalidFiles)
+        ));
+    }
+}
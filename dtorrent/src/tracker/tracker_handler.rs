@@ -1,72 +1,209 @@
 use super::http::http_handler::{HttpHandler, HttpHandlerError};
-use super::http::query_params::QueryParams;
+use super::http::query_params::{AnnounceEvent, QueryParams};
 use super::http::url_parser::{ConnectionProtocol, TrackerUrl, TrackerUrlError};
+use super::scrape_response::{FromScrapeResponseError, ScrapeResponse};
 use super::tracker_response::FromTrackerResponseError;
+use super::udp::udp_handler::{UdpHandler, UdpHandlerError};
 use crate::torrent_parser::torrent::Torrent;
 use crate::tracker::tracker_response::TrackerResponse;
+use rand::seq::SliceRandom;
+use std::collections::HashMap;
 
-/// `TrackerHandler` struct for communicating to a bt tracker.
+/// Maximum number of info hashes sent in a single **HTTP** scrape request, to keep the url short.
+const MAX_HTTP_SCRAPE_HASHES: usize = 50;
+
+/// `TrackerHandler` struct for communicating to the bt trackers of a torrent.
+///
+/// The trackers are grouped in tiers as BEP 12 describes: they are tried in tier order,
+/// the trackers within each tier are shuffled once, and a tracker that responds is moved to the front of its tier.
 ///
 /// To create a new `TrackerHandler` use the method builder `new()`.
 ///
 /// To get the tracker's peer list use the method `get_peer_list()`.
+///
+/// To get the seeders, leechers and completed counts of torrents without announcing use the method `scrape()`.
+///
+/// The same random `key` is sent in every announce, and the `tracker id` a tracker returns is sent back to it in the next announces.
 #[derive(Debug)]
 pub struct TrackerHandler {
     pub torrent: Torrent,
-    pub tracker_url: TrackerUrl,
+    pub tiers: Vec<Vec<TrackerUrl>>,
     pub client_port: u32,
     client_peer_id: String,
+    key: u32,
+    tracker_ids: HashMap<TrackerUrl, String>,
+    udp_handlers: HashMap<TrackerUrl, UdpHandler>,
 }
 /// Posible `TrackerHandler` errors.
 #[derive(Debug)]
 pub enum TrackerHandlerError {
     HttpHandlerError(HttpHandlerError),
+    UdpHandlerError(UdpHandlerError),
     FromTrackerResponseError(FromTrackerResponseError),
+    FromScrapeResponseError(FromScrapeResponseError),
+    ScrapeNotSupported,
     UrlParseError(TrackerUrlError),
+    AllTrackersFailed(Vec<TrackerHandlerError>),
 }
 
 impl TrackerHandler {
     /// Builds a new `TrackerHandler` from a **Torrent** and a **client_port** passed by paramaters.
     ///
+    /// Tracker urls that can not be parsed are ignored.
+    ///
     /// It returns an `TrackerHandlerError` if:
-    /// - There was an error parsing the torrent's announce_url.
+    /// - None of the torrent's tracker urls could be parsed.
     pub fn new(
         torrent: Torrent,
         client_port: u32,
         client_peer_id: String,
     ) -> Result<Self, TrackerHandlerError> {
-        let tracker_url = match TrackerUrl::parse(torrent.announce_url.as_str()) {
-            Ok(url) => url,
-            Err(err) => return Err(TrackerHandlerError::UrlParseError(err)),
-        };
+        let mut tiers = Vec::new();
+        let mut last_error = TrackerUrlError::InvalidTrackerURL;
+
+        for tier in torrent.announce_tiers() {
+            let mut tier_urls = Vec::new();
+            for url in tier {
+                match TrackerUrl::parse(url.as_str()) {
+                    Ok(url) => tier_urls.push(url),
+                    Err(err) => last_error = err,
+                }
+            }
+            if !tier_urls.is_empty() {
+                tier_urls.shuffle(&mut rand::thread_rng());
+                tiers.push(tier_urls);
+            }
+        }
+
+        if tiers.is_empty() {
+            return Err(TrackerHandlerError::UrlParseError(last_error));
+        }
 
         Ok(Self {
             torrent,
-            tracker_url,
+            tiers,
             client_port,
             client_peer_id,
+            key: rand::random(),
+            tracker_ids: HashMap::new(),
+            udp_handlers: HashMap::new(),
         })
     }
 
-    /// Gets the tracker's peers list.
+    /// Announces an **event** with the **uploaded**, **downloaded** and **left** byte counters of the torrent, and gets the tracker's peers list.
+    ///
+    /// The trackers are tried in tier order until one of them responds. The tracker that responded is moved to the front of its tier.
     ///
     /// On success it returns a `TrackerResponse` struct cointaining the tracker's response.
     ///
-    /// It returns an `TrackerHandlerError` if:
-    /// - There was a problem writing to the tracker.
-    /// - There was a problem reading the tracker's response.
-    /// - There was a problem decoding the parser response.
-    pub fn get_peers_list(&self) -> Result<TrackerResponse, TrackerHandlerError> {
-        let query_params = QueryParams::new(
+    /// It returns an `TrackerHandlerError::AllTrackersFailed` with the error of each tracker if none of them responded.
+    pub fn get_peers_list(
+        &mut self,
+        event: AnnounceEvent,
+        uploaded: u64,
+        downloaded: u64,
+        left: u64,
+    ) -> Result<TrackerResponse, TrackerHandlerError> {
+        let mut query_params = QueryParams::new(
             self.torrent.info_hash.clone(),
             self.client_port,
             self.torrent.info.length,
             self.client_peer_id.clone(),
         );
+        query_params.uploaded = uploaded;
+        query_params.downloaded = downloaded;
+        query_params.left = left;
+        query_params.event = event;
+        query_params.key = self.key;
+
+        let mut errors = Vec::new();
+
+        for tier_index in 0..self.tiers.len() {
+            for url_index in 0..self.tiers[tier_index].len() {
+                let tracker_url = self.tiers[tier_index][url_index].clone();
+
+                query_params.tracker_id = self.tracker_ids.get(&tracker_url).cloned();
+
+                match self.announce(tracker_url.clone(), query_params.clone()) {
+                    Ok(response) => {
+                        if let Some(tracker_id) = &response.tracker_id {
+                            self.tracker_ids.insert(tracker_url, tracker_id.clone());
+                        }
+                        self.promote(tier_index, url_index);
+                        return Ok(response);
+                    }
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+
+        Err(TrackerHandlerError::AllTrackersFailed(errors))
+    }
+
+    /// Scrapes the trackers for the given info hashes (as hex strings), they do not need to belong to this handler's torrent.
+    ///
+    /// The trackers are tried in tier order until one of them responds, like in `get_peers_list()`.
+    /// Several info hashes are sent in each request.
+    ///
+    /// On success it returns a `ScrapeResponse` struct cointaining the statistics of each torrent the tracker knows.
+    ///
+    /// It returns an `TrackerHandlerError::AllTrackersFailed` with the error of each tracker if none of them responded.
+    pub fn scrape(
+        &mut self,
+        info_hashes: &[String],
+    ) -> Result<ScrapeResponse, TrackerHandlerError> {
+        let mut errors = Vec::new();
+
+        for tier_index in 0..self.tiers.len() {
+            for url_index in 0..self.tiers[tier_index].len() {
+                let tracker_url = self.tiers[tier_index][url_index].clone();
+
+                match self.scrape_tracker(tracker_url, info_hashes) {
+                    Ok(response) => {
+                        self.promote(tier_index, url_index);
+                        return Ok(response);
+                    }
+                    Err(err) => errors.push(err),
+                }
+            }
+        }
+
+        Err(TrackerHandlerError::AllTrackersFailed(errors))
+    }
+
+    /// Moves a tracker to the front of its tier.
+    fn promote(&mut self, tier_index: usize, url_index: usize) {
+        let tracker_url = self.tiers[tier_index].remove(url_index);
+        self.tiers[tier_index].insert(0, tracker_url);
+    }
+
+    /// Announces to a single tracker.
+    ///
+    /// **UDP** trackers keep their `UdpHandler` between announces so the connection ID can be reused.
+    ///
+    /// It returns an `TrackerHandlerError` if:
+    /// - There was a problem writing to the tracker.
+    /// - There was a problem reading the tracker's response.
+    /// - There was a problem decoding the parser response.
+    fn announce(
+        &mut self,
+        tracker_url: TrackerUrl,
+        query_params: QueryParams,
+    ) -> Result<TrackerResponse, TrackerHandlerError> {
+        let protocol = tracker_url.protocol.clone();
+        if protocol == ConnectionProtocol::Udp {
+            let udp_handler = self
+                .udp_handlers
+                .entry(tracker_url.clone())
+                .or_insert_with(|| UdpHandler::new(tracker_url));
+            return udp_handler
+                .announce(&query_params)
+                .map_err(TrackerHandlerError::UdpHandlerError);
+        }
 
-        let http_handler = HttpHandler::new(self.tracker_url.clone(), query_params);
+        let http_handler = HttpHandler::new(tracker_url, query_params);
 
-        let response = if self.tracker_url.protocol == ConnectionProtocol::Https {
+        let response = if protocol == ConnectionProtocol::Https {
             match http_handler.https_request() {
                 Ok(response) => response,
                 Err(err) => return Err(TrackerHandlerError::HttpHandlerError(err)),
@@ -82,11 +219,60 @@ impl TrackerHandler {
             Err(err) => Err(TrackerHandlerError::FromTrackerResponseError(err)),
         }
     }
+
+    /// Scrapes a single tracker.
+    ///
+    /// It returns an `TrackerHandlerError` if:
+    /// - The tracker does not support scraping.
+    /// - There was a problem writing to the tracker.
+    /// - There was a problem reading the tracker's response.
+    /// - There was a problem decoding the scrape response.
+    fn scrape_tracker(
+        &mut self,
+        tracker_url: TrackerUrl,
+        info_hashes: &[String],
+    ) -> Result<ScrapeResponse, TrackerHandlerError> {
+        if tracker_url.protocol == ConnectionProtocol::Udp {
+            let udp_handler = self
+                .udp_handlers
+                .entry(tracker_url.clone())
+                .or_insert_with(|| UdpHandler::new(tracker_url));
+            return udp_handler
+                .scrape(info_hashes)
+                .map_err(TrackerHandlerError::UdpHandlerError);
+        }
+
+        let scrape_url = tracker_url
+            .scrape_url()
+            .ok_or(TrackerHandlerError::ScrapeNotSupported)?;
+
+        let mut scrape_response = ScrapeResponse::default();
+        for batch in info_hashes.chunks(MAX_HTTP_SCRAPE_HASHES) {
+            let http_handler =
+                HttpHandler::with_query(scrape_url.clone(), QueryParams::build_scrape(batch));
+
+            let response = if scrape_url.protocol == ConnectionProtocol::Https {
+                http_handler.https_request()
+            } else {
+                http_handler.http_request()
+            }
+            .map_err(TrackerHandlerError::HttpHandlerError)?;
+
+            scrape_response.merge(
+                ScrapeResponse::from(response)
+                    .map_err(TrackerHandlerError::FromScrapeResponseError)?,
+            );
+        }
+        Ok(scrape_response)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::torrent_parser::info::Info;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::thread;
 
     use super::*;
 
@@ -99,9 +285,13 @@ mod tests {
         let test_port = 6969;
         let test_peer_id = "LA_DEYMONETA_PAPA!!!".to_string();
 
-        let tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
+        let mut tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
 
-        assert!(!tracker_handler.get_peers_list().unwrap().peers.is_empty());
+        assert!(!tracker_handler
+            .get_peers_list(AnnounceEvent::Started, 0, 0, 100)
+            .unwrap()
+            .peers
+            .is_empty());
     }
 
     #[test]
@@ -113,9 +303,204 @@ mod tests {
         let test_port = 6969;
         let test_peer_id = "LA_DEYMONETA_PAPA!!!".to_string();
 
-        let tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
+        let mut tracker_handler = TrackerHandler::new(torrent, test_port, test_peer_id).unwrap();
+
+        assert!(!tracker_handler
+            .get_peers_list(AnnounceEvent::Started, 0, 0, 100)
+            .unwrap()
+            .peers
+            .is_empty());
+    }
+
+    #[test]
+    fn test_new_groups_trackers_in_tiers() {
+        let mut torrent = create_test_torrent(
+            "http://example.com/announce",
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+        );
+        torrent.announce_list = vec![
+            vec![
+                "http://tier1-a.com/announce".to_string(),
+                "http://tier1-b.com/announce".to_string(),
+            ],
+            vec!["http://tier2.com/announce".to_string()],
+        ];
+
+        let tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
+
+        assert_eq!(tracker_handler.tiers.len(), 2);
+        assert_eq!(tracker_handler.tiers[0].len(), 2);
+        assert!(tracker_handler.tiers[0]
+            .iter()
+            .any(|url| url.host == "tier1-a.com"));
+        assert!(tracker_handler.tiers[0]
+            .iter()
+            .any(|url| url.host == "tier1-b.com"));
+        assert_eq!(tracker_handler.tiers[1][0].host, "tier2.com");
+    }
+
+    #[test]
+    fn test_new_ignores_invalid_urls() {
+        let mut torrent = create_test_torrent(
+            "http://example.com/announce",
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+        );
+        torrent.announce_list = vec![
+            vec!["invalid".to_string()],
+            vec!["http://tier2.com/announce".to_string()],
+        ];
+
+        let tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
+
+        assert_eq!(tracker_handler.tiers.len(), 1);
+        assert_eq!(tracker_handler.tiers[0][0].host, "tier2.com");
+    }
+
+    #[test]
+    fn test_new_without_valid_urls() {
+        let torrent = create_test_torrent("invalid", "2c6b6858d61da9543d4231a71db4b1c9264b0685");
+
+        assert!(TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).is_err());
+    }
+
+    #[test]
+    fn test_failover_to_next_tier_and_promote() {
+        let dead_tracker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let dead_port = dead_tracker.local_addr().unwrap().port();
+        drop(dead_tracker);
+
+        let live_tracker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let live_port = live_tracker.local_addr().unwrap().port();
+        let join = thread::spawn(move || {
+            let (mut stream, _) = live_tracker.accept().unwrap();
+            let mut buffer = [0; 1024];
+            let _ = stream.read(&mut buffer).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\n\r\nd8:intervali1800e5:peerslee")
+                .unwrap();
+        });
+
+        let mut torrent = create_test_torrent(
+            "http://example.com/announce",
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+        );
+        torrent.announce_list = vec![
+            vec![format!("http://127.0.0.1:{}/announce", dead_port)],
+            vec![
+                format!("http://127.0.0.1:{}/announce", dead_port),
+                format!("http://127.0.0.1:{}/announce", live_port),
+            ],
+        ];
+
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
+        let response = tracker_handler
+            .get_peers_list(AnnounceEvent::Started, 0, 0, 100)
+            .unwrap();
+        join.join().unwrap();
+
+        assert_eq!(response.interval, 1800);
+        assert_eq!(tracker_handler.tiers[1][0].port, live_port as u32);
+    }
+
+    #[test]
+    fn test_tracker_id_and_key_are_sent_back() {
+        let tracker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tracker.local_addr().unwrap().port();
+        let join = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = tracker.accept().unwrap();
+                let mut buffer = [0; 1024];
+                let size = stream.read(&mut buffer).unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..size]).to_string());
+                stream
+                    .write_all(
+                        b"HTTP/1.1 200 OK\r\n\r\nd8:intervali1800e5:peers0:10:tracker id2:ide",
+                    )
+                    .unwrap();
+            }
+            requests
+        });
+
+        let torrent = create_test_torrent(
+            &format!("http://127.0.0.1:{}/announce", port),
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+        );
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
+        tracker_handler
+            .get_peers_list(AnnounceEvent::Started, 0, 0, 100)
+            .unwrap();
+        tracker_handler
+            .get_peers_list(AnnounceEvent::Completed, 5, 100, 0)
+            .unwrap();
+        let requests = join.join().unwrap();
+
+        let key = format!("&key={:08x}", tracker_handler.key);
+        assert!(requests[0].contains(&key));
+        assert!(requests[0].contains("&event=started"));
+        assert!(!requests[0].contains("trackerid"));
+        assert!(requests[1].contains(&key));
+        assert!(requests[1].contains("&uploaded=5&downloaded=100&left=0"));
+        assert!(requests[1].contains("&event=completed"));
+        assert!(requests[1].contains("&trackerid=%69%64"));
+    }
+
+    #[test]
+    fn test_scrape_batches_info_hashes() {
+        let tracker = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = tracker.local_addr().unwrap().port();
+        let join = thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..2 {
+                let (mut stream, _) = tracker.accept().unwrap();
+                let mut buffer = [0; 8192];
+                let size = stream.read(&mut buffer).unwrap();
+                requests.push(String::from_utf8_lossy(&buffer[..size]).to_string());
+                let mut response = b"HTTP/1.1 200 OK\r\n\r\nd5:filesd20:".to_vec();
+                response.extend([0x2c; 20]);
+                response.extend(b"d8:completei5e10:downloadedi50e10:incompletei10eeee");
+                stream.write_all(&response).unwrap();
+            }
+            requests
+        });
+
+        let torrent = create_test_torrent(
+            &format!("http://127.0.0.1:{}/announce", port),
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+        );
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
+        let info_hashes = vec!["2c".repeat(20); MAX_HTTP_SCRAPE_HASHES + 1];
+        let response = tracker_handler.scrape(&info_hashes).unwrap();
+        let requests = join.join().unwrap();
+
+        assert!(requests[0].starts_with("GET /scrape?info_hash="));
+        assert_eq!(
+            requests[0].matches("info_hash=").count(),
+            MAX_HTTP_SCRAPE_HASHES
+        );
+        assert_eq!(requests[1].matches("info_hash=").count(), 1);
+        assert_eq!(response.files.get(&"2c".repeat(20)).unwrap().complete, 5);
+    }
+
+    #[test]
+    fn test_scrape_not_supported() {
+        let torrent = create_test_torrent(
+            "http://127.0.0.1:1/ann",
+            "2c6b6858d61da9543d4231a71db4b1c9264b0685",
+        );
+        let mut tracker_handler =
+            TrackerHandler::new(torrent, 6969, "LA_DEYMONETA_PAPA!!!".to_string()).unwrap();
 
-        assert!(!tracker_handler.get_peers_list().unwrap().peers.is_empty());
+        let errors = match tracker_handler.scrape(&["2c".repeat(20)]) {
+            Err(TrackerHandlerError::AllTrackersFailed(errors)) => errors,
+            _ => panic!("Scrape should fail"),
+        };
+        assert!(matches!(errors[0], TrackerHandlerError::ScrapeNotSupported));
     }
 
     // Auxiliar
@@ -126,10 +511,12 @@ mod tests {
             name: "test".to_string(),
             piece_length: 100,
             pieces: vec![],
+            files: None,
         };
 
         Torrent {
             announce_url: announce.to_string(),
+            announce_list: vec![],
             info,
             info_hash: info_hash.to_string(),
         }
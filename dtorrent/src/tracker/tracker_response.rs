@@ -1,3 +1,5 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
 use bencoder::bencode::{Bencode, BencodeError};
 
 use crate::peer::bt_peer::{BtPeer, BtPeerError};
@@ -5,9 +7,13 @@ use crate::peer::bt_peer::{BtPeer, BtPeerError};
 /// `TrackerResponse` struct containing a tracker response.
 ///
 /// To create a new `TrackerResponse` use the method builder `from()`.
+///
+/// `peers` contains both the IPv4 peers of the `peers` key and the IPv6 peers of the `peers6` key.
 #[derive(Debug)]
 pub struct TrackerResponse {
     pub interval: i64,
+    pub min_interval: Option<i64>,
+    pub tracker_id: Option<String>,
     pub complete: i64,
     pub incomplete: i64,
     pub peers: Vec<BtPeer>,
@@ -18,9 +24,12 @@ pub struct TrackerResponse {
 pub enum FromTrackerResponseError {
     DecodeResponseError(BencodeError),
     InvalidInterval,
+    InvalidMinInterval,
+    InvalidTrackerId,
     InvalidComplete,
     InvalidIncomplete,
     InvalidPeers(BtPeerError),
+    InvalidPeers6,
     NotADict,
     NotAList,
 }
@@ -33,11 +42,16 @@ impl TrackerResponse {
     /// - The bencoded response is not a dict.
     /// - The bencoded peers are not a list.
     /// - The tracker response interval is invalid.
+    /// - The tracker response min interval is invalid.
+    /// - The tracker response tracker id is invalid.
     /// - The tracker response complete is invalid.
     /// - The tracker response incomplete is invalid.
     /// - The tracker response peers are invalid.
+    /// - The tracker response peers6 is not a compact string.
     pub fn from(response: Vec<u8>) -> Result<TrackerResponse, FromTrackerResponseError> {
         let mut interval = 0;
+        let mut min_interval = None;
+        let mut tracker_id = None;
         let mut complete = 0;
         let mut incomplete = 0;
         let mut peers = Vec::new();
@@ -55,17 +69,25 @@ impl TrackerResponse {
         for (k, v) in d.iter() {
             if k == b"interval" {
                 interval = Self::create_interval(v)?;
+            } else if k == b"min interval" {
+                min_interval = Some(Self::create_min_interval(v)?);
+            } else if k == b"tracker id" {
+                tracker_id = Some(Self::create_tracker_id(v)?);
             } else if k == b"complete" {
                 complete = Self::create_complete(v)?;
             } else if k == b"incomplete" {
                 incomplete = Self::create_incomplete(v)?;
             } else if k == b"peers" {
-                peers = Self::create_peers(v)?;
+                peers.extend(Self::create_peers(v)?);
+            } else if k == b"peers6" {
+                peers.extend(Self::create_peers6(v)?);
             }
         }
 
         Ok(TrackerResponse {
             interval,
+            min_interval,
+            tracker_id,
             complete,
             incomplete,
             peers,
@@ -81,6 +103,21 @@ impl TrackerResponse {
         Ok(interval)
     }
 
+    fn create_min_interval(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
+        match bencode {
+            Bencode::BNumber(n) => Ok(*n),
+            _ => Err(FromTrackerResponseError::InvalidMinInterval),
+        }
+    }
+
+    fn create_tracker_id(bencode: &Bencode) -> Result<String, FromTrackerResponseError> {
+        match bencode {
+            Bencode::BString(s) => String::from_utf8(s.to_vec())
+                .map_err(|_| FromTrackerResponseError::InvalidTrackerId),
+            _ => Err(FromTrackerResponseError::InvalidTrackerId),
+        }
+    }
+
     fn create_complete(bencode: &Bencode) -> Result<i64, FromTrackerResponseError> {
         let complete = match bencode {
             Bencode::BNumber(n) => *n,
@@ -102,11 +139,18 @@ impl TrackerResponse {
     fn create_peers(bencode: &Bencode) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
         match bencode {
             Bencode::BList(list) => Self::create_peers_from_dict(list),
-            Bencode::BString(str) => Self::create_peers_from_bstring(str),
+            Bencode::BString(str) => Ok(Self::create_peers_from_bstring(str)),
             _ => Err(FromTrackerResponseError::NotAList),
         }
     }
 
+    fn create_peers6(bencode: &Bencode) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
+        match bencode {
+            Bencode::BString(str) => Ok(Self::create_peers6_from_bstring(str)),
+            _ => Err(FromTrackerResponseError::InvalidPeers6),
+        }
+    }
+
     fn create_peers_from_dict(list: &[Bencode]) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
         let mut peers = Vec::new();
 
@@ -118,16 +162,31 @@ impl TrackerResponse {
         Ok(peers)
     }
 
-    fn create_peers_from_bstring(bstring: &[u8]) -> Result<Vec<BtPeer>, FromTrackerResponseError> {
-        Ok(bstring
-            .chunks(6)
+    /// Builds the peers from a compact peer list, where each peer is 4 bytes of ip and 2 bytes of port.
+    pub fn create_peers_from_bstring(bstring: &[u8]) -> Vec<BtPeer> {
+        bstring
+            .chunks_exact(6)
             .map(|chunk| {
-                let ip = format!("{}.{}.{}.{}", chunk[0], chunk[1], chunk[2], chunk[3]);
+                let ip = Ipv4Addr::new(chunk[0], chunk[1], chunk[2], chunk[3]);
                 let port = u16::from_be_bytes([chunk[4], chunk[5]]) as i64;
 
-                BtPeer::new(ip, port)
+                BtPeer::new(IpAddr::V4(ip), port)
             })
-            .collect())
+            .collect()
+    }
+
+    /// Builds the peers from a compact IPv6 peer list, where each peer is 16 bytes of ip and 2 bytes of port.
+    pub fn create_peers6_from_bstring(bstring: &[u8]) -> Vec<BtPeer> {
+        bstring
+            .chunks_exact(18)
+            .map(|chunk| {
+                let mut ip = [0; 16];
+                ip.copy_from_slice(&chunk[..16]);
+                let port = u16::from_be_bytes([chunk[16], chunk[17]]) as i64;
+
+                BtPeer::new(IpAddr::V6(Ipv6Addr::from(ip)), port)
+            })
+            .collect()
     }
 }
 
@@ -153,11 +212,107 @@ mod tests {
         let response_decoded = TrackerResponse::from(response).unwrap();
 
         assert_eq!(response_decoded.interval, 10);
+        assert_eq!(response_decoded.min_interval, None);
+        assert_eq!(response_decoded.tracker_id, None);
         assert_eq!(response_decoded.complete, 10);
         assert_eq!(response_decoded.incomplete, 10);
         assert_eq!(response_decoded.peers.len(), 2);
     }
 
+    #[test]
+    fn test_from_tracker_response_min_interval_and_tracker_id() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"interval".to_vec(), Bencode::BNumber(1800));
+        dict.insert(b"min interval".to_vec(), Bencode::BNumber(900));
+        dict.insert(
+            b"tracker id".to_vec(),
+            Bencode::BString(b"tracker-42".to_vec()),
+        );
+
+        let response = Bencode::encode(&dict);
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.interval, 1800);
+        assert_eq!(response_decoded.min_interval, Some(900));
+        assert_eq!(response_decoded.tracker_id, Some("tracker-42".to_string()));
+    }
+
+    #[test]
+    fn test_from_tracker_response_compact_peers() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"interval".to_vec(), Bencode::BNumber(10));
+        dict.insert(
+            b"peers".to_vec(),
+            Bencode::BString(vec![127, 0, 0, 1, 0x1A, 0xE1, 10, 0, 0, 2, 0x1A, 0xE2]),
+        );
+
+        let response = Bencode::encode(&dict);
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.peers.len(), 2);
+        assert_eq!(
+            response_decoded.peers[0].socket_addr().to_string(),
+            "127.0.0.1:6881"
+        );
+        assert_eq!(
+            response_decoded.peers[1].socket_addr().to_string(),
+            "10.0.0.2:6882"
+        );
+    }
+
+    #[test]
+    fn test_from_tracker_response_compact_peers_ignores_trailing_bytes() {
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"peers".to_vec(),
+            Bencode::BString(vec![127, 0, 0, 1, 0x1A, 0xE1, 10, 0]),
+        );
+
+        let response = Bencode::encode(&dict);
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.peers.len(), 1);
+    }
+
+    #[test]
+    fn test_from_tracker_response_peers6() {
+        let mut peers6 = "2001:db8::1".parse::<Ipv6Addr>().unwrap().octets().to_vec();
+        peers6.extend([0x1A, 0xE1]);
+
+        let mut dict = BTreeMap::new();
+        dict.insert(
+            b"peers".to_vec(),
+            Bencode::BString(vec![127, 0, 0, 1, 0x1A, 0xE1]),
+        );
+        dict.insert(b"peers6".to_vec(), Bencode::BString(peers6));
+
+        let response = Bencode::encode(&dict);
+        let response_decoded = TrackerResponse::from(response).unwrap();
+
+        assert_eq!(response_decoded.peers.len(), 2);
+        assert_eq!(
+            response_decoded.peers[0].socket_addr().to_string(),
+            "127.0.0.1:6881"
+        );
+        assert_eq!(
+            response_decoded.peers[1].socket_addr().to_string(),
+            "[2001:db8::1]:6881"
+        );
+    }
+
+    #[test]
+    fn test_from_tracker_response_invalid_peers6() {
+        let mut dict = BTreeMap::new();
+        dict.insert(b"peers6".to_vec(), Bencode::BNumber(10));
+
+        let response = Bencode::encode(&dict);
+
+        assert!(matches!(
+            TrackerResponse::from(response),
+            Err(FromTrackerResponseError::InvalidPeers6)
+        ));
+    }
+
     fn build_peer_dict(peer_id: Vec<u8>, ip: Vec<u8>, port: i64) -> BTreeMap<Vec<u8>, Bencode> {
         let mut peer_dict = BTreeMap::new();
         peer_dict.insert(b"peer id".to_vec(), Bencode::BString(peer_id));
@@ -0,0 +1,2 @@
+pub mod udp_handler;
+pub mod udp_message;
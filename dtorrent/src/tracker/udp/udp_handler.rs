@@ -0,0 +1,437 @@
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs, UdpSocket};
+use std::time::{Duration, Instant};
+
+use super::udp_message::{
+    AnnounceRequest, ConnectRequest, ScrapeRequest, UdpMessageError, UdpResponse,
+};
+use crate::torrent_parser::torrent::Torrent;
+use crate::tracker::http::query_params::QueryParams;
+use crate::tracker::http::url_parser::TrackerUrl;
+use crate::tracker::scrape_response::{ScrapeFile, ScrapeResponse};
+use crate::tracker::tracker_response::TrackerResponse;
+
+/// Time a connection ID can be used after it was received, as BEP 15 specifies.
+const CONNECTION_ID_LIFETIME: Duration = Duration::from_secs(60);
+
+/// Time to wait for the first response, it is doubled on every retransmission.
+const BASE_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// Number of retransmissions before giving up on a request.
+const MAX_RETRANSMISSIONS: u32 = 2;
+
+/// Maximum number of info hashes that fit in a single scrape request.
+const MAX_SCRAPE_HASHES: usize = 74;
+
+const MAX_RESPONSE_SIZE: usize = 65536;
+
+/// `UdpHandler` struct to talk to an **UDP** tracker (BEP 15).
+///
+/// To create a new `UdpHandler` use the method builder `new()`.
+///
+/// To announce to the tracker use the method `announce()`.
+///
+/// To scrape the tracker use the method `scrape()`.
+///
+/// The connection ID is kept between requests until it expires.
+#[derive(Debug)]
+pub struct UdpHandler {
+    tracker_url: TrackerUrl,
+    connection: Option<(u64, Instant)>,
+    base_timeout: Duration,
+    max_retransmissions: u32,
+}
+
+/// Posible `UdpHandler` errors.
+#[derive(Debug)]
+pub enum UdpHandlerError {
+    SocketError(io::Error),
+    InvalidAddress,
+    Timeout,
+    TrackerError(String),
+    InvalidResponse(UdpMessageError),
+    UnexpectedResponse,
+    InvalidInfoHash,
+}
+
+impl UdpHandler {
+    /// Builds a new `UdpHandler` from a **TrackerUrl** passed by parameter.
+    pub fn new(tracker_url: TrackerUrl) -> Self {
+        Self::with_retransmission(tracker_url, BASE_TIMEOUT, MAX_RETRANSMISSIONS)
+    }
+
+    /// Builds a new `UdpHandler` with a custom base timeout and number of retransmissions.
+    pub fn with_retransmission(
+        tracker_url: TrackerUrl,
+        base_timeout: Duration,
+        max_retransmissions: u32,
+    ) -> Self {
+        Self {
+            tracker_url,
+            connection: None,
+            base_timeout,
+            max_retransmissions,
+        }
+    }
+
+    /// Announces to the tracker.
+    ///
+    /// On success it returns a `TrackerResponse` with the tracker's response.
+    ///
+    /// It returns an `UdpHandlerError` if:
+    /// - The tracker address could not be resolved or the socket could not be used.
+    /// - The tracker did not respond after all the retransmissions.
+    /// - The tracker responded with an error or an invalid response.
+    /// - The info hash is not a valid hex string.
+    pub fn announce(
+        &mut self,
+        query_params: &QueryParams,
+    ) -> Result<TrackerResponse, UdpHandlerError> {
+        let info_hash = Torrent::decode_hex(query_params.info_hash.as_str())
+            .map_err(|_| UdpHandlerError::InvalidInfoHash)?;
+        let socket = self.connect_socket()?;
+        let is_ipv6 = socket
+            .peer_addr()
+            .map_err(UdpHandlerError::SocketError)?
+            .is_ipv6();
+        let connection_id = self.connection_id(&socket)?;
+
+        let transaction_id = rand::random();
+        let request = AnnounceRequest {
+            connection_id,
+            transaction_id,
+            info_hash,
+            peer_id: query_params.client_peer_id.as_bytes().to_vec(),
+            downloaded: query_params.downloaded,
+            left: query_params.left,
+            uploaded: query_params.uploaded,
+            event: query_params.event,
+            key: query_params.key,
+            num_want: -1,
+            port: query_params.client_port as u16,
+        };
+
+        match self.send_request(&socket, &request.as_bytes(), transaction_id)? {
+            UdpResponse::Announce {
+                interval,
+                leechers,
+                seeders,
+                peers,
+                ..
+            } => Ok(TrackerResponse {
+                interval: interval as i64,
+                min_interval: None,
+                tracker_id: None,
+                complete: seeders as i64,
+                incomplete: leechers as i64,
+                // Trackers reached over IPv6 return 18 byte IPv6 peers instead of 6 byte IPv4 ones.
+                peers: if is_ipv6 {
+                    TrackerResponse::create_peers6_from_bstring(&peers)
+                } else {
+                    TrackerResponse::create_peers_from_bstring(&peers)
+                },
+            }),
+            _ => Err(UdpHandlerError::UnexpectedResponse),
+        }
+    }
+
+    /// Scrapes the tracker for the given info hashes (as hex strings).
+    ///
+    /// The info hashes are sent in batches of at most 74, the maximum a request can hold.
+    ///
+    /// On success it returns a `ScrapeResponse` with the statistics of each torrent.
+    ///
+    /// It returns an `UdpHandlerError` if:
+    /// - The tracker address could not be resolved or the socket could not be used.
+    /// - The tracker did not respond after all the retransmissions.
+    /// - The tracker responded with an error or an invalid response.
+    /// - An info hash is not a valid hex string.
+    pub fn scrape(&mut self, info_hashes: &[String]) -> Result<ScrapeResponse, UdpHandlerError> {
+        let socket = self.connect_socket()?;
+        let mut scrape_response = ScrapeResponse::default();
+
+        for batch in info_hashes.chunks(MAX_SCRAPE_HASHES) {
+            let decoded_hashes = batch
+                .iter()
+                .map(|info_hash| Torrent::decode_hex(info_hash.as_str()))
+                .collect::<Result<Vec<Vec<u8>>, _>>()
+                .map_err(|_| UdpHandlerError::InvalidInfoHash)?;
+
+            let connection_id = self.connection_id(&socket)?;
+            let transaction_id = rand::random();
+            let request = ScrapeRequest {
+                connection_id,
+                transaction_id,
+                info_hashes: decoded_hashes,
+            };
+
+            let entries = match self.send_request(&socket, &request.as_bytes(), transaction_id)? {
+                UdpResponse::Scrape { entries, .. } => entries,
+                _ => return Err(UdpHandlerError::UnexpectedResponse),
+            };
+
+            for (info_hash, entry) in batch.iter().zip(entries) {
+                scrape_response.files.insert(
+                    info_hash.to_lowercase(),
+                    ScrapeFile {
+                        complete: entry.seeders as i64,
+                        downloaded: entry.completed as i64,
+                        incomplete: entry.leechers as i64,
+                    },
+                );
+            }
+        }
+
+        Ok(scrape_response)
+    }
+
+    fn connect_socket(&self) -> Result<UdpSocket, UdpHandlerError> {
+        let addr = self.resolve_address()?;
+        let bind_addr = if addr.is_ipv4() {
+            "0.0.0.0:0"
+        } else {
+            "[::]:0"
+        };
+
+        let socket = UdpSocket::bind(bind_addr).map_err(UdpHandlerError::SocketError)?;
+        socket.connect(addr).map_err(UdpHandlerError::SocketError)?;
+        Ok(socket)
+    }
+
+    fn resolve_address(&self) -> Result<SocketAddr, UdpHandlerError> {
+        let mut addrs = (self.tracker_url.host.as_str(), self.tracker_url.port as u16)
+            .to_socket_addrs()
+            .map_err(UdpHandlerError::SocketError)?;
+        addrs.next().ok_or(UdpHandlerError::InvalidAddress)
+    }
+
+    /// Returns the cached connection ID, or gets a new one if it expired.
+    fn connection_id(&mut self, socket: &UdpSocket) -> Result<u64, UdpHandlerError> {
+        if let Some((connection_id, received_at)) = self.connection {
+            if received_at.elapsed() < CONNECTION_ID_LIFETIME {
+                return Ok(connection_id);
+            }
+        }
+
+        let transaction_id = rand::random();
+        let request = ConnectRequest { transaction_id };
+
+        match self.send_request(socket, &request.as_bytes(), transaction_id)? {
+            UdpResponse::Connect { connection_id, .. } => {
+                self.connection = Some((connection_id, Instant::now()));
+                Ok(connection_id)
+            }
+            _ => Err(UdpHandlerError::UnexpectedResponse),
+        }
+    }
+
+    /// Sends a request and waits for its response, retransmitting it with an exponential backoff.
+    ///
+    /// Responses with a different transaction ID are ignored.
+    fn send_request(
+        &mut self,
+        socket: &UdpSocket,
+        request: &[u8],
+        transaction_id: u32,
+    ) -> Result<UdpResponse, UdpHandlerError> {
+        let mut buffer = vec![0; MAX_RESPONSE_SIZE];
+
+        for attempt in 0..=self.max_retransmissions {
+            socket.send(request).map_err(UdpHandlerError::SocketError)?;
+
+            let deadline = Instant::now() + self.base_timeout * 2u32.pow(attempt);
+            while let Some(remaining) = deadline.checked_duration_since(Instant::now()) {
+                if remaining.is_zero() {
+                    break;
+                }
+                socket
+                    .set_read_timeout(Some(remaining))
+                    .map_err(UdpHandlerError::SocketError)?;
+
+                let size = match socket.recv(&mut buffer) {
+                    Ok(size) => size,
+                    Err(err)
+                        if err.kind() == io::ErrorKind::WouldBlock
+                            || err.kind() == io::ErrorKind::TimedOut =>
+                    {
+                        break
+                    }
+                    Err(err) => return Err(UdpHandlerError::SocketError(err)),
+                };
+
+                let response = match UdpResponse::from_bytes(&buffer[..size]) {
+                    Ok(response) => response,
+                    Err(err) => return Err(UdpHandlerError::InvalidResponse(err)),
+                };
+                if response.transaction_id() != transaction_id {
+                    continue;
+                }
+                if let UdpResponse::Error { message, .. } = response {
+                    // The connection ID may have been rejected, get a new one next time.
+                    self.connection = None;
+                    return Err(UdpHandlerError::TrackerError(message));
+                }
+                return Ok(response);
+            }
+        }
+
+        Err(UdpHandlerError::Timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread;
+    use std::thread::JoinHandle;
+
+    use super::*;
+    use crate::tracker::http::url_parser::ConnectionProtocol;
+
+    const TEST_CONNECTION_ID: u64 = 0x1234;
+    const TEST_INFO_HASH: &str = "2c6b6858d61da9543d4231a71db4b1c9264b0685";
+
+    #[test]
+    fn test_announce() {
+        let (port, stand_in) = spawn_tracker_stand_in(0, 2);
+        let mut udp_handler = UdpHandler::new(create_tracker_url(port));
+
+        let response = udp_handler.announce(&create_query_params()).unwrap();
+
+        assert_eq!(response.interval, 1800);
+        assert_eq!(response.complete, 5);
+        assert_eq!(response.incomplete, 3);
+        assert_eq!(response.peers.len(), 1);
+        assert_eq!(
+            response.peers[0].socket_addr().to_string(),
+            "127.0.0.1:6881"
+        );
+        stand_in.join().unwrap();
+    }
+
+    #[test]
+    fn test_announce_retransmits_lost_requests() {
+        let (port, stand_in) = spawn_tracker_stand_in(1, 2);
+        let mut udp_handler = UdpHandler::with_retransmission(
+            create_tracker_url(port),
+            Duration::from_millis(100),
+            2,
+        );
+
+        let response = udp_handler.announce(&create_query_params()).unwrap();
+
+        assert_eq!(response.interval, 1800);
+        stand_in.join().unwrap();
+    }
+
+    #[test]
+    fn test_announce_reuses_connection_id() {
+        let (port, stand_in) = spawn_tracker_stand_in(0, 3);
+        let mut udp_handler = UdpHandler::new(create_tracker_url(port));
+
+        udp_handler.announce(&create_query_params()).unwrap();
+        udp_handler.announce(&create_query_params()).unwrap();
+
+        // The stand-in only answers three requests: one connect and two announces.
+        stand_in.join().unwrap();
+    }
+
+    #[test]
+    fn test_announce_timeout() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        let mut udp_handler =
+            UdpHandler::with_retransmission(create_tracker_url(port), Duration::from_millis(20), 1);
+
+        assert!(matches!(
+            udp_handler.announce(&create_query_params()),
+            Err(UdpHandlerError::Timeout)
+        ));
+    }
+
+    #[test]
+    fn test_scrape() {
+        let (port, stand_in) = spawn_tracker_stand_in(0, 2);
+        let mut udp_handler = UdpHandler::new(create_tracker_url(port));
+
+        let response = udp_handler.scrape(&[TEST_INFO_HASH.to_string()]).unwrap();
+
+        assert_eq!(
+            response.files.get(TEST_INFO_HASH),
+            Some(&ScrapeFile {
+                complete: 5,
+                downloaded: 10,
+                incomplete: 3
+            })
+        );
+        stand_in.join().unwrap();
+    }
+
+    // Auxiliary functions
+
+    fn create_tracker_url(port: u16) -> TrackerUrl {
+        TrackerUrl {
+            protocol: ConnectionProtocol::Udp,
+            host: "127.0.0.1".to_string(),
+            port: port as u32,
+            endpoint: "".to_string(),
+        }
+    }
+
+    fn create_query_params() -> QueryParams {
+        QueryParams::new(
+            TEST_INFO_HASH.to_string(),
+            6969,
+            100,
+            "LA_DEYMONETA_PAPA!!!".to_string(),
+        )
+    }
+
+    /// Spawns a local UDP tracker stand-in that ignores the first `dropped` requests and answers the following `answered` ones.
+    fn spawn_tracker_stand_in(dropped: usize, answered: usize) -> (u16, JoinHandle<()>) {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+
+        let handle = thread::spawn(move || {
+            let mut buffer = [0; 1024];
+            for _ in 0..dropped {
+                socket.recv_from(&mut buffer).unwrap();
+            }
+            for _ in 0..answered {
+                let (size, addr) = socket.recv_from(&mut buffer).unwrap();
+                let response = build_stand_in_response(&buffer[..size]);
+                socket.send_to(&response, addr).unwrap();
+            }
+        });
+
+        (port, handle)
+    }
+
+    fn build_stand_in_response(request: &[u8]) -> Vec<u8> {
+        let action = u32::from_be_bytes(request[8..12].try_into().unwrap());
+        let transaction_id = &request[12..16];
+
+        let mut response = vec![];
+        response.extend(action.to_be_bytes());
+        response.extend(transaction_id);
+        match action {
+            0 => response.extend(TEST_CONNECTION_ID.to_be_bytes()),
+            1 => {
+                assert_eq!(request[0..8], TEST_CONNECTION_ID.to_be_bytes());
+                response.extend(1800u32.to_be_bytes());
+                response.extend(3u32.to_be_bytes());
+                response.extend(5u32.to_be_bytes());
+                response.extend([127, 0, 0, 1, 0x1A, 0xE1]);
+            }
+            2 => {
+                assert_eq!(request[0..8], TEST_CONNECTION_ID.to_be_bytes());
+                for _ in request[16..].chunks(20) {
+                    response.extend(5u32.to_be_bytes());
+                    response.extend(10u32.to_be_bytes());
+                    response.extend(3u32.to_be_bytes());
+                }
+            }
+            _ => panic!("Unexpected action"),
+        }
+        response
+    }
+}
@@ -0,0 +1,383 @@
+use crate::tracker::http::query_params::AnnounceEvent;
+
+/// Magic constant that identifies the UDP tracker protocol in connect requests.
+pub const PROTOCOL_ID: u64 = 0x41727101980;
+
+const CONNECT_RESPONSE_SIZE: usize = 16;
+const ANNOUNCE_RESPONSE_HEADER_SIZE: usize = 20;
+const SCRAPE_RESPONSE_HEADER_SIZE: usize = 8;
+const SCRAPE_ENTRY_SIZE: usize = 12;
+
+/// Actions of the UDP tracker protocol (BEP 15).
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum UdpAction {
+    Connect = 0,
+    Announce = 1,
+    Scrape = 2,
+    Error = 3,
+}
+
+/// Posible UDP message errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UdpMessageError {
+    InvalidLength,
+    InvalidAction(u32),
+}
+
+/// Represents a connect request.
+#[derive(Debug)]
+pub struct ConnectRequest {
+    pub transaction_id: u32,
+}
+
+impl ConnectRequest {
+    /// Converts a `ConnectRequest` to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16);
+        bytes.extend(PROTOCOL_ID.to_be_bytes());
+        bytes.extend((UdpAction::Connect as u32).to_be_bytes());
+        bytes.extend(self.transaction_id.to_be_bytes());
+        bytes
+    }
+}
+
+/// Represents an announce request.
+#[derive(Debug)]
+pub struct AnnounceRequest {
+    pub connection_id: u64,
+    pub transaction_id: u32,
+    pub info_hash: Vec<u8>,
+    pub peer_id: Vec<u8>,
+    pub downloaded: u64,
+    pub left: u64,
+    pub uploaded: u64,
+    pub event: AnnounceEvent,
+    pub key: u32,
+    pub num_want: i32,
+    pub port: u16,
+}
+
+impl AnnounceRequest {
+    /// Converts an `AnnounceRequest` to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(98);
+        bytes.extend(self.connection_id.to_be_bytes());
+        bytes.extend((UdpAction::Announce as u32).to_be_bytes());
+        bytes.extend(self.transaction_id.to_be_bytes());
+        bytes.extend(&self.info_hash);
+        bytes.extend(&self.peer_id);
+        bytes.extend(self.downloaded.to_be_bytes());
+        bytes.extend(self.left.to_be_bytes());
+        bytes.extend(self.uploaded.to_be_bytes());
+        bytes.extend((self.event as u32).to_be_bytes());
+        bytes.extend(0u32.to_be_bytes()); // IP address, 0 means the sender address.
+        bytes.extend(self.key.to_be_bytes());
+        bytes.extend(self.num_want.to_be_bytes());
+        bytes.extend(self.port.to_be_bytes());
+        bytes
+    }
+}
+
+/// Represents a scrape request.
+#[derive(Debug)]
+pub struct ScrapeRequest {
+    pub connection_id: u64,
+    pub transaction_id: u32,
+    pub info_hashes: Vec<Vec<u8>>,
+}
+
+impl ScrapeRequest {
+    /// Converts a `ScrapeRequest` to a byte array.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(16 + 20 * self.info_hashes.len());
+        bytes.extend(self.connection_id.to_be_bytes());
+        bytes.extend((UdpAction::Scrape as u32).to_be_bytes());
+        bytes.extend(self.transaction_id.to_be_bytes());
+        for info_hash in &self.info_hashes {
+            bytes.extend(info_hash);
+        }
+        bytes
+    }
+}
+
+/// Statistics of a single torrent in a scrape response, in the same order as requested.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScrapeEntry {
+    pub seeders: u32,
+    pub completed: u32,
+    pub leechers: u32,
+}
+
+/// A response received from an UDP tracker.
+#[derive(Debug, PartialEq, Eq)]
+pub enum UdpResponse {
+    Connect {
+        transaction_id: u32,
+        connection_id: u64,
+    },
+    Announce {
+        transaction_id: u32,
+        interval: u32,
+        leechers: u32,
+        seeders: u32,
+        peers: Vec<u8>,
+    },
+    Scrape {
+        transaction_id: u32,
+        entries: Vec<ScrapeEntry>,
+    },
+    Error {
+        transaction_id: u32,
+        message: String,
+    },
+}
+
+impl UdpResponse {
+    /// Parses a byte array into an `UdpResponse`.
+    ///
+    /// It returns an `UdpMessageError` if:
+    /// - The response is shorter than its action requires.
+    /// - The action is unknown.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, UdpMessageError> {
+        if bytes.len() < 8 {
+            return Err(UdpMessageError::InvalidLength);
+        }
+        let action = read_u32(bytes, 0);
+        let transaction_id = read_u32(bytes, 4);
+
+        match action {
+            0 => {
+                if bytes.len() < CONNECT_RESPONSE_SIZE {
+                    return Err(UdpMessageError::InvalidLength);
+                }
+                Ok(UdpResponse::Connect {
+                    transaction_id,
+                    connection_id: read_u64(bytes, 8),
+                })
+            }
+            1 => {
+                if bytes.len() < ANNOUNCE_RESPONSE_HEADER_SIZE {
+                    return Err(UdpMessageError::InvalidLength);
+                }
+                Ok(UdpResponse::Announce {
+                    transaction_id,
+                    interval: read_u32(bytes, 8),
+                    leechers: read_u32(bytes, 12),
+                    seeders: read_u32(bytes, 16),
+                    peers: bytes[ANNOUNCE_RESPONSE_HEADER_SIZE..].to_vec(),
+                })
+            }
+            2 => Ok(UdpResponse::Scrape {
+                transaction_id,
+                entries: bytes[SCRAPE_RESPONSE_HEADER_SIZE..]
+                    .chunks_exact(SCRAPE_ENTRY_SIZE)
+                    .map(|entry| ScrapeEntry {
+                        seeders: read_u32(entry, 0),
+                        completed: read_u32(entry, 4),
+                        leechers: read_u32(entry, 8),
+                    })
+                    .collect(),
+            }),
+            3 => Ok(UdpResponse::Error {
+                transaction_id,
+                message: String::from_utf8_lossy(&bytes[8..]).to_string(),
+            }),
+            action => Err(UdpMessageError::InvalidAction(action)),
+        }
+    }
+
+    /// Returns the transaction ID of the response.
+    pub fn transaction_id(&self) -> u32 {
+        match self {
+            UdpResponse::Connect { transaction_id, .. }
+            | UdpResponse::Announce { transaction_id, .. }
+            | UdpResponse::Scrape { transaction_id, .. }
+            | UdpResponse::Error { transaction_id, .. } => *transaction_id,
+        }
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    let mut buffer = [0; 4];
+    buffer.copy_from_slice(&bytes[offset..offset + 4]);
+    u32::from_be_bytes(buffer)
+}
+
+fn read_u64(bytes: &[u8], offset: usize) -> u64 {
+    let mut buffer = [0; 8];
+    buffer.copy_from_slice(&bytes[offset..offset + 8]);
+    u64::from_be_bytes(buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_request_as_bytes() {
+        let request = ConnectRequest { transaction_id: 42 };
+
+        let bytes = request.as_bytes();
+
+        assert_eq!(bytes.len(), 16);
+        assert_eq!(bytes[0..8], PROTOCOL_ID.to_be_bytes());
+        assert_eq!(bytes[8..12], 0u32.to_be_bytes());
+        assert_eq!(bytes[12..16], 42u32.to_be_bytes());
+    }
+
+    #[test]
+    fn test_announce_request_as_bytes() {
+        let request = AnnounceRequest {
+            connection_id: 7,
+            transaction_id: 42,
+            info_hash: vec![1; 20],
+            peer_id: vec![2; 20],
+            downloaded: 10,
+            left: 20,
+            uploaded: 30,
+            event: AnnounceEvent::Started,
+            key: 99,
+            num_want: -1,
+            port: 6969,
+        };
+
+        let bytes = request.as_bytes();
+
+        assert_eq!(bytes.len(), 98);
+        assert_eq!(bytes[0..8], 7u64.to_be_bytes());
+        assert_eq!(bytes[8..12], 1u32.to_be_bytes());
+        assert_eq!(bytes[12..16], 42u32.to_be_bytes());
+        assert_eq!(bytes[16..36], [1; 20]);
+        assert_eq!(bytes[36..56], [2; 20]);
+        assert_eq!(bytes[56..64], 10u64.to_be_bytes());
+        assert_eq!(bytes[64..72], 20u64.to_be_bytes());
+        assert_eq!(bytes[72..80], 30u64.to_be_bytes());
+        assert_eq!(bytes[80..84], 2u32.to_be_bytes());
+        assert_eq!(bytes[84..88], 0u32.to_be_bytes());
+        assert_eq!(bytes[88..92], 99u32.to_be_bytes());
+        assert_eq!(bytes[92..96], (-1i32).to_be_bytes());
+        assert_eq!(bytes[96..98], 6969u16.to_be_bytes());
+    }
+
+    #[test]
+    fn test_scrape_request_as_bytes() {
+        let request = ScrapeRequest {
+            connection_id: 7,
+            transaction_id: 42,
+            info_hashes: vec![vec![1; 20], vec![2; 20]],
+        };
+
+        let bytes = request.as_bytes();
+
+        assert_eq!(bytes.len(), 56);
+        assert_eq!(bytes[8..12], 2u32.to_be_bytes());
+        assert_eq!(bytes[16..36], [1; 20]);
+        assert_eq!(bytes[36..56], [2; 20]);
+    }
+
+    #[test]
+    fn test_connect_response_from_bytes() {
+        let mut bytes = vec![];
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(42u32.to_be_bytes());
+        bytes.extend(1234u64.to_be_bytes());
+
+        let response = UdpResponse::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            response,
+            UdpResponse::Connect {
+                transaction_id: 42,
+                connection_id: 1234
+            }
+        );
+    }
+
+    #[test]
+    fn test_announce_response_from_bytes() {
+        let mut bytes = vec![];
+        bytes.extend(1u32.to_be_bytes());
+        bytes.extend(42u32.to_be_bytes());
+        bytes.extend(1800u32.to_be_bytes());
+        bytes.extend(3u32.to_be_bytes());
+        bytes.extend(5u32.to_be_bytes());
+        bytes.extend([127, 0, 0, 1, 0x1A, 0xE1]);
+
+        let response = UdpResponse::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            response,
+            UdpResponse::Announce {
+                transaction_id: 42,
+                interval: 1800,
+                leechers: 3,
+                seeders: 5,
+                peers: vec![127, 0, 0, 1, 0x1A, 0xE1],
+            }
+        );
+    }
+
+    #[test]
+    fn test_scrape_response_from_bytes() {
+        let mut bytes = vec![];
+        bytes.extend(2u32.to_be_bytes());
+        bytes.extend(42u32.to_be_bytes());
+        bytes.extend([0, 0, 0, 1, 0, 0, 0, 2, 0, 0, 0, 3]);
+
+        let response = UdpResponse::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            response,
+            UdpResponse::Scrape {
+                transaction_id: 42,
+                entries: vec![ScrapeEntry {
+                    seeders: 1,
+                    completed: 2,
+                    leechers: 3
+                }],
+            }
+        );
+    }
+
+    #[test]
+    fn test_error_response_from_bytes() {
+        let mut bytes = vec![];
+        bytes.extend(3u32.to_be_bytes());
+        bytes.extend(42u32.to_be_bytes());
+        bytes.extend(b"torrent not found");
+
+        let response = UdpResponse::from_bytes(&bytes).unwrap();
+
+        assert_eq!(
+            response,
+            UdpResponse::Error {
+                transaction_id: 42,
+                message: "torrent not found".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn test_short_response() {
+        let mut bytes = vec![];
+        bytes.extend(0u32.to_be_bytes());
+        bytes.extend(42u32.to_be_bytes());
+
+        assert_eq!(
+            UdpResponse::from_bytes(&bytes).unwrap_err(),
+            UdpMessageError::InvalidLength
+        );
+    }
+
+    #[test]
+    fn test_unknown_action() {
+        let mut bytes = vec![];
+        bytes.extend(9u32.to_be_bytes());
+        bytes.extend(42u32.to_be_bytes());
+
+        assert_eq!(
+            UdpResponse::from_bytes(&bytes).unwrap_err(),
+            UdpMessageError::InvalidAction(9)
+        );
+    }
+}
@@ -1,7 +1,9 @@
-use std::sync::Arc;
+use std::sync::{Arc, OnceLock};
 
 use super::client_window_data::ClientWindowData;
 use super::setup::UserInterfaceError;
+use crate::bt_client::btclient::BtClient;
+use crate::bt_client::btclient_error::BtClientError;
 use crate::statistics::torrent_stats::TorrentStats;
 use gtk::glib::Receiver;
 use gtk::prelude::*;
@@ -32,6 +34,7 @@ impl ClientWindow {
             window_data_clone.update_statistics(statistics);
             window_data_clone.update_torrent_liststore();
             window_data_clone.update_peer_liststore();
+            window_data_clone.update_files_liststore();
             glib::Continue(true)
         });
 
@@ -55,6 +58,73 @@ impl ClientWindow {
         treeview.connect_row_activated(move |_, row_path, _| {
             window_data_clone.select_torrent(row_path.indices()[0]);
             window_data_clone.update_peer_liststore();
+            window_data_clone.update_files_liststore();
+        });
+
+        Ok(())
+    }
+
+    /// Connects the pause, resume, recheck and remove buttons to the client, they act on the selected torrent.
+    ///
+    /// The buttons do nothing until the client is initialized.
+    pub fn control_on_click(
+        &self,
+        client: Arc<OnceLock<Arc<BtClient>>>,
+    ) -> Result<(), UserInterfaceError> {
+        let delete_data_check: gtk::CheckButton = self
+            .builder
+            .object("delete_data_check")
+            .ok_or(UserInterfaceError::WindowBuildingError)?;
+
+        self.connect_torrent_button("pause_button", "pause", &client, |client, info_hash| {
+            client.pause_torrent(info_hash)
+        })?;
+        self.connect_torrent_button("resume_button", "resume", &client, |client, info_hash| {
+            client.resume_torrent(info_hash)
+        })?;
+        self.connect_torrent_button("recheck_button", "recheck", &client, |client, info_hash| {
+            client.recheck_torrent(info_hash)
+        })?;
+        self.connect_torrent_button(
+            "remove_button",
+            "remove",
+            &client,
+            move |client, info_hash| {
+                client.remove_torrent(info_hash, delete_data_check.is_active())
+            },
+        )?;
+
+        Ok(())
+    }
+
+    fn connect_torrent_button<F>(
+        &self,
+        button_id: &str,
+        action_name: &'static str,
+        client: &Arc<OnceLock<Arc<BtClient>>>,
+        action: F,
+    ) -> Result<(), UserInterfaceError>
+    where
+        F: Fn(&BtClient, &str) -> Result<(), BtClientError> + 'static,
+    {
+        let button: gtk::Button = self
+            .builder
+            .object(button_id)
+            .ok_or(UserInterfaceError::WindowBuildingError)?;
+
+        let client = client.clone();
+        let window_data_clone = self.window_data.clone();
+        button.connect_clicked(move |_| {
+            if let (Some(client), Some(info_hash)) =
+                (client.get(), window_data_clone.selected_info_hash())
+            {
+                if let Err(err) = action(client, &info_hash) {
+                    client.logger_sender().error(&format!(
+                        "Couldn't {} torrent {}: {:?}",
+                        action_name, info_hash, err
+                    ));
+                }
+            }
         });
 
         Ok(())
@@ -1,4 +1,5 @@
 use super::setup::UserInterfaceError;
+use crate::statistics::file_stats::FileStats;
 use crate::statistics::peer_stats::PeerStats;
 use crate::statistics::torrent_stats::TorrentStats;
 use gtk::{
@@ -14,6 +15,7 @@ use std::sync::{
 pub struct ClientWindowData {
     torrents_liststore: ListStore,
     peers_liststore: ListStore,
+    files_liststore: ListStore,
     last_torrents_statistics: Mutex<Vec<TorrentStats>>,
     selected_torrent_index: AtomicI32,
 }
@@ -26,6 +28,9 @@ impl ClientWindowData {
         let peers_liststore: ListStore = builder
             .object("peers")
             .ok_or(UserInterfaceError::WindowDataError)?;
+        let files_liststore: ListStore = builder
+            .object("files")
+            .ok_or(UserInterfaceError::WindowDataError)?;
 
         // Sort by torrent name
         //torrents_liststore.set_sort_column_id(gtk::SortColumn::Index(0), gtk::SortType::Ascending);
@@ -37,6 +42,7 @@ impl ClientWindowData {
             last_torrents_statistics: Mutex::new(Vec::new()),
             torrents_liststore,
             peers_liststore,
+            files_liststore,
             selected_torrent_index: AtomicI32::new(0),
         })
     }
@@ -52,20 +58,43 @@ impl ClientWindowData {
         for (index, statistics) in torrent_stats.iter().enumerate() {
             self.update_torrent_store_row(index, statistics);
         }
+        // The rows of the removed torrents are left at the end.
+        while let Some(iter) = self
+            .torrents_liststore
+            .iter_from_string(torrent_stats.len().to_string().as_str())
+        {
+            self.torrents_liststore.remove(&iter);
+        }
     }
 
     pub fn update_peer_liststore(&self) {
         let torrent_stats = self.last_torrents_statistics.lock().unwrap();
         self.peers_liststore.clear();
-        for (peer_index, peer_stats) in torrent_stats[self.selected_torrent() as usize]
-            .peers
-            .iter()
-            .enumerate()
-        {
-            self.update_peer_store_row(peer_index, peer_stats);
+        if let Some(selected_stats) = torrent_stats.get(self.selected_torrent() as usize) {
+            for (peer_index, peer_stats) in selected_stats.peers.iter().enumerate() {
+                self.update_peer_store_row(peer_index, peer_stats);
+            }
         }
     }
 
+    pub fn update_files_liststore(&self) {
+        let torrent_stats = self.last_torrents_statistics.lock().unwrap();
+        self.files_liststore.clear();
+        if let Some(selected_stats) = torrent_stats.get(self.selected_torrent() as usize) {
+            for (file_index, file_stats) in selected_stats.files.iter().enumerate() {
+                self.update_file_store_row(file_index, file_stats);
+            }
+        }
+    }
+
+    /// Returns the info hash of the selected torrent, if there is one.
+    pub fn selected_info_hash(&self) -> Option<String> {
+        let torrent_stats = self.last_torrents_statistics.lock().unwrap();
+        torrent_stats
+            .get(self.selected_torrent() as usize)
+            .map(|selected_stats| selected_stats.info_hash.clone())
+    }
+
     fn selected_torrent(&self) -> i32 {
         self.selected_torrent_index.load(Ordering::Relaxed)
     }
@@ -86,10 +115,7 @@ impl ClientWindowData {
                 (2u32, &torrent_stats.info_hash),
                 (
                     3u32,
-                    &(glib::format_size_full(
-                        torrent_stats.length as u64,
-                        FormatSizeFlags::IEC_UNITS,
-                    )),
+                    &(glib::format_size_full(torrent_stats.length, FormatSizeFlags::IEC_UNITS)),
                 ),
                 (4u32, &(torrent_stats.peers_amount as u32)),
                 (5u32, &torrent_stats.pieces_amount),
@@ -97,7 +123,7 @@ impl ClientWindowData {
                 (7u32, &(torrent_stats.total_peers as u32)),
                 (8u32, &self.format_speed(torrent_stats.download_speed)),
                 (9u32, &self.format_speed(torrent_stats.upload_speed)),
-                (10u32, &torrent_stats.eta),
+                (10u32, &self.format_eta(torrent_stats)),
             ],
         );
     }
@@ -132,6 +158,27 @@ impl ClientWindowData {
         );
     }
 
+    fn update_file_store_row(&self, file_index: usize, file_stats: &FileStats) {
+        let fl_iter = match self
+            .files_liststore
+            .iter_from_string(file_index.to_string().as_str())
+        {
+            Some(iter) => iter,
+            None => self.files_liststore.append(),
+        };
+        self.files_liststore.set(
+            &fl_iter,
+            &[
+                (0u32, &file_stats.path),
+                (
+                    1u32,
+                    &(glib::format_size_full(file_stats.length, FormatSizeFlags::IEC_UNITS)),
+                ),
+                (2u32, &file_stats.length), // For sorting
+            ],
+        );
+    }
+
     fn format_state(&self, choked: bool, interested: bool) -> String {
         let choked_str = if choked { "choked" } else { "unchoked" };
         let interested_str = if interested {
@@ -142,6 +189,14 @@ impl ClientWindowData {
         format!("{}/{}", choked_str, interested_str)
     }
 
+    fn format_eta(&self, torrent_stats: &TorrentStats) -> String {
+        if torrent_stats.paused {
+            "Paused".to_string()
+        } else {
+            torrent_stats.eta.clone()
+        }
+    }
+
     fn format_speed(&self, speed: f64) -> String {
         let speed_in_kilobytes = speed / 8_f64;
         if speed_in_kilobytes < 1024_f64 {
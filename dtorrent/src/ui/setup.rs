@@ -3,6 +3,7 @@ use crate::bt_client::btclient::BtClient;
 use crate::bt_client::btclient_error::BtClientError;
 use crate::statistics::torrent_stats::TorrentStats;
 use gtk::glib;
+use std::sync::{Arc, OnceLock};
 use std::thread;
 
 pub enum UserInterfaceError {
@@ -17,22 +18,27 @@ pub fn start_dtorrent_application(
     torrents_directory: String,
 ) -> Result<(), UserInterfaceError> {
     let (sender, receiver) = glib::MainContext::channel(glib::PRIORITY_DEFAULT);
+    let client = Arc::new(OnceLock::new());
 
-    start_btclient(sender, torrents_directory).map_err(UserInterfaceError::ClientError)?;
+    start_btclient(sender, torrents_directory, client.clone())
+        .map_err(UserInterfaceError::ClientError)?;
 
     let client_window = ClientWindow::new(receiver)?;
     client_window.update_on_click()?;
+    client_window.control_on_click(client)?;
     client_window.display_on(app);
 
     Ok(())
 }
 
+/// Starts the client on another thread, and sets it in `client` once it is initialized so the window can control its torrents.
 pub fn start_btclient(
     sender: glib::Sender<Vec<TorrentStats>>,
     torrents_directory: String,
+    client: Arc<OnceLock<Arc<BtClient>>>,
 ) -> Result<(), BtClientError> {
     thread::spawn(move || match BtClient::init(torrents_directory) {
-        Ok(client) => client.run(sender),
+        Ok(btclient) => client.get_or_init(|| Arc::new(btclient)).run(sender),
         Err(btclient_error) => eprintln!("{:?}", btclient_error),
     });
     Ok(())
@@ -0,0 +1,587 @@
+use rand::Rng;
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use super::packet::{Packet, PacketType, HEADER_SIZE};
+
+/// Largest payload of a packet, so the datagrams fit in the MTU of most paths.
+pub const MAX_PAYLOAD_SIZE: usize = 1400 - HEADER_SIZE;
+
+/// Queuing delay LEDBAT aims for, in microseconds: above it the congestion window shrinks.
+const TARGET_DELAY: f64 = 100_000.0;
+
+/// Maximum growth of the congestion window in a round trip, in bytes.
+const MAX_CWND_INCREASE: f64 = 3000.0;
+
+const MIN_CWND: f64 = MAX_PAYLOAD_SIZE as f64;
+const INITIAL_CWND: f64 = 2.0 * MAX_PAYLOAD_SIZE as f64;
+const MAX_CWND: f64 = (1 << 20) as f64;
+
+/// Bytes received and not read yet that we accept, announced to the peer as our window.
+const RECEIVE_BUFFER_SIZE: usize = 1 << 20;
+
+/// Bytes written and not sent yet that we accept before blocking the writer.
+const SEND_BUFFER_SIZE: usize = 1 << 20;
+
+/// Packets received after a missing one that are kept until it arrives.
+const MAX_OUT_OF_ORDER_PACKETS: u16 = 1024;
+
+const INITIAL_TIMEOUT: Duration = Duration::from_secs(1);
+const MIN_TIMEOUT: Duration = Duration::from_millis(500);
+const MAX_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Times a packet is sent without being acknowledged before the connection is given up.
+const MAX_TRANSMISSIONS: u32 = 6;
+
+/// Acknowledgements of the same packet that make us resend the next one, as it was probably lost.
+const DUPLICATE_ACKS_BEFORE_RESEND: u32 = 3;
+
+/// Time each minimum of the base delay history covers. The base delay is the minimum of the last two.
+const BASE_DELAY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// State of a uTP connection.
+///
+/// - `SynSent`: we sent a `Syn` and wait for its acknowledgement,
+/// - `Connected`: data can be sent and received,
+/// - `Closed`: we sent a `Fin`,
+/// - `Reset`: the peer reset the connection,
+/// - `TimedOut`: a packet was not acknowledged after `MAX_TRANSMISSIONS`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    SynSent,
+    Connected,
+    Closed,
+    Reset,
+    TimedOut,
+}
+
+/// A packet sent and not acknowledged yet.
+#[derive(Debug)]
+struct SentPacket {
+    packet: Packet,
+    sent_at: Instant,
+    transmissions: u32,
+}
+
+/// State machine of one side of a uTP connection (BEP 29), without any I/O.
+///
+/// The methods return the packets that must be sent to the peer.
+/// Sent packets are kept until acknowledged and resent on timeout or after duplicate acks.
+/// The congestion window is controlled by LEDBAT: it grows while the one-way delay of the packets is close to its base delay,
+/// and shrinks when the queuing delay goes over `TARGET_DELAY`, so uTP yields to other traffic.
+///
+/// To create a new `Connection`, use Connection::connect(recv_id) or Connection::accept(syn).
+#[derive(Debug)]
+pub struct Connection {
+    state: State,
+    send_id: u16,
+    seq_nr: u16,
+    ack_nr: u16,
+    send_buffer: VecDeque<u8>,
+    in_flight: VecDeque<SentPacket>,
+    bytes_in_flight: usize,
+    cwnd: f64,
+    peer_window: usize,
+    last_ack: u16,
+    duplicate_acks: u32,
+    rtt: Option<(Duration, Duration)>,
+    timeout: Duration,
+    base_delays: VecDeque<(Instant, u32)>,
+    receive_buffer: VecDeque<u8>,
+    out_of_order: HashMap<u16, Packet>,
+    fin_received: bool,
+    reply_micro: u32,
+    last_window: usize,
+}
+
+impl Connection {
+    fn new(state: State, send_id: u16, seq_nr: u16, ack_nr: u16) -> Self {
+        Self {
+            state,
+            send_id,
+            seq_nr,
+            ack_nr,
+            send_buffer: VecDeque::new(),
+            in_flight: VecDeque::new(),
+            bytes_in_flight: 0,
+            cwnd: INITIAL_CWND,
+            peer_window: RECEIVE_BUFFER_SIZE,
+            last_ack: seq_nr.wrapping_sub(1),
+            duplicate_acks: 0,
+            rtt: None,
+            timeout: INITIAL_TIMEOUT,
+            base_delays: VecDeque::new(),
+            receive_buffer: VecDeque::new(),
+            out_of_order: HashMap::new(),
+            fin_received: false,
+            reply_micro: 0,
+            last_window: RECEIVE_BUFFER_SIZE,
+        }
+    }
+
+    /// Starts a connection receiving the packets with the given ID, and returns the `Syn` packet.
+    pub fn connect(recv_id: u16, now: Instant) -> (Self, Packet) {
+        let mut connection = Self::new(State::SynSent, recv_id.wrapping_add(1), 1, 0);
+        let syn = connection.send(Packet::new(PacketType::Syn, recv_id, 0, 0), now);
+        (connection, syn)
+    }
+
+    /// Accepts a connection started by the peer with the given `Syn`, and returns the packet that acknowledges it.
+    pub fn accept(syn: &Packet) -> (Self, Packet) {
+        let mut connection = Self::new(
+            State::Connected,
+            syn.connection_id,
+            rand::thread_rng().gen(),
+            syn.seq_nr,
+        );
+        connection.reply_micro = timestamp_micros().wrapping_sub(syn.timestamp);
+        let state = connection.state_packet();
+        (connection, state)
+    }
+
+    /// Returns the state of the connection.
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns `true` if a read would not block: there is data, the peer finished the connection or the connection is over.
+    pub fn is_readable(&self) -> bool {
+        !self.receive_buffer.is_empty() || self.fin_received || self.is_terminated()
+    }
+
+    /// Returns `true` if a write would not block.
+    pub fn is_writable(&self) -> bool {
+        self.send_buffer.len() < SEND_BUFFER_SIZE || self.is_terminated()
+    }
+
+    /// Returns `true` if no more data can be sent.
+    pub fn is_terminated(&self) -> bool {
+        matches!(self.state, State::Closed | State::Reset | State::TimedOut)
+    }
+
+    /// Returns the congestion window in bytes.
+    pub fn congestion_window(&self) -> usize {
+        self.cwnd as usize
+    }
+
+    /// Handles a packet received from the peer.
+    pub fn packet_received(&mut self, packet: Packet, now: Instant) -> Vec<Packet> {
+        self.reply_micro = timestamp_micros().wrapping_sub(packet.timestamp);
+        self.peer_window = packet.window_size as usize;
+
+        match packet.packet_type {
+            PacketType::Reset => {
+                self.state = State::Reset;
+                return vec![];
+            }
+            // The peer did not receive our acknowledgement of its `Syn`.
+            PacketType::Syn => return vec![self.state_packet()],
+            _ => {}
+        }
+
+        if self.state == State::SynSent {
+            if packet.packet_type != PacketType::State || packet.ack_nr != 1 {
+                return vec![];
+            }
+            self.state = State::Connected;
+            // The first data packet of the peer has the same sequence number as this acknowledgement.
+            self.ack_nr = packet.seq_nr.wrapping_sub(1);
+        }
+
+        let mut packets = self.process_ack(&packet, now);
+        if matches!(packet.packet_type, PacketType::Data | PacketType::Fin) {
+            self.data_received(packet);
+            packets.push(self.state_packet());
+        }
+        packets.extend(self.flush(now));
+        packets
+    }
+
+    /// Buffers data to send, returning how many bytes were accepted and the packets that can be sent within the window.
+    pub fn write(&mut self, data: &[u8], now: Instant) -> (usize, Vec<Packet>) {
+        let len = data
+            .len()
+            .min(SEND_BUFFER_SIZE.saturating_sub(self.send_buffer.len()));
+        self.send_buffer.extend(&data[..len]);
+        (len, self.flush(now))
+    }
+
+    /// Reads received data, returning how many bytes were read.
+    pub fn read(&mut self, buf: &mut [u8]) -> usize {
+        let len = self.peek(buf);
+        self.receive_buffer.drain(..len);
+        len
+    }
+
+    /// Reads received data without removing it from the buffer.
+    pub fn peek(&self, buf: &mut [u8]) -> usize {
+        let len = buf.len().min(self.receive_buffer.len());
+        for (byte, received) in buf.iter_mut().zip(self.receive_buffer.iter()) {
+            *byte = *received;
+        }
+        len
+    }
+
+    /// Resends the oldest packet if it was not acknowledged in time, and announces our window if it was almost full the last time.
+    pub fn tick(&mut self, now: Instant) -> Vec<Packet> {
+        let mut packets = vec![];
+        let timed_out = self
+            .in_flight
+            .front()
+            .is_some_and(|sent| now.duration_since(sent.sent_at) >= self.timeout);
+        if timed_out && !self.is_terminated() {
+            if self.in_flight[0].transmissions >= MAX_TRANSMISSIONS {
+                self.state = State::TimedOut;
+                return vec![];
+            }
+            self.cwnd = MIN_CWND;
+            self.timeout = (self.timeout * 2).min(MAX_TIMEOUT);
+            packets.push(self.resend_first(now));
+        }
+        if self.state == State::Connected
+            && self.last_window < RECEIVE_BUFFER_SIZE / 2
+            && self.receive_window() >= RECEIVE_BUFFER_SIZE / 2
+        {
+            packets.push(self.state_packet());
+        }
+        packets
+    }
+
+    /// Finishes the connection, returning the `Fin` packet.
+    pub fn close(&mut self, now: Instant) -> Option<Packet> {
+        if self.state != State::Connected {
+            return None;
+        }
+        self.state = State::Closed;
+        Some(self.send(Packet::new(PacketType::Fin, self.send_id, 0, 0), now))
+    }
+
+    /// Removes the packets acknowledged by the peer, updating the round trip time and the congestion window.
+    fn process_ack(&mut self, packet: &Packet, now: Instant) -> Vec<Packet> {
+        // Only the packets we sent can be acknowledged.
+        if !seq_before(packet.ack_nr, self.seq_nr) {
+            return vec![];
+        }
+
+        let mut acked_bytes = 0;
+        let mut rtt_sample = None;
+        while let Some(sent) = self.in_flight.front() {
+            if seq_before(packet.ack_nr, sent.packet.seq_nr) {
+                break;
+            }
+            if sent.transmissions == 1 {
+                rtt_sample = Some(now.duration_since(sent.sent_at));
+            }
+            acked_bytes += sent.packet.payload.len();
+            self.bytes_in_flight -= sent.packet.payload.len();
+            self.in_flight.pop_front();
+        }
+
+        if packet.ack_nr != self.last_ack {
+            self.last_ack = packet.ack_nr;
+            self.duplicate_acks = 0;
+            if let Some(sample) = rtt_sample {
+                self.update_timeout(sample);
+            }
+            self.update_congestion_window(packet.timestamp_difference, acked_bytes, now);
+            return vec![];
+        }
+
+        if packet.packet_type == PacketType::State && !self.in_flight.is_empty() {
+            self.duplicate_acks += 1;
+            if self.duplicate_acks == DUPLICATE_ACKS_BEFORE_RESEND {
+                self.cwnd = (self.cwnd / 2.0).max(MIN_CWND);
+                return vec![self.resend_first(now)];
+            }
+        }
+        vec![]
+    }
+
+    /// Adds the payload of a packet to the received data, keeping the packets that arrive out of order until the missing ones arrive.
+    fn data_received(&mut self, packet: Packet) {
+        let distance = packet.seq_nr.wrapping_sub(self.ack_nr);
+        if self.fin_received || distance == 0 || distance > MAX_OUT_OF_ORDER_PACKETS {
+            return;
+        }
+        self.out_of_order.insert(packet.seq_nr, packet);
+        while let Some(packet) = self.out_of_order.remove(&self.ack_nr.wrapping_add(1)) {
+            self.ack_nr = packet.seq_nr;
+            if packet.packet_type == PacketType::Fin {
+                self.fin_received = true;
+                self.out_of_order.clear();
+                break;
+            }
+            self.receive_buffer.extend(packet.payload);
+        }
+    }
+
+    /// Sends the buffered data that fits in the congestion window and the window of the peer.
+    ///
+    /// A packet is always allowed when nothing is in flight, so a closed window of the peer is probed.
+    fn flush(&mut self, now: Instant) -> Vec<Packet> {
+        let mut packets = vec![];
+        let window = (self.cwnd as usize).min(self.peer_window);
+        while self.state == State::Connected && !self.send_buffer.is_empty() {
+            let len = self.send_buffer.len().min(MAX_PAYLOAD_SIZE);
+            if self.bytes_in_flight + len > window && !self.in_flight.is_empty() {
+                break;
+            }
+            let mut packet = Packet::new(PacketType::Data, self.send_id, 0, 0);
+            packet.payload = self.send_buffer.drain(..len).collect();
+            packets.push(self.send(packet, now));
+        }
+        packets
+    }
+
+    /// Numbers and stamps a packet, keeping it until it is acknowledged.
+    fn send(&mut self, mut packet: Packet, now: Instant) -> Packet {
+        packet.seq_nr = self.seq_nr;
+        self.seq_nr = self.seq_nr.wrapping_add(1);
+        self.stamp(&mut packet);
+        self.bytes_in_flight += packet.payload.len();
+        self.in_flight.push_back(SentPacket {
+            packet: packet.clone(),
+            sent_at: now,
+            transmissions: 1,
+        });
+        packet
+    }
+
+    fn resend_first(&mut self, now: Instant) -> Packet {
+        let mut packet = self.in_flight[0].packet.clone();
+        self.stamp(&mut packet);
+        let sent = &mut self.in_flight[0];
+        sent.sent_at = now;
+        sent.transmissions += 1;
+        packet
+    }
+
+    /// Creates a packet that acknowledges the packets received, without payload nor sequence number of its own.
+    fn state_packet(&mut self) -> Packet {
+        let mut packet = Packet::new(PacketType::State, self.send_id, self.seq_nr, 0);
+        self.stamp(&mut packet);
+        packet
+    }
+
+    fn stamp(&mut self, packet: &mut Packet) {
+        // The connection ID of the `Syn` is the one we receive with.
+        if packet.packet_type != PacketType::Syn {
+            packet.connection_id = self.send_id;
+        }
+        packet.ack_nr = self.ack_nr;
+        packet.timestamp = timestamp_micros();
+        packet.timestamp_difference = self.reply_micro;
+        self.last_window = self.receive_window();
+        packet.window_size = self.last_window as u32;
+    }
+
+    fn receive_window(&self) -> usize {
+        RECEIVE_BUFFER_SIZE.saturating_sub(self.receive_buffer.len())
+    }
+
+    /// Updates the timeout with a round trip time sample, like TCP does.
+    fn update_timeout(&mut self, sample: Duration) {
+        let (rtt, rtt_var) = match self.rtt {
+            None => (sample, sample / 2),
+            Some((rtt, rtt_var)) => {
+                let delta = rtt.abs_diff(sample);
+                (
+                    rtt + sample / 8 - rtt / 8,
+                    rtt_var + delta / 4 - rtt_var / 4,
+                )
+            }
+        };
+        self.rtt = Some((rtt, rtt_var));
+        self.timeout = (rtt + rtt_var * 4).clamp(MIN_TIMEOUT, MAX_TIMEOUT);
+    }
+
+    /// LEDBAT: grows or shrinks the congestion window in proportion to how far the queuing delay is from the target.
+    ///
+    /// The queuing delay is the one-way delay measured by the peer minus the base delay, the lowest delay of the last minutes.
+    fn update_congestion_window(&mut self, delay: u32, acked_bytes: usize, now: Instant) {
+        if acked_bytes == 0 {
+            return;
+        }
+        let mut queuing_delay = 0;
+        if delay != 0 {
+            match self.base_delays.back_mut() {
+                Some((started, base_delay))
+                    if now.duration_since(*started) < BASE_DELAY_INTERVAL =>
+                {
+                    *base_delay = (*base_delay).min(delay);
+                }
+                _ => {
+                    self.base_delays.push_back((now, delay));
+                    if self.base_delays.len() > 2 {
+                        self.base_delays.pop_front();
+                    }
+                }
+            }
+            let base_delay = self
+                .base_delays
+                .iter()
+                .map(|(_, delay)| *delay)
+                .min()
+                .unwrap_or(delay);
+            queuing_delay = delay - base_delay;
+        }
+
+        let off_target = ((TARGET_DELAY - queuing_delay as f64) / TARGET_DELAY).clamp(-1.0, 1.0);
+        self.cwnd = (self.cwnd + MAX_CWND_INCREASE * off_target * acked_bytes as f64 / self.cwnd)
+            .clamp(MIN_CWND, MAX_CWND);
+    }
+}
+
+/// Returns `true` if the sequence number `a` comes before `b`, taking into account that they wrap around.
+fn seq_before(a: u16, b: u16) -> bool {
+    a != b && b.wrapping_sub(a) < 0x8000
+}
+
+/// Returns the current time in microseconds, truncated to 32 bits as the timestamps of the packets.
+fn timestamp_micros() -> u32 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_micros() as u32)
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_handshake_and_data_exchange() {
+        let now = Instant::now();
+        let (mut client, mut server) = create_test_connections(now);
+
+        let (len, packets) = client.write(b"hello", now);
+        assert_eq!(len, 5);
+        let acks = deliver(&mut server, packets, now);
+        deliver(&mut client, acks, now);
+        let (_, packets) = server.write(b"world", now);
+        let acks = deliver(&mut client, packets, now);
+        deliver(&mut server, acks, now);
+
+        assert_eq!(read_all(&mut server), b"hello");
+        assert_eq!(read_all(&mut client), b"world");
+        assert!(client.in_flight.is_empty());
+        assert!(server.in_flight.is_empty());
+    }
+
+    #[test]
+    fn test_packets_out_of_order_and_lost_packets() {
+        let now = Instant::now();
+        let (mut client, mut server) = create_test_connections(now);
+        client.cwnd = MAX_CWND;
+
+        let data: Vec<u8> = (0..MAX_PAYLOAD_SIZE * 3).map(|i| i as u8).collect();
+        let (_, mut packets) = client.write(&data, now);
+        assert_eq!(packets.len(), 3);
+
+        // The first packet is lost and the others arrive in reverse order.
+        packets.remove(0);
+        packets.reverse();
+        let acks = deliver(&mut server, packets, now);
+        assert!(server.receive_buffer.is_empty());
+        deliver(&mut client, acks, now);
+
+        // The lost packet is resent after its timeout.
+        let later = now + INITIAL_TIMEOUT;
+        let packets = client.tick(later);
+        assert_eq!(packets.len(), 1);
+        let acks = deliver(&mut server, packets, later);
+        deliver(&mut client, acks, later);
+
+        assert_eq!(read_all(&mut server), data);
+        assert!(client.in_flight.is_empty());
+    }
+
+    #[test]
+    fn test_connection_times_out() {
+        let mut now = Instant::now();
+        let (mut client, _) = Connection::connect(10, now);
+
+        for _ in 0..MAX_TRANSMISSIONS {
+            now += MAX_TIMEOUT;
+            client.tick(now);
+        }
+
+        assert_eq!(client.state(), State::TimedOut);
+        assert!(client.is_readable());
+    }
+
+    #[test]
+    fn test_fin_and_reset() {
+        let now = Instant::now();
+        let (mut client, mut server) = create_test_connections(now);
+
+        let fin = client.close(now).unwrap();
+        deliver(&mut server, vec![fin], now);
+        assert!(server.is_readable());
+        assert_eq!(read_all(&mut server), b"");
+
+        let reset = Packet::new(PacketType::Reset, client.send_id, 0, 0);
+        server.packet_received(reset, now);
+        assert_eq!(server.state(), State::Reset);
+    }
+
+    #[test]
+    fn test_ledbat_window_follows_the_delay() {
+        let now = Instant::now();
+        let (mut client, _) = create_test_connections(now);
+        let initial_cwnd = client.congestion_window();
+
+        // Delays close to the base delay make the window grow.
+        for delay in [10_000, 10_000, 12_000] {
+            ack_data(&mut client, delay, now);
+        }
+        let grown_cwnd = client.congestion_window();
+        assert!(grown_cwnd > initial_cwnd);
+
+        // A queuing delay above the target makes it shrink.
+        for _ in 0..3 {
+            ack_data(&mut client, 10_000 + 250_000, now);
+        }
+        assert!(client.congestion_window() < grown_cwnd);
+    }
+
+    // Auxiliary functions
+
+    fn create_test_connections(now: Instant) -> (Connection, Connection) {
+        let (mut client, syn) = Connection::connect(100, now);
+        let (server, state) = Connection::accept(&Packet::from_bytes(&syn.as_bytes()).unwrap());
+        client.packet_received(state, now);
+
+        assert_eq!(client.state(), State::Connected);
+        assert_eq!(client.send_id, 101);
+        assert_eq!(server.send_id, 100);
+        (client, server)
+    }
+
+    fn deliver(connection: &mut Connection, packets: Vec<Packet>, now: Instant) -> Vec<Packet> {
+        packets
+            .into_iter()
+            .flat_map(|packet| {
+                connection.packet_received(Packet::from_bytes(&packet.as_bytes()).unwrap(), now)
+            })
+            .collect()
+    }
+
+    fn read_all(connection: &mut Connection) -> Vec<u8> {
+        let mut buffer = vec![0; RECEIVE_BUFFER_SIZE];
+        let len = connection.read(&mut buffer);
+        buffer.truncate(len);
+        buffer
+    }
+
+    /// Sends a packet of data and acknowledges it with the given delay.
+    fn ack_data(connection: &mut Connection, delay: u32, now: Instant) {
+        let (_, packets) = connection.write(&[0; MAX_PAYLOAD_SIZE], now);
+        let mut ack = Packet::new(PacketType::State, 0, 0, packets[0].seq_nr);
+        ack.timestamp_difference = delay;
+        ack.window_size = RECEIVE_BUFFER_SIZE as u32;
+        connection.packet_received(ack, now);
+    }
+}
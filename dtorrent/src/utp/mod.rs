@@ -0,0 +1,4 @@
+mod connection;
+pub mod packet;
+pub mod socket;
+pub mod stream;
@@ -0,0 +1,169 @@
+/// Length in bytes of the header of a uTP packet, without extensions.
+pub const HEADER_SIZE: usize = 20;
+
+/// Version of the uTP protocol.
+const VERSION: u8 = 1;
+
+/// Type of a uTP packet.
+///
+/// - `Data`: a packet with payload,
+/// - `Fin`: the last packet of a connection,
+/// - `State`: a packet without payload, used to acknowledge packets,
+/// - `Reset`: terminates the connection forcefully,
+/// - `Syn`: starts a connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PacketType {
+    Data = 0,
+    Fin = 1,
+    State = 2,
+    Reset = 3,
+    Syn = 4,
+}
+
+/// Posible `Packet` errors.
+#[derive(Debug, PartialEq, Eq)]
+pub enum PacketError {
+    TooShort,
+    InvalidVersion,
+    InvalidType(u8),
+    InvalidExtension,
+}
+
+/// A uTP packet (BEP 29).
+///
+/// To create a new `Packet`, use Packet::new(packet_type, connection_id, seq_nr, ack_nr).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Packet {
+    pub packet_type: PacketType,
+    pub connection_id: u16,
+    pub timestamp: u32,
+    pub timestamp_difference: u32,
+    pub window_size: u32,
+    pub seq_nr: u16,
+    pub ack_nr: u16,
+    pub payload: Vec<u8>,
+}
+
+impl Packet {
+    /// Creates a new `Packet` without payload, the timestamps and window are set when it is sent.
+    pub fn new(packet_type: PacketType, connection_id: u16, seq_nr: u16, ack_nr: u16) -> Self {
+        Self {
+            packet_type,
+            connection_id,
+            timestamp: 0,
+            timestamp_difference: 0,
+            window_size: 0,
+            seq_nr,
+            ack_nr,
+            payload: vec![],
+        }
+    }
+
+    /// Parses a packet from a datagram.
+    ///
+    /// The extensions are skipped, as selective acks are not used.
+    ///
+    /// It returns a `PacketError` if the datagram is not a valid uTP packet.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, PacketError> {
+        if bytes.len() < HEADER_SIZE {
+            return Err(PacketError::TooShort);
+        }
+        if bytes[0] & 0x0F != VERSION {
+            return Err(PacketError::InvalidVersion);
+        }
+        let packet_type = match bytes[0] >> 4 {
+            0 => PacketType::Data,
+            1 => PacketType::Fin,
+            2 => PacketType::State,
+            3 => PacketType::Reset,
+            4 => PacketType::Syn,
+            n => return Err(PacketError::InvalidType(n)),
+        };
+
+        let mut extension = bytes[1];
+        let mut offset = HEADER_SIZE;
+        while extension != 0 {
+            if bytes.len() < offset + 2 {
+                return Err(PacketError::InvalidExtension);
+            }
+            extension = bytes[offset];
+            offset += 2 + bytes[offset + 1] as usize;
+            if bytes.len() < offset {
+                return Err(PacketError::InvalidExtension);
+            }
+        }
+
+        Ok(Self {
+            packet_type,
+            connection_id: u16::from_be_bytes([bytes[2], bytes[3]]),
+            timestamp: u32::from_be_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]),
+            timestamp_difference: u32::from_be_bytes([bytes[8], bytes[9], bytes[10], bytes[11]]),
+            window_size: u32::from_be_bytes([bytes[12], bytes[13], bytes[14], bytes[15]]),
+            seq_nr: u16::from_be_bytes([bytes[16], bytes[17]]),
+            ack_nr: u16::from_be_bytes([bytes[18], bytes[19]]),
+            payload: bytes[offset..].to_vec(),
+        })
+    }
+
+    /// Returns the packet as bytes, ready to be sent.
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_SIZE + self.payload.len());
+        bytes.push(((self.packet_type as u8) << 4) | VERSION);
+        bytes.push(0);
+        bytes.extend(self.connection_id.to_be_bytes());
+        bytes.extend(self.timestamp.to_be_bytes());
+        bytes.extend(self.timestamp_difference.to_be_bytes());
+        bytes.extend(self.window_size.to_be_bytes());
+        bytes.extend(self.seq_nr.to_be_bytes());
+        bytes.extend(self.ack_nr.to_be_bytes());
+        bytes.extend(&self.payload);
+        bytes
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_packet_round_trip() {
+        let mut packet = Packet::new(PacketType::Data, 1234, 10, 9);
+        packet.timestamp = 1_000_000;
+        packet.timestamp_difference = 500;
+        packet.window_size = 65536;
+        packet.payload = b"payload".to_vec();
+
+        let bytes = packet.as_bytes();
+
+        assert_eq!(bytes[0], 0x01);
+        assert_eq!(bytes.len(), HEADER_SIZE + 7);
+        assert_eq!(Packet::from_bytes(&bytes).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_packet_with_extensions() {
+        let mut bytes = Packet::new(PacketType::State, 1, 2, 3).as_bytes();
+        // Selective ack extension with a 4 bytes bitmask.
+        bytes[1] = 1;
+        bytes.extend([0, 4, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let packet = Packet::from_bytes(&bytes).unwrap();
+
+        assert_eq!(packet.packet_type, PacketType::State);
+        assert!(packet.payload.is_empty());
+    }
+
+    #[test]
+    fn test_invalid_packets() {
+        assert_eq!(Packet::from_bytes(&[0x01; 10]), Err(PacketError::TooShort));
+        // A bencoded DHT message is not a uTP packet.
+        assert_eq!(
+            Packet::from_bytes(b"d1:ad2:id20:abcdefghij0123456789e1:q4:ping1:t2:aa1:y1:qe"),
+            Err(PacketError::InvalidVersion)
+        );
+        assert_eq!(
+            Packet::from_bytes(&[0x51; HEADER_SIZE]),
+            Err(PacketError::InvalidType(5))
+        );
+    }
+}
@@ -0,0 +1,380 @@
+use logger::logger_sender::LoggerSender;
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    fmt, io,
+    net::{SocketAddr, UdpSocket},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Condvar, Mutex, MutexGuard,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use super::{
+    connection::{Connection, State},
+    packet::{Packet, PacketType},
+    stream::UtpStream,
+};
+
+/// Time between checks of the timeouts of the connections.
+const TICK_INTERVAL: Duration = Duration::from_millis(50);
+
+const MAX_DATAGRAM_SIZE: usize = 65536;
+
+/// Connections by the address of the peer and the connection ID of the packets we receive.
+type Connections = HashMap<(SocketAddr, u16), Arc<SharedConnection>>;
+
+/// Receiver of the datagrams of the socket that are not uTP packets, like the messages of a DHT node sharing the socket.
+pub trait DatagramHandler: Send + Sync {
+    fn datagram_received(&self, datagram: &[u8], addr: SocketAddr);
+}
+
+/// A connection shared by its `UtpStream` and the thread receiving the packets of the socket.
+///
+/// The condvar is notified every time the connection changes, so the stream can wait for data or for space to write.
+pub struct SharedConnection {
+    pub connection: Mutex<Connection>,
+    pub changed: Condvar,
+    pub addr: SocketAddr,
+    recv_id: u16,
+}
+
+impl SharedConnection {
+    /// Locks the connection.
+    ///
+    /// It returns an error if the lock is poisoned.
+    pub fn lock(&self) -> io::Result<MutexGuard<'_, Connection>> {
+        self.connection
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned uTP connection lock"))
+    }
+}
+
+/// UDP socket shared by all the uTP connections (BEP 29).
+///
+/// A thread receives the packets of the socket, hands them to their connections and resends the packets that were not acknowledged in time.
+/// Incoming connections are queued until they are accepted with `accept()`.
+///
+/// To bind a new `UtpSocket`, use UtpSocket::bind(port, logger_sender).
+pub struct UtpSocket {
+    socket: UdpSocket,
+    connections: Mutex<Connections>,
+    incoming_sender: Mutex<Sender<UtpStream>>,
+    incoming: Mutex<Receiver<UtpStream>>,
+    datagram_handler: Mutex<Option<Arc<dyn DatagramHandler>>>,
+    logger_sender: LoggerSender,
+}
+
+impl UtpSocket {
+    /// Binds the UDP socket on the given port and starts receiving packets.
+    ///
+    /// It returns an error if the socket could not be bound or the thread could not be spawned.
+    pub fn bind(port: u16, logger_sender: LoggerSender) -> io::Result<Arc<Self>> {
+        let socket = UdpSocket::bind(("0.0.0.0", port))?;
+        socket.set_read_timeout(Some(TICK_INTERVAL))?;
+        let (incoming_sender, incoming) = mpsc::channel();
+
+        let utp_socket = Arc::new(Self {
+            socket,
+            connections: Mutex::new(HashMap::new()),
+            incoming_sender: Mutex::new(incoming_sender),
+            incoming: Mutex::new(incoming),
+            datagram_handler: Mutex::new(None),
+            logger_sender,
+        });
+
+        let listener = utp_socket.clone();
+        thread::Builder::new()
+            .name("uTP socket".to_string())
+            .spawn(move || listener.listen())?;
+        Ok(utp_socket)
+    }
+
+    /// Returns the address the UDP socket is bound to.
+    pub fn local_addr(&self) -> io::Result<SocketAddr> {
+        self.socket.local_addr()
+    }
+
+    /// Returns a handle of the UDP socket, to send datagrams that are not uTP packets through it.
+    pub fn try_clone_udp_socket(&self) -> io::Result<UdpSocket> {
+        self.socket.try_clone()
+    }
+
+    /// Sets who receives the datagrams that are not uTP packets.
+    pub fn set_datagram_handler(&self, handler: Arc<dyn DatagramHandler>) {
+        if let Ok(mut datagram_handler) = self.datagram_handler.lock() {
+            *datagram_handler = Some(handler);
+        }
+    }
+
+    /// Connects to a peer, waiting up to `timeout` for the peer to acknowledge the connection.
+    ///
+    /// It returns an error if the peer did not answer in time or refused the connection.
+    pub fn connect(self: &Arc<Self>, addr: SocketAddr, timeout: Duration) -> io::Result<UtpStream> {
+        let shared = {
+            let mut connections = self.lock_connections()?;
+            let mut recv_id: u16 = rand::thread_rng().gen();
+            while connections.contains_key(&(addr, recv_id)) {
+                recv_id = rand::thread_rng().gen();
+            }
+            let (connection, syn) = Connection::connect(recv_id, Instant::now());
+            let shared = Arc::new(SharedConnection {
+                connection: Mutex::new(connection),
+                changed: Condvar::new(),
+                addr,
+                recv_id,
+            });
+            connections.insert((addr, recv_id), shared.clone());
+            self.send_packets(addr, vec![syn]);
+            shared
+        };
+        // The connection is removed from the socket if the stream is dropped before connecting.
+        let stream = UtpStream::new(self.clone(), shared.clone());
+
+        let deadline = Instant::now() + timeout;
+        let mut connection = shared.lock()?;
+        while connection.state() == State::SynSent {
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(io::ErrorKind::TimedOut.into());
+            }
+            connection = shared
+                .changed
+                .wait_timeout(connection, deadline - now)
+                .map_err(|_| io::Error::other("Poisoned uTP connection lock"))?
+                .0;
+        }
+        if connection.state() != State::Connected {
+            return Err(io::ErrorKind::ConnectionRefused.into());
+        }
+        drop(connection);
+        Ok(stream)
+    }
+
+    /// Blocks until a peer connects to the socket.
+    ///
+    /// It returns an error if the lock of the incoming connections is poisoned.
+    pub fn accept(&self) -> io::Result<UtpStream> {
+        self.incoming
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned uTP incoming lock"))?
+            .recv()
+            .map_err(|_| io::ErrorKind::NotConnected.into())
+    }
+
+    /// Sends the packets of a connection.
+    pub fn send_packets(&self, addr: SocketAddr, packets: Vec<Packet>) {
+        for packet in packets {
+            if let Err(err) = self.socket.send_to(&packet.as_bytes(), addr) {
+                self.logger_sender
+                    .warn(&format!("Could not send uTP packet to {}: {}", addr, err));
+            }
+        }
+    }
+
+    /// Removes a connection from the socket, its packets are ignored from now on.
+    pub fn remove_connection(&self, shared: &SharedConnection) {
+        if let Ok(mut connections) = self.lock_connections() {
+            connections.remove(&(shared.addr, shared.recv_id));
+        }
+    }
+
+    /// Receives the datagrams of the socket, checking the timeouts of the connections between them.
+    fn listen(self: Arc<Self>) {
+        let mut buffer = vec![0; MAX_DATAGRAM_SIZE];
+        let mut last_tick = Instant::now();
+        loop {
+            if let Ok((size, addr)) = self.socket.recv_from(&mut buffer) {
+                self.handle_datagram(&buffer[..size], addr);
+            }
+            if last_tick.elapsed() >= TICK_INTERVAL {
+                self.tick();
+                last_tick = Instant::now();
+            }
+        }
+    }
+
+    /// Hands a datagram to its connection, creates a new connection if it is a `Syn`, or hands it to the datagram handler if it is not a uTP packet.
+    fn handle_datagram(self: &Arc<Self>, datagram: &[u8], addr: SocketAddr) {
+        let packet = match Packet::from_bytes(datagram) {
+            Ok(packet) => packet,
+            Err(_) => {
+                let handler = match self.datagram_handler.lock() {
+                    Ok(handler) => handler.clone(),
+                    Err(_) => None,
+                };
+                if let Some(handler) = handler {
+                    handler.datagram_received(datagram, addr);
+                }
+                return;
+            }
+        };
+
+        let recv_id = match packet.packet_type {
+            PacketType::Syn => packet.connection_id.wrapping_add(1),
+            _ => packet.connection_id,
+        };
+        let shared = match self.lock_connections() {
+            Ok(connections) => connections.get(&(addr, recv_id)).cloned(),
+            Err(_) => return,
+        };
+
+        match shared {
+            Some(shared) => {
+                let packets = match shared.lock() {
+                    Ok(mut connection) => connection.packet_received(packet, Instant::now()),
+                    Err(_) => return,
+                };
+                shared.changed.notify_all();
+                self.send_packets(addr, packets);
+            }
+            None if packet.packet_type == PacketType::Syn => self.accept_connection(&packet, addr),
+            // Packets of unknown connections are ignored.
+            None => {}
+        }
+    }
+
+    /// Accepts a connection started by a peer, queuing its stream until it is accepted.
+    fn accept_connection(self: &Arc<Self>, syn: &Packet, addr: SocketAddr) {
+        let (connection, state) = Connection::accept(syn);
+        let recv_id = syn.connection_id.wrapping_add(1);
+        let shared = Arc::new(SharedConnection {
+            connection: Mutex::new(connection),
+            changed: Condvar::new(),
+            addr,
+            recv_id,
+        });
+        match self.lock_connections() {
+            Ok(mut connections) => connections.insert((addr, recv_id), shared.clone()),
+            Err(_) => return,
+        };
+        self.send_packets(addr, vec![state]);
+
+        let stream = UtpStream::new(self.clone(), shared);
+        if let Ok(incoming_sender) = self.incoming_sender.lock() {
+            let _ = incoming_sender.send(stream);
+        }
+    }
+
+    /// Resends the packets that were not acknowledged in time.
+    fn tick(&self) {
+        let connections: Vec<Arc<SharedConnection>> = match self.lock_connections() {
+            Ok(connections) => connections.values().cloned().collect(),
+            Err(_) => return,
+        };
+        let now = Instant::now();
+        for shared in connections {
+            let packets = match shared.lock() {
+                Ok(mut connection) => connection.tick(now),
+                Err(_) => continue,
+            };
+            shared.changed.notify_all();
+            self.send_packets(shared.addr, packets);
+        }
+    }
+
+    fn lock_connections(&self) -> io::Result<MutexGuard<'_, Connections>> {
+        self.connections
+            .lock()
+            .map_err(|_| io::Error::other("Poisoned uTP connections lock"))
+    }
+}
+
+impl fmt::Debug for UtpSocket {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UtpSocket({:?})", self.socket.local_addr())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use logger::logger_receiver::Logger;
+    use std::{
+        fs,
+        io::{Read, Write},
+    };
+
+    #[test]
+    fn test_connect_and_transfer_data() {
+        let name = "test_utp_connect_and_transfer_data";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let server = UtpSocket::bind(0, logger.new_sender()).unwrap();
+        let client = UtpSocket::bind(0, logger.new_sender()).unwrap();
+        let server_addr = loopback_addr(&server);
+        let data: Vec<u8> = (0..200_000).map(|i| (i % 251) as u8).collect();
+
+        let server_thread = thread::spawn(move || {
+            let mut stream = server.accept().unwrap();
+            let mut received = vec![0; 200_000];
+            stream.read_exact(&mut received).unwrap();
+            stream.write_all(b"done").unwrap();
+            received
+        });
+
+        let mut stream = client.connect(server_addr, Duration::from_secs(5)).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(5)));
+        stream.write_all(&data).unwrap();
+        let mut answer = [0; 4];
+        stream.read_exact(&mut answer).unwrap();
+
+        assert_eq!(&answer, b"done");
+        assert_eq!(server_thread.join().unwrap(), data);
+        assert_eq!(stream.peer_addr().unwrap(), server_addr);
+        fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    #[test]
+    fn test_connect_to_nobody_times_out() {
+        let name = "test_utp_connect_to_nobody_times_out";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let client = UtpSocket::bind(0, logger.new_sender()).unwrap();
+        let nobody = UdpSocket::bind("127.0.0.1:0").unwrap();
+
+        let result = client.connect(nobody.local_addr().unwrap(), Duration::from_millis(300));
+
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::TimedOut);
+        assert!(client.lock_connections().unwrap().is_empty());
+        fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    #[derive(Default)]
+    struct TestHandler {
+        datagrams: Mutex<Vec<Vec<u8>>>,
+    }
+
+    impl DatagramHandler for TestHandler {
+        fn datagram_received(&self, datagram: &[u8], _addr: SocketAddr) {
+            self.datagrams.lock().unwrap().push(datagram.to_vec());
+        }
+    }
+
+    #[test]
+    fn test_other_datagrams_go_to_the_handler() {
+        let name = "test_utp_other_datagrams_go_to_the_handler";
+        let logger = Logger::new(&format!("./{}", name), 1000).unwrap();
+        let utp_socket = UtpSocket::bind(0, logger.new_sender()).unwrap();
+        let handler = Arc::new(TestHandler::default());
+        utp_socket.set_datagram_handler(handler.clone());
+
+        let sender = UdpSocket::bind("127.0.0.1:0").unwrap();
+        sender
+            .send_to(b"d1:y1:qe", loopback_addr(&utp_socket))
+            .unwrap();
+        thread::sleep(Duration::from_millis(200));
+
+        assert_eq!(
+            *handler.datagrams.lock().unwrap(),
+            vec![b"d1:y1:qe".to_vec()]
+        );
+        fs::remove_dir_all(format!("./{}", name)).unwrap();
+    }
+
+    // Auxiliary functions
+
+    fn loopback_addr(utp_socket: &UtpSocket) -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], utp_socket.local_addr().unwrap().port()))
+    }
+}
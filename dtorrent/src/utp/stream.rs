@@ -0,0 +1,152 @@
+use std::{
+    cell::Cell,
+    fmt,
+    io::{self, Read, Write},
+    net::SocketAddr,
+    sync::{Arc, MutexGuard},
+    time::{Duration, Instant},
+};
+
+use super::{
+    connection::{Connection, State},
+    socket::{SharedConnection, UtpSocket},
+};
+
+/// A uTP connection with a peer, read and written like a `TcpStream`.
+///
+/// The connection is finished when the stream is dropped.
+///
+/// To create a new `UtpStream`, use UtpSocket::connect(addr, timeout) or UtpSocket::accept().
+pub struct UtpStream {
+    socket: Arc<UtpSocket>,
+    shared: Arc<SharedConnection>,
+    read_timeout: Cell<Option<Duration>>,
+    write_timeout: Cell<Option<Duration>>,
+}
+
+impl UtpStream {
+    pub(super) fn new(socket: Arc<UtpSocket>, shared: Arc<SharedConnection>) -> Self {
+        Self {
+            socket,
+            shared,
+            read_timeout: Cell::new(None),
+            write_timeout: Cell::new(None),
+        }
+    }
+
+    /// Reads received data without removing it, blocking until there is some.
+    ///
+    /// It returns an error if the timeout expires or the connection was reset or timed out.
+    pub fn peek(&self, buf: &mut [u8]) -> io::Result<usize> {
+        let connection = self.wait_readable()?;
+        Self::check_eof(&connection, connection.peek(buf))
+    }
+
+    /// Sets the timeout of the reads, `None` blocks indefinitely.
+    pub fn set_read_timeout(&self, timeout: Option<Duration>) {
+        self.read_timeout.set(timeout);
+    }
+
+    /// Sets the timeout of the writes, `None` blocks indefinitely.
+    pub fn set_write_timeout(&self, timeout: Option<Duration>) {
+        self.write_timeout.set(timeout);
+    }
+
+    /// Returns the address of the peer.
+    pub fn peer_addr(&self) -> io::Result<SocketAddr> {
+        Ok(self.shared.addr)
+    }
+
+    fn wait_readable(&self) -> io::Result<MutexGuard<'_, Connection>> {
+        self.wait(self.read_timeout.get(), Connection::is_readable)
+    }
+
+    /// Waits until the condition holds for the connection or the timeout expires.
+    fn wait(
+        &self,
+        timeout: Option<Duration>,
+        condition: fn(&Connection) -> bool,
+    ) -> io::Result<MutexGuard<'_, Connection>> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut connection = self.shared.lock()?;
+        while !condition(&connection) {
+            let wait = match deadline {
+                Some(deadline) => {
+                    let now = Instant::now();
+                    if now >= deadline {
+                        return Err(io::ErrorKind::WouldBlock.into());
+                    }
+                    deadline - now
+                }
+                // The socket notifies the connection at least on every tick.
+                None => Duration::from_secs(1),
+            };
+            connection = self
+                .shared
+                .changed
+                .wait_timeout(connection, wait)
+                .map_err(|_| io::Error::other("Poisoned uTP connection lock"))?
+                .0;
+        }
+        Ok(connection)
+    }
+
+    /// Turns an empty read into the end of the stream or the error that terminated the connection.
+    fn check_eof(connection: &Connection, read: usize) -> io::Result<usize> {
+        if read > 0 {
+            return Ok(read);
+        }
+        match connection.state() {
+            State::Reset => Err(io::ErrorKind::ConnectionReset.into()),
+            State::TimedOut => Err(io::ErrorKind::ConnectionAborted.into()),
+            _ => Ok(0),
+        }
+    }
+}
+
+impl Read for UtpStream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut connection = self.wait_readable()?;
+        let read = connection.read(buf);
+        Self::check_eof(&connection, read)
+    }
+}
+
+impl Write for UtpStream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let mut connection = self.wait(self.write_timeout.get(), Connection::is_writable)?;
+        if connection.state() != State::Connected {
+            return Err(io::ErrorKind::NotConnected.into());
+        }
+        let (written, packets) = connection.write(buf, Instant::now());
+        drop(connection);
+        self.socket.send_packets(self.shared.addr, packets);
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl fmt::Debug for UtpStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "UtpStream({})", self.shared.addr)
+    }
+}
+
+impl Drop for UtpStream {
+    fn drop(&mut self) {
+        let fin = match self.shared.lock() {
+            Ok(mut connection) => connection.close(Instant::now()),
+            Err(_) => None,
+        };
+        if let Some(fin) = fin {
+            self.socket.send_packets(self.shared.addr, vec![fin]);
+        }
+        self.socket.remove_connection(&self.shared);
+    }
+}